@@ -16,7 +16,8 @@ uniform vec3 viewPos;
 
 
 layout(binding=0) uniform sampler2D Texture;
-layout(binding=1) uniform sampler2D shadowMap;
+// only the first cascade is used, see lightSpaceMat
+layout(binding=1) uniform sampler2DArray shadowMap;
 
 
 
@@ -10,47 +10,118 @@ in VS_OUTPUT {
 } IN;
 
 
-uniform vec3 lightColor;
-uniform vec3 lightPos;
 uniform vec3 viewPos;
 layout(binding=0) uniform sampler2D Texture;
-layout(binding=1) uniform sampler2D shadowMap;
+layout(binding=1) uniform sampler2DArray shadowMap;
 
+#define MAX_LIGHTS 16
 
-float ShadowCalculation(vec4 fragPosLightSpace, vec3 normal, vec3 lightDir)
+// see scene_3d::lights
+struct Light {
+  vec4 posKind;        // xyz position, w kind: 0 directional, 1 point, 2 spot
+  vec4 directionRange; // xyz direction light points, w range, negative for no range
+  vec4 color;          // rgb color * intensity, w casts shadows
+  vec4 attenuation;    // constant, linear, quadratic
+  vec4 cone;           // cos of inner and outer angle
+};
+
+layout(std140, binding=0) uniform LightBlock {
+  Light lights[MAX_LIGHTS];
+  vec4 ambient;
+};
+
+// most relevant lights for this mesh, index into lights
+uniform int meshLightCount;
+uniform int meshLights[4];
+uniform int shadowLight;
+
+
+// direction from fragment towards light
+vec3 lightDirection(Light l, vec3 fragPos)
 {
+  if (l.posKind.w < 0.5) {
+    return normalize(-l.directionRange.xyz);
+  }
+  return normalize(l.posKind.xyz - fragPos);
+}
 
-  // if normal points away from light, we now that it is in shadow
-  // this can also eliminate the bias since that created notisable
-  float angle = dot(normal, lightDir);
-  if (angle < 0.0 ) {
-    return 1.0; // should be 1
+// attenuation and spot cone
+float lightFalloff(Light l, vec3 fragPos, vec3 L)
+{
+  if (l.posKind.w < 0.5) {
+    return 1.0;
   }
 
-  // get correct projection, when using perspective and ortho
-  // in [-1,1]
-  vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w;
+  float d = length(l.posKind.xyz - fragPos);
+  float att = 1.0 / (l.attenuation.x + l.attenuation.y * d + l.attenuation.z * d * d);
 
-  // map to [0,1]
-  projCoords = projCoords * 0.5 + 0.5;
+  // fade to 0 at range
+  float range = l.directionRange.w;
+  if (range > 0.0) {
+    float r = clamp(1.0 - pow(d / range, 4.0), 0.0, 1.0);
+    att *= r * r;
+  }
 
-  // light outside light view frustum z far
-  if(projCoords.z > 1.0)
-  {
-    return 0.0; // should be 0
+  if (l.posKind.w > 1.5) {
+    float theta = dot(-L, normalize(l.directionRange.xyz));
+    att *= smoothstep(l.cone.y, l.cone.x, theta);
   }
 
-  if (projCoords.x > 1.0 || projCoords.x  < 0.0 ||
-      projCoords.y > 1.0 || projCoords.y  < 0.0)
-  {
+  return att;
+}
+
+
+// see objects::shadow_map
+#define MAX_CASCADES 4
+uniform mat4 view;
+uniform mat4 lightSpaceMats[MAX_CASCADES];
+uniform float cascadeSplits[MAX_CASCADES];
+uniform int cascadeCount;
+uniform int pcfRadius;
+
+
+float ShadowCalculation(vec3 fragPos, vec3 normal, vec3 lightDir)
+{
+  // if normal points away from light, we now that it is in shadow
+  if (dot(normal, lightDir) < 0.0) {
+    return 1.0;
+  }
+
+  // pick cascade from view depth
+  float depth = abs((view * vec4(fragPos, 1.0)).z);
+  int layer = -1;
+  for (int i = 0; i < cascadeCount; i++) {
+    if (depth < cascadeSplits[i]) {
+      layer = i;
+      break;
+    }
+  }
+
+  // beyond shadow distance
+  if (layer < 0) {
+    return 0.0;
+  }
+
+  vec4 fragPosLightSpace = lightSpaceMats[layer] * vec4(fragPos, 1.0);
+  vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w;
+  projCoords = projCoords * 0.5 + 0.5;
+
+  if (projCoords.z > 1.0) {
     return 0.0;
   }
 
-  float closestDepth = texture(shadowMap, projCoords.xy).r;
-  float currentDepth = projCoords.z;
-  float shadow = currentDepth > closestDepth ? 1.0 : 0.0;
+  // PCF, outside the map samples the border which is never in shadow
+  vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+  float shadow = 0.0;
+  for (int x = -pcfRadius; x <= pcfRadius; x++) {
+    for (int y = -pcfRadius; y <= pcfRadius; y++) {
+      float closestDepth = texture(shadowMap, vec3(projCoords.xy + vec2(x, y) * texelSize, layer)).r;
+      shadow += projCoords.z > closestDepth ? 1.0 : 0.0;
+    }
+  }
 
-  return shadow;
+  float samples = float((2 * pcfRadius + 1) * (2 * pcfRadius + 1));
+  return shadow / samples;
 }
 
 
@@ -60,29 +131,34 @@ void main()
   vec3 col = texture(Texture, IN.TexCord).rgb;
 
   // ABIENT
-  float ambientStrength = 0.5;
-  vec3 ambient = ambientStrength * lightColor;
+  vec3 light = ambient.rgb;
 
-  //DIFFUSE
   vec3 norm = normalize(IN.Normal);
-  vec3 lightDir = normalize(lightPos - IN.FragPos);
-  float diff = max(dot(norm, lightDir), 0.0);
-  vec3 diffuse = (diff * lightColor) * 0.70;
-
-  // SPECULAR
-  float specularStrength = 0.1;
   vec3 viewDir = normalize(viewPos - IN.FragPos);
-  vec3 reflectionDir = reflect(-lightDir, IN.Normal);
-  float spec = pow(max(dot(viewDir, reflectionDir), 0.0), 5);
-  vec3 specular = specularStrength * spec * lightColor;
 
-  // SHADOW
-  float shadow = ShadowCalculation(IN.FragPosLightSpace, norm, lightDir);
+  for (int i = 0; i < meshLightCount; i++) {
+    int idx = meshLights[i];
+    Light l = lights[idx];
+
+    //DIFFUSE
+    vec3 lightDir = lightDirection(l, IN.FragPos);
+    float diff = max(dot(norm, lightDir), 0.0);
+    vec3 diffuse = (diff * l.color.rgb) * 0.70;
 
-  //shadow = 0.0;
-  Color = vec4((ambient + (1.0 - shadow) * (diffuse + specular)) * col, 1.0f);
+    // SPECULAR
+    float specularStrength = 0.1;
+    vec3 reflectionDir = reflect(-lightDir, norm);
+    float spec = pow(max(dot(viewDir, reflectionDir), 0.0), 5);
+    vec3 specular = specularStrength * spec * l.color.rgb;
 
+    // SHADOW
+    float shadow = 0.0;
+    if (idx == shadowLight) {
+      shadow = ShadowCalculation(IN.FragPos, norm, lightDir);
+    }
+
+    light += lightFalloff(l, IN.FragPos, lightDir) * (1.0 - shadow) * (diffuse + specular);
+  }
 
-  //Color = vec4(IN.Color, 1.0);
-  //Color = vec4(shadow, shadow, shadow, 1.0);
+  Color = vec4(light * col, 1.0f);
 }
@@ -17,6 +17,23 @@ out VS_OUTPUT {
 
 uniform mat4 uBones[32];
 
+// per instance model, and bones in a texture with a row per instance, when drawn instanced. See scene_3d::instancing
+layout (location = 7) in mat4 InstanceModel;
+uniform bool instanced;
+uniform bool boneTextureEnabled;
+uniform sampler2D boneTexture;
+
+mat4 bone(int i) {
+  if (boneTextureEnabled) {
+    int x = i * 4;
+    return mat4(texelFetch(boneTexture, ivec2(x, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 1, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 2, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 3, gl_InstanceID), 0));
+  }
+  return uBones[i];
+}
+
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
@@ -32,8 +49,8 @@ mat4 boneTransform() {
   mat4 ret;
 
   // Weight1 * Bone1 + Weight2 * Bone2
-  ret = BoneWeights.x * uBones[int(BoneIndices.x)]
-       + BoneWeights.y * uBones[int(BoneIndices.y)];
+  ret = BoneWeights.x * bone(int(BoneIndices.x))
+       + BoneWeights.y * bone(int(BoneIndices.y));
 
   return ret;
 
@@ -43,13 +60,14 @@ mat4 boneTransform() {
 void main()
 {
     mat4 bt = boneTransform();
-    vec4 pos = model * bt * vec4(Position, 1.0);
+    mat4 m = instanced ? InstanceModel : model;
+    vec4 pos = m * bt * vec4(Position, 1.0);
 
 
     OUT.FragPos = vec3(pos);
     // This is called normal matrix, maybe do on cpu(the transpose and invere part)
     // and send it in as a uniform
-    OUT.Normal = mat3(transpose(inverse(model * bt))) * Normal;
+    OUT.Normal = mat3(transpose(inverse(m * bt))) * Normal;
 
     OUT.FragPosLightSpace = lightSpaceMat * vec4(OUT.FragPos, 1.0);
 
@@ -0,0 +1,20 @@
+#version 330 core
+out vec4 FragColor;
+
+uniform sampler2D text_map;
+
+in VS_OUTPUT {
+  vec2 TexCoords;
+  vec4 Color;
+} IN;
+
+void main()
+{
+  vec4 col = texture(text_map, IN.TexCoords) * IN.Color;
+
+  if (col.a <= 0.001) {
+    discard;
+  }
+
+  FragColor = col;
+}
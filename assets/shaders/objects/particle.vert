@@ -0,0 +1,33 @@
+#version 330 core
+layout (location = 0) in vec2 aCorner;
+layout (location = 1) in vec4 aPosSize;
+layout (location = 2) in float aRotation;
+layout (location = 3) in vec4 aColor;
+layout (location = 4) in vec4 aUv;
+
+uniform mat4 view;
+uniform mat4 projection;
+
+out VS_OUTPUT {
+  vec2 TexCoords;
+  vec4 Color;
+} OUT;
+
+
+void main()
+{
+  // billboard, corner offset along camera right and up
+  vec3 right = vec3(view[0][0], view[1][0], view[2][0]);
+  vec3 up = vec3(view[0][1], view[1][1], view[2][1]);
+
+  float c = cos(aRotation);
+  float s = sin(aRotation);
+  vec2 corner = vec2(c * aCorner.x - s * aCorner.y, s * aCorner.x + c * aCorner.y) * aPosSize.w;
+
+  vec3 pos = aPosSize.xyz + right * corner.x + up * corner.y;
+  gl_Position = projection * view * vec4(pos, 1.0);
+
+  vec2 uv = aCorner + 0.5;
+  OUT.TexCoords = vec2(mix(aUv.x, aUv.z, uv.x), mix(aUv.y, aUv.w, uv.y));
+  OUT.Color = aColor;
+}
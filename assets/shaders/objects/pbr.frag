@@ -0,0 +1,264 @@
+#version 450 core
+out vec4 Color;
+
+in VS_OUTPUT {
+  vec3 Normal;
+  vec4 Tangent;
+  vec3 FragPos;
+  vec4 FragPosLightSpace;
+  vec2 TexCord;
+} IN;
+
+
+uniform vec3 viewPos;
+
+// glTF metallic roughness material, see Material
+uniform vec4 baseColorFactor;
+uniform float metallicFactor;
+uniform float roughnessFactor;
+uniform float normalScale;
+uniform float occlusionStrength;
+uniform vec3 emissiveFactor;
+
+// 0 = opaque, 1 = mask, 2 = blend
+uniform int alphaMode;
+uniform float alphaCutoff;
+
+uniform bool hasBaseColorTex;
+uniform bool hasMetallicRoughnessTex;
+uniform bool hasNormalTex;
+uniform bool hasOcclusionTex;
+uniform bool hasEmissiveTex;
+
+layout(binding=0) uniform sampler2D Texture;
+layout(binding=1) uniform sampler2DArray shadowMap;
+layout(binding=2) uniform sampler2D metallicRoughnessTex;
+layout(binding=3) uniform sampler2D normalTex;
+layout(binding=4) uniform sampler2D occlusionTex;
+layout(binding=5) uniform sampler2D emissiveTex;
+
+const float PI = 3.14159265359;
+
+#define MAX_LIGHTS 16
+
+// see scene_3d::lights
+struct Light {
+  vec4 posKind;        // xyz position, w kind: 0 directional, 1 point, 2 spot
+  vec4 directionRange; // xyz direction light points, w range, negative for no range
+  vec4 color;          // rgb color * intensity, w casts shadows
+  vec4 attenuation;    // constant, linear, quadratic
+  vec4 cone;           // cos of inner and outer angle
+};
+
+layout(std140, binding=0) uniform LightBlock {
+  Light lights[MAX_LIGHTS];
+  vec4 ambient;
+};
+
+// most relevant lights for this mesh, index into lights
+uniform int meshLightCount;
+uniform int meshLights[4];
+uniform int shadowLight;
+
+
+// direction from fragment towards light
+vec3 lightDirection(Light l, vec3 fragPos)
+{
+  if (l.posKind.w < 0.5) {
+    return normalize(-l.directionRange.xyz);
+  }
+  return normalize(l.posKind.xyz - fragPos);
+}
+
+// attenuation and spot cone
+float lightFalloff(Light l, vec3 fragPos, vec3 L)
+{
+  if (l.posKind.w < 0.5) {
+    return 1.0;
+  }
+
+  float d = length(l.posKind.xyz - fragPos);
+  float att = 1.0 / (l.attenuation.x + l.attenuation.y * d + l.attenuation.z * d * d);
+
+  // fade to 0 at range
+  float range = l.directionRange.w;
+  if (range > 0.0) {
+    float r = clamp(1.0 - pow(d / range, 4.0), 0.0, 1.0);
+    att *= r * r;
+  }
+
+  if (l.posKind.w > 1.5) {
+    float theta = dot(-L, normalize(l.directionRange.xyz));
+    att *= smoothstep(l.cone.y, l.cone.x, theta);
+  }
+
+  return att;
+}
+
+
+// see objects::shadow_map
+#define MAX_CASCADES 4
+uniform mat4 view;
+uniform mat4 lightSpaceMats[MAX_CASCADES];
+uniform float cascadeSplits[MAX_CASCADES];
+uniform int cascadeCount;
+uniform int pcfRadius;
+
+
+float ShadowCalculation(vec3 fragPos, vec3 normal, vec3 lightDir)
+{
+  // if normal points away from light, we now that it is in shadow
+  if (dot(normal, lightDir) < 0.0) {
+    return 1.0;
+  }
+
+  // pick cascade from view depth
+  float depth = abs((view * vec4(fragPos, 1.0)).z);
+  int layer = -1;
+  for (int i = 0; i < cascadeCount; i++) {
+    if (depth < cascadeSplits[i]) {
+      layer = i;
+      break;
+    }
+  }
+
+  // beyond shadow distance
+  if (layer < 0) {
+    return 0.0;
+  }
+
+  vec4 fragPosLightSpace = lightSpaceMats[layer] * vec4(fragPos, 1.0);
+  vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w;
+  projCoords = projCoords * 0.5 + 0.5;
+
+  if (projCoords.z > 1.0) {
+    return 0.0;
+  }
+
+  // PCF, outside the map samples the border which is never in shadow
+  vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+  float shadow = 0.0;
+  for (int x = -pcfRadius; x <= pcfRadius; x++) {
+    for (int y = -pcfRadius; y <= pcfRadius; y++) {
+      float closestDepth = texture(shadowMap, vec3(projCoords.xy + vec2(x, y) * texelSize, layer)).r;
+      shadow += projCoords.z > closestDepth ? 1.0 : 0.0;
+    }
+  }
+
+  float samples = float((2 * pcfRadius + 1) * (2 * pcfRadius + 1));
+  return shadow / samples;
+}
+
+
+vec3 srgbToLinear(vec3 c) {
+  return pow(c, vec3(2.2));
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+float distributionGGX(float NdotH, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float d = NdotH * NdotH * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+float geometrySchlickGGX(float NdotV, float roughness) {
+  float r = roughness + 1.0;
+  float k = (r * r) / 8.0;
+  return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 F0) {
+  return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+
+void main()
+{
+  vec4 baseColor = baseColorFactor;
+  if (hasBaseColorTex) {
+    vec4 tex = texture(Texture, IN.TexCord);
+    baseColor *= vec4(srgbToLinear(tex.rgb), tex.a);
+  }
+
+  if (alphaMode == 1 && baseColor.a < alphaCutoff) {
+    discard;
+  }
+
+  float metallic = metallicFactor;
+  float roughness = roughnessFactor;
+  if (hasMetallicRoughnessTex) {
+    vec4 mr = texture(metallicRoughnessTex, IN.TexCord);
+    roughness *= mr.g;
+    metallic *= mr.b;
+  }
+  roughness = clamp(roughness, 0.04, 1.0);
+
+  vec3 N = normalize(IN.Normal);
+  if (!gl_FrontFacing) {
+    N = -N;
+  }
+
+  if (hasNormalTex) {
+    vec3 T = normalize(IN.Tangent.xyz - N * dot(N, IN.Tangent.xyz));
+    vec3 B = cross(N, T) * IN.Tangent.w;
+    vec3 n = texture(normalTex, IN.TexCord).xyz * 2.0 - 1.0;
+    n.xy *= normalScale;
+    N = normalize(mat3(T, B, N) * n);
+  }
+
+  vec3 V = normalize(viewPos - IN.FragPos);
+  float NdotV = max(dot(N, V), 0.0001);
+
+  vec3 F0 = mix(vec3(0.04), baseColor.rgb, metallic);
+
+  vec3 direct = vec3(0.0);
+  for (int i = 0; i < meshLightCount; i++) {
+    int idx = meshLights[i];
+    Light l = lights[idx];
+
+    vec3 L = lightDirection(l, IN.FragPos);
+    vec3 H = normalize(V + L);
+
+    float NdotL = max(dot(N, L), 0.0);
+    float NdotH = max(dot(N, H), 0.0);
+
+    vec3 F = fresnelSchlick(max(dot(H, V), 0.0), F0);
+    float D = distributionGGX(NdotH, roughness);
+    float G = geometrySchlickGGX(NdotV, roughness) * geometrySchlickGGX(NdotL, roughness);
+
+    vec3 specular = (D * G * F) / (4.0 * NdotV * max(NdotL, 0.0001));
+    vec3 kd = (vec3(1.0) - F) * (1.0 - metallic);
+    vec3 diffuse = kd * baseColor.rgb / PI;
+
+    float shadow = 0.0;
+    if (idx == shadowLight) {
+      shadow = ShadowCalculation(IN.FragPos, N, L);
+    }
+
+    // light intensity scaled so a white light looks close to the basic mesh shader
+    vec3 radiance = l.color.rgb * PI * lightFalloff(l, IN.FragPos, L);
+    direct += (diffuse + specular) * radiance * NdotL * (1.0 - shadow);
+  }
+
+  float ao = 1.0;
+  if (hasOcclusionTex) {
+    ao = mix(1.0, texture(occlusionTex, IN.TexCord).r, occlusionStrength);
+  }
+
+  vec3 ambientLight = ambient.rgb * baseColor.rgb * ao;
+
+  vec3 emissive = emissiveFactor;
+  if (hasEmissiveTex) {
+    emissive *= srgbToLinear(texture(emissiveTex, IN.TexCord).rgb);
+  }
+
+  vec3 color = ambientLight + direct + emissive;
+
+  // reinhard tone map and back to srgb
+  color = color / (color + vec3(1.0));
+  color = pow(color, vec3(1.0 / 2.2));
+
+  float alpha = alphaMode == 2 ? baseColor.a : 1.0;
+  Color = vec4(color, alpha);
+}
@@ -0,0 +1,70 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Normal;
+layout (location = 2) in vec2 BoneWeights;
+layout (location = 3) in vec2 BoneIndices;
+layout (location = 4) in vec2 TexCord;
+layout (location = 6) in vec4 Tangent;
+
+out VS_OUTPUT {
+  vec3 Normal;
+  vec4 Tangent;
+  vec3 FragPos;
+  vec4 FragPosLightSpace;
+  vec2 TexCord;
+} OUT;
+
+
+uniform mat4 uBones[32];
+
+// per instance model, and bones in a texture with a row per instance, when drawn instanced. See scene_3d::instancing
+layout (location = 7) in mat4 InstanceModel;
+uniform bool instanced;
+uniform bool boneTextureEnabled;
+uniform sampler2D boneTexture;
+
+mat4 bone(int i) {
+  if (boneTextureEnabled) {
+    int x = i * 4;
+    return mat4(texelFetch(boneTexture, ivec2(x, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 1, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 2, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 3, gl_InstanceID), 0));
+  }
+  return uBones[i];
+}
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+uniform mat4 lightSpaceMat;
+
+mat4 boneTransform() {
+
+  if(int(BoneIndices.x) < 0)
+  {
+    return mat4(1.0);
+  }
+
+  // Weight1 * Bone1 + Weight2 * Bone2
+  return BoneWeights.x * bone(int(BoneIndices.x))
+       + BoneWeights.y * bone(int(BoneIndices.y));
+}
+
+
+void main()
+{
+  mat4 m = (instanced ? InstanceModel : model) * boneTransform();
+  vec4 pos = m * vec4(Position, 1.0);
+
+  mat3 normalMat = mat3(transpose(inverse(m)));
+
+  OUT.FragPos = vec3(pos);
+  OUT.Normal = normalMat * Normal;
+  OUT.Tangent = vec4(mat3(m) * Tangent.xyz, Tangent.w);
+  OUT.FragPosLightSpace = lightSpaceMat * vec4(OUT.FragPos, 1.0);
+  OUT.TexCord = TexCord;
+
+  gl_Position = projection * view * pos;
+}
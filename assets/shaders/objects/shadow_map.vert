@@ -7,6 +7,23 @@ uniform mat4 light_space_mat;
 uniform mat4 model;
 uniform mat4 uBones[32];
 
+// per instance model, and bones in a texture with a row per instance, when drawn instanced. See scene_3d::instancing
+layout (location = 7) in mat4 InstanceModel;
+uniform bool instanced;
+uniform bool boneTextureEnabled;
+uniform sampler2D boneTexture;
+
+mat4 bone(int i) {
+  if (boneTextureEnabled) {
+    int x = i * 4;
+    return mat4(texelFetch(boneTexture, ivec2(x, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 1, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 2, gl_InstanceID), 0),
+                texelFetch(boneTexture, ivec2(x + 3, gl_InstanceID), 0));
+  }
+  return uBones[i];
+}
+
 
 mat4 boneTransform() {
 
@@ -17,8 +34,8 @@ mat4 boneTransform() {
   mat4 ret;
 
   // Weight1 * Bone1 + Weight2 * Bone2
-  ret = BoneWeights.x * uBones[int(BoneIndices.x)]
-       + BoneWeights.y * uBones[int(BoneIndices.y)];
+  ret = BoneWeights.x * bone(int(BoneIndices.x))
+       + BoneWeights.y * bone(int(BoneIndices.y));
 
   return ret;
 
@@ -27,5 +44,6 @@ mat4 boneTransform() {
 void main()
 {
   mat4 bt = boneTransform();
-    gl_Position = light_space_mat * model * bt * vec4(aPos, 1.0);
+    mat4 m = instanced ? InstanceModel : model;
+    gl_Position = light_space_mat * m * bt * vec4(aPos, 1.0);
 }
@@ -0,0 +1,20 @@
+#version 330 core
+out vec4 FragColor;
+
+uniform sampler2D text_map;
+uniform float opacity;
+
+in VS_OUTPUT {
+  vec2 TexCoords;
+} IN;
+
+void main()
+{
+  vec4 col = texture(text_map, IN.TexCoords);
+
+  if (col.a == 0.0) {
+    discard;
+  }
+
+  FragColor = vec4(col.rgb, col.a * opacity);
+}
@@ -15,20 +15,21 @@ uniform vec3 lightPos;
 uniform vec3 viewPos;
 
 layout(binding=0) uniform sampler2D Texture;
-layout(binding=1) uniform sampler2D shadowMap;
+// only the first cascade is used, see lightSpaceMat
+layout(binding=1) uniform sampler2DArray shadowMap;
 
 
 
 float pcf(vec3 projCoords, float bias) {
   float shadow = 0.0;
-  vec2 texelSize = 1.0 / textureSize(shadowMap, 0);
+  vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
   float sum = 0.0;
   for(int x = -1; x <= 1; ++x)
     {
       for(int y = -1; y <= 1; ++y)
         {
           sum += 1;
-          float pcfDepth = texture(shadowMap, projCoords.xy + vec2(x, y) * texelSize).r;
+          float pcfDepth = texture(shadowMap, vec3(projCoords.xy + vec2(x, y) * texelSize, 0)).r;
           shadow += projCoords.z - bias > pcfDepth ? 1.0 : 0.0;
         }
     }
@@ -107,7 +108,7 @@ float ShadowCalculation(vec4 fragPosLightSpace, vec3 normal, vec3 lightDir)
     return 0.0; // 0 for light, 1 for dark
   }
 
-  float closestDepth = texture(shadowMap, projCoords.xy).r;
+  float closestDepth = texture(shadowMap, vec3(projCoords.xy, 0)).r;
   float currentDepth = projCoords.z;
   float shadow = currentDepth > closestDepth ? 1.0 : 0.0;
 
@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 FragColor;
+
+in VS_OUTPUT {
+  vec4 Color;
+} IN;
+
+void main()
+{
+  if (IN.Color.a <= 0.001) {
+    discard;
+  }
+
+  FragColor = IN.Color;
+}
@@ -0,0 +1,16 @@
+#version 330 core
+layout (location = 0) in vec3 pos;
+layout (location = 1) in vec4 aColor;
+
+uniform mat4 transform;
+
+out VS_OUTPUT {
+  vec4 Color;
+} OUT;
+
+
+void main()
+{
+  gl_Position = transform * vec4(pos, 1.0);
+  OUT.Color = aColor;
+}
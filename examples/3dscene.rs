@@ -243,19 +243,21 @@ fn run_scene(scene: &mut scene::Scene<PostPData, ControlledData>, event_pump: &m
             ui.body_text(&format!("root pos: {:.2?}", p1.root_motion));
 
             ui.newline();
-            ui.body_text(&format!("light_pos {:.2?}", scene.light_pos));
+            if let Some(light) = scene.lights.first_mut() {
+                ui.body_text(&format!("light_pos {:.2?}", light.pos));
 
-            ui.newline();
-            ui.body_text("x:");
-            ui.slider(&mut scene.light_pos.x, -30.0, 30.0 );
+                ui.newline();
+                ui.body_text("x:");
+                ui.slider(&mut light.pos.x, -30.0, 30.0 );
 
-            ui.newline();
-            ui.body_text("y:");
-            ui.slider(&mut scene.light_pos.y, -30.0, 30.0 );
+                ui.newline();
+                ui.body_text("y:");
+                ui.slider(&mut light.pos.y, -30.0, 30.0 );
 
-            ui.newline();
-            ui.body_text("z:");
-            ui.slider(&mut scene.light_pos.z, 1.0, 300.0 );
+                ui.newline();
+                ui.body_text("z:");
+                ui.slider(&mut light.pos.z, 1.0, 300.0 );
+            }
             ui.window_end("Options");
         }
 
@@ -139,7 +139,7 @@ fn generate_font_vec(gl: &gl::Gl) -> std::io::Result<Vec::<Font>> {
         }
     }
 
-    res.sort_by(|a, b| a.name().partial_cmp(&b.name()).unwrap());
+    res.sort_by(|a, b| a.name().cmp(b.name()));
     Ok(res)
 }
 
@@ -5,6 +5,7 @@ use gl_lib::audio::audio_player;
 use gl_lib::animations::sheet_animation::{load_folder, SheetAnimationPlayer};
 use gl_lib::typedef::*;
 use gl_lib::math::AsV2;
+use gl_lib::particle_system::trail_render::TrailRenderer;
 mod inputs;
 mod entity;
 mod ai;
@@ -37,6 +38,7 @@ pub fn load_and_run(mut audio_player: audio_player::AudioPlayer,
     audio_player.add_sound("deflect".into(), &"examples/pixel_sekiro/assets/audio/deflect_1.wav");
 
     let mut scene = scene::new(&mut animation_player, &assets, audio_player);
+    let mut trail_renderer = TrailRenderer::new(&ui.drawer2D.gl)?;
 
     scene.add_enemy("skeleton", pos2.v2());
 
@@ -91,6 +93,9 @@ pub fn load_and_run(mut audio_player: audio_player::AudioPlayer,
 
         // draw animation frame at locations
         scene.draw(&mut ui.drawer2D);
+        for trail in scene.sword_trails.iter() {
+            trail_renderer.render_2d(trail, &ui.drawer2D);
+        }
 
         if scene.animation_player.active_animations() != 2 {
             let _a = 2;
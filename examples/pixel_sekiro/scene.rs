@@ -6,6 +6,8 @@ use crate::{inputs::{self}, entity::{self, Entity, EntityState, Combo, EntityId,
 use gl_lib::animations::sheet_animation::{Start, SheetAnimationPlayer, AnimationId, SheetAssets};
 use gl_lib::typedef::*;
 use gl_lib::collision2d::polygon::{PolygonTransform};
+use gl_lib::particle_system::curve::Curve;
+use gl_lib::particle_system::trail::{TrailGroup, TrailDesc};
 use crate::ai;
 
 
@@ -32,6 +34,8 @@ pub struct Scene<'a: 'b, 'b> {
     pub assets: &'a SheetAssets<FrameData>,
     pub show_col_boxes: bool,
     pub hits: usize,
+    /// Follows the player's attack hitbox while attacking, one trail per attack animation
+    pub sword_trails: TrailGroup<AnimationId>,
     audio_player: AudioPlayer,
     next_entity_id: EntityId,
     scale: f32
@@ -68,6 +72,13 @@ pub fn new<'a: 'b, 'b>(animation_player: &'b mut SheetAnimationPlayer<'a, FrameD
         assets,
         show_col_boxes: true,
         hits: 0,
+        sword_trails: TrailGroup::new(TrailDesc {
+            lifetime: 0.15,
+            min_distance: 2.0,
+            width: Curve::linear(24.0, 0.0),
+            color: Curve::linear(V4::new(1.0, 1.0, 1.0, 0.8), V4::new(0.6, 0.8, 1.0, 0.0)),
+            ..Default::default()
+        }),
         audio_player,
         next_entity_id: 2, // player is 1
         scale
@@ -133,6 +144,10 @@ impl<'a: 'b, 'b> Scene<'a, 'b> {
         }
 
 
+        // a new attack starts a new trail, the trail of the last attack fades out
+        let attack = attack_point(self.animation_player, &self.player).map(|p| (self.player.state.animation_id(), p));
+        self.sword_trails.update_2d(dt, attack);
+
         // resolve deflections and update animations accordingly
         self.resolve_deflect();
 
@@ -203,6 +218,20 @@ impl<'a: 'b, 'b> Scene<'a, 'b> {
 }
 
 
+/// Center of the attack hitbox in the current frame, None when not attacking
+fn attack_point(animation_player: &SheetAnimationPlayer<FrameData>, entity: &Entity) -> Option<V2> {
+    let (attack, scale, flip_y) = animation_player.get_polygon(entity.state.animation_id(), "attack")?;
+
+    let mut transform = PolygonTransform::default();
+    transform.scale = scale;
+    transform.translation = entity.pos;
+    transform.flip_y = flip_y;
+
+    let c = attack.polygon.center();
+    Some((transform.mat3() * V3::new(c.x, c.y, 1.0)).xy())
+}
+
+
 fn deflect(animation_player: &SheetAnimationPlayer<FrameData>, ui: &mut Ui, deflector: &Entity, attacker: &Entity, show_col_boxes: bool) -> bool {
     let ct = CollisionTest {
         animation_player: animation_player,
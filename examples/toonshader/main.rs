@@ -141,7 +141,9 @@ fn setup(scene: &mut Scene, data: &mut GameData) {
 
     // LIGHT
     // Light pos, clear color and player as controlled entity
-    scene.light_pos = V3::new(-10.0, -5.0, 30.0);
+    if let Some(light) = scene.lights.first_mut() {
+        light.pos = V3::new(-10.0, -5.0, 30.0);
+    }
     scene.ui.style.clear_color = Color::Rgb(100, 100, 100);
 }
 
@@ -182,22 +182,24 @@ fn options(scene: &mut Scene, data : &mut UiData) {
 
 
     ui.newline();
-    ui.body_text(&format!("light_pos {:.2?}", scene.light_pos));
+    if let Some(light) = scene.lights.first_mut() {
+        ui.body_text(&format!("light_pos {:.2?}", light.pos));
 
-    ui.newline();
-    ui.body_text("x:");
-    ui.slider(&mut scene.light_pos.x, -30.0, 30.0 );
+        ui.newline();
+        ui.body_text("x:");
+        ui.slider(&mut light.pos.x, -30.0, 30.0 );
 
-    ui.newline();
-    ui.body_text("y:");
-    ui.slider(&mut scene.light_pos.y, -30.0, 30.0 );
+        ui.newline();
+        ui.body_text("y:");
+        ui.slider(&mut light.pos.y, -30.0, 30.0 );
 
-    ui.newline();
-    ui.body_text("z:");
-    ui.slider(&mut scene.light_pos.z, 0.0, 100.0 );
+        ui.newline();
+        ui.body_text("z:");
+        ui.slider(&mut light.pos.z, 0.0, 100.0 );
 
-    ui.newline();
-    ui.color_picker(&mut scene.light_color);
+        ui.newline();
+        ui.color_picker(&mut light.color);
+    }
 
     ui.newline();
     if ui.button("Wire Mode") {
@@ -216,19 +218,23 @@ fn options(scene: &mut Scene, data : &mut UiData) {
     let default_pipeline = scene.render_pipelines.default();
     if let Some(sm) = &mut default_pipeline.shadow_map {
         ui.newline();
-        ui.body_text(&format!("z_near: {:.2?}, z_far: {:.2?}", sm.z_near, sm.z_far));
+        ui.body_text(&format!("max_distance: {:.2?}, split_lambda: {:.2?}", sm.max_distance, sm.split_lambda));
+
+        ui.newline();
+        ui.body_text("max_distance:");
+        ui.slider(&mut sm.max_distance, 1.0, 200.0);
 
         ui.newline();
-        ui.body_text("z_near:");
-        ui.slider(&mut sm.z_near, 0.0, 10.0);
+        ui.body_text("split_lambda:");
+        ui.slider(&mut sm.split_lambda, 0.0, 1.0);
 
         ui.newline();
-        ui.body_text("z_far:");
-        ui.slider(&mut sm.z_far, 0.0, 50.0);
+        ui.body_text("z_margin:");
+        ui.slider(&mut sm.z_margin, 0.0, 100.0);
 
         ui.newline();
-        ui.body_text("size:");
-        ui.slider(&mut sm.size, 1.0, 50.0);
+        ui.body_text("pcf_radius:");
+        ui.slider(&mut sm.pcf_radius, 0, 3);
     }
 
 
@@ -11,24 +11,24 @@ fn main() -> Result<(), failure::Error> {
 
     let sdl_setup = helpers::setup_sdl()?;
     let window = sdl_setup.window;
-    let sdl = sdl_setup.sdl;
     let viewport = sdl_setup.viewport;
     let gl = &sdl_setup.gl;
 
     let drawer_2d = Drawer2D::new(&gl, viewport).unwrap();
-    let mut ui = Ui::new(drawer_2d);
+    let mut ui = Ui::new(drawer_2d, window.clone());
 
     // Set background color to white
     unsafe {
         gl.ClearColor(0.9, 0.9, 0.9, 1.0);
     }
 
-    let mut event_pump = sdl.event_pump().unwrap();
+    // only one event pump can exist, and the setup made it
+    let mut event_pump = sdl_setup.event_pump;
 
     let mut size = na::Vector2::<f32>::new(32.0, 32.0);
 
     let mut assets = load_assets(&mut ui);
-    let mut state = State::Shoot(shoot::State::new());
+    let mut state = State::Shoot(shoot::State::new(gl)?);
     loop {
 
         // Basic clear gl stuff and get events to UI
@@ -48,7 +48,7 @@ fn main() -> Result<(), failure::Error> {
             state = State::AssetViewer;
         }
         if ui.button("Shoot") {
-            state = State::Shoot(shoot::State::new());
+            state = State::Shoot(shoot::State::new(gl)?);
         }
 
         match state {
@@ -1,6 +1,10 @@
 use super::*;
 use crate::damage_text::TextAnimations;
 use rand::prelude::*;
+use gl_lib::typedef::V4;
+use gl_lib::particle_system::curve::Curve;
+use gl_lib::particle_system::trail::{TrailGroup, TrailDesc};
+use gl_lib::particle_system::trail_render::TrailRenderer;
 
 
 pub type V2 = na::Vector2::<f32>;
@@ -10,9 +14,13 @@ pub struct State {
     weapon: Weapon,
     mouse_pos: V2,
     arrow: Option<Arrow>,
+    next_arrow_id: usize,
     arrow_speed: f32,
     scale: f32,
     target: Option<Target>,
+    /// One trail per arrow, keyed by arrow id
+    arrow_trails: TrailGroup<usize>,
+    trail_renderer: TrailRenderer,
 
     text_anim: TextAnimations
 }
@@ -20,6 +28,7 @@ pub struct State {
 
 #[derive(Debug, Clone, Copy)]
 struct Arrow {
+    id: usize,
     center: V2,
     dir: V2,
     life: f32,
@@ -50,8 +59,8 @@ struct Weapon {
 
 
 impl State {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(gl: &gl::Gl) -> Result<Self, failure::Error> {
+        Ok(Self {
             mouse_down: false,
             mouse_pos: V2::new(0.0, 0.0),
             weapon: Weapon {
@@ -60,11 +69,20 @@ impl State {
                 size: V2::new(32.0, 32.0)
             },
             arrow: None,
+            next_arrow_id: 0,
             target: None,
             arrow_speed: 400.0,
             scale: 2.0,
+            arrow_trails: TrailGroup::new(TrailDesc {
+                lifetime: 0.2,
+                min_distance: 4.0,
+                width: Curve::linear(12.0, 0.0),
+                color: Curve::linear(V4::new(0.3, 0.5, 1.0, 0.8), V4::new(0.3, 0.5, 1.0, 0.0)),
+                ..Default::default()
+            }),
+            trail_renderer: TrailRenderer::new(gl)?,
             text_anim: TextAnimations::new()
-        }
+        })
     }
 }
 
@@ -89,6 +107,7 @@ pub fn shoot(ui: &mut Ui, assets: &Assets, state: &mut State, dt: f32) {
     state.weapon.dir = (state.mouse_pos - state.weapon.center).normalize();
     handle_arrow(ui, assets, state, dt);
     handle_target(ui, assets, state, dt);
+    handle_trail(ui, state, dt);
 
 
 
@@ -162,7 +181,9 @@ fn handle_arrow(ui: &mut Ui, assets: &Assets, state: &mut State, dt: f32) {
 
     } else if state.mouse_down {
 
+        state.next_arrow_id += 1;
         state.arrow = Some(Arrow {
+            id: state.next_arrow_id,
             center: state.weapon.center,
             dir : state.weapon.dir,
             life: 4.0,
@@ -172,12 +193,22 @@ fn handle_arrow(ui: &mut Ui, assets: &Assets, state: &mut State, dt: f32) {
 }
 
 
+fn handle_trail(ui: &mut Ui, state: &mut State, dt: f32) {
+    // a new arrow starts a new trail, the trail of the last one fades out
+    state.arrow_trails.update_2d(dt, state.arrow.map(|arrow| (arrow.id, arrow.center)));
+
+    for trail in state.arrow_trails.iter() {
+        state.trail_renderer.render_2d(trail, &ui.drawer2D);
+    }
+}
+
+
 fn draw(ui: &mut Ui, id: TextureId, center: V2, dir: V2, size: V2) {
     let angle = dir.x.atan2(dir.y);
     ui.drawer2D.render_img_rot(id,
                                center.x as i32 - (size.x / 2.0) as i32,
                                center.y as i32  - (size.y / 2.0) as i32,
-                               angle,
+                               RotationWithOrigin::Center(angle),
                                size);
 
 }
@@ -0,0 +1,204 @@
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+
+/// Named event at a time in an animation. Time is in seconds from the start of the animation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationEvent {
+    pub name: Rc::<str>,
+    pub time: f32,
+}
+
+/// Event fired by an animation player during `update`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiredEvent<AnimationId> {
+    pub id: AnimationId,
+    pub name: Rc::<str>,
+    pub time: f32,
+}
+
+/// Prefix used in Aseprite cel user data to mark a frame event. Fx `event:footstep`
+pub const SHEET_EVENT_PREFIX: &str = "event:";
+
+
+/// Parse cel user data like `event:footstep, event:swing` into event names.
+/// Returns empty vec if the data does not contain any events.
+pub fn parse_cel_events(data: &str) -> Vec::<Rc::<str>> {
+    data.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(|s| s.trim().strip_prefix(SHEET_EVENT_PREFIX))
+        .filter(|s| !s.is_empty())
+        .map(Rc::from)
+        .collect()
+}
+
+/// Cel user data with the events removed, fx `deflect, event:swing` gives `deflect`.
+/// Empty if the data is only events
+pub fn strip_cel_events(data: &str) -> String {
+    data.split([',', ';'])
+        .map(|part| part.split_whitespace().filter(|s| !s.starts_with(SHEET_EVENT_PREFIX)).collect::<Vec::<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec::<_>>()
+        .join(", ")
+}
+
+#[derive(Default, Debug, Deserialize)]
+struct GltfExtras {
+    #[serde(default)]
+    events: Vec::<AnimationEvent>
+}
+
+/// Parse glTF animation extras like `{"events": [{"name": "footstep", "time": 0.35}]}`.
+/// Invalid or missing extras give no events.
+pub fn parse_gltf_extras(extras: &str) -> Vec::<AnimationEvent> {
+    let mut events = serde_json::from_str::<GltfExtras>(extras).map(|e| e.events).unwrap_or_default();
+    sort_events(&mut events);
+    events
+}
+
+pub fn sort_events(events: &mut [AnimationEvent]) {
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+}
+
+
+/// Push all events with time in \[from; to) to output, in time order.
+/// When the animation repeats the range is wrapped around duration, so a dt larger than a full loop fires
+/// events once for each loop. Events at exactly duration are included once `to` reaches the end, for a repeating
+/// animation once per loop before the events at the start of the next loop.
+pub fn fire_events<AnimationId: Copy>(id: AnimationId,
+                                      events: &[AnimationEvent],
+                                      from: f32,
+                                      to: f32,
+                                      duration: f32,
+                                      repeat: bool,
+                                      output: &mut Vec::<FiredEvent<AnimationId>>) {
+
+    if events.is_empty() || to <= from {
+        return;
+    }
+
+    if !repeat || duration <= 0.0 {
+        let end_inclusive = to >= duration;
+        for e in events {
+            if e.time >= from && (e.time < to || (end_inclusive && e.time <= duration)) {
+                output.push(FiredEvent { id, name: e.name.clone(), time: e.time });
+            }
+        }
+        return;
+    }
+
+    let mut loop_start = (from / duration).floor() * duration;
+
+    while loop_start < to {
+        let start = f32::max(from - loop_start, 0.0);
+        let end = to - loop_start;
+        let end_inclusive = end >= duration;
+
+        for e in events {
+            if e.time >= start && (e.time < end.min(duration) || (end_inclusive && e.time <= duration)) {
+                output.push(FiredEvent { id, name: e.name.clone(), time: e.time });
+            }
+        }
+        loop_start += duration;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec::<AnimationEvent> {
+        vec![
+            AnimationEvent { name: "start".into(), time: 0.0 },
+            AnimationEvent { name: "step".into(), time: 0.35 },
+            AnimationEvent { name: "end".into(), time: 1.0 },
+        ]
+    }
+
+    fn names(fired: &[FiredEvent<usize>]) -> Vec::<&str> {
+        fired.iter().map(|e| &*e.name).collect()
+    }
+
+    #[test]
+    fn fires_in_range() {
+        let mut out = vec![];
+        fire_events(1, &events(), 0.0, 0.2, 1.0, false, &mut out);
+        assert_eq!(names(&out), vec!["start"]);
+
+        out.clear();
+        fire_events(1, &events(), 0.2, 0.35, 1.0, false, &mut out);
+        assert_eq!(names(&out), Vec::<&str>::new());
+
+        out.clear();
+        fire_events(1, &events(), 0.35, 0.4, 1.0, false, &mut out);
+        assert_eq!(names(&out), vec!["step"]);
+    }
+
+    #[test]
+    fn large_dt_fires_all_skipped() {
+        let mut out = vec![];
+        fire_events(1, &events(), 0.1, 5.0, 1.0, false, &mut out);
+        assert_eq!(names(&out), vec!["step", "end"]);
+    }
+
+    #[test]
+    fn repeat_wraps_around() {
+        let evs = vec![
+            AnimationEvent { name: "a".into(), time: 0.1 },
+            AnimationEvent { name: "b".into(), time: 0.6 },
+        ];
+
+        let mut out = vec![];
+        fire_events(1, &evs, 0.5, 1.2, 1.0, true, &mut out);
+        assert_eq!(names(&out), vec!["b", "a"]);
+
+        // more than one full loop in a single update
+        out.clear();
+        fire_events(1, &evs, 0.5, 2.7, 1.0, true, &mut out);
+        assert_eq!(names(&out), vec!["b", "a", "b", "a", "b"]);
+    }
+
+    #[test]
+    fn repeat_fires_last_keyframe() {
+        let mut out = vec![];
+        fire_events(1, &events(), 0.5, 1.2, 1.0, true, &mut out);
+        assert_eq!(names(&out), vec!["end", "start"]);
+
+        // ending exactly at the loop end fires end once, and start in the next update
+        out.clear();
+        fire_events(1, &events(), 0.5, 1.0, 1.0, true, &mut out);
+        fire_events(1, &events(), 1.0, 1.2, 1.0, true, &mut out);
+        assert_eq!(names(&out), vec!["end", "start"]);
+
+        out.clear();
+        fire_events(1, &events(), 0.5, 2.2, 1.0, true, &mut out);
+        assert_eq!(names(&out), vec!["end", "start", "step", "end", "start"]);
+    }
+
+    #[test]
+    fn parse_cel() {
+        assert_eq!(parse_cel_events("deflect"), Vec::<Rc::<str>>::new());
+        let evs = parse_cel_events("event:footstep, event:swing");
+        assert_eq!(evs, vec![Rc::<str>::from("footstep"), Rc::<str>::from("swing")]);
+    }
+
+    #[test]
+    fn strip_cel() {
+        let data = "deflect, event:swing";
+        assert_eq!(parse_cel_events(data), vec![Rc::<str>::from("swing")]);
+        assert_eq!(strip_cel_events(data), "deflect");
+        assert_eq!(strip_cel_events("event:a; parry block, event:b"), "parry block");
+        assert_eq!(strip_cel_events("event:footstep, event:swing"), "");
+    }
+
+    #[test]
+    fn parse_extras() {
+        let evs = parse_gltf_extras(r#"{"events": [{"name": "b", "time": 0.5}, {"name": "a", "time": 0.25}], "other": 1}"#);
+        assert_eq!(evs.len(), 2);
+        assert_eq!(&*evs[0].name, "a");
+        assert_eq!(evs[1].time, 0.5);
+
+        assert_eq!(parse_gltf_extras("{}").len(), 0);
+        assert_eq!(parse_gltf_extras("not json").len(), 0);
+    }
+}
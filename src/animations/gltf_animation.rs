@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use crate::objects::gltf_mesh::{KeyFrame, Animation};
-use crate::animations::{skeleton::{Skeleton, Bones}};
+use crate::animations::{skeleton::{Skeleton, Bones}, events::{self, FiredEvent}};
 use crate::typedef::V3;
 use std::rc::Rc;
 
@@ -49,7 +49,8 @@ pub struct AnimationPlayer<AnimationId>
 where AnimationId : Clone + Copy + Eq + std::hash::Hash + std::default::Default + std::fmt::Debug
 {
     animations: HashMap::<AnimationId, ActiveAnimation>,
-    tmp_keyframe: KeyFrame
+    tmp_keyframe: KeyFrame,
+    events: Vec::<FiredEvent<AnimationId>>,
 }
 
 impl<AnimationId> AnimationPlayer<AnimationId>
@@ -63,7 +64,9 @@ where AnimationId: Clone + Copy + Eq + std::hash::Hash + std::default::Default +
         // just update dt for each active animation
         // and set non repeating animations as expiredclear non repeating finished animations
 
-        for (_id, active) in &mut self.animations {
+        self.events.clear();
+
+        for (id, active) in &mut self.animations {
             // we don't want to remove expired animation automaticly, since we might still need the
             // keyframe info for transitioning
             if active.expired {
@@ -85,22 +88,23 @@ where AnimationId: Clone + Copy + Eq + std::hash::Hash + std::default::Default +
                 }
             }
 
+            let from = f32::max(active.elapsed, 0.0);
             active.elapsed += dt * active.speed;
 
+            // fire events for the whole range, also frames that are skipped by a large dt
+            events::fire_events(*id, &active.anim.events, from, active.elapsed, active.anim.total_secs, active.repeat, &mut self.events);
+
             if active.anim.total_secs > active.elapsed {
-                // set active.frame until we are at end, or current frame end after currently elapsed time
-                while active.frame < (active.anim.frames.len() - 1) &&
-                    active.anim.frames[active.frame].end_time() < active.elapsed {
-                    active.frame = usize::min(active.anim.frames.len() - 1 , active.frame  + 1);
-                }
+                advance_frame(active);
             } else {
                 if !active.repeat {
                     active.expired = true;
                 }
                 else {
-                    active.elapsed = active.anim.total_secs - active.elapsed;
-                    // this might not be correct if a frame is so short we skip the first keyframe in animation
+                    // keep the overshoot, so looping does not drift
+                    active.elapsed = if active.anim.total_secs > 0.0 { active.elapsed % active.anim.total_secs } else { 0.0 };
                     active.frame = 0;
+                    advance_frame(active);
                 }
             }
 
@@ -127,6 +131,16 @@ where AnimationId: Clone + Copy + Eq + std::hash::Hash + std::default::Default +
         self.animations.remove(&id);
     }
 
+    /// Events fired during the last `update`, in time order per animation.
+    pub fn events(&self) -> &[FiredEvent<AnimationId>] {
+        &self.events
+    }
+
+    /// Take the events fired during the last `update`.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, FiredEvent<AnimationId>> {
+        self.events.drain(..)
+    }
+
     pub fn key_frame(&mut self, id: &AnimationId) -> Option::<KeyFrame> {
         if let Some(active) = self.animations.get(id) {
             let mut frame = &active.anim.frames[active.frame];
@@ -228,6 +242,14 @@ where AnimationId: Clone + Copy + Eq + std::hash::Hash + std::default::Default +
     }
 }
 
+// set active.frame until we are at end, or current frame end after currently elapsed time
+fn advance_frame(active: &mut ActiveAnimation) {
+    while active.frame < (active.anim.frames.len() - 1) &&
+        active.anim.frames[active.frame].end_time() < active.elapsed {
+            active.frame = usize::min(active.anim.frames.len() - 1 , active.frame  + 1);
+        }
+}
+
 pub fn update_skeleton_to_key_frame(skeleton: &mut Skeleton, key_frame: &KeyFrame) {
     // interpolate joints new transformation
     for i in 0..skeleton.joints.len() {
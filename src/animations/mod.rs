@@ -8,7 +8,11 @@ pub mod skeleton;
 
 pub mod sheet_animation;
 
+pub mod sheet_polygons;
+
 mod types;
 pub use self::types::*;
 
 pub mod gltf_animation;
+
+pub mod events;
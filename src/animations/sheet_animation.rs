@@ -12,6 +12,8 @@ use crate::math::{AsV2, AsV2i};
 use crate::collision2d::polygon::{self, Polygon, ComplexPolygon};
 use crate::collision2d::gjk;
 use crate::image::PreMulAlpha;
+use crate::animations::events::{self, AnimationEvent, FiredEvent};
+use crate::animations::sheet_polygons::{SheetPolygons, LayerKind};
 
 
 pub type AnimationId = usize;
@@ -26,6 +28,7 @@ pub struct SheetAnimation<FrameDataT> {
     pub collision_polygons: ProcessedSheetCollisionPolygons,
     pub frame_data: FrameData<FrameDataT>,
     pub size: V2,
+    pub events: Vec::<AnimationEvent>,
 }
 
 
@@ -123,7 +126,8 @@ pub struct FrameTag {
 pub struct SheetAnimationPlayer<'a, FrameDataT> {
     animations: HashMap::<AnimationId, ActiveAnimation<'a, FrameDataT>>,
     next_id: AnimationId,
-    clear_buffer: Vec::<AnimationId>
+    clear_buffer: Vec::<AnimationId>,
+    events: Vec::<FiredEvent<AnimationId>>,
 }
 
 pub struct Start<'a, FrameDataT> {
@@ -139,7 +143,8 @@ impl<'a, FrameDataT> SheetAnimationPlayer<'a, FrameDataT> {
         Self {
             animations: Default::default(),
             next_id: 1,
-            clear_buffer: vec![]
+            clear_buffer: vec![],
+            events: vec![],
         }
     }
 
@@ -207,10 +212,15 @@ impl<'a, FrameDataT> SheetAnimationPlayer<'a, FrameDataT> {
     pub fn update(&mut self, dt: f32) {
 
         self.clear_buffer.clear();
+        self.events.clear();
 
         for (id,anim) in &mut self.animations {
+            let from = anim.elapsed;
             anim.elapsed += dt;
 
+            let total = anim.sheet.animation.total_seconds();
+            events::fire_events(*id, &anim.sheet.events, from, anim.elapsed, total, anim.repeat, &mut self.events);
+
             if let Some((s, frame)) = anim.sheet.animation.at(anim.elapsed) {
                 anim.sprite = s;
                 anim.frame = frame;
@@ -219,7 +229,12 @@ impl<'a, FrameDataT> SheetAnimationPlayer<'a, FrameDataT> {
                     self.clear_buffer.push(*id);
                 }
                 else {
-                    anim.elapsed = 0.0;
+                    // keep the overshoot, so events and frames stay in sync when looping
+                    anim.elapsed = if total > 0.0 { anim.elapsed % total } else { 0.0 };
+                    if let Some((s, frame)) = anim.sheet.animation.at(anim.elapsed) {
+                        anim.sprite = s;
+                        anim.frame = frame;
+                    }
                 }
             }
         }
@@ -229,6 +244,16 @@ impl<'a, FrameDataT> SheetAnimationPlayer<'a, FrameDataT> {
         }
     }
 
+    /// Events fired during the last `update`, in time order per animation.
+    pub fn events(&self) -> &[FiredEvent<AnimationId>] {
+        &self.events
+    }
+
+    /// Take the events fired during the last `update`.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, FiredEvent<AnimationId>> {
+        self.events.drain(..)
+    }
+
     pub fn remove(&mut self, id: AnimationId) {
         self.animations.remove(&id);
     }
@@ -346,10 +371,22 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
     }
 
     let mut user_data = HashMap::<usize, String>::new();
+    let mut frame_events = HashMap::<usize, Vec::<std::rc::Rc::<str>>>::new();
 
     for layer in &sheet_anim.meta.layers {
         for cel in &layer.cels {
-            user_data.insert(cel.frame, cel.data.clone());
+            // event:name tokens are events, the rest of the data is passed on as frame data
+            let cel_events = events::parse_cel_events(&cel.data);
+            if cel_events.is_empty() {
+                user_data.insert(cel.frame, cel.data.clone());
+                continue;
+            }
+
+            frame_events.entry(cel.frame).or_default().extend(cel_events);
+            let data = events::strip_cel_events(&cel.data);
+            if !data.is_empty() {
+                user_data.insert(cel.frame, data);
+            }
         }
     }
 
@@ -363,10 +400,23 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
         animations.push(FrameTag { name: file_name.to_string(), from: 0, to: sheet_anim.frames.len() - 1 });
     }
 
+    // polygons from the sidecar next to the sheet, fall back to the old per animation files
+    let sheet_polygons = match SheetPolygons::load(json_path) {
+        Ok(p) => p,
+        Err(err) => {
+            println!("Error loading polygons for {:?}\n{:?}", json_path, err);
+            Default::default()
+        }
+    };
+
     let mut res = vec![];
     base_path.pop();
     for tag in &animations {
-        let polygons = load_sheet_collision_polygons(&base_path, &tag.name);
+        let polygons = if sheet_polygons.animations.contains_key(&tag.name) {
+            sheet_polygons.collision_polygons(&tag.name)
+        } else {
+            load_sheet_collision_polygons(&base_path, &tag.name)
+        };
 
         let mut collision_polygons : ProcessedSheetCollisionPolygons = Default::default();
 
@@ -385,7 +435,8 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
 
                 inner.insert(polygon_name.clone(), SheetCollisionPolygon {
                     polygon: new_poly,
-                    sub_divisions
+                    sub_divisions,
+                    kind: sheet_polygons.layer_kind(&tag.name, polygon_name)
                 });
 
             }
@@ -396,11 +447,21 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
 
 
         let mut frame_data : FrameData<FrameDataT> = Default::default();
+        let mut anim_events = vec![];
+        let mut frame_start = 0.0;
 
         for frame in tag.from..=tag.to {
             if let Some(data) = user_data.get(&frame) {
                 frame_data.insert(frame - tag.from, data_map(&data));
             }
+
+            // events fire at the start of the frame
+            if let Some(names) = frame_events.get(&frame) {
+                for name in names {
+                    anim_events.push(AnimationEvent { name: name.clone(), time: frame_start });
+                }
+            }
+            frame_start += frames[frame].frame_seconds;
         }
 
         //println!("{file_name} - {:?} - {:#?}", &tag.name, frame_data);
@@ -410,7 +471,8 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
             collision_polygons,
             size: na::Vector2::new(sheet_anim.meta.size.w as f32, sheet_anim.meta.size.h as f32),
             animation: Animation { frames: frames[tag.from..=tag.to].iter().map(|f| (*f).clone()).collect() },
-            frame_data
+            frame_data,
+            events: anim_events
         };
 
         *id += 1;
@@ -425,6 +487,7 @@ pub fn load_by_name<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debu
 pub struct SheetCollisionPolygon {
     pub polygon: Polygon,
     sub_divisions: Vec::<Vec::<usize>>,
+    pub kind: LayerKind,
 }
 
 
@@ -515,7 +578,8 @@ pub fn load_folder<P: AsRef<Path> + std::fmt::Debug, FrameDataT: std::fmt::Debug
         .filter(|e| !e.file_type().is_dir()) {
             let file_name = entry.file_name().to_str().unwrap();
             let file_name_no_ending = file_name.split(".").next().unwrap().to_string();
-            if file_name.ends_with(".json") {
+            // skip polygon sidecars, they are loaded with the sheet
+            if file_name.ends_with(".json") && !file_name.ends_with(".polygons.json") {
                 let _json_names = match load_by_name(gl, &entry.path(), &file_name_no_ending, &mut id, data_map) {
                     Ok(mut sheet_anims) => {
                         let mut pb = std::path::PathBuf::new();
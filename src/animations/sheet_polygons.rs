@@ -0,0 +1,191 @@
+//! Versioned json sidecar with collision polygons for an Aseprite sheet.
+//! Stored next to the sheet json as `<sheet>.polygons.json`, with polygons for every animation (frame tag) in the sheet.
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::collision2d::polygon::Polygon;
+use crate::animations::sheet_animation::SheetCollisionPolygons;
+
+/// Current version of the sidecar format. Bump when making breaking changes and add migration in `parse`.
+pub const SHEET_POLYGONS_VERSION: u32 = 1;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKind {
+    /// Deals damage, fx a sword swing
+    Hitbox,
+    /// Takes damage, fx the body
+    Hurtbox,
+    #[default]
+    Other,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolygonLayer {
+    pub kind: LayerKind,
+    pub polygon: Polygon,
+}
+
+/// Layer name to polygon for a single frame
+pub type FramePolygons = BTreeMap::<String, PolygonLayer>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnimationPolygons {
+    /// Frame index within the animation to layers
+    pub frames: BTreeMap::<usize, FramePolygons>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SheetPolygons {
+    pub version: u32,
+    /// Animation (frame tag) name to polygons
+    pub animations: BTreeMap::<String, AnimationPolygons>,
+}
+
+impl Default for SheetPolygons {
+    fn default() -> Self {
+        Self {
+            version: SHEET_POLYGONS_VERSION,
+            animations: Default::default(),
+        }
+    }
+}
+
+
+impl SheetPolygons {
+
+    pub fn parse(json: &str) -> Result<Self, failure::Error> {
+        let res: SheetPolygons = serde_json::from_str(json)?;
+
+        if res.version > SHEET_POLYGONS_VERSION {
+            failure::bail!("Sheet polygons version {} is newer than supported version {}", res.version, SHEET_POLYGONS_VERSION);
+        }
+
+        Ok(res)
+    }
+
+    pub fn to_json(&self) -> Result<String, failure::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Load sidecar for the sheet json at `sheet_json_path`. Returns default (empty) when there is no sidecar
+    pub fn load<P: AsRef<Path>>(sheet_json_path: &P) -> Result<Self, failure::Error> {
+        let path = sidecar_path(sheet_json_path);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        Self::parse(&std::fs::read_to_string(&path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, sheet_json_path: &P) -> Result<(), failure::Error> {
+        let mut data = self.clone();
+        data.version = SHEET_POLYGONS_VERSION;
+        std::fs::write(sidecar_path(sheet_json_path), data.to_json()?)?;
+        Ok(())
+    }
+
+    /// Polygons for animation in the format used by `SheetAnimation`.
+    pub fn collision_polygons(&self, animation: &str) -> SheetCollisionPolygons {
+        let mut res = SheetCollisionPolygons::default();
+        if let Some(anim) = self.animations.get(animation) {
+            for (frame, layers) in &anim.frames {
+                res.insert(*frame, layers.iter().map(|(name, layer)| (name.clone(), layer.polygon.clone())).collect());
+            }
+        }
+        res
+    }
+
+    pub fn layer_kind(&self, animation: &str, layer: &str) -> LayerKind {
+        self.animations.get(animation)
+            .and_then(|anim| anim.frames.values().find_map(|f| f.get(layer)))
+            .map(|l| l.kind)
+            .unwrap_or_default()
+    }
+
+    /// Create from the old per animation `<name>_polygons.json` format, all layers get kind `Other`
+    pub fn from_legacy(animation: &str, polygons: &SheetCollisionPolygons) -> Self {
+        let mut res = Self::default();
+        let anim = res.animations.entry(animation.to_string()).or_default();
+        for (frame, map) in polygons {
+            anim.frames.insert(*frame, map.iter().map(|(name, polygon)| (name.clone(), PolygonLayer { kind: LayerKind::Other, polygon: polygon.clone() })).collect());
+        }
+        res
+    }
+}
+
+
+/// `path/player.json` -> `path/player.polygons.json`
+pub fn sidecar_path<P: AsRef<Path>>(sheet_json_path: &P) -> PathBuf {
+    let path = sheet_json_path.as_ref();
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    path.with_file_name(format!("{stem}.polygons.json"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra as na;
+
+    fn square(size: f32) -> Polygon {
+        Polygon {
+            vertices: vec![na::Vector2::new(0.0, 0.0), na::Vector2::new(size, 0.0), na::Vector2::new(size, size), na::Vector2::new(0.0, size)]
+        }
+    }
+
+    fn sheet() -> SheetPolygons {
+        let mut sheet = SheetPolygons::default();
+        let anim = sheet.animations.entry("attack".to_string()).or_default();
+        let mut frame = FramePolygons::default();
+        frame.insert("body".to_string(), PolygonLayer { kind: LayerKind::Hurtbox, polygon: square(2.0) });
+        frame.insert("sword".to_string(), PolygonLayer { kind: LayerKind::Hitbox, polygon: square(1.0) });
+        anim.frames.insert(3, frame);
+        sheet
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = sheet().to_json().unwrap();
+        let loaded = SheetPolygons::parse(&json).unwrap();
+
+        assert_eq!(loaded.version, SHEET_POLYGONS_VERSION);
+        let frame = &loaded.animations["attack"].frames[&3];
+        assert_eq!(frame["sword"].kind, LayerKind::Hitbox);
+        assert_eq!(frame["body"].polygon.vertices, square(2.0).vertices);
+        assert_eq!(loaded.layer_kind("attack", "body"), LayerKind::Hurtbox);
+        assert_eq!(loaded.layer_kind("attack", "missing"), LayerKind::Other);
+    }
+
+    #[test]
+    fn json_format() {
+        let json = sheet().to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["animations"]["attack"]["frames"]["3"]["sword"]["kind"], "hitbox");
+    }
+
+    #[test]
+    fn newer_version_fails() {
+        let json = r#"{"version": 999, "animations": {}}"#;
+        assert!(SheetPolygons::parse(json).is_err());
+        assert!(SheetPolygons::parse(r#"{"animations": {}}"#).is_err());
+    }
+
+    #[test]
+    fn collision_polygons_and_legacy() {
+        let polygons = sheet().collision_polygons("attack");
+        assert_eq!(polygons[&3].len(), 2);
+        assert!(sheet().collision_polygons("idle").is_empty());
+
+        let legacy = SheetPolygons::from_legacy("attack", &polygons);
+        assert_eq!(legacy.animations["attack"].frames[&3]["body"].kind, LayerKind::Other);
+        assert_eq!(legacy.collision_polygons("attack")[&3]["sword"].vertices, square(1.0).vertices);
+    }
+
+    #[test]
+    fn sidecar_next_to_sheet() {
+        assert_eq!(sidecar_path(&"assets/player/attack.json"), PathBuf::from("assets/player/attack.polygons.json"));
+    }
+}
@@ -77,6 +77,10 @@ impl Skeleton {
         bones
     }
 
+    pub fn joint_index(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
     pub fn update_joint_matrices(&mut self, joint_index: usize, rotation: na::UnitQuaternion::<f32>, translation: na::Vector3::<f32>) {
         update_joint_matrices(&mut self.joints, joint_index, rotation, translation);
     }
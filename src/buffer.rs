@@ -97,7 +97,8 @@ impl FrameBuffer {
 
 
     pub fn update_viewport(&mut self, gl: &gl::Gl, viewport: &gl::viewport::Viewport) {
-        // update textures
+        // update textures, the old ones are no longer used
+        self.delete_textures();
 
         self.color_tex = texture::gen_texture_framebuffer(&gl, viewport.w, viewport.h);
         self.depth_stencil_tex = texture::gen_texture_depth_and_stencil(&gl, viewport);
@@ -114,6 +115,13 @@ impl FrameBuffer {
 
     }
 
+    fn delete_textures(&self) {
+        unsafe {
+            self.gl.DeleteTextures(1, &self.color_tex);
+            self.gl.DeleteTextures(1, &self.depth_stencil_tex);
+        }
+    }
+
     pub fn complete(&self) -> bool {
         unsafe {
             let status = self.gl.CheckFramebufferStatus(gl::FRAMEBUFFER);
@@ -136,6 +144,13 @@ impl FrameBuffer {
         }
     }
 
+    /// Bind frame buffer for both read and write, without clearing
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+    }
+
     pub fn unbind(&self) {
         unsafe {
             self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
@@ -146,6 +161,7 @@ impl FrameBuffer {
 
 impl Drop for FrameBuffer {
     fn drop(&mut self) {
+        self.delete_textures();
         unsafe {
             self.gl.DeleteFramebuffers(1, &mut self.fbo);
         }
@@ -166,6 +182,11 @@ impl BufferType for BufferTypeElementArray {
     const BUFFER_TYPE: gl::types::GLuint = gl::ELEMENT_ARRAY_BUFFER;
 }
 
+pub struct BufferTypeUniform;
+impl BufferType for BufferTypeUniform {
+    const BUFFER_TYPE: gl::types::GLuint = gl::UNIFORM_BUFFER;
+}
+
 
 pub trait BufferType {
     const BUFFER_TYPE: gl::types::GLuint;
@@ -202,6 +223,13 @@ impl<B> Buffer<B> where B: BufferType {
         }
     }
 
+    /// Bind to an indexed binding point, fx a uniform block binding
+    pub fn bind_base(&self, index: u32) {
+        unsafe {
+            self.gl.BindBufferBase(B::BUFFER_TYPE, index, self.vbo);
+        }
+    }
+
 
     pub fn dynamic_draw_size(&self, size: u32) {
         unsafe {
@@ -261,6 +289,7 @@ impl<B> Drop for Buffer<B> where B: BufferType {
 
 pub type ArrayBuffer = Buffer<BufferTypeArray>;
 pub type ElementArrayBuffer = Buffer<BufferTypeElementArray>;
+pub type UniformBuffer = Buffer<BufferTypeUniform>;
 
 
 pub struct VertexArray {
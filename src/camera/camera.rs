@@ -1,5 +1,16 @@
 use crate::na;
 use crate::gl::viewport::*;
+use crate::collision3d::bounds::Frustum;
+use crate::camera::cinematic::CameraState;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Uses `fov`
+    Perspective,
+    /// World units visible from the bottom to the top of the screen, width follows the aspect ratio
+    Orthographic { height: f32 },
+}
 
 
 /// A general 3d camera
@@ -18,6 +29,7 @@ pub struct Camera {
     pub fov: f32,
     pub zfar: f32,
     pub znear: f32,
+    pub projection_mode: Projection,
 }
 
 impl Camera {
@@ -42,6 +54,7 @@ impl Camera {
             zfar: 100.0,
             yaw: (90.0_f32).to_radians(), // point along positive Y axis
             pitch: (-5.0_f32).to_radians(),
+            projection_mode: Projection::Perspective,
         }
     }
 
@@ -94,7 +107,14 @@ impl Camera {
 
 
     pub fn projection(&self) -> na::Matrix4::<f32> {
-        na::Matrix4::new_perspective(self.width / self.height, self.fov.to_radians(), self.znear, self.zfar)
+        match self.projection_mode {
+            Projection::Perspective => na::Matrix4::new_perspective(self.width / self.height, self.fov.to_radians(), self.znear, self.zfar),
+            Projection::Orthographic { height } => {
+                let half_h = height / 2.0;
+                let half_w = half_h * self.width / self.height;
+                self.orthographic(-half_w, half_w, -half_h, half_h)
+            }
+        }
     }
 
     pub fn orthographic(&self, left: f32, right: f32, bottom:f32, top: f32) -> na::Matrix4::<f32> {
@@ -114,6 +134,11 @@ impl Camera {
         na::Matrix::look_at_rh(&point_pos, &target, &self.up)
     }
 
+    /// View frustum in world space, for culling
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.projection() * self.view()))
+    }
+
     pub fn target(&self) -> na::Vector3::<f32> {
         self.pos + self.front
     }
@@ -123,6 +148,20 @@ impl Camera {
         self.zfar = zfar;
     }
 
+    /// Position, orientation and fov, for blending between cameras
+    pub fn state(&self) -> CameraState {
+        CameraState::from_yaw_pitch(self.pos, self.yaw, self.pitch, self.fov)
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) {
+        let (yaw, pitch) = state.yaw_pitch();
+        self.pos = state.pos;
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.fov = state.fov;
+        self.update_camera_vectors();
+    }
+
 
     fn update_camera_vectors(&mut self) {
         self.front = na::Vector3::new(
@@ -140,7 +179,11 @@ impl Camera {
     /// Given a screen x,y return the ray shooting fom camera.pos(origin), throught that pixel, into the world.
     /// Returned ray direction is in world space
     /// 0,0 in screen space is lower left, 1,1 is upper right
+    /// For orthographic cameras all rays are `front`, use `screen_to_ray_origin` for where they start
     pub fn screen_to_ray(&self, screen_x: f32, screen_y: f32) -> na::Vector3::<f32> {
+        if let Projection::Orthographic { .. } = self.projection_mode {
+            return self.front;
+        }
 
         // first transform to clip space
 
@@ -159,6 +202,18 @@ impl Camera {
     }
 
 
+    /// Start of the ray from `screen_to_ray`. Camera pos for perspective, a point on the near plane for orthographic
+    pub fn screen_to_ray_origin(&self, screen_x: f32, screen_y: f32) -> na::Vector3::<f32> {
+        match self.projection_mode {
+            Projection::Perspective => self.pos,
+            Projection::Orthographic { height } => {
+                let x = ((screen_x / self.width) - 0.5) * height * self.width / self.height;
+                let y = ((screen_y / self.height) - 0.5) * height;
+                self.pos + self.right * x + self.up * y
+            }
+        }
+    }
+
     /// Given a world position, return the screen position
     pub fn world_pos_to_screen(&self, world_pos: na::Vector3::<f32>) -> na::Vector2::<f32> {
         let transform = self.projection() * self.view();
@@ -204,3 +259,30 @@ pub struct ClipSpace {
     pub top: f32,
     pub bottom: f32
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic() {
+        let mut camera = Camera::new(200.0, 100.0);
+        camera.projection_mode = Projection::Orthographic { height: 10.0 };
+        camera.move_to(na::Vector3::new(0.0, -10.0, 0.0));
+        camera.look_at(na::Vector3::new(0.0, 0.0, 0.0));
+
+        // size on screen does not depend on distance
+        let near = camera.world_pos_to_screen(na::Vector3::new(5.0, 0.0, 0.0));
+        let far = camera.world_pos_to_screen(na::Vector3::new(5.0, 50.0, 0.0));
+        assert!((near - far).magnitude() < 0.01);
+
+        // 10 units high, so 20 wide and x = 5 is a quarter of the screen right of center
+        assert!((near.x - 150.0).abs() < 0.01);
+        assert!((near.y - 50.0).abs() < 0.01);
+
+        let origin = camera.screen_to_ray_origin(150.0, 50.0);
+        assert!((origin - na::Vector3::new(5.0, -10.0, 0.0)).magnitude() < 0.01);
+        assert_eq!(camera.screen_to_ray(0.0, 0.0), camera.front);
+    }
+}
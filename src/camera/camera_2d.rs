@@ -0,0 +1,229 @@
+//! 2d camera for scrolling worlds drawn with `Drawer2D`. World units use the same axes as the screen,
+//! x right and y down, so a camera at zoom 1 with pos at half the screen size draws exactly like without a camera.
+//! Push it with `Drawer2D::push_camera` to draw in world units.
+use crate::na::{Matrix4, Translation3, Rotation3};
+use crate::typedef::*;
+
+
+/// Axis aligned world area, min is top left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds2D {
+    pub min: V2,
+    pub max: V2,
+}
+
+impl Bounds2D {
+    pub fn new(min: V2, max: V2) -> Self {
+        Self { min, max }
+    }
+
+    pub fn size(&self) -> V2 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> V2 {
+        (self.min + self.max) * 0.5
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Camera2D {
+    /// World position shown at the center of the screen
+    pub pos: V2,
+    /// Screen pixels per world unit
+    pub zoom: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Radians, positive turns the world clockwise on screen
+    pub rotation: f32,
+    /// When set the view is kept inside, see `clamp_to_bounds`
+    pub bounds: Option<Bounds2D>,
+    /// Screen size in pixels
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Camera2D {
+
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            pos: V2::new(width / 2.0, height / 2.0),
+            zoom: 1.0,
+            min_zoom: 0.1,
+            max_zoom: 10.0,
+            rotation: 0.0,
+            bounds: None,
+            width,
+            height,
+        }
+    }
+
+    /// World to screen pixels
+    pub fn view(&self) -> Mat4 {
+        let center = Translation3::new(self.width / 2.0, self.height / 2.0, 0.0);
+        let rot = Rotation3::from_euler_angles(0.0, 0.0, self.rotation);
+        let scale = Matrix4::new_nonuniform_scaling(&V3::new(self.zoom, self.zoom, 1.0));
+        let pos = Translation3::new(-self.pos.x, -self.pos.y, 0.0);
+
+        center.to_homogeneous() * scale * rot.to_homogeneous() * pos.to_homogeneous()
+    }
+
+    pub fn world_to_screen(&self, world: V2) -> V2 {
+        let p = self.view() * V4::new(world.x, world.y, 0.0, 1.0);
+        p.xy()
+    }
+
+    pub fn screen_to_world(&self, screen: V2) -> V2 {
+        let inv = self.view().try_inverse().unwrap_or_else(Mat4::identity);
+        let p = inv * V4::new(screen.x, screen.y, 0.0, 1.0);
+        p.xy()
+    }
+
+    /// Size of the visible world area, ignoring rotation
+    pub fn visible_size(&self) -> V2 {
+        V2::new(self.width, self.height) / self.zoom
+    }
+
+    pub fn visible_bounds(&self) -> Bounds2D {
+        let half = self.visible_size() * 0.5;
+        Bounds2D::new(self.pos - half, self.pos + half)
+    }
+
+    pub fn move_by(&mut self, world_delta: V2) {
+        self.pos += world_delta;
+        self.clamp_to_bounds();
+    }
+
+    /// Change zoom by factor and keep the world point under screen_point in place, fx zoom towards the mouse
+    pub fn zoom_at(&mut self, screen_point: V2, factor: f32) {
+        let before = self.screen_to_world(screen_point);
+        self.zoom = (self.zoom * factor).clamp(self.min_zoom, self.max_zoom);
+        let after = self.screen_to_world(screen_point);
+
+        self.pos += before - after;
+        self.clamp_to_bounds();
+    }
+
+    /// Keep the visible area inside bounds. When bounds are smaller than the view, the view is centered on them.
+    /// Rotation is ignored
+    pub fn clamp_to_bounds(&mut self) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+
+        let half = self.visible_size() * 0.5;
+        let center = bounds.center();
+
+        for i in 0..2 {
+            let min = bounds.min[i] + half[i];
+            let max = bounds.max[i] - half[i];
+            self.pos[i] = if min > max { center[i] } else { self.pos[i].clamp(min, max) };
+        }
+    }
+
+    /// Camera for a layer that scrolls at factor of the speed, fx 0.5 for a far background and 0.0 for a fixed one.
+    /// Layers line up at world origin
+    pub fn parallax(&self, factor: f32) -> Camera2D {
+        let mut cam = self.clone();
+        let screen_center = V2::new(self.width / 2.0, self.height / 2.0);
+        cam.pos = screen_center + (self.pos - screen_center) * factor;
+        cam.bounds = None;
+        cam
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: V2, b: V2) -> bool {
+        (a - b).magnitude() < 0.001
+    }
+
+    #[test]
+    fn default_is_screen_space() {
+        let cam = Camera2D::new(800.0, 600.0);
+        assert!(close(cam.world_to_screen(V2::new(10.0, 20.0)), V2::new(10.0, 20.0)));
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        let mut cam = Camera2D::new(800.0, 600.0);
+        cam.pos = V2::new(100.0, 50.0);
+        cam.zoom = 2.0;
+
+        assert!(close(cam.world_to_screen(cam.pos), V2::new(400.0, 300.0)));
+        assert!(close(cam.world_to_screen(V2::new(110.0, 50.0)), V2::new(420.0, 300.0)));
+
+        cam.rotation = 0.7;
+        let p = V2::new(-30.0, 12.0);
+        assert!(close(cam.screen_to_world(cam.world_to_screen(p)), p));
+        assert!(close(cam.world_to_screen(cam.pos), V2::new(400.0, 300.0)));
+    }
+
+    #[test]
+    fn zoom_at_keeps_point() {
+        let mut cam = Camera2D::new(800.0, 600.0);
+        let mouse = V2::new(100.0, 100.0);
+        let world = cam.screen_to_world(mouse);
+
+        cam.zoom_at(mouse, 2.0);
+        assert_eq!(cam.zoom, 2.0);
+        assert!(close(cam.world_to_screen(world), mouse));
+
+        cam.zoom_at(mouse, 100.0);
+        assert_eq!(cam.zoom, cam.max_zoom);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut cam = Camera2D::new(100.0, 100.0);
+        cam.bounds = Some(Bounds2D::new(V2::new(0.0, 0.0), V2::new(1000.0, 200.0)));
+
+        cam.move_by(V2::new(-500.0, 0.0));
+        assert!(close(cam.pos, V2::new(50.0, 50.0)));
+
+        cam.move_by(V2::new(5000.0, 5000.0));
+        assert!(close(cam.pos, V2::new(950.0, 150.0)));
+
+        // view larger than bounds in y
+        cam.zoom = 0.25;
+        cam.clamp_to_bounds();
+        assert!(close(cam.pos, V2::new(800.0, 100.0)));
+    }
+
+    #[test]
+    fn drawer_transform() {
+        use crate::gl::viewport::Viewport;
+        use crate::imode_gui::drawer2d::camera_clip_transform;
+        use crate::na::Orthographic3;
+
+        let viewport = Viewport::for_window(800, 600);
+        let mut cam = Camera2D::new(800.0, 600.0);
+        cam.pos = V2::new(-20.0, 35.0);
+        cam.zoom = 3.0;
+        cam.rotation = 0.3;
+
+        // what drawer2d does with screen pixels
+        let to_clip = |p: V2| {
+            let proj = Orthographic3::new(0.0, 800.0, 0.0, 600.0, -10.0, 100.0);
+            proj.to_homogeneous() * V4::new(p.x, 600.0 - p.y, 0.0, 1.0)
+        };
+
+        let world = V2::new(12.0, -4.0);
+        let clip = camera_clip_transform(&cam.view(), &viewport) * to_clip(world);
+        assert!((clip - to_clip(cam.world_to_screen(world))).magnitude() < 0.001);
+    }
+
+    #[test]
+    fn parallax() {
+        let mut cam = Camera2D::new(100.0, 100.0);
+        cam.move_by(V2::new(40.0, 0.0));
+
+        assert!(close(cam.parallax(1.0).pos, cam.pos));
+        assert!(close(cam.parallax(0.5).pos, V2::new(70.0, 50.0)));
+        assert!(close(cam.parallax(0.0).pos, V2::new(50.0, 50.0)));
+    }
+}
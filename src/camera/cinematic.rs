@@ -0,0 +1,347 @@
+//! Camera states that can be blended, trauma based shake, and spline paths for cutscenes.
+//! None of it touches gl, a `CameraState` is applied to a `Camera` with `Camera::set_state`.
+use noise::{NoiseFn, Perlin};
+use crate::na::UnitQuaternion;
+use crate::typedef::*;
+use crate::tween::Easing;
+use crate::objects::bezier;
+
+
+/// Position, orientation and fov of a camera. Orientation turns +x to the look direction, and has no roll
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraState {
+    pub pos: V3,
+    pub rotation: UnitQuaternion<f32>,
+    /// Vertical field of view in degrees, same as `Camera::fov`
+    pub fov: f32,
+}
+
+impl CameraState {
+
+    /// Same angles as `Camera`, yaw around z from +x and pitch up from the xy plane, in radians
+    pub fn from_yaw_pitch(pos: V3, yaw: f32, pitch: f32, fov: f32) -> Self {
+        let rotation = UnitQuaternion::from_axis_angle(&V3::z_axis(), yaw) * UnitQuaternion::from_axis_angle(&V3::y_axis(), -pitch);
+        Self { pos, rotation, fov }
+    }
+
+    pub fn look_at(pos: V3, target: V3, fov: f32) -> Self {
+        let dir = target - pos;
+        let yaw = dir.y.atan2(dir.x);
+        let pitch = dir.z.atan2(dir.xy().magnitude());
+        Self::from_yaw_pitch(pos, yaw, pitch, fov)
+    }
+
+    pub fn front(&self) -> V3 {
+        self.rotation * V3::x()
+    }
+
+    pub fn yaw_pitch(&self) -> (f32, f32) {
+        let f = self.front();
+        (f.y.atan2(f.x), f.z.clamp(-1.0, 1.0).asin())
+    }
+
+    /// Position and fov linear, orientation spherical
+    pub fn lerp(&self, other: &CameraState, t: f32) -> Self {
+        Self {
+            pos: self.pos + (other.pos - self.pos) * t,
+            rotation: self.rotation.try_slerp(&other.rotation, t, 1e-6).unwrap_or(other.rotation),
+            fov: self.fov + (other.fov - self.fov) * t,
+        }
+    }
+}
+
+
+/// Shake from trauma in \[0;1\]. Hits add trauma, it decays over time, and the shake is trauma squared so small
+/// hits are subtle. Offsets come from perlin noise, so the shake is smooth and not jittery
+#[derive(Debug, Clone)]
+pub struct CameraShake {
+    pub trauma: f32,
+    /// Trauma lost per second
+    pub decay: f32,
+    pub max_offset: V3,
+    /// Radians
+    pub max_yaw: f32,
+    pub max_pitch: f32,
+    /// Noise samples per second, higher is more violent
+    pub frequency: f32,
+    time: f32,
+    noise: Perlin,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 0.8,
+            max_offset: V3::new(0.3, 0.3, 0.3),
+            max_yaw: 0.05,
+            max_pitch: 0.05,
+            frequency: 15.0,
+            time: 0.0,
+            noise: Perlin::new(7),
+        }
+    }
+}
+
+impl CameraShake {
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    /// Amount of shake in \[0;1\]
+    pub fn shake(&self) -> f32 {
+        self.trauma * self.trauma
+    }
+
+    // noise in [-1;1], each channel is its own row in the noise
+    fn channel(&self, i: usize) -> f32 {
+        self.noise.get([(self.time * self.frequency) as f64, i as f64 * 10.0 + 0.5]) as f32
+    }
+
+    pub fn apply(&self, state: &CameraState) -> CameraState {
+        let s = self.shake();
+        if s <= 0.0 {
+            return *state;
+        }
+
+        let offset = V3::new(self.channel(0), self.channel(1), self.channel(2)).component_mul(&self.max_offset) * s;
+        let (yaw, pitch) = state.yaw_pitch();
+
+        CameraState::from_yaw_pitch(state.pos + offset, yaw + self.max_yaw * s * self.channel(3), pitch + self.max_pitch * s * self.channel(4), state.fov)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathKey {
+    /// Seconds from path start
+    pub time: f32,
+    pub pos: V3,
+    pub look_at: V3,
+    pub fov: f32,
+}
+
+
+/// Catmull-Rom spline through keys, for both position and look at target, so the camera passes every key
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    keys: Vec::<PathKey>,
+}
+
+impl CameraPath {
+
+    /// Keys are sorted by time
+    pub fn new(mut keys: Vec::<PathKey>) -> Self {
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keys }
+    }
+
+    pub fn keys(&self) -> &[PathKey] {
+        &self.keys
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    /// State at time, clamped to the path. None for a path without keys
+    pub fn sample(&self, time: f32) -> Option<CameraState> {
+        let last = self.keys.len().checked_sub(1)?;
+
+        let i = self.keys.iter().rposition(|k| k.time <= time).unwrap_or(0).min(last.saturating_sub(1));
+        let k1 = &self.keys[i];
+        let k2 = &self.keys[(i + 1).min(last)];
+
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { ((time - k1.time) / span).clamp(0.0, 1.0) } else { 1.0 };
+
+        // end points are repeated, so the path starts and stops at the first and last key
+        let k0 = &self.keys[i.saturating_sub(1)];
+        let k3 = &self.keys[(i + 2).min(last)];
+
+        let spline = |f: fn(&PathKey) -> V3| {
+            let [p0, p1, p2, p3] = bezier::catmull_rom_to_bezier(f(k0), f(k1), f(k2), f(k3));
+            bezier::cubic_point(p0, p1, p2, p3, t)
+        };
+
+        let pos = spline(|k| k.pos);
+        let look_at = spline(|k| k.look_at);
+
+        Some(CameraState::look_at(pos, look_at, k1.fov + (k2.fov - k1.fov) * t))
+    }
+}
+
+
+/// Plays a path from the start
+#[derive(Debug, Clone)]
+pub struct PathPlayback {
+    pub path: CameraPath,
+    pub time: f32,
+}
+
+impl PathPlayback {
+
+    pub fn new(path: CameraPath) -> Self {
+        Self { path, time: 0.0 }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn state(&self) -> Option<CameraState> {
+        self.path.sample(self.time)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.time >= self.path.duration()
+    }
+}
+
+
+/// Timed blend from a fixed state to a target that may keep moving, fx a follow camera
+#[derive(Debug, Clone)]
+pub struct CameraBlend {
+    pub from: CameraState,
+    pub duration: f32,
+    pub easing: Easing,
+    elapsed: f32,
+}
+
+impl CameraBlend {
+
+    pub fn new(from: CameraState, duration: f32) -> Self {
+        Self { from, duration, easing: Easing::QuadInOut, elapsed: 0.0 }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+
+        (self.elapsed / self.duration).clamp(0.0, 1.0)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    pub fn sample(&self, to: &CameraState) -> CameraState {
+        self.from.lerp(to, self.easing.apply(self.progress()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    fn close(a: V3, b: V3) -> bool {
+        (a - b).magnitude() < 0.001
+    }
+
+    #[test]
+    fn state_and_camera() {
+        let s = CameraState::look_at(V3::new(0.0, 0.0, 0.0), V3::new(0.0, 5.0, 5.0), 45.0);
+        assert!(close(s.front(), V3::new(0.0, 1.0, 1.0).normalize()));
+
+        let mut camera = Camera::new(100.0, 100.0);
+        camera.set_state(&s);
+        assert!(close(camera.front(), s.front()));
+        assert_eq!(camera.fov, 45.0);
+
+        let back = camera.state();
+        assert!(close(back.front(), s.front()));
+        assert!(close(back.pos, s.pos));
+    }
+
+    #[test]
+    fn blend() {
+        let a = CameraState::from_yaw_pitch(V3::new(0.0, 0.0, 0.0), 0.0, 0.0, 60.0);
+        let b = CameraState::from_yaw_pitch(V3::new(10.0, 0.0, 0.0), std::f32::consts::FRAC_PI_2, 0.0, 90.0);
+
+        let mid = a.lerp(&b, 0.5);
+        assert!(close(mid.pos, V3::new(5.0, 0.0, 0.0)));
+        assert_eq!(mid.fov, 75.0);
+        assert!((mid.yaw_pitch().0 - std::f32::consts::FRAC_PI_4).abs() < 0.001);
+
+        let mut blend = CameraBlend::new(a, 2.0);
+        blend.easing = Easing::Linear;
+        assert_eq!(blend.sample(&b), a);
+
+        blend.update(1.0);
+        assert!(close(blend.sample(&b).pos, V3::new(5.0, 0.0, 0.0)));
+        assert!(!blend.is_done());
+
+        blend.update(5.0);
+        assert!(blend.is_done());
+        assert!(close(blend.sample(&b).pos, b.pos));
+    }
+
+    #[test]
+    fn path() {
+        assert!(CameraPath::default().sample(0.0).is_none());
+
+        let key = |time: f32, x: f32| PathKey { time, pos: V3::new(x, 0.0, 0.0), look_at: V3::new(x, 10.0, 0.0), fov: 60.0 + x };
+        let path = CameraPath::new(vec![key(2.0, 2.0), key(0.0, 0.0), key(1.0, 1.0), key(4.0, 3.0)]);
+
+        assert_eq!(path.duration(), 4.0);
+
+        // passes every key
+        for k in path.keys() {
+            let s = path.sample(k.time).unwrap();
+            assert!(close(s.pos, k.pos));
+            assert!((s.fov - k.fov).abs() < 0.001);
+            assert!(close(s.front(), V3::new(0.0, 1.0, 0.0)));
+        }
+
+        // between keys, and clamped outside
+        let mid = path.sample(0.5).unwrap().pos;
+        assert!(mid.x > 0.4 && mid.x < 0.6 && mid.y == 0.0);
+        assert!(close(path.sample(-1.0).unwrap().pos, V3::new(0.0, 0.0, 0.0)));
+        assert!(close(path.sample(10.0).unwrap().pos, V3::new(3.0, 0.0, 0.0)));
+
+        let single = CameraPath::new(vec![key(1.0, 5.0)]);
+        assert!(close(single.sample(0.0).unwrap().pos, V3::new(5.0, 0.0, 0.0)));
+
+        let mut playback = PathPlayback::new(path);
+        playback.update(3.0);
+        assert!(!playback.is_done());
+        playback.update(1.0);
+        assert!(playback.is_done());
+    }
+
+    #[test]
+    fn shake() {
+        let state = CameraState::from_yaw_pitch(V3::new(1.0, 2.0, 3.0), 0.3, 0.1, 60.0);
+        let mut shake = CameraShake::default();
+
+        assert_eq!(shake.apply(&state), state);
+
+        shake.add_trauma(0.5);
+        shake.add_trauma(0.8);
+        assert_eq!(shake.trauma, 1.0);
+        assert_eq!(shake.shake(), 1.0);
+
+        shake.update(0.123);
+        let shaken = shake.apply(&state);
+        assert!(shaken.pos != state.pos);
+        assert!((shaken.pos - state.pos).abs() <= shake.max_offset);
+
+        // decays to nothing
+        shake.update(10.0);
+        assert_eq!(shake.trauma, 0.0);
+        assert_eq!(shake.apply(&state), state);
+    }
+}
@@ -4,3 +4,6 @@ pub use self::camera::*;
 pub mod free_camera;
 pub mod follow_camera;
 pub mod rts_camera;
+pub mod third_person;
+pub mod cinematic;
+pub mod camera_2d;
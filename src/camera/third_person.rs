@@ -0,0 +1,372 @@
+//! Third person camera rig. Orbits a target with spring damped focus, angles and distance, keeps a shoulder offset,
+//! pulls in when level geometry is between the target and the camera, and can lock on to a second target.
+//!
+//! ```ignore
+//! rig.rotate(mouse_dx * sens, mouse_dy * sens);
+//! rig.lock_on = enemy_locked.then(|| enemy_pos);
+//! rig.update(&mut camera, player_pos, &level_bounds, dt);
+//! ```
+use crate::camera::Camera;
+use crate::collision3d::bounds::Aabb;
+use crate::typedef::*;
+use std::f32::consts::{PI, TAU};
+
+
+/// Critically damped spring towards target, stable for any dt. Starting at rest it covers about 59% of the way
+/// in smooth_time and about 98% in 3 * smooth_time.
+/// Velocity is state kept between calls. From Game Programming Gems 4, 1.10
+pub fn smooth_damp(current: f32, target: f32, velocity: &mut f32, smooth_time: f32, dt: f32) -> f32 {
+    if smooth_time <= 0.0 {
+        *velocity = 0.0;
+        return target;
+    }
+
+    let omega = 2.0 / smooth_time;
+    let x = omega * dt;
+    let exp = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * dt;
+    *velocity = (*velocity - omega * temp) * exp;
+
+    target + (change + temp) * exp
+}
+
+pub fn smooth_damp_v3(current: V3, target: V3, velocity: &mut V3, smooth_time: f32, dt: f32) -> V3 {
+    V3::new(
+        smooth_damp(current.x, target.x, &mut velocity.x, smooth_time, dt),
+        smooth_damp(current.y, target.y, &mut velocity.y, smooth_time, dt),
+        smooth_damp(current.z, target.z, &mut velocity.z, smooth_time, dt),
+    )
+}
+
+/// Smooth damp of an angle in radians, the short way around
+pub fn smooth_damp_angle(current: f32, target: f32, velocity: &mut f32, smooth_time: f32, dt: f32) -> f32 {
+    smooth_damp(current, current + angle_diff(current, target), velocity, smooth_time, dt)
+}
+
+/// Signed difference from a to b in \[-PI; PI\]
+pub fn angle_diff(a: f32, b: f32) -> f32 {
+    let d = (b - a).rem_euclid(TAU);
+    if d > PI { d - TAU } else { d }
+}
+
+
+/// Distance along dir until a sphere moving from origin touches the box. The box is grown by radius, so
+/// corners are square and the sphere stops a bit early there, which is fine for cameras.
+/// When origin is already that close, the box itself is used, so a target standing at a wall can still look away from it
+pub fn sphere_cast_aabb(aabb: &Aabb, origin: &V3, dir: &V3, radius: f32) -> Option<f32> {
+    let r = V3::new(radius, radius, radius);
+    let grown = aabb.expand(r);
+
+    match grown.ray_intersection(origin, dir) {
+        Some(t) if t > 0.0 => Some(t),
+        Some(_) => aabb.ray_intersection(origin, dir).filter(|t| *t > 0.0),
+        None => None
+    }
+}
+
+/// Closest hit within max_dist of a sphere cast against every box. dir has to be normalized
+pub fn sphere_cast(obstacles: &[Aabb], origin: &V3, dir: &V3, radius: f32, max_dist: f32) -> Option<f32> {
+    obstacles.iter()
+        .filter_map(|b| sphere_cast_aabb(b, origin, dir, radius))
+        .filter(|t| *t < max_dist)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ThirdPersonRig {
+    /// Desired angles in radians. Yaw 0 places the camera along +x of the target, positive pitch is above the target
+    pub yaw: f32,
+    pub pitch: f32,
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+
+    /// Desired distance from the shoulder pivot
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+
+    /// Offset from target to look at, fx head height
+    pub target_offset: V3,
+    /// Right and up offset of the pivot, fx (0.6, 0.2) for over the right shoulder
+    pub shoulder_offset: V2,
+
+    /// Time in seconds to catch up, 0 snaps
+    pub position_smooth_time: f32,
+    pub rotation_smooth_time: f32,
+    /// Time to move back out after being pulled in by geometry. Pulling in is instant
+    pub distance_smooth_time: f32,
+
+    /// Radius of the sphere cast against geometry
+    pub collision_radius: f32,
+
+    /// When set the rig turns so the camera looks at this position past the target, fx an enemy
+    pub lock_on: Option<V3>,
+    /// Pitch used while locked on
+    pub lock_on_pitch: f32,
+
+    focus: Option<V3>,
+    focus_velocity: V3,
+    current_yaw: f32,
+    yaw_velocity: f32,
+    current_pitch: f32,
+    pitch_velocity: f32,
+    current_distance: f32,
+    distance_velocity: f32,
+}
+
+
+impl Default for ThirdPersonRig {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.3,
+            min_pitch: -0.6,
+            max_pitch: 1.3,
+            distance: 7.0,
+            min_distance: 1.0,
+            max_distance: 15.0,
+            target_offset: V3::new(0.0, 0.0, 1.5),
+            shoulder_offset: V2::new(0.0, 0.0),
+            position_smooth_time: 0.1,
+            rotation_smooth_time: 0.08,
+            distance_smooth_time: 0.3,
+            collision_radius: 0.3,
+            lock_on: None,
+            lock_on_pitch: 0.25,
+            focus: None,
+            focus_velocity: V3::new(0.0, 0.0, 0.0),
+            current_yaw: 0.0,
+            yaw_velocity: 0.0,
+            current_pitch: 0.3,
+            pitch_velocity: 0.0,
+            current_distance: 7.0,
+            distance_velocity: 0.0,
+        }
+    }
+}
+
+
+impl ThirdPersonRig {
+
+    pub fn rotate(&mut self, d_yaw: f32, d_pitch: f32) {
+        self.yaw = (self.yaw + d_yaw).rem_euclid(TAU);
+        self.pitch = (self.pitch + d_pitch).clamp(self.min_pitch, self.max_pitch);
+    }
+
+    pub fn zoom(&mut self, delta: f32) {
+        self.distance = (self.distance + delta).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Jump to the desired state without smoothing, fx after a teleport or cut
+    pub fn snap(&mut self, target: V3) {
+        self.focus = Some(target + self.target_offset);
+        self.focus_velocity = V3::new(0.0, 0.0, 0.0);
+        self.current_yaw = self.yaw;
+        self.current_pitch = self.pitch;
+        self.current_distance = self.distance;
+        self.yaw_velocity = 0.0;
+        self.pitch_velocity = 0.0;
+        self.distance_velocity = 0.0;
+    }
+
+    /// Current point the camera orbits, None before the first update
+    pub fn focus(&self) -> Option<V3> {
+        self.focus
+    }
+
+    /// Distance after smoothing and collision
+    pub fn current_distance(&self) -> f32 {
+        self.current_distance
+    }
+
+    /// Unit vector from pivot to camera for yaw and pitch
+    pub fn orbit_dir(yaw: f32, pitch: f32) -> V3 {
+        V3::new(pitch.cos() * yaw.cos(), pitch.cos() * yaw.sin(), pitch.sin())
+    }
+
+    /// Step the springs, resolve collision with obstacles and place the camera
+    pub fn update(&mut self, camera: &mut Camera, target: V3, obstacles: &[Aabb], dt: f32) {
+        if self.focus.is_none() {
+            self.snap(target);
+        }
+
+        let focus = smooth_damp_v3(self.focus.unwrap_or(target), target + self.target_offset, &mut self.focus_velocity, self.position_smooth_time, dt);
+        self.focus = Some(focus);
+
+        if let Some(lock) = self.lock_on {
+            // behind the target, opposite the lock on point
+            let away = focus - lock;
+            if away.xy().magnitude() > 0.001 {
+                self.yaw = away.y.atan2(away.x).rem_euclid(TAU);
+                self.pitch = self.lock_on_pitch.clamp(self.min_pitch, self.max_pitch);
+            }
+        }
+
+        self.current_yaw = smooth_damp_angle(self.current_yaw, self.yaw, &mut self.yaw_velocity, self.rotation_smooth_time, dt).rem_euclid(TAU);
+        self.current_pitch = smooth_damp(self.current_pitch, self.pitch, &mut self.pitch_velocity, self.rotation_smooth_time, dt);
+
+        let dir = Self::orbit_dir(self.current_yaw, self.current_pitch);
+        let right = V3::new(-self.current_yaw.sin(), self.current_yaw.cos(), 0.0);
+        let up = dir.cross(&right).normalize();
+
+        // pivot is pulled in too when a wall is at the shoulder
+        let shoulder = right * self.shoulder_offset.x + up * self.shoulder_offset.y;
+        let shoulder_len = shoulder.magnitude();
+        let pivot = if shoulder_len > 0.0 {
+            let s_dir = shoulder / shoulder_len;
+            let hit = sphere_cast(obstacles, &focus, &s_dir, self.collision_radius, shoulder_len);
+            focus + s_dir * hit.unwrap_or(shoulder_len)
+        } else {
+            focus
+        };
+
+        let smoothed = smooth_damp(self.current_distance, self.distance, &mut self.distance_velocity, self.distance_smooth_time, dt);
+        self.current_distance = match sphere_cast(obstacles, &pivot, &dir, self.collision_radius, smoothed) {
+            Some(hit) => {
+                self.distance_velocity = 0.0;
+                hit
+            },
+            None => smoothed
+        };
+
+        let look_at = match self.lock_on {
+            // between target and lock on, so both are in view
+            Some(lock) => pivot + (lock - focus) * 0.5,
+            None => pivot
+        };
+
+        camera.move_to(pivot + dir * self.current_distance);
+        if (look_at - camera.pos).xy().magnitude() > 0.0001 {
+            camera.look_at(look_at);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn spring() {
+        // converges without overshoot, and does not depend much on frame rate
+        let mut v = 0.0;
+        let mut x = 0.0;
+        let mut max = 0.0_f32;
+        for _ in 0..60 {
+            x = smooth_damp(x, 10.0, &mut v, 0.2, 1.0 / 60.0);
+            max = max.max(x);
+        }
+        // after 5 smooth times a critically damped spring has 11 * e^-10, about 0.05%, of the distance left
+        assert!((x - 10.0).abs() < 0.01);
+        assert!(max <= 10.0);
+
+        let mut v_slow = 0.0;
+        let mut x_slow = 0.0;
+        for _ in 0..10 {
+            x_slow = smooth_damp(x_slow, 10.0, &mut v_slow, 0.2, 1.0 / 10.0);
+        }
+        assert!((x_slow - x).abs() < 0.2);
+
+        // huge dt is stable
+        let mut v = 0.0;
+        assert!(close(smooth_damp(0.0, 1.0, &mut v, 0.1, 100.0), 1.0));
+
+        // zero smooth time snaps
+        assert_eq!(smooth_damp(3.0, 1.0, &mut v, 0.0, 0.016), 1.0);
+    }
+
+    #[test]
+    fn angles() {
+        assert!(close(angle_diff(0.1, TAU - 0.1), -0.2));
+        assert!(close(angle_diff(TAU - 0.1, 0.1), 0.2));
+        assert!(close(angle_diff(0.0, PI * 0.5), PI * 0.5));
+
+        // takes the short way across 0
+        let mut v = 0.0;
+        let a = smooth_damp_angle(0.1, TAU - 0.1, &mut v, 0.1, 0.016);
+        assert!(a < 0.1);
+    }
+
+    #[test]
+    fn casts() {
+        let wall = Aabb::new(V3::new(5.0, -10.0, -10.0), V3::new(6.0, 10.0, 10.0));
+        let origin = V3::new(0.0, 0.0, 0.0);
+        let x = V3::new(1.0, 0.0, 0.0);
+
+        assert!(close(sphere_cast_aabb(&wall, &origin, &x, 0.5).unwrap(), 4.5));
+        assert!(sphere_cast_aabb(&wall, &origin, &(-x), 0.5).is_none());
+
+        // closer than radius, uses the box
+        let near = V3::new(4.8, 0.0, 0.0);
+        assert!(close(sphere_cast_aabb(&wall, &near, &x, 0.5).unwrap(), 0.2));
+        assert!(sphere_cast_aabb(&wall, &near, &(-x), 0.5).is_none());
+
+        let other = Aabb::new(V3::new(2.0, -1.0, -1.0), V3::new(3.0, 1.0, 1.0));
+        assert!(close(sphere_cast(&[wall, other], &origin, &x, 0.5, 10.0).unwrap(), 1.5));
+        assert!(sphere_cast(&[wall, other], &origin, &x, 0.5, 1.0).is_none());
+    }
+
+    #[test]
+    fn rig_limits_and_collision() {
+        let mut rig = ThirdPersonRig {
+            target_offset: V3::new(0.0, 0.0, 0.0),
+            pitch: 0.0,
+            ..Default::default()
+        };
+
+        rig.rotate(0.0, 10.0);
+        assert_eq!(rig.pitch, rig.max_pitch);
+        rig.rotate(-0.5, -10.0);
+        assert_eq!(rig.pitch, rig.min_pitch);
+        assert!(close(rig.yaw, TAU - 0.5));
+        rig.zoom(100.0);
+        assert_eq!(rig.distance, rig.max_distance);
+
+        rig.yaw = 0.0;
+        rig.pitch = 0.0;
+        rig.distance = 10.0;
+
+        let mut camera = Camera::new(800.0, 600.0);
+        rig.update(&mut camera, V3::new(0.0, 0.0, 0.0), &[], 0.016);
+        assert!(close(camera.pos.x, 10.0));
+
+        // wall behind target, pulled in at once
+        let wall = Aabb::new(V3::new(4.0, -5.0, -5.0), V3::new(5.0, 5.0, 5.0));
+        rig.update(&mut camera, V3::new(0.0, 0.0, 0.0), &[wall], 0.016);
+        assert!(close(camera.pos.x, 4.0 - rig.collision_radius));
+
+        // and back out smoothly
+        rig.update(&mut camera, V3::new(0.0, 0.0, 0.0), &[], 0.016);
+        assert!(camera.pos.x > 3.7 && camera.pos.x < 10.0);
+    }
+
+    #[test]
+    fn lock_on_and_shoulder() {
+        let mut rig = ThirdPersonRig {
+            target_offset: V3::new(0.0, 0.0, 0.0),
+            shoulder_offset: V2::new(1.0, 0.0),
+            position_smooth_time: 0.0,
+            rotation_smooth_time: 0.0,
+            ..Default::default()
+        };
+
+        let mut camera = Camera::new(800.0, 600.0);
+        rig.lock_on = Some(V3::new(0.0, 10.0, 0.0));
+        rig.update(&mut camera, V3::new(0.0, 0.0, 0.0), &[], 0.016);
+
+        // behind player looking towards the enemy at +y
+        assert!(close(rig.yaw, PI * 1.5));
+        assert!(camera.pos.y < 0.0);
+        assert!(camera.front().y > 0.0);
+
+        // right of the camera is +x, shoulder puts it there
+        assert!(close(camera.pos.x, 1.0));
+    }
+}
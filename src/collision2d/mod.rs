@@ -2,6 +2,8 @@ pub mod gjk;
 
 pub mod polygon;
 
+pub mod trace;
+
 pub mod line_segment_intersection;
 
 pub use line_segment_intersection as lsi;
@@ -472,6 +472,28 @@ impl<'a> gjk::Shape for ComplexPolygon<'a> {
     }
 }
 
+/// Treated as convex, concave polygons collide as their convex hull
+impl gjk::Shape for Polygon {
+
+    fn support(&self, d: V2) -> V2 {
+        let mut p = self.vertices[0];
+        let mut val = p.dot(&d);
+
+        for v in &self.vertices {
+            let dot_val = v.dot(&d);
+            if dot_val > val {
+                val = dot_val;
+                p = *v;
+            }
+        }
+        p
+    }
+
+    fn center(&self) -> V2 {
+        Polygon::center(self)
+    }
+}
+
 impl<'a> drawer2d::ConvexPolygon for ComplexPolygon<'a> {
     fn set_vertices(&self, buffer: &mut Vec::<f32>, viewport_height: f32, z: f32) {
         for &i in self.indices {
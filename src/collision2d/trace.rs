@@ -0,0 +1,354 @@
+//! Trace the alpha outline of a sprite into a polygon, and split it into convex parts.
+use nalgebra as na;
+use crate::collision2d::polygon::{self, Polygon, Dir};
+type V2 = na::Vector2::<f32>;
+
+
+/// Mask of opaque pixels, row major
+#[derive(Debug, Clone)]
+pub struct AlphaMask {
+    pub w: i32,
+    pub h: i32,
+    pub solid: Vec::<bool>,
+}
+
+impl AlphaMask {
+
+    /// Create mask from sub rect of image. Pixels with alpha > threshold are solid
+    pub fn from_image(img: &image::RgbaImage, x: i32, y: i32, w: i32, h: i32, alpha_threshold: u8) -> Self {
+        let mut solid = Vec::with_capacity((w * h) as usize);
+        for py in y..(y + h) {
+            for px in x..(x + w) {
+                let inside = px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height();
+                solid.push(inside && img.get_pixel(px as u32, py as u32)[3] > alpha_threshold);
+            }
+        }
+
+        Self { w, h, solid }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.w || y >= self.h {
+            return false;
+        }
+        self.solid[(y * self.w + x) as usize]
+    }
+
+    /// Keep only the largest 4-connected region
+    pub fn largest_region(&self) -> AlphaMask {
+        let mut label = vec![0usize; self.solid.len()];
+        let mut best = (0, 0); // (label, count)
+        let mut next = 1;
+        let mut stack = vec![];
+
+        for start in 0..self.solid.len() {
+            if !self.solid[start] || label[start] != 0 {
+                continue;
+            }
+
+            let mut count = 0;
+            label[start] = next;
+            stack.push(start as i32);
+            while let Some(i) = stack.pop() {
+                count += 1;
+                let (x, y) = (i % self.w, i / self.w);
+                for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if self.get(nx, ny) {
+                        let ni = (ny * self.w + nx) as usize;
+                        if label[ni] == 0 {
+                            label[ni] = next;
+                            stack.push(ni as i32);
+                        }
+                    }
+                }
+            }
+
+            if count > best.1 {
+                best = (next, count);
+            }
+            next += 1;
+        }
+
+        AlphaMask {
+            w: self.w,
+            h: self.h,
+            solid: label.iter().map(|l| *l != 0 && *l == best.0).collect()
+        }
+    }
+}
+
+
+/// Trace the outer outline of the largest opaque region along pixel edges.
+/// Vertices are in pixel coordinates of the mask, with only the corners kept.
+/// Returns None when the mask has no solid pixels.
+pub fn trace_outline(mask: &AlphaMask) -> Option<Vec::<V2>> {
+    let region = mask.largest_region();
+
+    // top most, then left most solid pixel. Its top edge is on the outline
+    let start_idx = region.solid.iter().position(|s| *s)? as i32;
+    let start = (start_idx % region.w, start_idx / region.w);
+
+    // walk with solid pixels on the right hand side (y is down), starting along the top edge
+    let mut p = start;
+    let mut d = (1, 0);
+    let mut res = vec![];
+
+    loop {
+        // right hand normal
+        let r = (-d.1, d.0);
+        // pixels ahead of p, to the right and left of direction
+        let ahead_right = region.get(p.0 + (d.0 + r.0 - 1) / 2, p.1 + (d.1 + r.1 - 1) / 2);
+        let ahead_left = region.get(p.0 + (d.0 - r.0 - 1) / 2, p.1 + (d.1 - r.1 - 1) / 2);
+
+        let new_d = if !ahead_right {
+            r
+        } else if ahead_left {
+            (-r.0, -r.1)
+        } else {
+            d
+        };
+
+        if new_d != d || res.is_empty() {
+            if p == start && !res.is_empty() {
+                break;
+            }
+            res.push(V2::new(p.0 as f32, p.1 as f32));
+        }
+
+        d = new_d;
+        p = (p.0 + d.0, p.1 + d.1);
+
+        if p == start && d == (1, 0) {
+            break;
+        }
+    }
+
+    Some(res)
+}
+
+/// Ramer-Douglas-Peucker simplification of a closed outline. Epsilon is max distance in pixels
+pub fn simplify(outline: &[V2], epsilon: f32) -> Vec::<V2> {
+    if outline.len() <= 3 {
+        return outline.to_vec();
+    }
+
+    // split the loop at the first point and the point furthest from it
+    let first = outline[0];
+    let mut far = 0;
+    let mut far_dist = 0.0;
+    for (i, p) in outline.iter().enumerate() {
+        let dist = (p - first).magnitude();
+        if dist > far_dist {
+            far_dist = dist;
+            far = i;
+        }
+    }
+
+    let mut keep = vec![false; outline.len()];
+    keep[0] = true;
+    keep[far] = true;
+
+    let mut second_half: Vec::<V2> = outline[far..].to_vec();
+    second_half.push(first);
+
+    rdp(&outline[0..=far], epsilon, &mut keep[0..=far]);
+    let mut keep_second = vec![false; second_half.len()];
+    rdp(&second_half, epsilon, &mut keep_second);
+    for (i, k) in keep_second.iter().enumerate().take(second_half.len() - 1) {
+        keep[far + i] |= *k;
+    }
+
+    outline.iter().zip(keep.iter()).filter(|(_, k)| **k).map(|(p, _)| *p).collect()
+}
+
+fn rdp(points: &[V2], epsilon: f32, keep: &mut [bool]) {
+    let len = points.len();
+    if len < 3 {
+        return;
+    }
+
+    let a = points[0];
+    let b = points[len - 1];
+    let ab = b - a;
+    let ab_len = ab.magnitude();
+
+    let mut max_dist = 0.0;
+    let mut max_i = 0;
+    for (i, p) in points.iter().enumerate().take(len - 1).skip(1) {
+        let ap = p - a;
+        let dist = if ab_len > 0.0 {
+            (ab.x * ap.y - ab.y * ap.x).abs() / ab_len
+        } else {
+            ap.magnitude()
+        };
+
+        if dist > max_dist {
+            max_dist = dist;
+            max_i = i;
+        }
+    }
+
+    if max_dist > epsilon {
+        keep[max_i] = true;
+        rdp(&points[0..=max_i], epsilon, &mut keep[0..=max_i]);
+        rdp(&points[max_i..], epsilon, &mut keep[max_i..]);
+    }
+}
+
+
+/// Trace the alpha outline of a sprite in a sheet image and simplify it.
+/// The polygon is relative to the bottom center of the sprite, the same anchor that `Drawer2D::render_sprite_sheet_frame` uses.
+pub fn trace_sprite(img: &image::RgbaImage, x: i32, y: i32, w: i32, h: i32, alpha_threshold: u8, epsilon: f32) -> Option<Polygon> {
+    let mask = AlphaMask::from_image(img, x, y, w, h, alpha_threshold);
+    let outline = trace_outline(&mask)?;
+    let simple = simplify(&outline, epsilon);
+
+    let mut polygon = Polygon {
+        vertices: simple.iter().map(|v| V2::new(v.x - w as f32 / 2.0, v.y - h as f32)).collect()
+    };
+
+    if polygon.direction() == Dir::Left {
+        polygon.vertices.reverse();
+    }
+
+    Some(polygon)
+}
+
+/// Split polygon into convex polygons. Uses the same subdivision as collision checks at runtime.
+pub fn convex_decomposition(polygon: &Polygon) -> Vec::<Polygon> {
+    let mut p = polygon.clone();
+    let subs: Vec::<Vec::<usize>> = polygon::calculate_subdivision(&mut p).into_iter().map(|s| s.indices).collect();
+
+    subs.iter().map(|indices| Polygon {
+        vertices: indices.iter().map(|i| p.vertices[*i]).collect()
+    }).collect()
+}
+
+/// True if the polygon has no wide (> 180 deg) angles
+pub fn is_convex(polygon: &Polygon) -> bool {
+    let len = polygon.vertices.len();
+    if len < 4 {
+        return true;
+    }
+
+    let mut sign = 0.0;
+    for i in 0..len {
+        let a = polygon.vertices[i];
+        let b = polygon.vertices[(i + 1) % len];
+        let c = polygon.vertices[(i + 2) % len];
+        let cross = (b - a).perp(&(c - b));
+        if cross.abs() < 1e-6 {
+            continue;
+        }
+        if sign == 0.0 {
+            sign = cross.signum();
+        } else if cross.signum() != sign {
+            return false;
+        }
+    }
+    true
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(rows: &[&str]) -> AlphaMask {
+        let h = rows.len() as i32;
+        let w = rows[0].len() as i32;
+        AlphaMask {
+            w,
+            h,
+            solid: rows.iter().flat_map(|r| r.chars().map(|c| c == '#')).collect()
+        }
+    }
+
+    #[test]
+    fn trace_square() {
+        let m = mask(&[
+            "....",
+            ".##.",
+            ".##.",
+            "....",
+        ]);
+
+        let outline = trace_outline(&m).unwrap();
+        assert_eq!(outline, vec![V2::new(1.0, 1.0), V2::new(3.0, 1.0), V2::new(3.0, 3.0), V2::new(1.0, 3.0)]);
+    }
+
+    #[test]
+    fn trace_l_shape() {
+        let m = mask(&[
+            "#...",
+            "#...",
+            "####",
+        ]);
+
+        let outline = trace_outline(&m).unwrap();
+        assert_eq!(outline.len(), 6);
+        assert_eq!(outline[0], V2::new(0.0, 0.0));
+        assert!(outline.contains(&V2::new(4.0, 3.0)));
+        assert!(outline.contains(&V2::new(1.0, 2.0)));
+    }
+
+    #[test]
+    fn trace_picks_largest_region() {
+        let m = mask(&[
+            "#.....",
+            "...###",
+            "...###",
+        ]);
+
+        let outline = trace_outline(&m).unwrap();
+        assert_eq!(outline[0], V2::new(3.0, 1.0));
+        assert_eq!(outline.len(), 4);
+    }
+
+    #[test]
+    fn trace_empty() {
+        let m = mask(&["...", "..."]);
+        assert!(trace_outline(&m).is_none());
+    }
+
+    #[test]
+    fn simplify_staircase() {
+        // diagonal staircase collapses to a triangle
+        let m = mask(&[
+            "#...",
+            "##..",
+            "###.",
+            "####",
+        ]);
+        let outline = trace_outline(&m).unwrap();
+        assert!(outline.len() > 3);
+        let simple = simplify(&outline, 1.0);
+        assert_eq!(simple.len(), 3);
+    }
+
+    #[test]
+    fn sprite_l_shape_decomposition() {
+        let mut img = image::RgbaImage::new(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                if x < 2 || y >= 6 {
+                    img.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+                }
+            }
+        }
+
+        let polygon = trace_sprite(&img, 0, 0, 8, 8, 0, 0.5).unwrap();
+        assert_eq!(polygon.vertices.len(), 6);
+        assert!(!is_convex(&polygon));
+
+        // relative to bottom center
+        assert!(polygon.vertices.contains(&V2::new(-4.0, -8.0)));
+        assert!(polygon.vertices.contains(&V2::new(4.0, 0.0)));
+
+        let parts = convex_decomposition(&polygon);
+        assert!(parts.len() >= 2);
+        for p in &parts {
+            assert!(is_convex(p), "{:?}", p);
+        }
+    }
+}
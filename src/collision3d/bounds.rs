@@ -0,0 +1,241 @@
+//! Bounding volumes and view frustum, used for culling meshes before rendering
+use crate::typedef::*;
+
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: V3,
+    pub max: V3,
+}
+
+impl Aabb {
+
+    pub fn new(min: V3, max: V3) -> Self {
+        Self { min, max }
+    }
+
+    /// None when points is empty
+    pub fn from_points<'a, I: IntoIterator<Item = &'a V3>>(points: I) -> Option<Self> {
+        let mut iter = points.into_iter();
+        let first = *iter.next()?;
+
+        let mut res = Self::new(first, first);
+        for p in iter {
+            res.min = res.min.inf(p);
+            res.max = res.max.sup(p);
+        }
+
+        Some(res)
+    }
+
+    pub fn center(&self) -> V3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_size(&self) -> V3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Grow by amount in all directions
+    pub fn expand(&self, amount: V3) -> Self {
+        Self::new(self.min - amount, self.max + amount)
+    }
+
+    /// Distance along dir to where the ray enters the box, 0 when origin is inside. Slab method
+    pub fn ray_intersection(&self, origin: &V3, dir: &V3) -> Option<f32> {
+        let mut t_min = 0.0_f32;
+        let mut t_max = f32::INFINITY;
+
+        for i in 0..3 {
+            if dir[i].abs() < 1e-8 {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (self.min[i] - origin[i]) / dir[i];
+            let t1 = (self.max[i] - origin[i]) / dir[i];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+
+    /// Smallest aabb containing this box transformed by mat, see "Transforming Axis-Aligned Bounding Boxes" by Arvo
+    pub fn transform(&self, mat: &Mat4) -> Self {
+        let center = mat.transform_point(&self.center().into()).coords;
+        let half = self.half_size();
+
+        let rot = mat.fixed_slice::<3, 3>(0, 0);
+        let new_half = rot.abs() * half;
+
+        Self::new(center - new_half, center + new_half)
+    }
+}
+
+
+/// Plane with normal pointing into the frustum. Point p is inside when normal.dot(p) + d >= 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: V3,
+    pub d: f32,
+}
+
+impl Plane {
+
+    fn from_v4(v: V4) -> Self {
+        let len = v.xyz().magnitude();
+        Self {
+            normal: v.xyz() / len,
+            d: v.w / len,
+        }
+    }
+
+    pub fn distance(&self, p: &V3) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+
+    /// Extract planes from projection * view, see "Fast Extraction of Viewing Frustum Planes" by Gribb and Hartmann
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let row = |i: usize| m.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_v4(r3 + r0),
+                Plane::from_v4(r3 - r0),
+                Plane::from_v4(r3 + r1),
+                Plane::from_v4(r3 - r1),
+                Plane::from_v4(r3 + r2),
+                Plane::from_v4(r3 - r2),
+            ]
+        }
+    }
+
+    pub fn contains_point(&self, p: &V3) -> bool {
+        self.planes.iter().all(|plane| plane.distance(p) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: &V3, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.distance(center) >= -radius)
+    }
+
+    /// Conservative, a box near a frustum corner can be reported as intersecting even when it is outside
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // corner furthest along the plane normal
+            let p = V3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z });
+
+            plane.distance(&p) >= 0.0
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::na;
+
+    fn frustum() -> Frustum {
+        // camera at origin looking down -z
+        let proj = Mat4::new_perspective(1.0, 90.0_f32.to_radians(), 1.0, 100.0);
+        Frustum::from_matrix(&proj)
+    }
+
+    #[test]
+    fn aabb_from_points() {
+        let points = [V3::new(1.0, -2.0, 3.0), V3::new(-1.0, 4.0, 0.0)];
+        let aabb = Aabb::from_points(&points).unwrap();
+        assert_eq!(aabb.min, V3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, V3::new(1.0, 4.0, 3.0));
+
+        assert!(Aabb::from_points(&[]).is_none());
+    }
+
+    #[test]
+    fn aabb_transform() {
+        let aabb = Aabb::new(V3::new(-1.0, -2.0, -3.0), V3::new(1.0, 2.0, 3.0));
+
+        let trans = na::Translation3::new(10.0, 0.0, 0.0).to_homogeneous();
+        let moved = aabb.transform(&trans);
+        assert_eq!(moved.min, V3::new(9.0, -2.0, -3.0));
+        assert_eq!(moved.max, V3::new(11.0, 2.0, 3.0));
+
+        // 90 degrees around z swaps x and y extents
+        let rot = na::Rotation3::from_euler_angles(0.0, 0.0, 90.0_f32.to_radians()).to_homogeneous();
+        let rotated = aabb.transform(&rot);
+        assert!((rotated.max - V3::new(2.0, 1.0, 3.0)).magnitude() < 0.0001);
+        assert!((rotated.min + rotated.max).magnitude() < 0.0001);
+    }
+
+    #[test]
+    fn aabb_ray() {
+        let aabb = Aabb::new(V3::new(-1.0, -1.0, -1.0), V3::new(1.0, 1.0, 1.0));
+
+        let hit = aabb.ray_intersection(&V3::new(-5.0, 0.0, 0.0), &V3::new(1.0, 0.0, 0.0));
+        assert_eq!(hit, Some(4.0));
+
+        // inside
+        assert_eq!(aabb.ray_intersection(&V3::new(0.0, 0.0, 0.0), &V3::new(0.0, 1.0, 0.0)), Some(0.0));
+
+        // pointing away and parallel outside
+        assert!(aabb.ray_intersection(&V3::new(-5.0, 0.0, 0.0), &V3::new(-1.0, 0.0, 0.0)).is_none());
+        assert!(aabb.ray_intersection(&V3::new(-5.0, 2.0, 0.0), &V3::new(1.0, 0.0, 0.0)).is_none());
+
+        let diagonal = V3::new(1.0, 1.0, 0.0).normalize();
+        assert!(aabb.ray_intersection(&V3::new(-3.0, -3.0, 0.0), &diagonal).is_some());
+        assert!(aabb.ray_intersection(&V3::new(-3.0, -1.0, 0.0), &V3::new(1.0, 1.5, 0.0).normalize()).is_none());
+    }
+
+    #[test]
+    fn frustum_points() {
+        let f = frustum();
+        assert!(f.contains_point(&V3::new(0.0, 0.0, -10.0)));
+        assert!(f.contains_point(&V3::new(9.0, -9.0, -10.0)));
+        assert!(!f.contains_point(&V3::new(0.0, 0.0, 10.0)));
+        assert!(!f.contains_point(&V3::new(11.0, 0.0, -10.0)));
+        assert!(!f.contains_point(&V3::new(0.0, 0.0, -0.5)));
+        assert!(!f.contains_point(&V3::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn frustum_aabb() {
+        let f = frustum();
+        let unit = Aabb::new(V3::new(-1.0, -1.0, -1.0), V3::new(1.0, 1.0, 1.0));
+
+        let at = |x, y, z| unit.transform(&na::Translation3::new(x, y, z).to_homogeneous());
+
+        assert!(f.intersects_aabb(&at(0.0, 0.0, -10.0)));
+        // behind camera
+        assert!(!f.intersects_aabb(&at(0.0, 0.0, 10.0)));
+        // partly inside the right plane
+        assert!(f.intersects_aabb(&at(10.5, 0.0, -10.0)));
+        assert!(!f.intersects_aabb(&at(12.5, 0.0, -10.0)));
+        // beyond far plane
+        assert!(!f.intersects_aabb(&at(0.0, 0.0, -102.0)));
+
+        assert!(f.intersects_sphere(&V3::new(0.0, 0.0, 1.0), 2.5));
+        assert!(!f.intersects_sphere(&V3::new(0.0, 0.0, 5.0), 2.5));
+    }
+}
@@ -1,6 +1,7 @@
 use nalgebra as na;
 use crate::typedef::*;
 pub mod projection_collision;
+pub mod bounds;
 use projection_collision::*;
 
 
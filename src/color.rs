@@ -1,6 +1,7 @@
 use nalgebra as na;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum Color {
     Rgb(u8, u8, u8),
     RgbA(u8, u8, u8, u8),
@@ -25,8 +25,12 @@ impl Deltatime {
 
     /// Get the time passed
     pub fn time(&self) -> f32 {
-        let ms = (self.value.as_millis() as f32 )/ 1000.0;
-        ms  * self.time_speed
+        self.unscaled_time() * self.time_speed
+    }
+
+    /// Time passed without time speed
+    pub fn unscaled_time(&self) -> f32 {
+        (self.value.as_millis() as f32 )/ 1000.0
     }
 
     /// Update the time passed
@@ -34,6 +38,12 @@ impl Deltatime {
         self.value = self.last_update.elapsed();
         self.last_update = Instant::now();
     }
+
+    /// Override the time passed since last update, fx with a recorded `unscaled_time`. Time speed still applies
+    pub fn set(&mut self, seconds: f32) {
+        // time is in whole ms, round so fx 0.015 stored as 0.0149999 is not 14 ms
+        self.value = Duration::from_millis((seconds.max(0.0) * 1000.0).round() as u64);
+    }
 }
 
 
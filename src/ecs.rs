@@ -0,0 +1,446 @@
+//! Lightweight entity component system. Components are plain structs stored per type in a map keyed by
+//! entity id, queries run a closure for every entity that has all components in a tuple, and a
+//! `Schedule` runs systems in stage order. `Scene::systems` is an `OwnerSchedule` run every fixed step.
+//!
+//! ```ignore
+//! world.query::<(Transform, Velocity), _>(|id, (transform, velocity)| {
+//!     transform.pos += velocity.velocity * dt;
+//! });
+//! ```
+use std::any::{Any, TypeId};
+use std::cell::{RefCell, Ref, RefMut};
+use std::collections::{HashMap, BTreeSet};
+use std::marker::PhantomData;
+
+
+pub type EntityId = usize;
+
+
+/// Storage of one component type
+pub type ComponentStorage<T> = HashMap::<EntityId, T>;
+
+
+// type erased storage, so the world can remove an entity from every storage
+trait AnyStorage {
+    fn remove(&self, id: EntityId);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: 'static> AnyStorage for RefCell<ComponentStorage<T>> {
+    fn remove(&self, id: EntityId) {
+        self.borrow_mut().remove(&id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+pub struct World {
+    next_id: EntityId,
+    alive: BTreeSet::<EntityId>,
+    storages: HashMap::<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            alive: Default::default(),
+            storages: Default::default(),
+        }
+    }
+}
+
+
+impl World {
+
+    pub fn spawn(&mut self) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.alive.insert(id);
+        id
+    }
+
+    /// Remove entity and all of its components
+    pub fn despawn(&mut self, id: EntityId) {
+        if self.alive.remove(&id) {
+            for storage in self.storages.values() {
+                storage.remove(id);
+            }
+        }
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.alive.contains(&id)
+    }
+
+    /// Living entities in spawn order
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.alive.iter().copied()
+    }
+
+    /// Add or replace component on entity. Components on entities that are not alive are ignored
+    pub fn insert<T: 'static>(&mut self, id: EntityId, component: T) {
+        if !self.is_alive(id) {
+            return;
+        }
+
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(ComponentStorage::<T>::new())));
+
+        if let Some(mut storage) = self.storage_mut::<T>() {
+            storage.insert(id, component);
+        }
+    }
+
+    pub fn remove<T: 'static>(&mut self, id: EntityId) -> Option<T> {
+        self.storage_mut::<T>()?.remove(&id)
+    }
+
+    pub fn has<T: 'static>(&self, id: EntityId) -> bool {
+        self.storage::<T>().map(|s| s.contains_key(&id)).unwrap_or(false)
+    }
+
+    pub fn get<T: 'static>(&self, id: EntityId) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.storage::<T>()?, |s| s.get(&id)).ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, id: EntityId) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.storage_mut::<T>()?, |s| s.get_mut(&id)).ok()
+    }
+
+    /// None when no component of type T has been inserted yet. Panics if the storage is mutably borrowed,
+    /// fx inside a query using T
+    pub fn storage<T: 'static>(&self) -> Option<Ref<'_, ComponentStorage<T>>> {
+        self.cell::<T>().map(|c| c.borrow())
+    }
+
+    pub fn storage_mut<T: 'static>(&self) -> Option<RefMut<'_, ComponentStorage<T>>> {
+        self.cell::<T>().map(|c| c.borrow_mut())
+    }
+
+    fn cell<T: 'static>(&self) -> Option<&RefCell<ComponentStorage<T>>> {
+        self.storages.get(&TypeId::of::<T>())?.as_any().downcast_ref()
+    }
+
+    /// Run f for every entity with all components in Q, in id order. The same type can not be used twice in Q
+    pub fn query<Q: Query, F: FnMut(EntityId, Q::Item<'_>)>(&self, f: F) {
+        Q::each(self, f);
+    }
+
+    /// Ids of entities with all components in Q, in id order
+    pub fn query_ids<Q: Query>(&self) -> Vec::<EntityId> {
+        let mut res = Vec::<EntityId>::new();
+        Q::each(self, |id, _| res.push(id));
+        res
+    }
+}
+
+
+/// Tuple of component types, fx `(Transform, Velocity)`
+pub trait Query {
+    type Item<'a>;
+
+    fn each<F: FnMut(EntityId, Self::Item<'_>)>(world: &World, f: F);
+}
+
+
+macro_rules! impl_query {
+    ($first:ident $(, $rest:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<$first: 'static $(, $rest: 'static)*> Query for ($first, $($rest,)*) {
+            type Item<'a> = (&'a mut $first, $(&'a mut $rest,)*);
+
+            fn each<Func: FnMut(EntityId, Self::Item<'_>)>(world: &World, mut f: Func) {
+                let (Some(mut $first), $(Some(mut $rest),)*) = (world.storage_mut::<$first>(), $(world.storage_mut::<$rest>(),)*) else {
+                    return;
+                };
+
+                let mut ids : Vec::<EntityId> = $first.keys().copied()$(.filter(|id| $rest.contains_key(id)))*.collect();
+                ids.sort_unstable();
+
+                for id in ids {
+                    if let (Some($first), $(Some($rest),)*) = ($first.get_mut(&id), $($rest.get_mut(&id),)*) {
+                        f(id, ($first, $($rest,)*));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+impl_query!(A, B, C, D, E);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+}
+
+/// Ctx is game data systems need besides the world
+pub type SystemFn<Ctx> = fn(&mut World, &mut Ctx, f32);
+
+/// System for a ctx that owns its world, fx a `Scene` where systems use `scene.ecs`. See `OwnerSchedule`
+pub type OwnerSystemFn<Ctx> = fn(&mut Ctx, f32);
+
+/// Schedule of systems that get the ctx owning the world, so spawning through the ctx, fx `Scene::spawn`,
+/// uses the same world as the systems
+pub type OwnerSchedule<Ctx> = Schedule<Ctx, OwnerSystemFn<Ctx>>;
+
+
+struct System<Run> {
+    name: &'static str,
+    stage: Stage,
+    enabled: bool,
+    run: Run,
+}
+
+
+/// Systems run once per `run` call, by stage and then in the order they were added
+pub struct Schedule<Ctx, Run = SystemFn<Ctx>> {
+    systems: Vec::<System<Run>>,
+    _ctx: PhantomData<fn(&mut Ctx)>,
+}
+
+impl<Ctx, Run> Default for Schedule<Ctx, Run> {
+    fn default() -> Self {
+        Self { systems: vec![], _ctx: PhantomData }
+    }
+}
+
+impl<Ctx, Run> Schedule<Ctx, Run> {
+
+    pub fn add(&mut self, name: &'static str, stage: Stage, run: Run) -> &mut Self {
+        // stable sort keeps insertion order within stage
+        self.systems.push(System { name, stage, enabled: true, run });
+        self.systems.sort_by_key(|s| s.stage);
+        self
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.systems.retain(|s| s.name != name);
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for s in self.systems.iter_mut().filter(|s| s.name == name) {
+            s.enabled = enabled;
+        }
+    }
+
+    /// System names in run order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.systems.iter().map(|s| s.name)
+    }
+
+    /// Add the systems of other after the systems of self, by stage
+    pub fn merge(&mut self, other: Self) {
+        for s in other.systems {
+            self.add(s.name, s.stage, s.run);
+        }
+    }
+}
+
+impl<Ctx> Schedule<Ctx> {
+
+    pub fn run(&self, world: &mut World, ctx: &mut Ctx, dt: f32) {
+        for s in self.systems.iter().filter(|s| s.enabled) {
+            (s.run)(world, ctx, dt);
+        }
+    }
+}
+
+impl<Ctx> OwnerSchedule<Ctx> {
+
+    pub fn run_owner(&self, ctx: &mut Ctx, dt: f32) {
+        for s in self.systems.iter().filter(|s| s.enabled) {
+            (s.run)(ctx, dt);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Pos(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Vel(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Frozen;
+
+    fn world() -> (World, [EntityId; 3]) {
+        let mut world = World::default();
+        let a = world.spawn();
+        let b = world.spawn();
+        let c = world.spawn();
+
+        world.insert(a, Pos(0.0));
+        world.insert(a, Vel(1.0));
+        world.insert(b, Pos(10.0));
+        world.insert(c, Pos(20.0));
+        world.insert(c, Vel(-2.0));
+        world.insert(c, Frozen);
+
+        (world, [a, b, c])
+    }
+
+    #[test]
+    fn components() {
+        let (mut world, [a, b, c]) = world();
+
+        assert_eq!(*world.get::<Pos>(b).unwrap(), Pos(10.0));
+        assert!(world.get::<Vel>(b).is_none());
+        assert!(world.has::<Frozen>(c));
+
+        world.get_mut::<Pos>(b).unwrap().0 = 5.0;
+        assert_eq!(world.remove::<Pos>(b), Some(Pos(5.0)));
+        assert!(!world.has::<Pos>(b));
+
+        world.despawn(a);
+        assert!(!world.is_alive(a));
+        assert!(world.get::<Pos>(a).is_none());
+
+        // dead entities do not get components
+        world.insert(a, Pos(1.0));
+        assert!(!world.has::<Pos>(a));
+
+        assert_eq!(world.entities().collect::<Vec<_>>(), vec![b, c]);
+    }
+
+    #[test]
+    fn queries() {
+        let (world, [a, b, c]) = world();
+
+        assert_eq!(world.query_ids::<(Pos,)>(), vec![a, b, c]);
+        assert_eq!(world.query_ids::<(Pos, Vel)>(), vec![a, c]);
+        assert_eq!(world.query_ids::<(Vel, Pos, Frozen)>(), vec![c]);
+
+        // never inserted type gives nothing
+        assert!(world.query_ids::<(Pos, String)>().is_empty());
+
+        world.query::<(Pos, Vel), _>(|_, (pos, vel)| {
+            pos.0 += vel.0;
+        });
+
+        assert_eq!(*world.get::<Pos>(a).unwrap(), Pos(1.0));
+        assert_eq!(*world.get::<Pos>(b).unwrap(), Pos(10.0));
+        assert_eq!(*world.get::<Pos>(c).unwrap(), Pos(18.0));
+    }
+
+    #[derive(Default)]
+    struct Log {
+        ran: Vec::<&'static str>,
+    }
+
+    fn movement(world: &mut World, log: &mut Log, dt: f32) {
+        world.query::<(Pos, Vel), _>(|_, (pos, vel)| pos.0 += vel.0 * dt);
+        log.ran.push("movement");
+    }
+
+    fn input(_: &mut World, log: &mut Log, _: f32) {
+        log.ran.push("input");
+    }
+
+    fn cleanup(world: &mut World, log: &mut Log, _: f32) {
+        for id in world.query_ids::<(Frozen,)>() {
+            world.despawn(id);
+        }
+        log.ran.push("cleanup");
+    }
+
+    #[test]
+    fn schedule() {
+        let (mut world, [a, _, c]) = world();
+        let mut log = Log::default();
+
+        let mut schedule = Schedule::<Log>::default();
+        schedule.add("cleanup", Stage::PostUpdate, cleanup)
+            .add("movement", Stage::Update, movement)
+            .add("input", Stage::PreUpdate, input);
+
+        assert_eq!(schedule.names().collect::<Vec<_>>(), vec!["input", "movement", "cleanup"]);
+
+        schedule.run(&mut world, &mut log, 0.5);
+        assert_eq!(log.ran, vec!["input", "movement", "cleanup"]);
+        assert_eq!(*world.get::<Pos>(a).unwrap(), Pos(0.5));
+        assert!(!world.is_alive(c));
+
+        log.ran.clear();
+        schedule.set_enabled("movement", false);
+        schedule.remove("input");
+        schedule.run(&mut world, &mut log, 0.5);
+        assert_eq!(log.ran, vec!["cleanup"]);
+        assert_eq!(*world.get::<Pos>(a).unwrap(), Pos(0.5));
+    }
+
+    // owns its world like `Scene` owns `ecs`, with entities also kept outside the world
+    #[derive(Default)]
+    struct Game {
+        world: World,
+        names: HashMap::<EntityId, &'static str>,
+        spawned: Vec::<EntityId>,
+    }
+
+    impl Game {
+        fn spawn(&mut self, name: &'static str) -> EntityId {
+            let id = self.world.spawn();
+            self.world.insert(id, Pos(0.0));
+            self.names.insert(id, name);
+            id
+        }
+
+        fn remove(&mut self, id: EntityId) {
+            self.names.remove(&id);
+            self.world.despawn(id);
+        }
+    }
+
+    fn spawner(game: &mut Game, _: f32) {
+        let id = game.spawn("bullet");
+        game.spawned.push(id);
+        if game.spawned.len() > 1 {
+            let first = game.spawned.remove(0);
+            game.remove(first);
+        }
+    }
+
+    fn game_movement(game: &mut Game, dt: f32) {
+        game.world.query::<(Pos,), _>(|_, (pos,)| pos.0 += dt);
+    }
+
+    #[test]
+    fn schedule_on_owner() {
+        let mut game = Game::default();
+        let player = game.spawn("player");
+
+        let mut schedule = OwnerSchedule::<Game>::default();
+        schedule.add("movement", Stage::Update, game_movement)
+            .add("spawner", Stage::PreUpdate, spawner);
+
+        schedule.run_owner(&mut game, 1.0);
+        let bullet = game.spawned[0];
+        assert_ne!(bullet, player);
+        assert_eq!(game.names[&player], "player");
+        assert_eq!(*game.world.get::<Pos>(bullet).unwrap(), Pos(1.0));
+
+        // removed through the owner, so gone from the world too
+        schedule.run_owner(&mut game, 1.0);
+        assert!(!game.world.is_alive(bullet));
+        assert!(!game.names.contains_key(&bullet));
+        assert!(game.world.get::<Pos>(bullet).is_none());
+        assert_eq!(game.world.entities().collect::<Vec<_>>(), vec![player, game.spawned[0]]);
+        assert_eq!(*game.world.get::<Pos>(player).unwrap(), Pos(2.0));
+    }
+}
@@ -0,0 +1,271 @@
+//! Fixed timestep game loop. Frame time is added to an accumulator and simulation runs in steps of
+//! `fixed_dt`, so results do not depend on frame rate. `alpha` is how far rendering is between the last
+//! two steps, for interpolating positions.
+//! # Example
+//! ```ignore
+//! let mut game_loop = GameLoop::new(1.0 / 60.0);
+//! loop {
+//!     for _ in 0..game_loop.begin_frame() {
+//!         update(game_loop.fixed_dt);
+//!     }
+//!     render(game_loop.alpha());
+//! }
+//! ```
+use std::time::Instant;
+
+
+/// Source of time in seconds, so stepping can be tested without waiting
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+
+/// Clock that only moves when told to
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ManualClock {
+    pub time: f64,
+}
+
+impl ManualClock {
+    pub fn advance(&mut self, seconds: f64) {
+        self.time += seconds;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        self.time
+    }
+}
+
+
+pub struct GameLoop<C: Clock = SystemClock> {
+    clock: C,
+    last_time: Option<f64>,
+    accumulator: f32,
+    steps: usize,
+    frame_dt: f32,
+    step_requests: usize,
+
+    /// Seconds per simulation step
+    pub fixed_dt: f32,
+    /// Speed of game time, fx 0.25 for slow motion
+    pub time_scale: f32,
+    pub paused: bool,
+    /// Most steps per frame. When a frame takes longer the game slows down instead of
+    /// taking even longer to catch up
+    pub max_steps: usize,
+}
+
+
+impl GameLoop<SystemClock> {
+    pub fn new(fixed_dt: f32) -> Self {
+        Self::with_clock(SystemClock::default(), fixed_dt)
+    }
+}
+
+
+impl<C: Clock> GameLoop<C> {
+
+    pub fn with_clock(clock: C, fixed_dt: f32) -> Self {
+        Self {
+            clock,
+            last_time: None,
+            accumulator: 0.0,
+            steps: 0,
+            frame_dt: 0.0,
+            step_requests: 0,
+            fixed_dt,
+            time_scale: 1.0,
+            paused: false,
+            max_steps: 8,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Sample the clock and return the number of fixed steps to run this frame.
+    /// The first frame only starts the clock and runs no steps
+    pub fn begin_frame(&mut self) -> usize {
+        let now = self.clock.now();
+        let elapsed = self.last_time.map(|t| (now - t).max(0.0) as f32).unwrap_or(0.0);
+        self.last_time = Some(now);
+
+        self.advance(elapsed)
+    }
+
+    /// Like `begin_frame`, but with elapsed seconds given instead of from the clock, fx a recorded dt
+    pub fn advance(&mut self, elapsed: f32) -> usize {
+        if self.paused {
+            // frame advance runs whole steps and keeps the accumulator, so alpha stays the same
+            self.steps = self.step_requests.min(self.max_steps);
+            self.step_requests = 0;
+            self.frame_dt = self.steps as f32 * self.fixed_dt;
+            return self.steps;
+        }
+
+        self.step_requests = 0;
+        self.frame_dt = elapsed * self.time_scale;
+        self.accumulator += self.frame_dt;
+
+        self.steps = 0;
+        while self.accumulator >= self.fixed_dt && self.steps < self.max_steps {
+            self.accumulator -= self.fixed_dt;
+            self.steps += 1;
+        }
+
+        // drop time we could not catch up on
+        if self.steps == self.max_steps {
+            self.accumulator = self.accumulator.min(self.fixed_dt);
+        }
+
+        self.steps
+    }
+
+    /// Clears the steps of the frame, so `steps` is 0 outside of a frame
+    pub fn end_frame(&mut self) {
+        self.steps = 0;
+    }
+
+    /// Run one step in the next frame while paused
+    pub fn step_once(&mut self) {
+        self.step_requests += 1;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Fixed steps to run in the current frame
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Scaled time of the current frame. 0 when paused, unless stepping a frame
+    pub fn frame_dt(&self) -> f32 {
+        self.frame_dt
+    }
+
+    /// How far between the previous and the current step to render, in \[0.0; 1.0\]
+    pub fn alpha(&self) -> f32 {
+        if self.fixed_dt <= 0.0 {
+            return 1.0;
+        }
+
+        (self.accumulator / self.fixed_dt).clamp(0.0, 1.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_loop() -> GameLoop<ManualClock> {
+        let mut gl = GameLoop::with_clock(ManualClock::default(), 0.1);
+        assert_eq!(gl.begin_frame(), 0);
+        gl
+    }
+
+    fn frame(gl: &mut GameLoop<ManualClock>, seconds: f64) -> usize {
+        gl.clock_mut().advance(seconds);
+        let steps = gl.begin_frame();
+        gl.end_frame();
+        steps
+    }
+
+    #[test]
+    fn fixed_steps() {
+        let mut gl = game_loop();
+
+        assert_eq!(frame(&mut gl, 0.05), 0);
+        assert!((gl.alpha() - 0.5).abs() < 0.001);
+
+        assert_eq!(frame(&mut gl, 0.07), 1);
+        assert!((gl.alpha() - 0.2).abs() < 0.001);
+
+        assert_eq!(frame(&mut gl, 0.35), 3);
+        assert!((gl.alpha() - 0.7).abs() < 0.001);
+
+        // same total time gives same steps no matter the frame rate. Times are exact in binary to not depend on rounding
+        let mut slow = game_loop();
+        let mut fast = game_loop();
+        slow.fixed_dt = 0.125;
+        fast.fixed_dt = 0.125;
+        let slow_steps : usize = (0..8).map(|_| frame(&mut slow, 1.0 / 16.0)).sum();
+        let fast_steps : usize = (0..32).map(|_| frame(&mut fast, 1.0 / 64.0)).sum();
+        assert_eq!(slow_steps, 4);
+        assert_eq!(fast_steps, 4);
+    }
+
+    #[test]
+    fn max_steps() {
+        let mut gl = game_loop();
+        gl.max_steps = 4;
+
+        assert_eq!(frame(&mut gl, 10.0), 4);
+        // the rest is dropped
+        assert_eq!(frame(&mut gl, 0.0), 1);
+        assert_eq!(frame(&mut gl, 0.0), 0);
+    }
+
+    #[test]
+    fn time_scale() {
+        let mut gl = game_loop();
+        gl.time_scale = 0.5;
+
+        assert_eq!(frame(&mut gl, 0.1), 0);
+        assert_eq!(frame(&mut gl, 0.1), 1);
+        assert!((gl.frame_dt() - 0.05).abs() < 0.001);
+    }
+
+    #[test]
+    fn pause_and_step() {
+        let mut gl = game_loop();
+        assert_eq!(frame(&mut gl, 0.05), 0);
+
+        gl.toggle_pause();
+        assert_eq!(frame(&mut gl, 1.0), 0);
+        assert_eq!(gl.frame_dt(), 0.0);
+
+        gl.step_once();
+        gl.clock_mut().advance(1.0);
+        assert_eq!(gl.begin_frame(), 1);
+        assert!((gl.frame_dt() - 0.1).abs() < 0.001);
+        gl.end_frame();
+        assert_eq!(gl.steps(), 0);
+
+        assert_eq!(frame(&mut gl, 1.0), 0);
+
+        // time while paused is not caught up on, and the half step from before is kept
+        gl.toggle_pause();
+        assert_eq!(frame(&mut gl, 0.05), 1);
+
+        // step requests while running are ignored
+        gl.step_once();
+        assert_eq!(frame(&mut gl, 0.0), 0);
+    }
+}
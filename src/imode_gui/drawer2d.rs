@@ -22,7 +22,8 @@ use crate::imode_gui::viewport::Viewport;
 use crate::math::numeric::Numeric;
 use crate::shader::BaseShader;
 use crate::Geom;
-use crate::typedef::V2;
+use crate::typedef::{V2, Mat4};
+use crate::camera::camera_2d::Camera2D;
 
 
 pub struct Drawer2D {
@@ -63,6 +64,9 @@ pub struct Drawer2D {
     pub instance_colors: Vec::<na::Vector4::<f32>>,
     pub instance_transform_vbo: buffer::ArrayBuffer,
     pub instance_color_vbo: buffer::ArrayBuffer,
+
+    // world to screen pixel transforms, the last is used
+    camera_stack: Vec::<Mat4>,
 }
 
 impl Drawer2D {
@@ -125,6 +129,7 @@ impl Drawer2D {
             instance_transform_vbo: buffer::ArrayBuffer::new(&gl),
             instance_colors: vec![],
             instance_color_vbo: buffer::ArrayBuffer::new(&gl),
+            camera_stack: vec![],
             instanced: false, //TODO: does not work, since shaders assumes instanced, so maybe just always use instanced
         })
     }
@@ -218,6 +223,36 @@ impl Drawer2D {
 
     }
 
+    /// Draw in world units of the camera until `pop_camera`. Text is still drawn in screen pixels
+    pub fn push_camera(&mut self, camera: &Camera2D) {
+        self.camera_stack.push(camera.view());
+    }
+
+    /// Like `push_camera` with a world to screen pixels transform
+    pub fn push_transform(&mut self, view: Mat4) {
+        self.camera_stack.push(view);
+    }
+
+    /// Back to the previous camera, or screen pixels when there is none
+    pub fn pop_camera(&mut self) {
+        self.camera_stack.pop();
+    }
+
+    /// Clip space transform for vertices in screen pixels with y down, with the current camera applied.
+    /// For custom batched rendering, fx tile maps
+    pub fn screen_transform(&self) -> Mat4 {
+        let proj = Orthographic3::new(0.0, self.viewport.w as f32, 0.0, self.viewport.h as f32, -10.0, 100.0);
+        self.camera_transform() * proj.to_homogeneous() * screen_flip(&self.viewport)
+    }
+
+    /// Applied on top of the screen space transforms, so they draw with the current camera
+    fn camera_transform(&self) -> Mat4 {
+        match self.camera_stack.last() {
+            Some(view) => camera_clip_transform(view, &self.viewport),
+            None => Mat4::identity(),
+        }
+    }
+
     pub fn line<T1: Numeric, T2: Numeric, T3: Numeric, T4: Numeric, T5: Numeric>(
         &self, x_t: T1, y_t: T2, x1_t: T3, y1_t: T4, thickness_t: T5) {
 
@@ -237,7 +272,7 @@ impl Drawer2D {
 
         let l = v.magnitude();
 
-        let transform = unit_line_transform(x, y, l,  thickness, angle, &self.viewport);
+        let transform = self.camera_transform() * unit_line_transform(x, y, l,  thickness, angle, &self.viewport);
 
         self.rounded_rect_shader.set_transform(transform);
 
@@ -256,7 +291,7 @@ impl Drawer2D {
 
         let geom = Geom { x, y, w, h };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
         self.color_square_shader.set_mat4(&self.gl, "transform", transform);
 
         self.color_square.render(&self.gl);
@@ -276,7 +311,7 @@ impl Drawer2D {
             h: r * 2.0,
         };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
 
 
         self.circle_shader.set_transform(transform);
@@ -314,7 +349,7 @@ impl Drawer2D {
             h: r * 2.0,
         };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
 
 
         self.circle_outline_shader.set_transform(transform);
@@ -367,13 +402,15 @@ impl Drawer2D {
             self.polygon_indices_buffer.push((i + 2) as u32);
         }
 
-        polygon(&self.gl, &mut self.polygon, &self.polygon_shader, &self.polygon_vertex_buffer, &self.polygon_indices_buffer, &self.viewport);
+        let camera = self.camera_transform();
+        polygon(&self.gl, &mut self.polygon, &self.polygon_shader, &self.polygon_vertex_buffer, &self.polygon_indices_buffer, &self.viewport, camera);
 
     }
 
     /// Assume vertices is in world/screen space
     pub fn polygon(&mut self, vertices: &[f32], indices: &[u32]) {
-        polygon(&self.gl, &mut self.polygon, &self.polygon_shader, vertices, indices, &self.viewport);
+        let camera = self.camera_transform();
+        polygon(&self.gl, &mut self.polygon, &self.polygon_shader, vertices, indices, &self.viewport, camera);
     }
 
 
@@ -382,7 +419,7 @@ impl Drawer2D {
 
         let geom = Geom { x, y, w, h };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
 
 
         self.color_square_h_line_shader.set_mat4(&self.gl, "transform", transform);
@@ -396,7 +433,7 @@ impl Drawer2D {
 
         let geom = Geom { x, y, w, h };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z - 1.0);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z - 1.0);
 
         if self.instanced {
             self.instance_transforms.push(transform);
@@ -474,7 +511,7 @@ impl Drawer2D {
             h: size.y
         };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
         self.texture_shader.setup(ts::Uniforms { texture_id, transform, zoom: 1.0});
 
         self.texture_square.render(&self.gl);
@@ -492,7 +529,7 @@ impl Drawer2D {
             h: size.y
         };
 
-        let mut transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let mut transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
 
         self.texture_shader.setup(ts::Uniforms { texture_id, transform, zoom });
 
@@ -513,7 +550,7 @@ impl Drawer2D {
         };
 
 
-        let transform = unit_square_transform_matrix(&geom, rot, &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, rot, &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
 
         self.texture_shader.setup(ts::Uniforms { texture_id, transform, zoom: 1.0 });
 
@@ -534,7 +571,7 @@ impl Drawer2D {
         };
 
 
-        let transform = unit_square_transform_matrix(&geom, rot, &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z + 1.0);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, rot, &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z + 1.0);
 
         self.texture_shader.setup(ts::Uniforms { texture_id, transform, zoom: 1.0 });
 
@@ -554,7 +591,7 @@ impl Drawer2D {
             h: size.y
         };
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(0.0, 0.0), 1.0, self.z);
         shader.setup(ts::Uniforms { texture_id, transform, zoom: 1.0 });
 
         self.texture_square.render(&self.gl);
@@ -575,7 +612,7 @@ impl Drawer2D {
 
         let y_flip = if sprite.flip_y { -1.0} else { 1.0};
 
-        let transform = unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(size.x.to_f32() / 2.0, size.y.to_f32()), y_flip, self.z);
+        let transform = self.camera_transform() * unit_square_transform_matrix(&geom, RotationWithOrigin::Center(0.0), &self.viewport, na::Vector2::new(size.x.to_f32() / 2.0, size.y.to_f32()), y_flip, self.z);
         self.texture_shader.setup(ts::Uniforms { texture_id, transform, zoom: 1.0 });
 
         let l = sprite.pixel_l as f32 / sprite.sheet_size.x;
@@ -595,7 +632,7 @@ impl Drawer2D {
 
         let transform =  Orthographic3::new(0.0, self.viewport.w as f32, 0.0, self.viewport.h as f32, -10.0, 100.0);
 
-        self.viewport_shader.setup(vps::Uniforms { transform: transform.into(), color });
+        self.viewport_shader.setup(vps::Uniforms { transform: self.camera_transform() * transform.to_homogeneous(), color });
 
         obj.render(&self.gl);
     }
@@ -823,6 +860,24 @@ pub fn unit_line_transform<T1: Numeric, T2: Numeric, T3: Numeric, T4: Numeric, T
 }
 
 
+/// Clip space transform that applies view to everything drawn in screen pixels. Screen pixels have y down,
+/// while the transforms above flip to y up before projecting, so view is applied in between
+pub fn camera_clip_transform(view: &Mat4, viewport: &viewport::Viewport) -> Mat4 {
+    let proj = Orthographic3::new(0.0, viewport.w as f32, 0.0, viewport.h as f32, -10.0, 100.0);
+
+    let flip = screen_flip(viewport);
+
+    proj.to_homogeneous() * flip * view * flip * proj.inverse()
+}
+
+// screen pixels with y down to y up, and back since it is its own inverse
+fn screen_flip(viewport: &viewport::Viewport) -> Mat4 {
+    let mut flip = Translation3::new(0.0, viewport.h as f32, 0.0).to_homogeneous();
+    flip[(1, 1)] = -1.0;
+    flip
+}
+
+
 fn unit_square_transform_zoom_matrix<T1: Numeric, T2: Numeric, T3: Numeric, T4: Numeric, T5: Numeric + std::fmt::Debug>(
     geom: &Geom<T1, T2, T3, T4>,
     rot: RotationWithOrigin,
@@ -899,7 +954,7 @@ fn unit_square_transform_matrix<T1: Numeric, T2: Numeric, T3: Numeric, T4: Numer
 }
 
 
-fn polygon(gl: &gl::Gl, polygon: &mut polygon::Polygon, polygon_shader: &Box::<Shader>, vertices: &[f32], indices: &[u32], viewport: &Viewport) {
+fn polygon(gl: &gl::Gl, polygon: &mut polygon::Polygon, polygon_shader: &Box::<Shader>, vertices: &[f32], indices: &[u32], viewport: &Viewport, camera: Mat4) {
     // setup polygon_data
     polygon.sub_data(&gl, indices, vertices, None);
 
@@ -907,7 +962,7 @@ fn polygon(gl: &gl::Gl, polygon: &mut polygon::Polygon, polygon_shader: &Box::<S
 
     let proj = Orthographic3::new(0.0, viewport.w as f32, 0.0, viewport.h as f32, -10.0, 100.0);
 
-    let transform = proj.to_homogeneous();
+    let transform = camera * proj.to_homogeneous();
 
     polygon_shader.set_mat4(&gl, "transform", transform);
 
@@ -16,6 +16,8 @@ use drawer2d::*;
 
 pub mod widgets;
 
+pub mod pixel_perfect;
+
 pub mod style;
 
 pub type Pos = na::Vector2::<i32>;
@@ -65,6 +67,13 @@ pub struct Rect {
     pub h: i32
 }
 
+/// Widget states that can be animated with `Ui::transition`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WidgetTransition {
+    Hover,
+    Press
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum WidgetStatus {
     Inactive,
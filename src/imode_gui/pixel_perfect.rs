@@ -0,0 +1,102 @@
+//! Render pixel art to a low resolution framebuffer and draw it to the window scaled by a whole number,
+//! so every art pixel is the same number of screen pixels. Space left over is letterboxed.
+//! # Example
+//! ```ignore
+//! let mut pixel = PixelPerfect::new(&gl, 320, 180);
+//! pixel.begin(&mut drawer2d);
+//! // draw as if the window is 320x180
+//! pixel.end(&mut drawer2d);
+//! ```
+use crate::buffer::FrameBuffer;
+use crate::gl::{self, viewport::Viewport};
+use crate::imode_gui::Rect;
+use crate::imode_gui::drawer2d::Drawer2D;
+use crate::typedef::V2;
+
+
+pub struct PixelPerfect {
+    pub fbo: FrameBuffer,
+    pub width: i32,
+    pub height: i32,
+    // window viewport while rendering to fbo
+    window: Option<Viewport>,
+}
+
+impl PixelPerfect {
+
+    pub fn new(gl: &gl::Gl, width: i32, height: i32) -> Self {
+        let fbo = FrameBuffer::new(gl, &Viewport::for_window(width, height));
+
+        // sharp pixels when scaled up
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, fbo.color_tex);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        Self { fbo, width, height, window: None }
+    }
+
+    /// Start drawing to the low resolution buffer. Drawer viewport is the low resolution size until `end`
+    pub fn begin(&mut self, drawer: &mut Drawer2D) {
+        self.window = Some(drawer.viewport);
+
+        self.fbo.bind_and_clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        drawer.update_viewport(self.width, self.height);
+    }
+
+    /// Draw the buffer to the window, scaled and centered
+    pub fn end(&mut self, drawer: &mut Drawer2D) {
+        self.fbo.unbind();
+
+        let Some(window) = self.window.take() else {
+            return;
+        };
+
+        drawer.update_viewport(window.w, window.h);
+
+        let (_, rect) = integer_scale(self.width, self.height, window.w, window.h);
+        drawer.render_img(self.fbo.color_tex, rect.x, rect.y, V2::new(rect.w as f32, rect.h as f32));
+    }
+
+    /// Window position to position in the low resolution buffer, fx for the mouse
+    pub fn window_to_buffer(&self, window_w: i32, window_h: i32, x: i32, y: i32) -> V2 {
+        let (scale, rect) = integer_scale(self.width, self.height, window_w, window_h);
+        V2::new((x - rect.x) as f32 / scale as f32, (y - rect.y) as f32 / scale as f32)
+    }
+}
+
+
+/// Largest whole scale of width x height that fits in window, at least 1, and where to draw it centered in window
+pub fn integer_scale(width: i32, height: i32, window_w: i32, window_h: i32) -> (i32, Rect) {
+    let scale = (window_w / width.max(1)).min(window_h / height.max(1)).max(1);
+
+    let w = width * scale;
+    let h = height * scale;
+
+    (scale, Rect { x: (window_w - w) / 2, y: (window_h - h) / 2, w, h })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale() {
+        let (scale, rect) = integer_scale(320, 180, 1920, 1080);
+        assert_eq!(scale, 6);
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (0, 0, 1920, 1080));
+
+        // letterboxed
+        let (scale, rect) = integer_scale(320, 180, 1000, 800);
+        assert_eq!(scale, 3);
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (20, 130, 960, 540));
+
+        // never below 1
+        let (scale, rect) = integer_scale(320, 180, 100, 100);
+        assert_eq!(scale, 1);
+        assert_eq!((rect.x, rect.y), (-110, -40));
+    }
+}
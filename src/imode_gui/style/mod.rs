@@ -1,5 +1,6 @@
 use crate::imode_gui::Rect;
 use crate::imode_gui::Color;
+use crate::tween::Easing;
 
 #[derive(Debug, Clone)]
 pub struct Style {
@@ -11,7 +12,7 @@ pub struct Style {
     pub drag_point: Color,
     pub clear_color: Color,
     pub text_field: TextFieldStyle,
-
+    pub transition: TransitionStyle,
 }
 
 impl Default for Style {
@@ -25,6 +26,7 @@ impl Default for Style {
             drag_point: Color::Rgb(220, 220, 220),
             clear_color: Color::Rgb(27, 27, 27),
             text_field: Default::default(),
+            transition: Default::default(),
         }
     }
 }
@@ -173,3 +175,20 @@ impl Default for TextFieldStyle {
         }
     }
 }
+
+
+/// How widgets animate between states like hover and pressed. Duration of 0 is instant
+#[derive(Debug, Clone, Copy)]
+pub struct TransitionStyle {
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl Default for TransitionStyle {
+    fn default() -> Self {
+        Self {
+            duration: 0.1,
+            easing: Easing::QuadOut,
+        }
+    }
+}
@@ -11,6 +11,7 @@ use super::*;
 use std::collections::HashMap;
 use crate::math::numeric::Numeric;
 use crate::deltatime;
+use crate::input::recording::{Recording, Playback};
 use std::rc::Rc;
 use std::cell::Cell;
 use std::borrow::BorrowMut;
@@ -58,6 +59,14 @@ pub struct Ui {
 
     pub enabled: bool,
     pub window: Rc<sdl2::video::Window>,
+
+    /// Linear progress in [0;1] of widget state transitions, see `transition`
+    pub transitions: HashMap::<(Id, WidgetTransition), f32>,
+
+    /// Events and dt of each frame are added when set
+    pub recording: Option<Recording>,
+    /// Used in place of the event pump and deltatime while set and not done
+    pub playback: Option<Playback>,
 }
 
 
@@ -99,6 +108,9 @@ impl Ui {
             active_window: None,
             enabled: true,
             window,
+            transitions: Default::default(),
+            recording: None,
+            playback: None,
         }
     }
 
@@ -110,6 +122,32 @@ impl Ui {
         1.0 / self.deltatime.time()
     }
 
+    /// Animate a widget state over time using dt. Moves towards 1 while `on` and back to 0 when not.
+    /// Returns the eased progress, using `style.transition`. Call once per frame per widget state.
+    pub fn transition(&mut self, id: Id, state: WidgetTransition, on: bool) -> f32 {
+        let duration = self.style.transition.duration;
+        let easing = self.style.transition.easing;
+        let dt = self.dt();
+
+        let t = self.transitions.entry((id, state)).or_insert(0.0);
+
+        if duration <= 0.0 {
+            *t = if on { 1.0 } else { 0.0 };
+        } else {
+            let step = dt / duration;
+            *t = if on { f32::min(1.0, *t + step) } else { f32::max(0.0, *t - step) };
+        }
+
+        let res = easing.apply(*t);
+
+        // no need to keep widgets at rest
+        if !on && *t <= 0.0 {
+            self.transitions.remove(&(id, state));
+        }
+
+        res
+    }
+
     pub fn set_hot(&mut self, id: Id) {
         self.ctx_fn(|ctx| ctx.set_hot(id));
     }
@@ -305,18 +343,18 @@ impl Ui {
         }
 
 
+        let events = self.poll_events(event_pump);
+
         // has keep clearing windows is drawn otherwise it will never redraw
         if !self.enabled {
-            for event in event_pump.poll_iter() {
-                self.frame_events.push(event);
-            }
+            self.frame_events = events;
             return;
         }
 
         use event::Event::*;
         use sdl2::keyboard::Keycode::*;
 
-        for event in event_pump.poll_iter() {
+        for event in events {
             match event {
                 MouseButtonDown {x, y, ..} => {
                     self.mouse_down = true;
@@ -380,6 +418,47 @@ impl Ui {
         }
     }
 
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::default());
+    }
+
+    /// Stop recording and store state_hash as the final hash, see `recording::state_hash`
+    pub fn stop_recording(&mut self, state_hash: u64) -> Option<Recording> {
+        let mut rec = self.recording.take()?;
+        rec.final_hash = Some(state_hash);
+        Some(rec)
+    }
+
+    pub fn start_playback(&mut self, recording: Recording) {
+        self.playback = Some(Playback::new(recording));
+    }
+
+    pub fn playback_done(&self) -> bool {
+        self.playback.as_ref().map(|p| p.is_done()).unwrap_or(true)
+    }
+
+    // live events, or the next recorded frame while playing back. Live events are dropped during playback,
+    // except quit so the window can still be closed
+    fn poll_events(&mut self, event_pump: &mut sdl2::EventPump) -> Vec::<event::Event> {
+        let live : Vec::<event::Event> = event_pump.poll_iter().collect();
+
+        let events = match self.playback.as_mut().and_then(|p| p.next_frame()) {
+            Some((dt, mut events)) => {
+                self.deltatime.set(dt);
+                events.extend(live.into_iter().filter(|e| matches!(e, event::Event::Quit { .. })));
+                events
+            },
+            None => live
+        };
+
+        let dt = self.deltatime.unscaled_time();
+        if let Some(rec) = &mut self.recording {
+            rec.record_frame(dt, &events);
+        }
+
+        events
+    }
+
     pub fn set_window_pos(&mut self, pos: Pos) {
         let window : &mut Window = self.windows.get_mut(self.current_window.last().unwrap_or(&0)).unwrap();
         window.base_container_context.anchor_pos = pos - window.drag_point;
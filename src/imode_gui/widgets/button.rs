@@ -100,15 +100,21 @@ impl Ui {
 
         let r = self.style.button.radius.get(rect);
 
-        if self.is_active(id) {
-            color = self.style.button.active_color;
+        let hot = self.is_hot(id);
+        let active = self.is_active(id);
+        let hover_t = self.transition(id, WidgetTransition::Hover, hot);
+        let press_t = self.transition(id, WidgetTransition::Press, active);
+
+        if press_t > 0.0 {
+            color = Color::lerp(color, self.style.button.active_color, press_t);
         }
 
         // outline
-        if self.is_hot(id) {
+        if hover_t > 0.0 {
             let z = self.drawer2D.z;
             self.drawer2D.z = -0.1;
-            self.drawer2D.rounded_rect_color(rect.x, rect.y , rect.w, rect.h, r, self.style.button.hover_color);
+            let hover_color = Color::lerp(self.style.button.color, self.style.button.hover_color, hover_t);
+            self.drawer2D.rounded_rect_color(rect.x, rect.y , rect.w, rect.h, r, hover_color);
             self.drawer2D.z = z
         }
 
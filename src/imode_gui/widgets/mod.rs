@@ -38,3 +38,6 @@ pub use polygon::*;
 
 mod cell_grid;
 pub use cell_grid::*;
+
+mod sheet_polygon_editor;
+pub use sheet_polygon_editor::*;
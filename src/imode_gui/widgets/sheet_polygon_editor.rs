@@ -0,0 +1,307 @@
+use super::*;
+use crate::animations::sheet_animation::{self, SheetAnimation, SheetArrayAnimation, Sprite};
+use crate::animations::sheet_polygons::{SheetPolygons, LayerKind, PolygonLayer};
+use crate::collision2d::polygon::{Polygon, PolygonTransform};
+use crate::collision2d::trace;
+use crate::texture::TextureId;
+use std::path::{Path, PathBuf};
+
+type V2 = na::Vector2::<f32>;
+
+
+/// State for `Ui::sheet_polygon_editor`. Edits collision polygons for one animation in a sheet,
+/// and saves them to the sidecar next to the sheet json.
+pub struct SheetPolygonEditor {
+    pub sheet_json_path: PathBuf,
+    pub animation: String,
+    pub polygons: SheetPolygons,
+    pub frame: usize,
+    pub layer: String,
+    pub scale: f32,
+    pub anchor: Pos,
+    /// Max distance in pixels when simplifying traced outlines
+    pub trace_epsilon: f32,
+    pub alpha_threshold: u8,
+    pub options: PolygonOptions,
+    texture_id: TextureId,
+    sheet_size: V2,
+    sprites: Vec::<Sprite>,
+    image: Option<image::RgbaImage>,
+    new_layer: String,
+    copy: Option<Polygon>,
+    status: String,
+}
+
+
+impl SheetPolygonEditor {
+
+    /// Load polygons for `sheet` from the sidecar next to `sheet_json_path`. If there is no sidecar entry for
+    /// the animation, the old `<name>_polygons.json` is imported
+    pub fn new<P: AsRef<Path> + std::fmt::Debug, FrameDataT>(sheet: &SheetAnimation<FrameDataT>, sheet_json_path: &P) -> Result<Self, failure::Error> {
+
+        let mut polygons = SheetPolygons::load(sheet_json_path)?;
+
+        let mut dir = PathBuf::new();
+        dir.push(sheet_json_path);
+        dir.pop();
+
+        if !polygons.animations.contains_key(&sheet.name) {
+            let legacy = sheet_animation::load_sheet_collision_polygons(&dir, &sheet.name);
+            if let Some(anim) = SheetPolygons::from_legacy(&sheet.name, &legacy).animations.remove(&sheet.name) {
+                polygons.animations.insert(sheet.name.clone(), anim);
+            }
+        }
+
+        // cpu side image for tracing
+        let sheet_json : SheetArrayAnimation = serde_json::from_str(&std::fs::read_to_string(sheet_json_path)?)?;
+        dir.push(&sheet_json.meta.image);
+        let image = image::open(&dir).ok().map(|img| img.into_rgba8());
+
+        Ok(Self {
+            sheet_json_path: sheet_json_path.as_ref().to_path_buf(),
+            animation: sheet.name.clone(),
+            polygons,
+            frame: 0,
+            layer: "body".to_string(),
+            scale: 10.0,
+            anchor: Pos::new(400, 600),
+            trace_epsilon: 1.0,
+            alpha_threshold: 0,
+            options: Default::default(),
+            texture_id: sheet.texture_id,
+            sheet_size: sheet.size,
+            sprites: sheet.animation.frames.iter().map(|f| f.data).collect(),
+            image,
+            new_layer: "".to_string(),
+            copy: None,
+            status: "".to_string(),
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// All layer names used in any frame of the animation
+    pub fn layer_names(&self) -> Vec::<String> {
+        let mut res : Vec::<String> = vec![];
+        if let Some(anim) = self.polygons.animations.get(&self.animation) {
+            for frame in anim.frames.values() {
+                for name in frame.keys() {
+                    if !res.contains(name) {
+                        res.push(name.clone());
+                    }
+                }
+            }
+        }
+        res.sort();
+        res
+    }
+
+    pub fn layer_mut(&mut self, frame: usize, name: &str) -> &mut PolygonLayer {
+        let kind = self.polygons.layer_kind(&self.animation, name);
+        let anim = self.polygons.animations.entry(self.animation.clone()).or_default();
+        anim.frames.entry(frame).or_default()
+            .entry(name.to_string())
+            .or_insert_with(|| PolygonLayer { kind, polygon: Default::default() })
+    }
+
+    /// Set kind of layer in all frames
+    pub fn set_layer_kind(&mut self, name: &str, kind: LayerKind) {
+        if let Some(anim) = self.polygons.animations.get_mut(&self.animation) {
+            for frame in anim.frames.values_mut() {
+                if let Some(layer) = frame.get_mut(name) {
+                    layer.kind = kind;
+                }
+            }
+        }
+    }
+
+    /// Copy current layer polygon to the next frame, and select that frame
+    pub fn copy_to_next_frame(&mut self) {
+        if self.frame + 1 >= self.frames() {
+            return;
+        }
+
+        let layer = self.layer.clone();
+        let polygon = self.layer_mut(self.frame, &layer).polygon.clone();
+        self.frame += 1;
+        self.layer_mut(self.frame, &layer).polygon = polygon;
+    }
+
+    /// Replace current layer polygon with the traced alpha outline of the current frame
+    pub fn trace_frame(&mut self) -> bool {
+        let sprite = self.sprites[self.frame];
+        let traced = self.image.as_ref().and_then(|img| trace::trace_sprite(img, sprite.x, sprite.y, sprite.w, sprite.h, self.alpha_threshold, self.trace_epsilon));
+
+        match traced {
+            Some(polygon) => {
+                let layer = self.layer.clone();
+                self.layer_mut(self.frame, &layer).polygon = polygon;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Remove empty polygons and save the sidecar
+    pub fn save(&mut self) -> Result<(), failure::Error> {
+        if let Some(anim) = self.polygons.animations.get_mut(&self.animation) {
+            for frame in anim.frames.values_mut() {
+                frame.retain(|_, layer| layer.polygon.vertices.len() > 2);
+            }
+            anim.frames.retain(|_, layers| !layers.is_empty());
+        }
+
+        self.polygons.save(&self.sheet_json_path)
+    }
+}
+
+
+impl Ui {
+
+    /// Editor for sheet collision polygons. Returns true when the polygons were saved
+    pub fn sheet_polygon_editor(&mut self, editor: &mut SheetPolygonEditor) -> bool {
+        let mut saved = false;
+
+        if editor.frames() == 0 {
+            self.body_text("Sheet has no frames");
+            return false;
+        }
+
+        editor.frame = usize::min(editor.frame, editor.frames() - 1);
+
+        // frame selection
+        for i in 0..editor.frames() {
+            if editor.frame == i {
+                self.body_text(&format!("Frame {i}"));
+            } else if self.button(&format!("Frame {i}")) {
+                editor.frame = i;
+            }
+        }
+
+        self.newline();
+        self.label("Scale");
+        self.slider(&mut editor.scale, 1.0, 30.0);
+        self.newline();
+
+        // layers
+        let mut names = editor.layer_names();
+        if !names.contains(&editor.layer) {
+            names.push(editor.layer.clone());
+        }
+
+        for name in &names {
+            if name == &editor.layer {
+                self.body_text(name);
+            } else if self.button(name) {
+                editor.layer = name.clone();
+            }
+        }
+
+        self.textbox(&mut editor.new_layer);
+        if self.button("Add layer") && !editor.new_layer.is_empty() {
+            editor.layer = std::mem::take(&mut editor.new_layer);
+        }
+
+        self.newline();
+
+        let kind = editor.polygons.layer_kind(&editor.animation, &editor.layer);
+        for (text, k) in [("Hitbox", LayerKind::Hitbox), ("Hurtbox", LayerKind::Hurtbox), ("Other", LayerKind::Other)] {
+            if kind == k {
+                self.body_text(text);
+            } else if self.button(text) {
+                let layer = editor.layer.clone();
+                editor.layer_mut(editor.frame, &layer);
+                editor.set_layer_kind(&layer, k);
+            }
+        }
+
+        self.newline();
+
+        if self.button("Copy to next frame") {
+            editor.copy_to_next_frame();
+        }
+
+        if self.button("Copy") {
+            let layer = editor.layer.clone();
+            editor.copy = Some(editor.layer_mut(editor.frame, &layer).polygon.clone());
+        }
+
+        if self.button("Paste") {
+            if let Some(p) = editor.copy.clone() {
+                let layer = editor.layer.clone();
+                editor.layer_mut(editor.frame, &layer).polygon = p;
+            }
+        }
+
+        if self.button("Trace") && !editor.trace_frame() {
+            editor.status = "Nothing to trace".to_string();
+        }
+
+        if self.button("Reset") {
+            let layer = editor.layer.clone();
+            editor.layer_mut(editor.frame, &layer).polygon = Polygon::default();
+        }
+
+        if self.button("Save") {
+            match editor.save() {
+                Ok(()) => {
+                    editor.status = format!("Saved {:?}", editor.sheet_json_path);
+                    saved = true;
+                },
+                Err(err) => {
+                    editor.status = format!("Failed to save: {}", err);
+                }
+            }
+        }
+
+        if !editor.status.is_empty() {
+            self.newline();
+            let status = editor.status.clone();
+            self.small_text(&status);
+        }
+
+        // sprite with polygon on top
+        let sprite = editor.sprites[editor.frame];
+        let sub_sprite = SheetSubSprite {
+            sheet_size: editor.sheet_size,
+            pixel_l: sprite.x,
+            pixel_r: sprite.x + sprite.w,
+            pixel_b: sprite.y,
+            pixel_t: sprite.y + sprite.h,
+            flip_y: false
+        };
+
+        let size = V2::new(sprite.w as f32, sprite.h as f32) * editor.scale;
+        self.drawer2D.render_sprite_sheet_frame(editor.texture_id, editor.anchor.x, editor.anchor.y, size, &sub_sprite);
+
+        self.drag_point(&mut editor.anchor, 10.0);
+
+        editor.options.transform.translation = V2::new(editor.anchor.x as f32, editor.anchor.y as f32);
+        editor.options.transform.scale = editor.scale;
+
+        let layer = editor.layer.clone();
+        let frame = editor.frame;
+
+        let mut polygon = editor.layer_mut(frame, &layer).polygon.clone();
+        self.polygon_editor(&mut polygon, &mut editor.options);
+
+        // show the convex parts used for collision
+        let transform = PolygonTransform {
+            translation: editor.options.transform.translation,
+            scale: editor.scale,
+            ..Default::default()
+        };
+
+        if polygon.vertices.len() > 3 {
+            for part in trace::convex_decomposition(&polygon) {
+                self.view_polygon(&part, &transform);
+            }
+        }
+
+        editor.layer_mut(frame, &layer).polygon = polygon;
+
+        saved
+    }
+}
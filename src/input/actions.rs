@@ -0,0 +1,628 @@
+//! Named input actions bound to keys, mouse and game controllers. Feed every sdl event to `update_events`,
+//! call `frame_start` before the events of a frame, and read actions by name.
+//!
+//! ```ignore
+//! let mut actions = InputActions::load(&"assets/bindings.toml")?;
+//! // in frame
+//! actions.frame_start();
+//! for event in &events { actions.update_events(event); }
+//! if actions.pressed("jump") { ... }
+//! let move_dir = actions.axis2d("move");
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::convert::TryFrom;
+use serde::{Serialize, Deserialize};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::controller::{Axis, Button, GameController};
+use crate::typedef::*;
+use crate::input::keycodes::{key_name, key_from_name};
+
+
+/// Value an action needs to count as held
+pub const PRESS_THRESHOLD: f32 = 0.5;
+
+pub const DEFAULT_DEAD_ZONE: f32 = 0.15;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionKind {
+    Button,
+    Axis1d,
+    Axis2d,
+}
+
+
+/// Physical input. Stored as text in bindings files, fx "Key:Space", "Mouse:Left", "Pad:A" and "PadAxis:LeftX"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Input {
+    Key(Keycode),
+    Mouse(MouseButton),
+    /// Relative mouse motion in pixels this frame
+    MouseX,
+    MouseY,
+    /// Wheel steps this frame
+    MouseWheel,
+    PadButton(Button),
+    /// In \[-1.0; 1.0\], triggers in \[0.0; 1.0\]
+    PadAxis(Axis),
+}
+
+impl Input {
+
+    /// Mouse motion and wheel are not in \[-1.0; 1.0\] and are not clamped or dead zoned
+    pub fn is_relative(&self) -> bool {
+        matches!(self, Input::MouseX | Input::MouseY | Input::MouseWheel)
+    }
+}
+
+
+pub(crate) const PAD_BUTTONS: [Button; 15] = [
+    Button::A, Button::B, Button::X, Button::Y, Button::Back, Button::Guide, Button::Start, Button::LeftStick,
+    Button::RightStick, Button::LeftShoulder, Button::RightShoulder, Button::DPadUp, Button::DPadDown,
+    Button::DPadLeft, Button::DPadRight
+];
+
+pub(crate) const PAD_AXES: [Axis; 6] = [Axis::LeftX, Axis::LeftY, Axis::RightX, Axis::RightY, Axis::TriggerLeft, Axis::TriggerRight];
+
+pub(crate) const MOUSE_BUTTONS: [MouseButton; 5] = [MouseButton::Left, MouseButton::Middle, MouseButton::Right, MouseButton::X1, MouseButton::X2];
+
+
+pub(crate) fn find_by_name<T: std::fmt::Debug + Copy>(values: &[T], name: &str) -> Option<T> {
+    values.iter().copied().find(|v| format!("{:?}", v) == name)
+}
+
+
+impl From<Input> for String {
+    fn from(input: Input) -> String {
+        match input {
+            Input::Key(kc) => format!("Key:{}", key_name(kc)),
+            Input::Mouse(b) => format!("Mouse:{:?}", b),
+            Input::MouseX => "MouseX".to_string(),
+            Input::MouseY => "MouseY".to_string(),
+            Input::MouseWheel => "MouseWheel".to_string(),
+            Input::PadButton(b) => format!("Pad:{:?}", b),
+            Input::PadAxis(a) => format!("PadAxis:{:?}", a),
+        }
+    }
+}
+
+impl TryFrom<String> for Input {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (device, name) = s.split_once(':').unwrap_or((&s, ""));
+
+        let res = match device {
+            "Key" => key_from_name(name).map(Input::Key),
+            "Mouse" => find_by_name(&MOUSE_BUTTONS, name).map(Input::Mouse),
+            "MouseX" => Some(Input::MouseX),
+            "MouseY" => Some(Input::MouseY),
+            "MouseWheel" => Some(Input::MouseWheel),
+            "Pad" => find_by_name(&PAD_BUTTONS, name).map(Input::PadButton),
+            "PadAxis" => find_by_name(&PAD_AXES, name).map(Input::PadAxis),
+            _ => None
+        };
+
+        res.ok_or_else(|| format!("Unknown input '{}'", s))
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AxisTarget {
+    #[default]
+    X,
+    Y,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ActionBinding {
+    pub input: Input,
+    /// Which axis of the action the input adds to. Buttons and 1D axes only use X
+    #[serde(default)]
+    pub axis: AxisTarget,
+    /// Input value is multiplied by this, fx -1.0 for the key moving left
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl ActionBinding {
+
+    pub fn new(input: Input) -> Self {
+        Self { input, axis: AxisTarget::X, scale: 1.0 }
+    }
+
+    pub fn axis(input: Input, axis: AxisTarget, scale: f32) -> Self {
+        Self { input, axis, scale }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub kind: ActionKind,
+    /// Controller axis values smaller than this are 0, the rest is rescaled to start from 0. 2D actions use
+    /// the length of the vector
+    #[serde(default = "default_dead_zone")]
+    pub dead_zone: f32,
+    #[serde(default)]
+    pub bindings: Vec::<ActionBinding>,
+
+    #[serde(skip)]
+    was_down: bool,
+}
+
+fn default_dead_zone() -> f32 {
+    DEFAULT_DEAD_ZONE
+}
+
+impl Action {
+
+    pub fn new(name: &str, kind: ActionKind, bindings: Vec::<ActionBinding>) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            dead_zone: DEFAULT_DEAD_ZONE,
+            bindings,
+            was_down: false,
+        }
+    }
+}
+
+
+/// Raw state of every device, from events
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub keys: HashSet::<Keycode>,
+    pub mouse: HashSet::<MouseButton>,
+    pub pad_buttons: HashSet::<Button>,
+    pub pad_axes: HashMap::<Axis, f32>,
+    pub mouse_motion: V2,
+    pub mouse_wheel: f32,
+}
+
+impl DeviceState {
+
+    pub fn value(&self, input: Input) -> f32 {
+        let digital = |b: bool| if b { 1.0 } else { 0.0 };
+        match input {
+            Input::Key(kc) => digital(self.keys.contains(&kc)),
+            Input::Mouse(b) => digital(self.mouse.contains(&b)),
+            Input::MouseX => self.mouse_motion.x,
+            Input::MouseY => self.mouse_motion.y,
+            Input::MouseWheel => self.mouse_wheel,
+            Input::PadButton(b) => digital(self.pad_buttons.contains(&b)),
+            Input::PadAxis(a) => self.pad_axes.get(&a).copied().unwrap_or(0.0),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rebind {
+    action: usize,
+    /// None adds a new binding
+    binding: Option<usize>,
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputActions {
+    pub actions: Vec::<Action>,
+
+    #[serde(skip)]
+    pub state: DeviceState,
+
+    #[serde(skip)]
+    rebind: Option<Rebind>,
+}
+
+
+impl InputActions {
+
+    pub fn new(actions: Vec::<Action>) -> Self {
+        Self { actions, ..Default::default() }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, failure::Error> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, failure::Error> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, failure::Error> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<(), failure::Error> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    pub fn action_mut(&mut self, name: &str) -> Option<&mut Action> {
+        self.actions.iter_mut().find(|a| a.name == name)
+    }
+
+    /// Call before the events of a frame. Remembers held actions for `pressed` and `released` and resets relative mouse input
+    pub fn frame_start(&mut self) {
+        for i in 0..self.actions.len() {
+            self.actions[i].was_down = self.is_down(&self.actions[i]);
+        }
+
+        self.state.mouse_motion = V2::new(0.0, 0.0);
+        self.state.mouse_wheel = 0.0;
+    }
+
+    /// Returns true when the event was used to rebind, and should not be used as game input
+    pub fn update_events(&mut self, event: &Event) -> bool {
+        if let Some(rebind) = self.rebind {
+            if let Some(input) = rebind_input(event) {
+                self.apply_rebind(rebind, input);
+                return true;
+            }
+        }
+
+        let state = &mut self.state;
+        match event {
+            Event::KeyDown { keycode: Some(kc), .. } => { state.keys.insert(*kc); },
+            Event::KeyUp { keycode: Some(kc), .. } => { state.keys.remove(kc); },
+            Event::MouseButtonDown { mouse_btn, .. } => { state.mouse.insert(*mouse_btn); },
+            Event::MouseButtonUp { mouse_btn, .. } => { state.mouse.remove(mouse_btn); },
+            Event::MouseMotion { xrel, yrel, .. } => {
+                state.mouse_motion += V2::new(*xrel as f32, *yrel as f32);
+            },
+            Event::MouseWheel { y, .. } => { state.mouse_wheel += *y as f32; },
+            Event::ControllerButtonDown { button, .. } => { state.pad_buttons.insert(*button); },
+            Event::ControllerButtonUp { button, .. } => { state.pad_buttons.remove(button); },
+            Event::ControllerAxisMotion { axis, value, .. } => {
+                state.pad_axes.insert(*axis, (*value as f32 / i16::MAX as f32).clamp(-1.0, 1.0));
+            },
+            // a removed pad can not send button up
+            Event::ControllerDeviceRemoved { .. } => {
+                state.pad_buttons.clear();
+                state.pad_axes.clear();
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    /// Value of action, dead zone applied. Buttons and 1D axes only use x
+    pub fn value(&self, name: &str) -> V2 {
+        self.action(name).map(|a| self.action_value(a)).unwrap_or_else(|| V2::new(0.0, 0.0))
+    }
+
+    pub fn axis1d(&self, name: &str) -> f32 {
+        self.value(name).x
+    }
+
+    pub fn axis2d(&self, name: &str) -> V2 {
+        self.value(name)
+    }
+
+    /// Held this frame
+    pub fn down(&self, name: &str) -> bool {
+        self.action(name).map(|a| self.is_down(a)).unwrap_or(false)
+    }
+
+    /// Held this frame, but not last frame
+    pub fn pressed(&self, name: &str) -> bool {
+        self.action(name).map(|a| !a.was_down && self.is_down(a)).unwrap_or(false)
+    }
+
+    /// Held last frame, but not this frame
+    pub fn released(&self, name: &str) -> bool {
+        self.action(name).map(|a| a.was_down && !self.is_down(a)).unwrap_or(false)
+    }
+
+    fn is_down(&self, action: &Action) -> bool {
+        self.action_value(action).magnitude() >= PRESS_THRESHOLD
+    }
+
+    fn action_value(&self, action: &Action) -> V2 {
+        let mut value = V2::new(0.0, 0.0);
+        let mut relative = V2::new(0.0, 0.0);
+
+        for b in &action.bindings {
+            let v = self.state.value(b.input) * b.scale;
+            let target = if b.input.is_relative() { &mut relative } else { &mut value };
+            match b.axis {
+                AxisTarget::X => target.x += v,
+                AxisTarget::Y => target.y += v,
+            }
+        }
+
+        let value = match action.kind {
+            ActionKind::Button | ActionKind::Axis1d => V2::new(dead_zone(value.x.clamp(-1.0, 1.0), action.dead_zone), 0.0),
+            ActionKind::Axis2d => {
+                // clamp length so diagonals are not faster
+                let len = value.magnitude();
+                if len > 0.0 {
+                    value * (dead_zone(len.min(1.0), action.dead_zone) / len)
+                } else {
+                    value
+                }
+            },
+        };
+
+        match action.kind {
+            ActionKind::Axis2d => value + relative,
+            _ => value + V2::new(relative.x, 0.0),
+        }
+    }
+
+    /// Replace a binding of action with the next key, mouse button or controller input
+    pub fn rebind(&mut self, action: &str, binding: usize) {
+        if let Some(i) = self.actions.iter().position(|a| a.name == action) {
+            self.rebind = Some(Rebind { action: i, binding: Some(binding) });
+        }
+    }
+
+    /// Add a binding to action from the next key, mouse button or controller input
+    pub fn rebind_add(&mut self, action: &str) {
+        if let Some(i) = self.actions.iter().position(|a| a.name == action) {
+            self.rebind = Some(Rebind { action: i, binding: None });
+        }
+    }
+
+    pub fn cancel_rebind(&mut self) {
+        self.rebind = None;
+    }
+
+    /// Name of action waiting for an input to bind
+    pub fn rebinding(&self) -> Option<&str> {
+        self.rebind.map(|r| self.actions[r.action].name.as_str())
+    }
+
+    fn apply_rebind(&mut self, rebind: Rebind, input: Input) {
+        self.rebind = None;
+        let action = &mut self.actions[rebind.action];
+
+        match rebind.binding.and_then(|i| action.bindings.get_mut(i)) {
+            // keep axis and scale, so rebinding left keeps moving left
+            Some(b) => b.input = input,
+            None => action.bindings.push(ActionBinding::new(input)),
+        }
+
+        // the rebind event is not game input, so the action keeps its state and neither presses nor releases
+        let down = self.is_down(&self.actions[rebind.action]);
+        self.actions[rebind.action].was_down = down;
+    }
+}
+
+
+fn dead_zone(v: f32, dead_zone: f32) -> f32 {
+    if v.abs() < dead_zone || dead_zone >= 1.0 {
+        return 0.0;
+    }
+
+    v.signum() * (v.abs() - dead_zone) / (1.0 - dead_zone)
+}
+
+
+// controller axes only rebind when pushed far, so noise and resting triggers are ignored
+fn rebind_input(event: &Event) -> Option<Input> {
+    match event {
+        Event::KeyDown { keycode: Some(kc), .. } => Some(Input::Key(*kc)),
+        Event::MouseButtonDown { mouse_btn, .. } => Some(Input::Mouse(*mouse_btn)),
+        Event::ControllerButtonDown { button, .. } => Some(Input::PadButton(*button)),
+        Event::ControllerAxisMotion { axis, value, .. } if value.unsigned_abs() > i16::MAX as u16 / 2 => Some(Input::PadAxis(*axis)),
+        _ => None
+    }
+}
+
+
+/// Open every connected game controller. Controllers only send events while open, so keep the result alive
+pub fn open_game_controllers(subsystem: &sdl2::GameControllerSubsystem) -> Vec::<GameController> {
+    let count = subsystem.num_joysticks().unwrap_or(0);
+    (0..count)
+        .filter(|i| subsystem.is_game_controller(*i))
+        .filter_map(|i| subsystem.open(i).ok())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Mod;
+
+    fn key(kc: Keycode, down: bool) -> Event {
+        if down {
+            Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(kc), scancode: None, keymod: Mod::NOMOD, repeat: false }
+        } else {
+            Event::KeyUp { timestamp: 0, window_id: 0, keycode: Some(kc), scancode: None, keymod: Mod::NOMOD, repeat: false }
+        }
+    }
+
+    fn pad_axis(axis: Axis, value: i16) -> Event {
+        Event::ControllerAxisMotion { timestamp: 0, which: 0, axis, value }
+    }
+
+    fn pad_button(button: Button) -> Event {
+        Event::ControllerButtonDown { timestamp: 0, which: 0, button }
+    }
+
+    fn actions() -> InputActions {
+        InputActions::new(vec![
+            Action::new("jump", ActionKind::Button, vec![
+                ActionBinding::new(Input::Key(Keycode::Space)),
+                ActionBinding::new(Input::PadButton(Button::A)),
+            ]),
+            Action::new("throttle", ActionKind::Axis1d, vec![
+                ActionBinding::new(Input::PadAxis(Axis::TriggerRight)),
+                ActionBinding::axis(Input::Key(Keycode::S), AxisTarget::X, -1.0),
+            ]),
+            Action::new("move", ActionKind::Axis2d, vec![
+                ActionBinding::axis(Input::Key(Keycode::W), AxisTarget::Y, 1.0),
+                ActionBinding::axis(Input::Key(Keycode::D), AxisTarget::X, 1.0),
+                ActionBinding::axis(Input::PadAxis(Axis::LeftX), AxisTarget::X, 1.0),
+                ActionBinding::axis(Input::PadAxis(Axis::LeftY), AxisTarget::Y, -1.0),
+            ]),
+            Action::new("look", ActionKind::Axis2d, vec![
+                ActionBinding::axis(Input::MouseX, AxisTarget::X, 1.0),
+                ActionBinding::axis(Input::MouseY, AxisTarget::Y, 1.0),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn button_states() {
+        let mut a = actions();
+
+        a.frame_start();
+        a.update_events(&key(Keycode::Space, true));
+        assert!(a.pressed("jump") && a.down("jump") && !a.released("jump"));
+
+        a.frame_start();
+        assert!(!a.pressed("jump") && a.down("jump"));
+
+        // other binding held too, still not a new press
+        a.update_events(&pad_button(Button::A));
+        a.update_events(&key(Keycode::Space, false));
+        assert!(!a.pressed("jump") && a.down("jump"));
+
+        a.frame_start();
+        a.update_events(&Event::ControllerButtonUp { timestamp: 0, which: 0, button: Button::A });
+        assert!(a.released("jump") && !a.down("jump"));
+
+        a.frame_start();
+        assert!(!a.released("jump"));
+
+        assert!(!a.down("unknown"));
+    }
+
+    #[test]
+    fn axes_and_dead_zone() {
+        let mut a = actions();
+
+        // inside dead zone
+        a.update_events(&pad_axis(Axis::LeftX, i16::MAX / 10));
+        assert_eq!(a.axis2d("move"), V2::new(0.0, 0.0));
+
+        // rescaled to start at dead zone
+        a.update_events(&pad_axis(Axis::LeftX, i16::MAX));
+        assert!((a.axis2d("move") - V2::new(1.0, 0.0)).magnitude() < 0.001);
+
+        // keys and stick added and clamped to length 1
+        a.update_events(&pad_axis(Axis::LeftX, 0));
+        a.update_events(&key(Keycode::W, true));
+        a.update_events(&key(Keycode::D, true));
+        let v = a.axis2d("move");
+        assert!((v.magnitude() - 1.0).abs() < 0.001);
+        assert!((v.x - v.y).abs() < 0.001);
+
+        // up on the stick is negative
+        a.update_events(&key(Keycode::W, false));
+        a.update_events(&key(Keycode::D, false));
+        a.update_events(&pad_axis(Axis::LeftY, i16::MIN));
+        assert!((a.axis2d("move") - V2::new(0.0, 1.0)).magnitude() < 0.001);
+
+        a.update_events(&key(Keycode::S, true));
+        assert_eq!(a.axis1d("throttle"), -1.0);
+
+        // mouse is not clamped and resets each frame
+        a.update_events(&Event::MouseMotion {
+            timestamp: 0, window_id: 0, which: 0, mousestate: sdl2::mouse::MouseState::from_sdl_state(0),
+            x: 0, y: 0, xrel: 30, yrel: -4
+        });
+        assert_eq!(a.axis2d("look"), V2::new(30.0, -4.0));
+        a.frame_start();
+        assert_eq!(a.axis2d("look"), V2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn rebind() {
+        let mut a = actions();
+
+        a.rebind("jump", 0);
+        assert_eq!(a.rebinding(), Some("jump"));
+
+        // motion does not bind, and is normal input
+        assert!(!a.update_events(&pad_axis(Axis::LeftX, 100)));
+        assert!(a.update_events(&key(Keycode::J, true)));
+        assert_eq!(a.rebinding(), None);
+        assert_eq!(a.action("jump").unwrap().bindings[0].input, Input::Key(Keycode::J));
+
+        // used for binding, so not pressed
+        assert!(!a.down("jump"));
+
+        // keeps scale when replacing
+        a.rebind("throttle", 1);
+        a.update_events(&key(Keycode::Down, true));
+        assert_eq!(a.action("throttle").unwrap().bindings[1], ActionBinding::axis(Input::Key(Keycode::Down), AxisTarget::X, -1.0));
+
+        a.rebind_add("jump");
+        a.update_events(&pad_axis(Axis::TriggerLeft, i16::MAX));
+        assert_eq!(a.action("jump").unwrap().bindings.len(), 3);
+        assert!(!a.pressed("jump"));
+    }
+
+    #[test]
+    fn rebind_frame() {
+        let mut a = actions();
+
+        a.frame_start();
+        a.rebind("jump", 0);
+        a.update_events(&key(Keycode::J, true));
+        assert!(!a.pressed("jump"));
+        assert!(!a.released("jump"));
+
+        // the old input held when rebinding is no longer bound, still no release
+        a.update_events(&key(Keycode::Space, true));
+        a.frame_start();
+        a.rebind("jump", 0);
+        a.update_events(&key(Keycode::K, true));
+        assert!(!a.pressed("jump"));
+        assert!(!a.released("jump"));
+
+        // the next press of the new input is a press
+        a.frame_start();
+        a.update_events(&key(Keycode::K, false));
+        a.frame_start();
+        a.update_events(&key(Keycode::K, true));
+        assert!(a.pressed("jump"));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let a = actions();
+        let s = a.to_toml().unwrap();
+        assert!(s.contains("Key:Space"));
+        assert!(s.contains("PadAxis:TriggerRight"));
+
+        let loaded = InputActions::from_toml(&s).unwrap();
+        assert_eq!(loaded.to_toml().unwrap(), s);
+        assert_eq!(loaded.action("move").unwrap().bindings, a.action("move").unwrap().bindings);
+
+        let minimal = r#"
+            [[actions]]
+            name = "fire"
+            kind = "Button"
+            bindings = [{ input = "Mouse:Left" }, { input = "Key:LCtrl" }]
+        "#;
+        let loaded = InputActions::from_toml(minimal).unwrap();
+        let fire = loaded.action("fire").unwrap();
+        assert_eq!(fire.dead_zone, DEFAULT_DEAD_ZONE);
+        assert_eq!(fire.bindings[1], ActionBinding::new(Input::Key(Keycode::LCtrl)));
+
+        assert!(InputActions::from_toml(&minimal.replace("LCtrl", "NotAKey")).is_err());
+    }
+}
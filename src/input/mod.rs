@@ -0,0 +1,5 @@
+pub mod actions;
+
+pub mod keycodes;
+
+pub mod recording;
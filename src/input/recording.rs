@@ -0,0 +1,329 @@
+//! Record the sdl events and dt of every frame to a file, and play them back in place of the event pump.
+//! Used for bug reports and gameplay regression tests. A hash of the game state is stored at the end of a
+//! recording, so playback can check that the game ended up in the same state.
+//!
+//! Only input, window resize and quit events are recorded. Key scancodes are not recorded and are None in playback.
+//!
+//! ```ignore
+//! ui.start_recording();
+//! // play
+//! ui.stop_recording(state_hash(&game)).unwrap().save(&"bug_123.json")?;
+//!
+//! ui.start_playback(Recording::load(&"bug_123.json")?);
+//! // run frames until ui.playback_done()
+//! assert!(ui.playback.unwrap().check(state_hash(&game)));
+//! ```
+use std::path::Path;
+use std::hash::{Hash, Hasher};
+use serde::{Serialize, Deserialize};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Mod;
+use sdl2::mouse::{MouseButton, MouseState, MouseWheelDirection};
+use crate::input::actions::{find_by_name, PAD_BUTTONS, PAD_AXES};
+use crate::input::keycodes::{key_name, key_from_name};
+
+
+/// Current version of the recording format
+pub const RECORDING_VERSION: u32 = 1;
+
+
+/// Serializable copy of the sdl events that matter for replaying a game. Keys and controller
+/// inputs are stored by name, so files can be read and edited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    KeyDown { key: Option<String>, keymod: u16, repeat: bool },
+    KeyUp { key: Option<String>, keymod: u16, repeat: bool },
+    TextInput { text: String },
+    MouseMotion { state: u32, x: i32, y: i32, xrel: i32, yrel: i32 },
+    MouseButtonDown { button: u8, clicks: u8, x: i32, y: i32 },
+    MouseButtonUp { button: u8, clicks: u8, x: i32, y: i32 },
+    MouseWheel { which: u32, x: i32, y: i32, flipped: bool },
+    ControllerAxisMotion { which: u32, axis: String, value: i16 },
+    ControllerButtonDown { which: u32, button: String },
+    ControllerButtonUp { which: u32, button: String },
+    Resized { w: i32, h: i32 },
+    Quit,
+}
+
+
+impl RecordedEvent {
+
+    /// None for events that are not recorded
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let key = |kc: &Option<sdl2::keyboard::Keycode>| kc.map(key_name);
+
+        Some(match event {
+            Event::KeyDown { keycode, keymod, repeat, .. } => RecordedEvent::KeyDown { key: key(keycode), keymod: keymod.bits(), repeat: *repeat },
+            Event::KeyUp { keycode, keymod, repeat, .. } => RecordedEvent::KeyUp { key: key(keycode), keymod: keymod.bits(), repeat: *repeat },
+            Event::TextInput { text, .. } => RecordedEvent::TextInput { text: text.clone() },
+            Event::MouseMotion { mousestate, x, y, xrel, yrel, .. } => RecordedEvent::MouseMotion {
+                state: mousestate.to_sdl_state(), x: *x, y: *y, xrel: *xrel, yrel: *yrel
+            },
+            Event::MouseButtonDown { mouse_btn, clicks, x, y, .. } => RecordedEvent::MouseButtonDown { button: *mouse_btn as u8, clicks: *clicks, x: *x, y: *y },
+            Event::MouseButtonUp { mouse_btn, clicks, x, y, .. } => RecordedEvent::MouseButtonUp { button: *mouse_btn as u8, clicks: *clicks, x: *x, y: *y },
+            Event::MouseWheel { which, x, y, direction, .. } => RecordedEvent::MouseWheel {
+                which: *which, x: *x, y: *y, flipped: *direction == MouseWheelDirection::Flipped
+            },
+            Event::ControllerAxisMotion { which, axis, value, .. } => RecordedEvent::ControllerAxisMotion { which: *which, axis: format!("{:?}", axis), value: *value },
+            Event::ControllerButtonDown { which, button, .. } => RecordedEvent::ControllerButtonDown { which: *which, button: format!("{:?}", button) },
+            Event::ControllerButtonUp { which, button, .. } => RecordedEvent::ControllerButtonUp { which: *which, button: format!("{:?}", button) },
+            Event::Window { win_event: WindowEvent::Resized(w, h), .. } => RecordedEvent::Resized { w: *w, h: *h },
+            Event::Quit { .. } => RecordedEvent::Quit,
+            _ => return None
+        })
+    }
+
+    /// None when a key or controller input name is unknown. Timestamps and window ids are 0
+    pub fn to_event(&self) -> Option<Event> {
+        let key = |name: &Option<String>| match name {
+            Some(n) => key_from_name(n).map(Some),
+            None => Some(None),
+        };
+
+        Some(match self {
+            RecordedEvent::KeyDown { key: k, keymod, repeat } => Event::KeyDown {
+                timestamp: 0, window_id: 0, keycode: key(k)?, scancode: None, keymod: Mod::from_bits_truncate(*keymod), repeat: *repeat
+            },
+            RecordedEvent::KeyUp { key: k, keymod, repeat } => Event::KeyUp {
+                timestamp: 0, window_id: 0, keycode: key(k)?, scancode: None, keymod: Mod::from_bits_truncate(*keymod), repeat: *repeat
+            },
+            RecordedEvent::TextInput { text } => Event::TextInput { timestamp: 0, window_id: 0, text: text.clone() },
+            RecordedEvent::MouseMotion { state, x, y, xrel, yrel } => Event::MouseMotion {
+                timestamp: 0, window_id: 0, which: 0, mousestate: MouseState::from_sdl_state(*state), x: *x, y: *y, xrel: *xrel, yrel: *yrel
+            },
+            RecordedEvent::MouseButtonDown { button, clicks, x, y } => Event::MouseButtonDown {
+                timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::from_ll(*button), clicks: *clicks, x: *x, y: *y
+            },
+            RecordedEvent::MouseButtonUp { button, clicks, x, y } => Event::MouseButtonUp {
+                timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::from_ll(*button), clicks: *clicks, x: *x, y: *y
+            },
+            RecordedEvent::MouseWheel { which, x, y, flipped } => Event::MouseWheel {
+                timestamp: 0, window_id: 0, which: *which, x: *x, y: *y,
+                direction: if *flipped { MouseWheelDirection::Flipped } else { MouseWheelDirection::Normal }
+            },
+            RecordedEvent::ControllerAxisMotion { which, axis, value } => Event::ControllerAxisMotion {
+                timestamp: 0, which: *which, axis: find_by_name(&PAD_AXES, axis)?, value: *value
+            },
+            RecordedEvent::ControllerButtonDown { which, button } => Event::ControllerButtonDown {
+                timestamp: 0, which: *which, button: find_by_name(&PAD_BUTTONS, button)?
+            },
+            RecordedEvent::ControllerButtonUp { which, button } => Event::ControllerButtonUp {
+                timestamp: 0, which: *which, button: find_by_name(&PAD_BUTTONS, button)?
+            },
+            RecordedEvent::Resized { w, h } => Event::Window { timestamp: 0, window_id: 0, win_event: WindowEvent::Resized(*w, *h) },
+            RecordedEvent::Quit => Event::Quit { timestamp: 0 },
+        })
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub dt: f32,
+    #[serde(default)]
+    pub events: Vec::<RecordedEvent>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub frames: Vec::<RecordedFrame>,
+    /// Hash of game state when recording stopped, see `state_hash`
+    #[serde(default)]
+    pub final_hash: Option<u64>,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            frames: vec![],
+            final_hash: None,
+        }
+    }
+}
+
+
+impl Recording {
+
+    pub fn record_frame(&mut self, dt: f32, events: &[Event]) {
+        self.frames.push(RecordedFrame {
+            dt,
+            events: events.iter().filter_map(RecordedEvent::from_event).collect(),
+        });
+    }
+
+    pub fn parse(json: &str) -> Result<Self, failure::Error> {
+        let res: Recording = serde_json::from_str(json)?;
+
+        if res.version > RECORDING_VERSION {
+            failure::bail!("Recording version {} is newer than supported version {}", res.version, RECORDING_VERSION);
+        }
+
+        Ok(res)
+    }
+
+    pub fn to_json(&self) -> Result<String, failure::Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, failure::Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<(), failure::Error> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+
+/// Feeds the frames of a recording one at a time
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub recording: Recording,
+    frame: usize,
+}
+
+impl Playback {
+
+    pub fn new(recording: Recording) -> Self {
+        Self { recording, frame: 0 }
+    }
+
+    /// dt and events of the next frame, None when all frames are played
+    pub fn next_frame(&mut self) -> Option<(f32, Vec::<Event>)> {
+        let frame = self.recording.frames.get(self.frame)?;
+        self.frame += 1;
+        Some((frame.dt, frame.events.iter().filter_map(|e| e.to_event()).collect()))
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    /// True when the recording has a final hash and it matches hash. Call when done
+    pub fn check(&self, hash: u64) -> bool {
+        self.recording.final_hash == Some(hash)
+    }
+}
+
+
+/// Hash of game state to compare a playback with its recording. Floats are not Hash, use `f32::to_bits` for them.
+/// Uses FNV-1a and not `DefaultHasher`, whose algorithm can change between Rust versions, so hashes saved in
+/// recordings keep matching
+pub fn state_hash<T: Hash + ?Sized>(state: &T) -> u64 {
+    let mut hasher = Fnv1a::default();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+
+/// 64 bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Keycode;
+    use sdl2::controller::{Axis, Button};
+
+    fn events() -> Vec::<Event> {
+        vec![
+            Event::KeyDown { timestamp: 12, window_id: 1, keycode: Some(Keycode::A), scancode: None, keymod: Mod::LSHIFTMOD, repeat: false },
+            Event::KeyUp { timestamp: 13, window_id: 1, keycode: Some(Keycode::Escape), scancode: None, keymod: Mod::NOMOD, repeat: true },
+            Event::TextInput { timestamp: 0, window_id: 0, text: "a".to_string() },
+            Event::MouseMotion { timestamp: 0, window_id: 0, which: 0, mousestate: MouseState::from_sdl_state(1), x: 10, y: 20, xrel: -1, yrel: 2 },
+            Event::MouseButtonDown { timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::Right, clicks: 2, x: 5, y: 6 },
+            Event::MouseWheel { timestamp: 0, window_id: 0, which: 0, x: 0, y: -1, direction: MouseWheelDirection::Normal },
+            Event::ControllerAxisMotion { timestamp: 0, which: 1, axis: Axis::TriggerLeft, value: 1234 },
+            Event::ControllerButtonUp { timestamp: 0, which: 1, button: Button::DPadLeft },
+            Event::Window { timestamp: 0, window_id: 0, win_event: WindowEvent::Resized(800, 600) },
+            Event::Quit { timestamp: 0 },
+        ]
+    }
+
+    // timestamps and window ids are not recorded
+    fn without_ids(e: &Event) -> String {
+        format!("{:?}", e).replace("timestamp: 12", "timestamp: 0").replace("timestamp: 13", "timestamp: 0").replace("window_id: 1", "window_id: 0")
+    }
+
+    #[test]
+    fn event_round_trip() {
+        for e in events() {
+            let recorded = RecordedEvent::from_event(&e).unwrap();
+            assert_eq!(without_ids(&recorded.to_event().unwrap()), without_ids(&e));
+        }
+
+        // not recorded
+        assert!(RecordedEvent::from_event(&Event::Window { timestamp: 0, window_id: 0, win_event: WindowEvent::Shown }).is_none());
+        assert!(RecordedEvent::ControllerButtonDown { which: 0, button: "Nope".to_string() }.to_event().is_none());
+    }
+
+    #[test]
+    fn fixed_hash() {
+        // reference values of FNV-1a, so saved hashes do not change
+        let mut h = Fnv1a::default();
+        assert_eq!(h.finish(), 0xcbf29ce484222325);
+        h.write(b"a");
+        assert_eq!(h.finish(), 0xaf63dc4c8601ec8c);
+
+        assert_eq!(state_hash(&0u8), 0xaf63bd4c8601b7df);
+    }
+
+    #[test]
+    fn record_and_play() {
+        let mut rec = Recording::default();
+        rec.record_frame(0.016, &events()[0..2]);
+        rec.record_frame(0.5, &[]);
+        rec.record_frame(0.02, &events()[4..5]);
+        rec.final_hash = Some(state_hash(&(3, "end")));
+
+        let json = rec.to_json().unwrap();
+        let loaded = Recording::parse(&json).unwrap();
+        assert_eq!(loaded, rec);
+
+        let mut playback = Playback::new(loaded);
+        let (dt, events) = playback.next_frame().unwrap();
+        assert_eq!(dt, 0.016);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], Event::KeyDown { keycode: Some(Keycode::A), .. }));
+
+        assert_eq!(playback.next_frame().unwrap(), (0.5, vec![]));
+        assert!(!playback.is_done());
+        playback.next_frame().unwrap();
+        assert!(playback.is_done());
+        assert!(playback.next_frame().is_none());
+
+        assert!(playback.check(state_hash(&(3, "end"))));
+        assert!(!playback.check(state_hash(&(4, "end"))));
+
+        let newer = format!(r#"{{ "version": {}, "frames": [] }}"#, RECORDING_VERSION + 1);
+        assert!(Recording::parse(&newer).is_err());
+    }
+}
@@ -50,10 +50,22 @@ pub mod particle_system;
 
 pub mod general_animation;
 
+pub mod tween;
+
 pub mod audio;
 
 pub mod goap;
 
+pub mod ecs;
+
+pub mod game_loop;
+
+pub mod input;
+
+pub mod tilemap;
+
+pub mod navigation;
+
 /// Defines point in ScreenBox x,y in \[0.0; 1.0\]
 /// Top left corner is x=0, y=0
 #[derive(Debug, Copy, Clone)]
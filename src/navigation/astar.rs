@@ -0,0 +1,190 @@
+//! A* over anything that implements `Graph`. `NavGrid`, `NavMesh` and `NodeGraph` implement it.
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+use core::cmp::Ordering;
+use crate::typedef::*;
+
+
+pub trait Graph {
+    type Node: Copy + Eq + Hash;
+
+    /// Push neighbours of node with the cost of moving there
+    fn neighbours(&self, node: Self::Node, out: &mut Vec::<(Self::Node, f32)>);
+
+    /// Estimated cost from node to goal. Must not be more than the real cost for paths to be shortest
+    fn heuristic(&self, node: Self::Node, goal: Self::Node) -> f32;
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path<N> {
+    /// Start to goal, both included
+    pub nodes: Vec::<N>,
+    pub cost: f32,
+}
+
+
+struct Open<N> {
+    f: f32,
+    g: f32,
+    node: N,
+}
+
+impl<N> PartialEq for Open<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.f == other.f
+    }
+}
+
+impl<N> Eq for Open<N> {}
+
+impl<N> PartialOrd for Open<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N> Ord for Open<N> {
+    // lowest f first, ties to the one closest to the goal
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.total_cmp(&self.f).then(self.g.total_cmp(&other.g))
+    }
+}
+
+
+/// Cheapest path from start to goal, None when goal can not be reached
+pub fn astar<G: Graph>(graph: &G, start: G::Node, goal: G::Node) -> Option<Path<G::Node>> {
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::<G::Node, G::Node>::default();
+    let mut g_score = HashMap::<G::Node, f32>::default();
+    let mut neighbours = vec![];
+
+    g_score.insert(start, 0.0);
+    open.push(Open { f: graph.heuristic(start, goal), g: 0.0, node: start });
+
+    while let Some(Open { g, node, .. }) = open.pop() {
+        if node == goal {
+            let mut nodes = vec![node];
+            let mut current = node;
+            while let Some(prev) = came_from.get(&current) {
+                nodes.push(*prev);
+                current = *prev;
+            }
+            nodes.reverse();
+            return Some(Path { nodes, cost: g });
+        }
+
+        // already found a cheaper way here
+        if g > *g_score.get(&node).unwrap_or(&f32::INFINITY) {
+            continue;
+        }
+
+        neighbours.clear();
+        graph.neighbours(node, &mut neighbours);
+
+        for &(next, cost) in &neighbours {
+            let new_g = g + cost;
+            if new_g < *g_score.get(&next).unwrap_or(&f32::INFINITY) {
+                g_score.insert(next, new_g);
+                came_from.insert(next, node);
+                open.push(Open { f: new_g + graph.heuristic(next, goal), g: new_g, node: next });
+            }
+        }
+    }
+
+    None
+}
+
+
+/// Graph of points with explicit edges, fx waypoints placed in a level
+#[derive(Debug, Clone, Default)]
+pub struct NodeGraph {
+    pub positions: Vec::<V3>,
+    /// Per node, neighbour and cost
+    pub edges: Vec::<Vec::<(usize, f32)>>,
+}
+
+impl NodeGraph {
+
+    pub fn add_node(&mut self, pos: V3) -> usize {
+        self.positions.push(pos);
+        self.edges.push(vec![]);
+        self.positions.len() - 1
+    }
+
+    /// One way edge
+    pub fn add_edge(&mut self, from: usize, to: usize, cost: f32) {
+        self.edges[from].push((to, cost));
+    }
+
+    /// Two way edge with the distance as cost
+    pub fn connect(&mut self, a: usize, b: usize) {
+        let dist = (self.positions[a] - self.positions[b]).magnitude();
+        self.add_edge(a, b, dist);
+        self.add_edge(b, a, dist);
+    }
+
+    /// Node closest to pos
+    pub fn closest(&self, pos: V3) -> Option<usize> {
+        (0..self.positions.len()).min_by(|a, b| {
+            (self.positions[*a] - pos).magnitude_squared().total_cmp(&(self.positions[*b] - pos).magnitude_squared())
+        })
+    }
+
+    pub fn find_path(&self, start: usize, goal: usize) -> Option<Vec::<V3>> {
+        astar(self, start, goal).map(|p| p.nodes.iter().map(|n| self.positions[*n]).collect())
+    }
+}
+
+impl Graph for NodeGraph {
+    type Node = usize;
+
+    fn neighbours(&self, node: usize, out: &mut Vec::<(usize, f32)>) {
+        out.extend_from_slice(&self.edges[node]);
+    }
+
+    /// Straight line distance, so edge costs should be at least the distance
+    fn heuristic(&self, node: usize, goal: usize) -> f32 {
+        (self.positions[node] - self.positions[goal]).magnitude()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_graph() {
+        let mut g = NodeGraph::default();
+        let a = g.add_node(V3::new(0.0, 0.0, 0.0));
+        let b = g.add_node(V3::new(10.0, 0.0, 0.0));
+        let c = g.add_node(V3::new(5.0, 5.0, 0.0));
+        let d = g.add_node(V3::new(10.0, 10.0, 0.0));
+        let lonely = g.add_node(V3::new(50.0, 50.0, 0.0));
+
+        g.connect(a, b);
+        g.connect(a, c);
+        g.connect(c, d);
+        g.connect(b, d);
+
+        let path = astar(&g, a, d).unwrap();
+        assert_eq!(path.nodes, vec![a, c, d]);
+        assert!((path.cost - 2.0 * 50.0_f32.sqrt()).abs() < 0.001);
+
+        // expensive edge is avoided even when shorter
+        g.edges[c].retain(|e| e.0 != d);
+        g.add_edge(c, d, 100.0);
+        assert_eq!(astar(&g, a, d).unwrap().nodes, vec![a, b, d]);
+
+        assert_eq!(astar(&g, a, a).unwrap().nodes, vec![a]);
+        assert!(astar(&g, a, lonely).is_none());
+
+        // one way
+        g.add_edge(lonely, a, 100.0);
+        assert!(astar(&g, a, lonely).is_none());
+        assert_eq!(g.find_path(lonely, b).unwrap().len(), 3);
+
+        assert_eq!(g.closest(V3::new(9.0, 9.0, 0.0)), Some(d));
+    }
+}
@@ -0,0 +1,327 @@
+//! Weighted grid for A*, fx from a tile map. Cells are (x, y) with y down like tile maps.
+use crate::tilemap::TileMap;
+use super::astar::{self, Graph};
+
+pub type Cell = (i32, i32);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    /// Diagonal moves cost sqrt 2 and can not cut corners of blocked cells
+    Eight,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct NavGrid {
+    pub width: i32,
+    pub height: i32,
+    pub connectivity: Connectivity,
+    /// Cost multiplier of entering each cell, None is blocked. Should be at least 1 for shortest paths
+    costs: Vec::<Option<f32>>,
+}
+
+impl NavGrid {
+
+    /// All cells walkable with cost 1
+    pub fn new(width: i32, height: i32, connectivity: Connectivity) -> Self {
+        Self { width, height, connectivity, costs: vec![Some(1.0); (width.max(0) * height.max(0)) as usize] }
+    }
+
+    /// Cells with solid tiles in any visible tile layer are blocked. Tiles with a number property "cost" set
+    /// their cell cost, fx for mud or roads
+    pub fn from_tilemap(map: &TileMap, connectivity: Connectivity) -> Self {
+        let mut grid = Self::new(map.width as i32, map.height as i32, connectivity);
+
+        for (layer_idx, layer) in map.layers.iter().enumerate() {
+            if !layer.visible || layer.gids().is_none() {
+                continue;
+            }
+
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    let Some(data) = map.tile(layer_idx, x, y).and_then(|t| map.tile_data(&t)) else { continue; };
+                    let cell = (x as i32, y as i32);
+
+                    if data.is_solid() {
+                        grid.set_blocked(cell);
+                    } else if let Some(cost) = data.properties.get("cost").and_then(|c| c.as_f64()) {
+                        if grid.is_walkable(cell) {
+                            grid.set_cost(cell, cost as f32);
+                        }
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    pub fn in_bounds(&self, (x, y): Cell) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn index(&self, (x, y): Cell) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn cost(&self, cell: Cell) -> Option<f32> {
+        if !self.in_bounds(cell) {
+            return None;
+        }
+
+        self.costs[self.index(cell)]
+    }
+
+    pub fn set_cost(&mut self, cell: Cell, cost: f32) {
+        if self.in_bounds(cell) {
+            let i = self.index(cell);
+            self.costs[i] = Some(cost);
+        }
+    }
+
+    pub fn set_blocked(&mut self, cell: Cell) {
+        if self.in_bounds(cell) {
+            let i = self.index(cell);
+            self.costs[i] = None;
+        }
+    }
+
+    pub fn is_walkable(&self, cell: Cell) -> bool {
+        self.cost(cell).is_some()
+    }
+
+    pub fn find_path(&self, start: Cell, goal: Cell) -> Option<Vec::<Cell>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        astar::astar(self, start, goal).map(|p| p.nodes)
+    }
+
+    /// True when every cell the line between the cell centers touches is walkable
+    pub fn line_of_sight(&self, a: Cell, b: Cell) -> bool {
+        // walk the cells the line crosses, both side cells when it passes exactly through a corner
+        let (mut x, mut y) = a;
+        let nx = (b.0 - a.0).abs();
+        let ny = (b.1 - a.1).abs();
+        let sx = (b.0 - a.0).signum();
+        let sy = (b.1 - a.1).signum();
+
+        if !self.is_walkable(a) {
+            return false;
+        }
+
+        let (mut ix, mut iy) = (0, 0);
+        while ix < nx || iy < ny {
+            // which cell border the line crosses first, compared in half cells
+            let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+            if decision == 0 {
+                if !self.is_walkable((x + sx, y)) || !self.is_walkable((x, y + sy)) {
+                    return false;
+                }
+                x += sx;
+                y += sy;
+                ix += 1;
+                iy += 1;
+            } else if decision < 0 {
+                x += sx;
+                ix += 1;
+            } else {
+                y += sy;
+                iy += 1;
+            }
+
+            if !self.is_walkable((x, y)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Remove cells that can be skipped by walking straight, keeps start and end. Only looks at walkability,
+    /// so cell costs are not considered
+    pub fn smooth_path(&self, path: &[Cell]) -> Vec::<Cell> {
+        string_pull(path, |a, b| self.line_of_sight(a, b))
+    }
+}
+
+
+/// Greedy string pulling, from each kept point go to the furthest later point that is visible
+pub fn string_pull<T: Copy, F: Fn(T, T) -> bool>(path: &[T], visible: F) -> Vec::<T> {
+    if path.len() <= 2 {
+        return path.to_vec();
+    }
+
+    let mut res = vec![path[0]];
+    let mut anchor = 0;
+
+    while anchor < path.len() - 1 {
+        let mut next = anchor + 1;
+        for i in (anchor + 2..path.len()).rev() {
+            if visible(path[anchor], path[i]) {
+                next = i;
+                break;
+            }
+        }
+
+        res.push(path[next]);
+        anchor = next;
+    }
+
+    res
+}
+
+
+impl Graph for NavGrid {
+    type Node = Cell;
+
+    fn neighbours(&self, (x, y): Cell, out: &mut Vec::<(Cell, f32)>) {
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            if let Some(c) = self.cost((x + dx, y + dy)) {
+                out.push(((x + dx, y + dy), c));
+            }
+        }
+
+        if self.connectivity == Connectivity::Eight {
+            for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                if !self.is_walkable((x + dx, y)) || !self.is_walkable((x, y + dy)) {
+                    continue;
+                }
+
+                if let Some(c) = self.cost((x + dx, y + dy)) {
+                    out.push(((x + dx, y + dy), c * std::f32::consts::SQRT_2));
+                }
+            }
+        }
+    }
+
+    fn heuristic(&self, (x, y): Cell, (gx, gy): Cell) -> f32 {
+        let dx = (gx - x).abs() as f32;
+        let dy = (gy - y).abs() as f32;
+
+        match self.connectivity {
+            Connectivity::Four => dx + dy,
+            // octile distance
+            Connectivity::Eight => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // # is blocked, ~ costs 5
+    fn grid(rows: &[&str], connectivity: Connectivity) -> NavGrid {
+        let mut g = NavGrid::new(rows[0].len() as i32, rows.len() as i32, connectivity);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => g.set_blocked((x as i32, y as i32)),
+                    '~' => g.set_cost((x as i32, y as i32), 5.0),
+                    _ => {}
+                }
+            }
+        }
+        g
+    }
+
+    #[test]
+    fn four_connected() {
+        let g = grid(&[
+            "....",
+            ".##.",
+            "....",
+        ], Connectivity::Four);
+
+        let path = g.find_path((0, 1), (3, 1)).unwrap();
+        assert_eq!(path.len(), 6);
+        assert_eq!(path[0], (0, 1));
+        assert_eq!(path[5], (3, 1));
+
+        // every step is one cell
+        for w in path.windows(2) {
+            assert_eq!((w[0].0 - w[1].0).abs() + (w[0].1 - w[1].1).abs(), 1);
+        }
+
+        assert!(g.find_path((0, 0), (1, 1)).is_none());
+        assert!(g.find_path((0, 0), (10, 1)).is_none());
+    }
+
+    #[test]
+    fn eight_connected() {
+        let g = grid(&[
+            "...",
+            ".#.",
+            "...",
+        ], Connectivity::Eight);
+
+        // can not cut the corner of the blocked cell, so around the edge
+        let path = astar::astar(&g, (0, 0), (2, 2)).unwrap();
+        assert_eq!(path.nodes.len(), 5);
+        assert_eq!(path.cost, 4.0);
+
+        let open = NavGrid::new(5, 5, Connectivity::Eight);
+        let path = astar::astar(&open, (0, 0), (4, 4)).unwrap();
+        assert_eq!(path.nodes.len(), 5);
+        assert!((path.cost - 4.0 * std::f32::consts::SQRT_2).abs() < 0.001);
+    }
+
+    #[test]
+    fn weighted() {
+        let g = grid(&[
+            ".....",
+            ".~~~.",
+            ".....",
+            ".....",
+        ], Connectivity::Four);
+
+        // straight through the mud costs 2 + 3 * 5, around costs 6
+        let path = astar::astar(&g, (0, 1), (4, 1)).unwrap();
+        assert_eq!(path.cost, 6.0);
+        assert!(path.nodes.iter().all(|c| c.1 != 1 || c.0 == 0 || c.0 == 4));
+    }
+
+    #[test]
+    fn line_of_sight_and_smoothing() {
+        let g = grid(&[
+            "......",
+            "......",
+            "...#..",
+            "......",
+        ], Connectivity::Eight);
+
+        assert!(g.line_of_sight((0, 0), (5, 0)));
+        assert!(g.line_of_sight((0, 0), (5, 1)));
+        assert!(!g.line_of_sight((0, 2), (5, 2)));
+        assert!(!g.line_of_sight((1, 0), (5, 3)));
+        // diagonal through corners touches the blocked cell
+        assert!(!g.line_of_sight((2, 1), (4, 3)));
+        assert!(g.line_of_sight((3, 3), (3, 3)));
+
+        let path = g.find_path((0, 2), (5, 2)).unwrap();
+        let smooth = g.smooth_path(&path);
+        assert_eq!(smooth[0], (0, 2));
+        assert_eq!(*smooth.last().unwrap(), (5, 2));
+        assert!(smooth.len() < path.len());
+        for w in smooth.windows(2) {
+            assert!(g.line_of_sight(w[0], w[1]));
+        }
+    }
+
+    #[test]
+    fn from_tilemap() {
+        let map = TileMap::parse(crate::tilemap::tests::MAP).unwrap();
+        let g = NavGrid::from_tilemap(&map, Connectivity::Four);
+
+        // bottom row is solid, tile 1 has a collision shape and tile 4 the solid property
+        assert!(g.is_walkable((0, 0)));
+        assert!(!g.is_walkable((0, 2)));
+        assert!(!g.is_walkable((2, 2)));
+        assert!(g.find_path((0, 0), (3, 1)).is_some());
+    }
+}
@@ -0,0 +1,20 @@
+//! Pathfinding and steering, all on the cpu. A* works on anything implementing `astar::Graph`: weighted grids
+//! (fx from a tile map), navmeshes built from walkable triangles of a gltf mesh, and plain node graphs.
+//! Steering turns the found paths into velocities.
+//! # Example
+//! ```ignore
+//! let mesh = NavMesh::from_triangles(&gltf.triangles("level"), 40.0);
+//! let path = mesh.find_path(unit.pos, target)?;
+//! let mut follower = PathFollower::new(path, 0.5);
+//!
+//! // each frame
+//! if let Some(target) = follower.update(unit.pos) {
+//!     let desired = steering::arrive(unit.pos, target, max_speed, 2.0) + steering::separation(unit.pos, &others, 1.0);
+//!     unit.vel += steering::steer(unit.vel, desired, max_force) * dt;
+//! }
+//! ```
+
+pub mod astar;
+pub mod grid;
+pub mod navmesh;
+pub mod steering;
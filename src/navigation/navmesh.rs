@@ -0,0 +1,300 @@
+//! Navmesh of walkable triangles with z as up. A* runs over triangles and the string of triangles is pulled
+//! tight with the simple stupid funnel algorithm, so paths hug corners instead of going through triangle centers.
+use std::collections::HashMap;
+use crate::typedef::*;
+use crate::collision3d::Triangle;
+use super::astar::{self, Graph};
+
+
+/// Vertices closer than this are welded together, so triangles from separate meshes connect
+const WELD_DIST: f32 = 0.001;
+
+
+#[derive(Debug, Clone, Default)]
+pub struct NavMesh {
+    pub vertices: Vec::<V3>,
+    /// Vertex indices, counter clockwise seen from above
+    pub triangles: Vec::<[usize; 3]>,
+    /// Per triangle, the triangle on the other side of edge i, which goes from vertex i to i + 1
+    pub neighbours: Vec::<[Option<usize>; 3]>,
+    pub centers: Vec::<V3>,
+}
+
+impl NavMesh {
+
+    /// Keep triangles facing up with a slope of at most max_slope_deg, fx from `GltfMeshes::triangles`.
+    /// Triangles sharing an edge are connected
+    pub fn from_triangles(triangles: &[Triangle], max_slope_deg: f32) -> Self {
+        let min_up = max_slope_deg.to_radians().cos();
+        let mut mesh = Self::default();
+        let mut welded = HashMap::<(i64, i64, i64), usize>::default();
+
+        for t in triangles {
+            if t.normal.z < min_up || t.normal.z.is_nan() {
+                continue;
+            }
+
+            // facing up means counter clockwise seen from above
+            let idx = [t.v0, t.v1, t.v2].map(|v| {
+                let key = ((v.x / WELD_DIST).round() as i64, (v.y / WELD_DIST).round() as i64, (v.z / WELD_DIST).round() as i64);
+                *welded.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(v);
+                    mesh.vertices.len() - 1
+                })
+            });
+
+            // degenerate after welding
+            if idx[0] == idx[1] || idx[1] == idx[2] || idx[2] == idx[0] {
+                continue;
+            }
+
+            let [a, b, c] = idx.map(|i| mesh.vertices[i]);
+            mesh.centers.push((a + b + c) / 3.0);
+            mesh.triangles.push(idx);
+            mesh.neighbours.push([None; 3]);
+        }
+
+        // connect triangles through shared edges
+        let mut edges = HashMap::<(usize, usize), (usize, usize)>::default();
+        for (ti, tri) in mesh.triangles.iter().enumerate() {
+            for e in 0..3 {
+                let (a, b) = (tri[e], tri[(e + 1) % 3]);
+                let key = (a.min(b), a.max(b));
+
+                match edges.get(&key) {
+                    Some(&(other, other_e)) => {
+                        mesh.neighbours[ti][e] = Some(other);
+                        mesh.neighbours[other][other_e] = Some(ti);
+                    },
+                    None => {
+                        edges.insert(key, (ti, e));
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn corner(&self, tri: usize, i: usize) -> V3 {
+        self.vertices[self.triangles[tri][i % 3]]
+    }
+
+    /// Triangle below or above pos, when several the one closest in z
+    pub fn find_triangle(&self, pos: V3) -> Option<usize> {
+        let mut best = None;
+        let mut best_dist = f32::INFINITY;
+
+        for ti in 0..self.triangles.len() {
+            let (a, b, c) = (self.corner(ti, 0), self.corner(ti, 1), self.corner(ti, 2));
+
+            let area = cross2(a, b, c);
+            let u = cross2(b, c, pos) / area;
+            let v = cross2(c, a, pos) / area;
+            let w = 1.0 - u - v;
+
+            let eps = -0.0001;
+            if u < eps || v < eps || w < eps {
+                continue;
+            }
+
+            let z = a.z * u + b.z * v + c.z * w;
+            let dist = (z - pos.z).abs();
+            if dist < best_dist {
+                best_dist = dist;
+                best = Some(ti);
+            }
+        }
+
+        best
+    }
+
+    /// Shortest path from start to goal on the mesh, start and goal included. None when either is not above or below
+    /// the mesh, or they are not connected
+    pub fn find_path(&self, start: V3, goal: V3) -> Option<Vec::<V3>> {
+        let start_tri = self.find_triangle(start)?;
+        let goal_tri = self.find_triangle(goal)?;
+
+        let corridor = astar::astar(self, start_tri, goal_tri)?.nodes;
+
+        let mut portals = vec![(start, start)];
+        for w in corridor.windows(2) {
+            let e = (0..3).find(|e| self.neighbours[w[0]][*e] == Some(w[1]))?;
+            // walking out of a counter clockwise triangle the edge end is on the left
+            portals.push((self.corner(w[0], e + 1), self.corner(w[0], e)));
+        }
+        portals.push((goal, goal));
+
+        Some(funnel(&portals))
+    }
+}
+
+impl Graph for NavMesh {
+    type Node = usize;
+
+    fn neighbours(&self, node: usize, out: &mut Vec::<(usize, f32)>) {
+        for n in self.neighbours[node].iter().flatten() {
+            out.push((*n, (self.centers[node] - self.centers[*n]).magnitude()));
+        }
+    }
+
+    fn heuristic(&self, node: usize, goal: usize) -> f32 {
+        (self.centers[node] - self.centers[goal]).magnitude()
+    }
+}
+
+
+/// Twice the signed area of a, b, c in xy. Positive when c is left of a to b
+fn cross2(a: V3, b: V3, c: V3) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+fn same(a: V3, b: V3) -> bool {
+    (a - b).magnitude_squared() < WELD_DIST * WELD_DIST
+}
+
+
+/// Simple stupid funnel over portals given as left and right points seen walking along the path.
+/// First and last portal are the start and goal as both left and right
+pub fn funnel(portals: &[(V3, V3)]) -> Vec::<V3> {
+    let Some(&(start, _)) = portals.first() else {
+        return vec![];
+    };
+
+    let mut res = vec![start];
+    let (mut apex, mut left, mut right) = (start, start, start);
+    let (mut left_i, mut right_i) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (l, r) = portals[i];
+
+        // tighten right side
+        if cross2(apex, right, r) >= 0.0 {
+            if same(apex, right) || cross2(apex, left, r) < 0.0 {
+                right = r;
+                right_i = i;
+            } else {
+                // right crossed over left, left is a corner
+                apex = left;
+                if !same(*res.last().unwrap(), apex) {
+                    res.push(apex);
+                }
+                right = apex;
+                right_i = left_i;
+                i = left_i + 1;
+                continue;
+            }
+        }
+
+        // tighten left side
+        if cross2(apex, left, l) <= 0.0 {
+            if same(apex, left) || cross2(apex, right, l) > 0.0 {
+                left = l;
+                left_i = i;
+            } else {
+                apex = right;
+                if !same(*res.last().unwrap(), apex) {
+                    res.push(apex);
+                }
+                left = apex;
+                left_i = right_i;
+                i = right_i + 1;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    let (goal, _) = portals[portals.len() - 1];
+    if !same(*res.last().unwrap(), goal) {
+        res.push(goal);
+    }
+
+    res
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // unit squares in an L, 3 along x and then 2 more up along y from the last
+    fn l_shape() -> Vec::<Triangle> {
+        let cells = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 1.0), (2.0, 2.0)];
+        let mut res = vec![];
+        for (x, y) in cells {
+            let p = |dx: f32, dy: f32| V3::new(x + dx, y + dy, 1.0);
+            res.push(Triangle::new(p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0)));
+            res.push(Triangle::new(p(0.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)));
+        }
+        res
+    }
+
+    fn flat_l() -> NavMesh {
+        NavMesh::from_triangles(&l_shape(), 45.0)
+    }
+
+    #[test]
+    fn build() {
+        let mut tris = l_shape();
+        // wall, steep ramp and a ceiling facing down are not walkable
+        tris.push(Triangle::new(V3::new(0.0, 0.0, 1.0), V3::new(1.0, 0.0, 1.0), V3::new(1.0, 0.0, 3.0)));
+        tris.push(Triangle::new(V3::new(0.0, 0.0, 1.0), V3::new(0.0, -1.0, 3.0), V3::new(1.0, 0.0, 1.0)));
+        tris.push(Triangle::new(V3::new(0.0, 0.0, 3.0), V3::new(1.0, 1.0, 3.0), V3::new(1.0, 0.0, 3.0)));
+
+        let mesh = NavMesh::from_triangles(&tris, 45.0);
+        assert_eq!(mesh.triangles.len(), 10);
+        assert_eq!(mesh.vertices.len(), 12);
+
+        // 9 inner edges, each linked both ways
+        let links : usize = mesh.neighbours.iter().map(|n| n.iter().flatten().count()).sum();
+        assert_eq!(links, 18);
+
+        assert!(mesh.find_triangle(V3::new(0.5, 0.2, 0.0)).is_some());
+        assert!(mesh.find_triangle(V3::new(0.5, 1.5, 0.0)).is_none());
+
+        // ramp is kept with a high enough max slope
+        assert_eq!(NavMesh::from_triangles(&tris, 70.0).triangles.len(), 11);
+    }
+
+    #[test]
+    fn path_around_corner() {
+        let mesh = flat_l();
+
+        let start = V3::new(0.5, 0.5, 1.0);
+        let goal = V3::new(2.5, 2.5, 1.0);
+        let path = mesh.find_path(start, goal).unwrap();
+
+        assert_eq!(path.len(), 3);
+        assert_eq!(path[0], start);
+        assert!((path[1] - V3::new(2.0, 1.0, 1.0)).magnitude() < 0.001);
+        assert_eq!(path[2], goal);
+
+        // straight when in line of sight
+        let path = mesh.find_path(start, V3::new(2.8, 0.3, 1.0)).unwrap();
+        assert_eq!(path.len(), 2);
+
+        // same triangle
+        let path = mesh.find_path(start, V3::new(0.6, 0.4, 1.0)).unwrap();
+        assert_eq!(path.len(), 2);
+
+        // back the other way
+        let path = mesh.find_path(goal, start).unwrap();
+        assert_eq!(path.len(), 3);
+        assert!((path[1] - V3::new(2.0, 1.0, 1.0)).magnitude() < 0.001);
+
+        assert!(mesh.find_path(start, V3::new(0.5, 2.5, 1.0)).is_none());
+    }
+
+    #[test]
+    fn disconnected() {
+        let mut tris = l_shape();
+        tris.push(Triangle::new(V3::new(10.0, 10.0, 0.0), V3::new(11.0, 10.0, 0.0), V3::new(10.0, 11.0, 0.0)));
+        let mesh = NavMesh::from_triangles(&tris, 45.0);
+
+        assert!(mesh.find_triangle(V3::new(10.2, 10.2, 0.0)).is_some());
+        assert!(mesh.find_path(V3::new(0.5, 0.5, 1.0), V3::new(10.2, 10.2, 0.0)).is_none());
+    }
+}
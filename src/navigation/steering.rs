@@ -0,0 +1,153 @@
+//! Steering behaviours. Behaviours return a desired velocity, sum them with weights and use `steer` to get the
+//! acceleration towards it, limited by how hard the agent can turn.
+use crate::typedef::*;
+
+
+/// Full speed towards target
+pub fn seek(pos: V3, target: V3, max_speed: f32) -> V3 {
+    let dir = target - pos;
+    let dist = dir.magnitude();
+    if dist < 0.0001 {
+        return V3::zeros();
+    }
+
+    dir * (max_speed / dist)
+}
+
+/// Full speed away from target
+pub fn flee(pos: V3, target: V3, max_speed: f32) -> V3 {
+    -seek(pos, target, max_speed)
+}
+
+/// Like seek, but slows down linearly inside slow_radius to stop at target
+pub fn arrive(pos: V3, target: V3, max_speed: f32, slow_radius: f32) -> V3 {
+    let dist = (target - pos).magnitude();
+    let speed = if dist < slow_radius { max_speed * dist / slow_radius } else { max_speed };
+
+    seek(pos, target, speed)
+}
+
+/// Push away from neighbours closer than radius, stronger the closer they are. Neighbours at pos are ignored,
+/// so the agent itself can be in the list
+pub fn separation(pos: V3, neighbours: &[V3], radius: f32) -> V3 {
+    let mut res = V3::zeros();
+
+    for n in neighbours {
+        let away = pos - n;
+        let dist = away.magnitude();
+        if dist < 0.0001 || dist >= radius {
+            continue;
+        }
+
+        res += away * ((radius - dist) / (radius * dist));
+    }
+
+    res
+}
+
+/// Acceleration to change velocity to desired, at most max_force
+pub fn steer(velocity: V3, desired: V3, max_force: f32) -> V3 {
+    let force = desired - velocity;
+    let len = force.magnitude();
+    if len > max_force {
+        return force * (max_force / len);
+    }
+
+    force
+}
+
+
+/// Walks a path one point at a time, fx from `NavMesh::find_path`
+#[derive(Debug, Clone, Default)]
+pub struct PathFollower {
+    pub path: Vec::<V3>,
+    /// Index of the point currently walked to
+    pub index: usize,
+    /// Distance where a point counts as reached
+    pub reach_radius: f32,
+}
+
+impl PathFollower {
+
+    pub fn new(path: Vec::<V3>, reach_radius: f32) -> Self {
+        Self { path, index: 0, reach_radius }
+    }
+
+    pub fn target(&self) -> Option<V3> {
+        self.path.get(self.index).copied()
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.index + 1 >= self.path.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.index >= self.path.len()
+    }
+
+    /// Advance past reached points and return the point to walk to, None when the end is reached
+    pub fn update(&mut self, pos: V3) -> Option<V3> {
+        while let Some(target) = self.target() {
+            if (target - pos).magnitude() > self.reach_radius {
+                return Some(target);
+            }
+            self.index += 1;
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: V3, b: V3) -> bool {
+        (a - b).magnitude() < 0.001
+    }
+
+    #[test]
+    fn behaviours() {
+        let zero = V3::zeros();
+        let target = V3::new(10.0, 0.0, 0.0);
+
+        assert!(close(seek(zero, target, 2.0), V3::new(2.0, 0.0, 0.0)));
+        assert!(close(seek(target, target, 2.0), zero));
+        assert!(close(flee(zero, target, 2.0), V3::new(-2.0, 0.0, 0.0)));
+
+        assert!(close(arrive(zero, target, 2.0, 5.0), V3::new(2.0, 0.0, 0.0)));
+        assert!(close(arrive(V3::new(7.5, 0.0, 0.0), target, 2.0, 5.0), V3::new(1.0, 0.0, 0.0)));
+        assert!(close(arrive(target, target, 2.0, 5.0), zero));
+
+        // self and far away are ignored, closer pushes harder
+        let sep = separation(zero, &[zero, V3::new(0.5, 0.0, 0.0), V3::new(0.0, 5.0, 0.0)], 1.0);
+        assert!(close(sep, V3::new(-0.5, 0.0, 0.0)));
+        let near = separation(zero, &[V3::new(0.1, 0.0, 0.0)], 1.0);
+        assert!(near.magnitude() > sep.magnitude());
+
+        assert!(close(steer(zero, V3::new(10.0, 0.0, 0.0), 3.0), V3::new(3.0, 0.0, 0.0)));
+        assert!(close(steer(V3::new(1.0, 0.0, 0.0), V3::new(2.0, 0.0, 0.0), 3.0), V3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn follow_path() {
+        let path = vec![V3::new(0.0, 0.0, 0.0), V3::new(5.0, 0.0, 0.0), V3::new(5.0, 5.0, 0.0)];
+        let mut follower = PathFollower::new(path, 0.5);
+
+        // start point is already reached
+        assert_eq!(follower.update(V3::zeros()), Some(V3::new(5.0, 0.0, 0.0)));
+        assert!(!follower.is_last());
+
+        let mut pos = V3::zeros();
+        let mut steps = 0;
+        while let Some(target) = follower.update(pos) {
+            pos += seek(pos, target, 0.25);
+            steps += 1;
+            assert!(steps < 100);
+        }
+
+        assert!(follower.is_done());
+        assert!((pos - V3::new(5.0, 5.0, 0.0)).magnitude() <= 0.5);
+    }
+}
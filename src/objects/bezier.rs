@@ -1,6 +1,7 @@
 use crate::buffer;
 use crate::gl;
 use crate::na;
+use std::ops::{Add, Sub, Mul};
 
 pub struct Bezier {
     vao: buffer::VertexArray,
@@ -98,3 +99,53 @@ impl Bezier {
         self.vao.unbind();
     }
 }
+
+
+impl Curve {
+
+    /// Point on the curve at t in \[0;1\]
+    pub fn point(&self, t: f32) -> na::Vector2::<f32> {
+        quadratic_point(self.p0, self.p1, self.p2, t)
+    }
+}
+
+
+/// Point at t on the quadratic bezier curve p0, p1, p2. Works for any vector type, fx 2d and 3d
+pub fn quadratic_point<T>(p0: T, p1: T, p2: T, t: f32) -> T
+where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+    let u = 1.0 - t;
+    p0 * (u * u) + p1 * (2.0 * u * t) + p2 * (t * t)
+}
+
+/// Point at t on the cubic bezier curve p0, p1, p2, p3
+pub fn cubic_point<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+/// Cubic bezier control points of the Catmull-Rom segment from p1 to p2, so a path through points is smooth
+pub fn catmull_rom_to_bezier<T>(p0: T, p1: T, p2: T, p3: T) -> [T; 4]
+where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> {
+    [p1, p1 + (p2 - p0) * (1.0 / 6.0), p2 - (p3 - p1) * (1.0 / 6.0), p2]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_points() {
+        let curve = Curve { p0: na::Vector2::new(0.0, 0.0), p1: na::Vector2::new(1.0, 2.0), p2: na::Vector2::new(2.0, 0.0) };
+        assert_eq!(curve.point(0.0), curve.p0);
+        assert_eq!(curve.point(1.0), curve.p2);
+        assert_eq!(curve.point(0.5), na::Vector2::new(1.0, 1.0));
+
+        let p = [na::Vector3::new(0.0, 0.0, 0.0), na::Vector3::new(1.0, 0.0, 0.0), na::Vector3::new(2.0, 0.0, 0.0), na::Vector3::new(3.0, 0.0, 0.0)];
+        // points on a line stay on the line, evenly spaced
+        let [b0, b1, b2, b3] = catmull_rom_to_bezier(p[0], p[1], p[2], p[3]);
+        assert!((cubic_point(b0, b1, b2, b3, 0.5) - na::Vector3::new(1.5, 0.0, 0.0)).magnitude() < 0.0001);
+        assert_eq!(cubic_point(b0, b1, b2, b3, 1.0), p[2]);
+    }
+}
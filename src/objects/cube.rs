@@ -2,6 +2,8 @@ use crate::buffer;
 use crate::{gl, na};
 use crate::objects::mesh::Mesh;
 use crate::collision3d::CollisionBox;
+use crate::collision3d::bounds::Aabb;
+use crate::typedef::V3;
 
 pub struct Cube;
 
@@ -134,7 +136,9 @@ impl Cube {
             vao,
             vbo,
             ebo,
-            elements: indices.len() as i32
+            elements: indices.len() as i32,
+            bounds: Some(Aabb::new(V3::new(-0.5, -0.5, -0.5), V3::new(0.5, 0.5, 0.5))),
+            instance_vbo: None,
         }
     }
 
@@ -269,7 +273,10 @@ impl Cube {
             vao,
             vbo,
             ebo,
-            elements: indices.len() as i32
+            elements: indices.len() as i32,
+            bounds: Aabb::from_points(&[collision_box.v0, collision_box.v1, collision_box.v2, collision_box.v3,
+                                        collision_box.v4, collision_box.v5, collision_box.v6, collision_box.v7]),
+            instance_vbo: None,
         }
     }
 }
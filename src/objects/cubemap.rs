@@ -98,7 +98,9 @@ impl Cubemap {
             vao,
             vbo,
             ebo,
-            elements: indices.len() as i32
+            elements: indices.len() as i32,
+            bounds: None,
+            instance_vbo: None,
         };
 
         let texture_id = texture::gen_texture_cube_map(gl, images);
@@ -2,6 +2,10 @@ use crate::na;
 use crate::gl;
 use crate::objects::mesh::Mesh;
 use crate::animations::skeleton::{load_skins, SkinId, Skins};
+use crate::animations::events::{self, AnimationEvent};
+use crate::objects::material::Material;
+use crate::collision3d::bounds::Aabb;
+use crate::objects::lod::{self, LodGroup};
 use std::collections::HashMap;
 use image::{self, buffer::ConvertBuffer};
 use std::rc::Rc;
@@ -17,6 +21,41 @@ fn format_name(name: String, arm_name: &str) -> String {
     name.replace(&format!("_{}", &arm_name), "")
 }
 
+/// Groups from the `_LOD<n>` naming convention and from nodes with the `MSFT_lod` extension.
+/// Groups from the extension use the name of the finest level node as base name
+fn lod_groups(gltf: &gltf::Document) -> HashMap::<String, LodGroup> {
+    let nodes : Vec::<gltf::Node> = gltf.nodes().collect();
+    let mut groups = lod::lod_groups_from_names(nodes.iter().filter(|n| n.mesh().is_some()).filter_map(|n| n.name()));
+
+    for node in &nodes {
+        let (name, ext) = match (node.name(), node.extension_value("MSFT_lod")) {
+            (Some(name), Some(ext)) if node.mesh().is_some() => (name, ext),
+            _ => continue
+        };
+
+        let mut levels = vec![name.to_string()];
+        for id in lod::parse_msft_lod_ids(ext) {
+            if let Some(level_name) = nodes.get(id).filter(|n| n.mesh().is_some()).and_then(|n| n.name()) {
+                levels.push(level_name.to_string());
+            }
+        }
+
+        if levels.len() < 2 {
+            continue;
+        }
+
+        let mut group = LodGroup::new(levels);
+        if let Some(coverage) = node.extras().as_ref().and_then(|e| lod::parse_screen_coverage(e.get(), group.levels.len())) {
+            group.screen_coverage = coverage;
+        }
+
+        groups.insert(name.to_string(), group);
+    }
+
+    groups
+}
+
+
 pub fn meshes_from_gltf(file_path: &str, root_motion: bool) -> Result<GltfData, failure::Error> {
 
     let (gltf, buffers, images) = gltf::import(file_path)?;
@@ -24,7 +63,8 @@ pub fn meshes_from_gltf(file_path: &str, root_motion: bool) -> Result<GltfData,
     let skins = load_skins(&gltf)?;
 
     let mut res = GltfMeshes {
-        meshes: std::collections::HashMap::new()
+        meshes: std::collections::HashMap::new(),
+        lod_groups: HashMap::new(),
     };
 
     let mut loaded_images =  vec![];
@@ -71,6 +111,8 @@ pub fn meshes_from_gltf(file_path: &str, root_motion: bool) -> Result<GltfData,
         };
     }
 
+    res.lod_groups = lod_groups(&gltf);
+
     let mut name_to_idx = HashMap::<String, usize>::default();
 
     let mut i = 0;
@@ -124,6 +166,8 @@ pub fn meshes_from_gltf(file_path: &str, root_motion: bool) -> Result<GltfData,
 
         let mut total_secs = 0.0;
 
+        let anim_events = ani.extras().as_ref().map(|extras| events::parse_gltf_extras(extras.get())).unwrap_or_default();
+
         for channel in ani.channels() {
 
             let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
@@ -254,9 +298,9 @@ pub fn meshes_from_gltf(file_path: &str, root_motion: bool) -> Result<GltfData,
                 }
 
                 // bound to skin, reformat name
-                map.insert(Rc::from(format_name(name, skins.skin_to_name.get(&s_id).unwrap())), Rc::from(Animation {frames: frames.into(), total_secs, root_motion: Some(rm)}));
+                map.insert(Rc::from(format_name(name, skins.skin_to_name.get(&s_id).unwrap())), Rc::from(Animation {frames: frames.into(), total_secs, root_motion: Some(rm), events: anim_events}));
             } else {
-                map.insert(Rc::from(name.clone()), Rc::from(Animation {frames: frames.into(), total_secs, root_motion: None }));
+                map.insert(Rc::from(name.clone()), Rc::from(Animation {frames: frames.into(), total_secs, root_motion: None, events: anim_events }));
             }
         }
 
@@ -293,15 +337,15 @@ fn load_gltf_mesh_data(mesh: &gltf::mesh::Mesh, buffers: &Vec<gltf::buffer::Data
 
     let mut weights_data = Vec::new();
 
+    let mut tangent_data = Vec::new();
+
     let set = 0;
 
-    let mut texture = None;
+    let mut material = Material::default();
 
     for primitive in mesh.primitives() {
 
-        let mat = primitive.material();
-
-        texture = mat.index();
+        material = Material::from_gltf(&primitive.material());
 
         let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
@@ -319,6 +363,12 @@ fn load_gltf_mesh_data(mesh: &gltf::mesh::Mesh, buffers: &Vec<gltf::buffer::Data
             }
         }
 
+        if let Some(iter) = reader.read_tangents() {
+            for t in iter {
+                tangent_data.push(t);
+            }
+        }
+
         if let Some(reader) = reader.read_tex_coords(set) {
             for tex in reader.into_f32() {
                 tex_data.push(tex);
@@ -416,6 +466,10 @@ fn load_gltf_mesh_data(mesh: &gltf::mesh::Mesh, buffers: &Vec<gltf::buffer::Data
 
     let vertex_weights = reduce_to_2_joints(&joints_data, &weights_data);
 
+    // blender only exports tangents when asked to, so compute them from uvs when missing
+    if tangent_data.len() != pos_data.len() {
+        tangent_data = compute_tangents(&pos_data, &normal_data, &tex_data, &indices_data);
+    }
 
     Ok(GltfMesh {
         name,
@@ -425,7 +479,9 @@ fn load_gltf_mesh_data(mesh: &gltf::mesh::Mesh, buffers: &Vec<gltf::buffer::Data
         indices_data,
         tex_data,
         vertex_weights,
-        texture
+        tangent_data,
+        texture: material.base_color_texture,
+        material,
     })
 }
 
@@ -495,6 +551,8 @@ pub struct GltfMesh {
     pub tex_data: Vec<[f32; 2]>,
     pub vertex_weights: Vec<VertexWeights>,
     pub texture: Option<usize>, // index in to GltfData images
+    pub tangent_data: Vec<[f32; 4]>,
+    pub material: Material,
 }
 
 impl GltfMesh {
@@ -544,12 +602,28 @@ impl GltfMesh {
             &self.smooth_normal_data,
             &self.indices_data,
             &self.tex_data,
+            &self.tangent_data,
             &mut mesh,
             skin_data
         );
 
+        mesh.bounds = self.bounds();
+        mesh.enable_instancing(gl);
+
         mesh
     }
+
+    /// Bounds of the vertices. Skinned meshes are padded by half their size, since animations can move
+    /// vertices outside the bind pose bounds
+    pub fn bounds(&self) -> Option<Aabb> {
+        let aabb = Aabb::from_points(&self.pos_data)?;
+
+        if self.vertex_weights.is_empty() {
+            Some(aabb)
+        } else {
+            Some(aabb.expand(aabb.half_size()))
+        }
+    }
 }
 
 
@@ -583,7 +657,9 @@ impl std::convert::From<na::Vector3::<f32>> for Key {
 
 
 pub struct GltfMeshes {
-    pub meshes: std::collections::HashMap::<String, GltfMesh>
+    pub meshes: std::collections::HashMap::<String, GltfMesh>,
+    /// Level of detail groups keyed by base name, fx "Rock" for "Rock_LOD0", "Rock_LOD1"
+    pub lod_groups: HashMap::<String, LodGroup>,
 }
 
 
@@ -653,6 +729,7 @@ fn bind_mesh_data(
     smooth_norm_data: &Vec<[f32; 3]>,
     ebo_data: &Vec<u32>,
     tex_data: &Vec<[f32; 2]>,
+    tangent_data: &Vec<[f32; 4]>,
     mesh: &mut Mesh,
     skinning_data: Option<&Vec<VertexWeights>>,
 
@@ -708,11 +785,14 @@ fn bind_mesh_data(
         vertex_data.push(smooth_norm_data[i][0]);
         vertex_data.push(smooth_norm_data[i][1]);
         vertex_data.push(smooth_norm_data[i][2]);
+
+        // TANGENTS, W IS HANDEDNESS OF BITANGENT
+        vertex_data.extend_from_slice(&tangent_data[i]);
     }
 
 
 
-    let stride = ((3 + 3 + 2 + 2 + 2 + 3) * std::mem::size_of::<f32>()) as gl::types::GLint;
+    let stride = ((3 + 3 + 2 + 2 + 2 + 3 + 4) * std::mem::size_of::<f32>()) as gl::types::GLint;
     unsafe {
         // 1
         mesh.vao.bind();
@@ -803,6 +883,17 @@ fn bind_mesh_data(
 
         gl.EnableVertexAttribArray(5);
 
+        // tangents
+        gl.VertexAttribPointer(
+            6,
+            4,
+            gl::FLOAT,
+            gl::FALSE,
+            stride,
+            (15 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid,
+        );
+
+        gl.EnableVertexAttribArray(6);
 
     }
 
@@ -815,6 +906,61 @@ fn bind_mesh_data(
 
 
 
+/// Per vertex tangents from uv derivatives, orthogonalized against the normal.
+/// W is the handedness of the bitangent, as in glTF
+pub fn compute_tangents(pos_data: &[na::Vector3::<f32>], normal_data: &[[f32; 3]], tex_data: &[[f32; 2]], indices: &[u32]) -> Vec<[f32; 4]> {
+
+    let mut tan = vec![na::Vector3::<f32>::zeros(); pos_data.len()];
+    let mut bitan = vec![na::Vector3::<f32>::zeros(); pos_data.len()];
+
+    if tex_data.len() == pos_data.len() {
+        for tri in indices.chunks_exact(3) {
+            let (i0, i1, i2) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+
+            let e1 = pos_data[i1] - pos_data[i0];
+            let e2 = pos_data[i2] - pos_data[i0];
+
+            let du1 = tex_data[i1][0] - tex_data[i0][0];
+            let dv1 = tex_data[i1][1] - tex_data[i0][1];
+            let du2 = tex_data[i2][0] - tex_data[i0][0];
+            let dv2 = tex_data[i2][1] - tex_data[i0][1];
+
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < 1e-12 {
+                continue;
+            }
+
+            let r = 1.0 / det;
+            let t = (e1 * dv2 - e2 * dv1) * r;
+            let b = (e2 * du1 - e1 * du2) * r;
+
+            for i in [i0, i1, i2] {
+                tan[i] += t;
+                bitan[i] += b;
+            }
+        }
+    }
+
+    let mut res = Vec::with_capacity(pos_data.len());
+    for i in 0..pos_data.len() {
+        let n = normal_data.get(i).map(|n| na::Vector3::new(n[0], n[1], n[2])).unwrap_or(na::Vector3::z());
+
+        // gram-schmidt
+        let mut t = tan[i] - n * n.dot(&tan[i]);
+        if t.magnitude_squared() < 1e-12 {
+            // no uvs, pick any vector orthogonal to normal
+            t = if n.x.abs() < 0.9 { na::Vector3::x().cross(&n) } else { na::Vector3::y().cross(&n) };
+        }
+        let t = t.normalize();
+
+        let w = if n.cross(&t).dot(&bitan[i]) < 0.0 { -1.0 } else { 1.0 };
+        res.push([t.x, t.y, t.z, w]);
+    }
+
+    res
+}
+
+
 #[derive(Debug, Copy, Clone)]
 pub struct Triangle {
     pub v0: na::Vector3::<f32>,
@@ -836,7 +982,8 @@ pub struct VertexWeights {
 pub struct Animation {
     pub total_secs: f32,
     pub frames: Rc::<[KeyFrame]>,
-    pub root_motion: Option::<Vec::<na::Vector3::<f32>>> // translation for each frame
+    pub root_motion: Option::<Vec::<na::Vector3::<f32>>>, // translation for each frame
+    pub events: Vec::<AnimationEvent>, // sorted by time, loaded from animation extras
 }
 
 #[derive(Debug, Default, Clone)]
@@ -865,3 +1012,42 @@ pub struct Transformation {
     pub translation: na::Vector3::<f32>,
     pub rotation: na::UnitQuaternion::<f32>,
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tangents_follow_u() {
+        // quad in xy plane, u along x and v along y
+        let pos = vec![na::Vector3::new(0.0, 0.0, 0.0), na::Vector3::new(1.0, 0.0, 0.0), na::Vector3::new(1.0, 1.0, 0.0), na::Vector3::new(0.0, 1.0, 0.0)];
+        let normals = vec![[0.0, 0.0, 1.0]; 4];
+        let uvs = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let indices = vec![0, 1, 2, 0, 2, 3];
+
+        let tangents = compute_tangents(&pos, &normals, &uvs, &indices);
+        for t in &tangents {
+            assert!((t[0] - 1.0).abs() < 1e-5 && t[1].abs() < 1e-5 && t[2].abs() < 1e-5, "{:?}", t);
+            assert_eq!(t[3], 1.0);
+        }
+
+        // mirrored uvs flips handedness
+        let mirrored = vec![[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let tangents = compute_tangents(&pos, &normals, &mirrored, &indices);
+        assert!((tangents[0][0] + 1.0).abs() < 1e-5);
+        assert_eq!(tangents[0][3], -1.0);
+    }
+
+    #[test]
+    fn tangents_without_uvs_are_orthogonal() {
+        let pos = vec![na::Vector3::new(0.0, 0.0, 0.0); 3];
+        let normals = vec![[0.0, 1.0, 0.0]; 3];
+        let tangents = compute_tangents(&pos, &normals, &[], &[0, 1, 2]);
+        for t in &tangents {
+            let t = na::Vector3::new(t[0], t[1], t[2]);
+            assert!(t.dot(&na::Vector3::y()).abs() < 1e-5);
+            assert!((t.magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Level of detail groups. Levels come from glTF nodes named fx `Rock_LOD0`, `Rock_LOD1`, or from the
+//! `MSFT_lod` extension, and are picked by how much of the screen the mesh covers.
+use std::collections::HashMap;
+
+
+/// Fraction of the screen height a level is used down to, when the file does not say
+pub const DEFAULT_COVERAGE: f32 = 0.25;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodGroup {
+    /// Mesh names, finest level first
+    pub levels: Vec::<String>,
+    /// Level i is used while the mesh covers at least screen_coverage[i] of the screen height,
+    /// the last level is used when smaller
+    pub screen_coverage: Vec::<f32>,
+}
+
+impl LodGroup {
+
+    pub fn new(levels: Vec::<String>) -> Self {
+        let screen_coverage = default_screen_coverage(levels.len());
+        Self { levels, screen_coverage }
+    }
+}
+
+
+/// Halves coverage for each level, starting from `DEFAULT_COVERAGE`
+pub fn default_screen_coverage(levels: usize) -> Vec::<f32> {
+    (0..levels).map(|i| DEFAULT_COVERAGE / (1 << i) as f32).collect()
+}
+
+
+/// Split fx `Rock_LOD1` into ("Rock", 1)
+pub fn parse_lod_name(name: &str) -> Option<(&str, usize)> {
+    let idx = name.rfind("_LOD")?;
+    let level = name[idx + 4..].parse().ok()?;
+    Some((&name[..idx], level))
+}
+
+
+/// Groups of names following the `_LOD<n>` convention, keyed by base name. Groups with a single level are skipped
+pub fn lod_groups_from_names<'a, I: IntoIterator<Item = &'a str>>(names: I) -> HashMap::<String, LodGroup> {
+    let mut levels = HashMap::<String, Vec::<(usize, String)>>::new();
+
+    for name in names {
+        if let Some((base, level)) = parse_lod_name(name) {
+            levels.entry(base.to_string()).or_default().push((level, name.to_string()));
+        }
+    }
+
+    levels.into_iter()
+        .filter(|(_, l)| l.len() > 1)
+        .map(|(base, mut l)| {
+            l.sort();
+            (base, LodGroup::new(l.into_iter().map(|(_, n)| n).collect()))
+        })
+        .collect()
+}
+
+
+/// Node indices of the coarser levels from the `MSFT_lod` extension value of the finest level node
+pub fn parse_msft_lod_ids(value: &serde_json::Value) -> Vec::<usize> {
+    value.get("ids")
+        .and_then(|ids| ids.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_u64().map(|i| i as usize)).collect())
+        .unwrap_or_default()
+}
+
+
+/// Screen coverage from `MSFT_screencoverage` in node extras, if it has a value for every level
+pub fn parse_screen_coverage(extras: &str, levels: usize) -> Option<Vec::<f32>> {
+    let value : serde_json::Value = serde_json::from_str(extras).ok()?;
+    let coverage : Vec::<f32> = value.get("MSFT_screencoverage")?
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_f64().map(|f| f as f32))
+        .collect();
+
+    if coverage.len() < levels {
+        return None;
+    }
+
+    Some(coverage[..levels].to_vec())
+}
+
+
+/// Projected height of a sphere as a fraction of the screen height
+pub fn screen_size(radius: f32, dist: f32, fov_y: f32) -> f32 {
+    if dist <= radius {
+        return 1.0;
+    }
+
+    radius / (dist * (fov_y * 0.5).tan())
+}
+
+
+/// Level for screen size. To not pop back and forth when size is close to a threshold,
+/// the size has to be hysteresis (fx 0.1 = 10%) past the threshold before changing from current
+pub fn select_lod(current: usize, size: f32, screen_coverage: &[f32], hysteresis: f32) -> usize {
+    if screen_coverage.is_empty() {
+        return 0;
+    }
+
+    let last = screen_coverage.len() - 1;
+    let current = current.min(last);
+    let target = screen_coverage.iter().position(|c| size >= *c).unwrap_or(last);
+
+    if target > current && size < screen_coverage[current] * (1.0 - hysteresis) {
+        return target;
+    }
+
+    if target < current && size >= screen_coverage[current - 1] * (1.0 + hysteresis) {
+        return target;
+    }
+
+    current
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lod_names() {
+        assert_eq!(parse_lod_name("Rock_LOD0"), Some(("Rock", 0)));
+        assert_eq!(parse_lod_name("Big_Rock_LOD12"), Some(("Big_Rock", 12)));
+        assert_eq!(parse_lod_name("Rock"), None);
+        assert_eq!(parse_lod_name("Rock_LODx"), None);
+
+        let groups = lod_groups_from_names(["Rock_LOD1", "Tree", "Rock_LOD0", "Rock_LOD2", "Bush_LOD0"]);
+        assert_eq!(groups.len(), 1);
+        let rock = &groups["Rock"];
+        assert_eq!(rock.levels, vec!["Rock_LOD0", "Rock_LOD1", "Rock_LOD2"]);
+        assert_eq!(rock.screen_coverage, vec![0.25, 0.125, 0.0625]);
+    }
+
+    #[test]
+    fn msft_lod() {
+        let value : serde_json::Value = serde_json::from_str(r#"{"ids": [3, 5]}"#).unwrap();
+        assert_eq!(parse_msft_lod_ids(&value), vec![3, 5]);
+        assert!(parse_msft_lod_ids(&serde_json::Value::Null).is_empty());
+    }
+
+    #[test]
+    fn msft_screen_coverage() {
+        let extras = r#"{"MSFT_screencoverage": [0.5, 0.2, 0.01]}"#;
+        assert_eq!(parse_screen_coverage(extras, 3), Some(vec![0.5, 0.2, 0.01]));
+        assert_eq!(parse_screen_coverage(extras, 4), None);
+        assert_eq!(parse_screen_coverage("{}", 2), None);
+    }
+
+    #[test]
+    fn projected_size() {
+        let fov = 90.0_f32.to_radians();
+        assert_eq!(screen_size(2.0, 1.0, fov), 1.0);
+        assert!((screen_size(1.0, 10.0, fov) - 0.1).abs() < 0.0001);
+        assert!(screen_size(1.0, 20.0, fov) < screen_size(1.0, 10.0, fov));
+    }
+
+    #[test]
+    fn select_with_hysteresis() {
+        let coverage = [0.5, 0.2, 0.05];
+
+        // no current state, jumps straight to target
+        assert_eq!(select_lod(0, 0.01, &coverage, 0.1), 2);
+        assert_eq!(select_lod(0, 0.3, &coverage, 0.1), 1);
+
+        // just below threshold of level 0, stays
+        assert_eq!(select_lod(0, 0.48, &coverage, 0.1), 0);
+        assert_eq!(select_lod(0, 0.44, &coverage, 0.1), 1);
+
+        // just above threshold of level 0 when at level 1, stays
+        assert_eq!(select_lod(1, 0.52, &coverage, 0.1), 1);
+        assert_eq!(select_lod(1, 0.56, &coverage, 0.1), 0);
+
+        // no hysteresis
+        assert_eq!(select_lod(1, 0.5, &coverage, 0.0), 0);
+        assert_eq!(select_lod(5, 0.0, &coverage, 0.0), 2);
+    }
+}
@@ -0,0 +1,157 @@
+//! glTF metallic-roughness material. Loaded with image indices into `GltfData::images`,
+//! and mapped to gl textures with `map_textures` when the images are uploaded.
+use crate::na;
+
+type V3 = na::Vector3::<f32>;
+type V4 = na::Vector4::<f32>;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Fragments with alpha below cutoff are discarded
+    Mask(f32),
+    /// Alpha blended, rendered after opaque meshes
+    Blend,
+}
+
+
+/// Texture is the image index when loading, and `TextureId` when used for rendering
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material<Tex = usize> {
+    pub name: Option<String>,
+    pub base_color_factor: V4,
+    pub base_color_texture: Option<Tex>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in green and metallic in blue channel
+    pub metallic_roughness_texture: Option<Tex>,
+    pub normal_texture: Option<Tex>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<Tex>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: V3,
+    pub emissive_texture: Option<Tex>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl<Tex> Default for Material<Tex> {
+    // defaults from the glTF spec
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: V4::new(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: V3::new(0.0, 0.0, 0.0),
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+
+impl<Tex> Material<Tex> {
+
+    pub fn map_textures<T, F: FnMut(&Tex) -> T>(&self, mut f: F) -> Material<T> {
+        Material {
+            name: self.name.clone(),
+            base_color_factor: self.base_color_factor,
+            base_color_texture: self.base_color_texture.as_ref().map(&mut f),
+            metallic_factor: self.metallic_factor,
+            roughness_factor: self.roughness_factor,
+            metallic_roughness_texture: self.metallic_roughness_texture.as_ref().map(&mut f),
+            normal_texture: self.normal_texture.as_ref().map(&mut f),
+            normal_scale: self.normal_scale,
+            occlusion_texture: self.occlusion_texture.as_ref().map(&mut f),
+            occlusion_strength: self.occlusion_strength,
+            emissive_factor: self.emissive_factor,
+            emissive_texture: self.emissive_texture.as_ref().map(&mut f),
+            alpha_mode: self.alpha_mode,
+            double_sided: self.double_sided,
+        }
+    }
+
+    /// All textures, in the order base color, metallic roughness, normal, occlusion, emissive
+    pub fn textures(&self) -> [Option<&Tex>; 5] {
+        [self.base_color_texture.as_ref(),
+         self.metallic_roughness_texture.as_ref(),
+         self.normal_texture.as_ref(),
+         self.occlusion_texture.as_ref(),
+         self.emissive_texture.as_ref()]
+    }
+
+    pub fn is_blend(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+}
+
+
+impl Material<usize> {
+
+    pub fn from_gltf(mat: &gltf::Material) -> Self {
+        let pbr = mat.pbr_metallic_roughness();
+        let image = |tex: gltf::Texture| tex.source().index();
+
+        let alpha_mode = match mat.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask(mat.alpha_cutoff().unwrap_or(0.5)),
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+
+        let normal = mat.normal_texture();
+        let occlusion = mat.occlusion_texture();
+
+        Self {
+            name: mat.name().map(|n| n.to_string()),
+            base_color_factor: pbr.base_color_factor().into(),
+            base_color_texture: pbr.base_color_texture().map(|t| image(t.texture())),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr.metallic_roughness_texture().map(|t| image(t.texture())),
+            normal_scale: normal.as_ref().map(|n| n.scale()).unwrap_or(1.0),
+            normal_texture: normal.map(|n| image(n.texture())),
+            occlusion_strength: occlusion.as_ref().map(|o| o.strength()).unwrap_or(1.0),
+            occlusion_texture: occlusion.map(|o| image(o.texture())),
+            emissive_factor: mat.emissive_factor().into(),
+            emissive_texture: mat.emissive_texture().map(|t| image(t.texture())),
+            alpha_mode,
+            double_sided: mat.double_sided(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_textures_keeps_factors() {
+        let mat = Material::<usize> {
+            base_color_texture: Some(2),
+            normal_texture: Some(0),
+            metallic_factor: 0.3,
+            alpha_mode: AlphaMode::Mask(0.4),
+            ..Default::default()
+        };
+
+        let ids = [10u32, 11, 12];
+        let mapped = mat.map_textures(|i| ids[*i]);
+        assert_eq!(mapped.base_color_texture, Some(12));
+        assert_eq!(mapped.normal_texture, Some(10));
+        assert_eq!(mapped.emissive_texture, None);
+        assert_eq!(mapped.metallic_factor, 0.3);
+        assert_eq!(mapped.alpha_mode, AlphaMode::Mask(0.4));
+        assert_eq!(mapped.textures().iter().filter(|t| t.is_some()).count(), 2);
+    }
+}
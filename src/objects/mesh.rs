@@ -1,12 +1,21 @@
 use crate::buffer;
 use crate::gl;
+use crate::collision3d::bounds::Aabb;
+use crate::typedef::Mat4;
+
+/// First attribute location of the per instance model matrix, uses 4 locations
+pub const INSTANCE_MODEL_LOCATION: u32 = 7;
 
 
 pub struct Mesh {
     pub vao: buffer::VertexArray,
     pub vbo: buffer::ArrayBuffer,
     pub ebo: buffer::ElementArrayBuffer,
-    pub elements: i32
+    pub elements: i32,
+    /// Bounds in model space, used for frustum culling. Meshes without bounds are never culled
+    pub bounds: Option<Aabb>,
+    /// Per instance model matrices, set with `enable_instancing`
+    pub instance_vbo: Option<buffer::ArrayBuffer>,
 }
 
 
@@ -22,7 +31,9 @@ impl Mesh {
             vao,
             vbo,
             ebo,
-            elements: 0
+            elements: 0,
+            bounds: None,
+            instance_vbo: None,
         }
 
      }
@@ -42,4 +53,70 @@ impl Mesh {
         }
         self.vao.unbind();
     }
+
+    /// Add a per instance model matrix attribute at `INSTANCE_MODEL_LOCATION`, so the mesh can be used with `render_instanced`
+    pub fn enable_instancing(&mut self, gl: &gl::Gl) {
+        if self.instance_vbo.is_some() {
+            return;
+        }
+
+        let vbo = buffer::ArrayBuffer::new(gl);
+        self.vao.bind();
+        vbo.bind();
+
+        let stride = std::mem::size_of::<Mat4>() as gl::types::GLint;
+        unsafe {
+            // a mat4 attribute is 4 vec4 attributes
+            for i in 0..4 {
+                let loc = INSTANCE_MODEL_LOCATION + i;
+                gl.VertexAttribPointer(
+                    loc,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (i as usize * 4 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid,
+                );
+                gl.EnableVertexAttribArray(loc);
+                gl.VertexAttribDivisor(loc, 1);
+            }
+        }
+
+        vbo.unbind();
+        self.vao.unbind();
+
+        self.instance_vbo = Some(vbo);
+    }
+
+    pub fn is_instanced(&self) -> bool {
+        self.instance_vbo.is_some()
+    }
+
+    /// Draw one instance per model matrix. Falls back to a single normal render when instancing is not enabled,
+    /// so only batch meshes where `is_instanced` is true
+    pub fn render_instanced(&self, gl: &gl::Gl, models: &[Mat4]) {
+        let vbo = match &self.instance_vbo {
+            Some(vbo) => vbo,
+            None => {
+                self.render(gl);
+                return;
+            }
+        };
+
+        vbo.bind();
+        vbo.dynamic_draw_data(models);
+        vbo.unbind();
+
+        self.vao.bind();
+        unsafe {
+            gl.DrawElementsInstanced(
+                gl::TRIANGLES,
+                self.elements,
+                gl::UNSIGNED_INT,
+                0 as *const gl::types::GLvoid,
+                models.len() as i32
+            );
+        }
+        self.vao.unbind();
+    }
 }
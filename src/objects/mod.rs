@@ -29,6 +29,10 @@ pub mod texture_quad;
 
 pub mod gltf_mesh;
 
+pub mod lod;
+
+pub mod material;
+
 pub mod sprite_sheet;
 
 pub mod cubemap;
@@ -2,6 +2,8 @@ use crate::buffer;
 use crate::gl;
 use crate::shader::BaseShader;
 use crate::objects::mesh::Mesh;
+use crate::collision3d::bounds::Aabb;
+use crate::typedef::V3;
 use nalgebra as na;
 use na::vector;
 
@@ -81,7 +83,9 @@ impl Plane {
             vao,
             vbo,
             ebo,
-            elements: 6
+            elements: 6,
+            bounds: Some(Aabb::new(V3::new(-0.5, -0.5, 0.0), V3::new(0.5, 0.5, 0.0))),
+            instance_vbo: None,
         }
     }
 
@@ -1,19 +1,45 @@
+//! Cascaded shadow maps. The camera frustum is split into `cascades` slices along the view direction,
+//! and each slice gets its own orthographic light projection and layer in a depth texture array.
+//! See https://learnopengl.com/Guest-Articles/2021/CSM
 use crate::gl;
 use crate::texture;
 use crate::na;
+use crate::camera::{Camera, Projection};
 use crate::shader::{self, Shader};
-use crate::typedef::{V4, Mat4};
+use crate::typedef::{V3, V4, Mat4};
+
+/// Has to match size of lightSpaceMats and cascadeSplits in the mesh shaders
+pub const MAX_CASCADES: usize = 4;
+
+
+/// Light space matrices and split distances for the current frame
+#[derive(Debug, Clone, Default)]
+pub struct Cascades {
+    pub light_space_mats: Vec::<Mat4>,
+    /// View space distance where each cascade ends
+    pub splits: Vec::<f32>,
+    pub pcf_radius: i32,
+}
+
 
 pub struct ShadowMap {
     depth_map_fbo: u32,
+    /// Depth texture array with one layer per cascade
     pub depth_map: texture::TextureId,
     pub shader: shader::BaseShader,
     w: i32,
     h: i32,
     pub texture_offset: u32,
-    pub z_far: f32,
-    pub z_near: f32,
-    pub size: f32
+    /// Number of cascades, at most `MAX_CASCADES`
+    pub cascades: usize,
+    /// Shadows are rendered up to this distance from the camera, capped at camera zfar
+    pub max_distance: f32,
+    /// 0.0 is uniform splits, 1.0 is logarithmic splits
+    pub split_lambda: f32,
+    /// Extra depth towards the light, so casters outside the view still cast shadows into it
+    pub z_margin: f32,
+    /// PCF kernel is (2 * pcf_radius + 1)^2 samples, 0 is no filtering
+    pub pcf_radius: i32,
 }
 
 impl ShadowMap {
@@ -23,14 +49,14 @@ impl ShadowMap {
         // we could use buffer::FrameBuffer, but it is set up for color, depth ect, so easier to just to it manually here
         // so we can set drawbuffer none and reader buffer none,
         let mut depth_map_fbo = 0;
-        let w = 4096;
-        let h = 4096;
-        let depth_map = texture::gen_texture_depth(gl, w, h);
+        let w = 2048;
+        let h = 2048;
+        let depth_map = texture::gen_texture_depth_array(gl, w, h, MAX_CASCADES as i32);
 
         unsafe {
             gl.GenFramebuffers(1, &mut depth_map_fbo);
             gl.BindFramebuffer(gl::FRAMEBUFFER, depth_map_fbo);
-            gl.FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth_map, 0);
+            gl.FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, depth_map, 0, 0);
             gl.DrawBuffer(gl::NONE);
             gl.ReadBuffer(gl::NONE);
             gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
@@ -45,98 +71,223 @@ impl ShadowMap {
             w,
             h,
             texture_offset: 0,
-            z_near: 0.5,
-            z_far: 50.5,
-            size: 10.0
+            cascades: MAX_CASCADES,
+            max_distance: 100.0,
+            split_lambda: 0.7,
+            z_margin: 50.0,
+            pcf_radius: 1,
         }
     }
 
 
-    pub fn pre_render(&self, gl: &gl::Gl, light_pos: na::Vector3::<f32>, light_space_mats: &mut Vec::<na::Matrix4::<f32>>) {
+    /// Calculate cascades for camera and bind the shadow map fbo. Call `bind_cascade` before rendering each cascade
+    pub fn pre_render(&self, gl: &gl::Gl, camera: &Camera, light_dir: V3, cascades: &mut Cascades) {
+
+        self.calc_cascades(camera, light_dir, cascades);
 
         unsafe {
             gl.Viewport(0, 0, self.w, self.h);
             gl.BindFramebuffer(gl::FRAMEBUFFER, self.depth_map_fbo);
-            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         }
 
         self.shader.set_used();
-
-        self.light_space_mats(light_pos, light_space_mats);
-
-        // TODO: we should set the whole vec into mats
-        self.shader.set_mat4(gl, "light_space_mat", light_space_mats[0]);
     }
 
 
+    /// Render target to layer of cascade and set its light space matrix
+    pub fn bind_cascade(&self, gl: &gl::Gl, index: usize, cascades: &Cascades) {
+        unsafe {
+            gl.FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.depth_map, 0, index as i32);
+            gl.Clear(gl::DEPTH_BUFFER_BIT);
+        }
 
-    fn light_space_mats(&self, light_pos: na::Vector3::<f32>, light_space_mats: &mut Vec::<na::Matrix4::<f32>>) {
-        light_space_mats.push(self.projection() * self.view(light_pos));
+        self.shader.set_mat4(gl, "light_space_mat", cascades.light_space_mats[index]);
     }
 
-    pub fn projection(&self) -> na::Matrix4::<f32> {
-        // see https://learnopengl.com/Guest-Articles/2021/CSM for info about how to generate this and view
-        // so that everything in the view frustrum is in the shadow map, but also multiple leves, so small close things
-        // still has a nice shadow
-        // how much space out light sees, bigger is more space, but also lower resolution in shadow map
-        // making this big, like 50 or 100, makes the shadows pixelated
-        let size = self.size;
-        na::Matrix4::new_orthographic(-size, size, -size, size, self.z_near, self.z_far)
 
+    pub fn calc_cascades(&self, camera: &Camera, light_dir: V3, cascades: &mut Cascades) {
+        let count = self.cascades.clamp(1, MAX_CASCADES);
+        let far = self.max_distance.min(camera.zfar);
+
+        cascades.splits = cascade_splits(camera.znear, far, count, self.split_lambda);
+        cascades.light_space_mats.clear();
+        cascades.pcf_radius = self.pcf_radius;
+
+        let mut near = camera.znear;
+        for split in &cascades.splits {
+            let corners = frustum_slice_corners(camera, near, *split);
+            cascades.light_space_mats.push(fit_cascade(&corners, light_dir, self.w as f32, self.z_margin));
+            near = *split;
+        }
     }
 
 
-    fn get_frustum_corners_world_space(proj: &Mat4, view: Mat4) -> Vec::<V4>
-    {
+    pub fn post_render(&self, gl: &gl::Gl, width: i32, height: i32) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl.Viewport(0, 0, width, height);
 
-        let inv: Mat4 = (proj * view).try_inverse().unwrap();
+            gl.Enable(gl::DEPTH_TEST);
+            gl.ActiveTexture(gl::TEXTURE0 + self.texture_offset);
+            gl.BindTexture(gl::TEXTURE_2D_ARRAY, self.depth_map);
+        }
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            self.shader.gl().DeleteFramebuffers(1, &self.depth_map_fbo);
+            self.shader.gl().DeleteTextures(1, &self.depth_map);
+        }
+    }
+}
 
-        let mut frustum_corners = vec![];
-        for xi in 0..2 {
-            for yi in 0..2 {
-                for zi in 0..2 {
-                    let x = xi as f32;
-                    let y = yi as f32;
-                    let z = zi as f32;
 
-                    let pt = inv * V4::new(
-                        2.0 * x - 1.0,
-                        2.0 * y - 1.0,
-                        2.0 * z - 1.0,
-                        1.0);
+/// Far distance of each cascade. Blends logarithmic and uniform splits with lambda,
+/// see "Parallel-split shadow maps" by Zhang et al.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec::<f32> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    (1..=count).map(|i| {
+        let p = i as f32 / count as f32;
+        let log = near * (far / near).powf(p);
+        let uniform = near + (far - near) * p;
+        lambda * log + (1.0 - lambda) * uniform
+    }).collect()
+}
 
-                    frustum_corners.push(pt / pt.w);
 
-                }
+/// World space corners of the camera frustum between near and far, a box for orthographic cameras
+pub fn frustum_slice_corners(camera: &Camera, near: f32, far: f32) -> Vec::<V3> {
+    let aspect = camera.width / camera.height;
+    let proj = match camera.projection_mode {
+        Projection::Perspective => na::Matrix4::new_perspective(aspect, camera.fov.to_radians(), near, far),
+        Projection::Orthographic { height } => {
+            let half_h = height / 2.0;
+            let half_w = half_h * aspect;
+            na::Matrix4::new_orthographic(-half_w, half_w, -half_h, half_h, near, far)
+        }
+    };
+    let inv: Mat4 = (proj * camera.view()).try_inverse().unwrap_or_else(Mat4::identity);
+
+    let mut corners = Vec::with_capacity(8);
+    for x in [-1.0, 1.0] {
+        for y in [-1.0, 1.0] {
+            for z in [-1.0, 1.0] {
+                let pt = inv * V4::new(x, y, z, 1.0);
+                corners.push(pt.xyz() / pt.w);
             }
         }
-
-        frustum_corners
     }
 
-    pub fn view(&self, light_pos: na::Vector3::<f32>) -> na::Matrix4::<f32> {
+    corners
+}
+
+
+/// Orthographic light projection * view that contains all corners. Uses a bounding sphere so the size does not
+/// change when the camera rotates, and snaps to texels of a shadow map with resolution, so shadows don't shimmer
+/// when the camera moves
+pub fn fit_cascade(corners: &[V3], light_dir: V3, resolution: f32, z_margin: f32) -> Mat4 {
+    let center = corners.iter().sum::<V3>() / corners.len() as f32;
+    let mut radius = corners.iter().map(|c| (c - center).magnitude()).fold(0.0, f32::max);
+
+    // round up so small changes don't change the size, and make room for the texel snapping
+    radius = (radius * 16.0).ceil() / 16.0;
+    radius += 2.0 * radius / resolution;
 
-        // TODO: look at https://learnopengl.com/Guest-Articles/2021/CSM so view "follows"
-        // user camera, so when not viewing 0,0,0. We still get shadows
-        let target = na::Point3::new(0.0, 0.0, 0.0);
+    let dir = light_dir.normalize();
+    let up = if dir.z.abs() > 0.99 { V3::new(0.0, 1.0, 0.0) } else { V3::new(0.0, 0.0, 1.0) };
 
-        let point_pos = na::Point3::new(light_pos.x, light_pos.y, light_pos.z);
+    let eye = center - dir * (radius + z_margin);
+    let view = na::Matrix::look_at_rh(&na::Point3::from(eye), &na::Point3::from(center), &up);
+    let mut proj = na::Matrix4::new_orthographic(-radius, radius, -radius, radius, 0.0, 2.0 * radius + z_margin);
 
-        let up = na::Vector3::new(0.0, 0.0, 1.0);
+    // snap world origin to texel grid
+    let origin = (proj * view) * V4::new(0.0, 0.0, 0.0, 1.0);
+    let texels = origin.xy() * resolution / 2.0;
+    let offset = (texels.map(|t| t.round()) - texels) * 2.0 / resolution;
+    proj[(0, 3)] += offset.x;
+    proj[(1, 3)] += offset.y;
 
-        na::Matrix::look_at_rh(&point_pos, &target, &up)
+    proj * view
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_increase_to_far() {
+        let splits = cascade_splits(0.1, 100.0, 4, 0.5);
+        assert_eq!(splits.len(), 4);
+        assert!((splits[3] - 100.0).abs() < 0.001);
+        assert!(splits.windows(2).all(|w| w[0] < w[1]));
 
+        let uniform = cascade_splits(1.0, 101.0, 4, 0.0);
+        assert_eq!(uniform, vec![26.0, 51.0, 76.0, 101.0]);
+
+        // log splits are closer to the camera
+        let log = cascade_splits(0.1, 100.0, 4, 1.0);
+        assert!(log[0] < splits[0]);
+        assert!((log[1] - 0.1 * 1000.0_f32.sqrt()).abs() < 0.001);
     }
 
-    pub fn post_render(&self, gl: &gl::Gl, width: i32, height: i32) {
-        unsafe {
-            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-            gl.Viewport(0, 0, width, height);
+    #[test]
+    fn slice_corners_at_distance() {
+        let mut camera = Camera::new(800.0, 600.0);
+        camera.move_to(V3::new(0.0, 0.0, 5.0));
+        camera.look_at(V3::new(10.0, 0.0, 5.0));
+
+        let corners = frustum_slice_corners(&camera, 1.0, 10.0);
+        assert_eq!(corners.len(), 8);
+        for c in &corners {
+            let d = (c - camera.pos).dot(&camera.front);
+            assert!((d - 1.0).abs() < 0.01 || (d - 10.0).abs() < 0.01, "{:?}", d);
+        }
+    }
 
-            gl.Enable(gl::DEPTH_TEST);
-            gl.ActiveTexture(gl::TEXTURE0 + self.texture_offset);
-            gl.BindTexture(gl::TEXTURE_2D, self.depth_map);
+    #[test]
+    fn orthographic_slice_is_box() {
+        let mut camera = Camera::new(800.0, 600.0);
+        camera.projection_mode = Projection::Orthographic { height: 6.0 };
+        camera.move_to(V3::new(0.0, 0.0, 5.0));
+        camera.look_at(V3::new(10.0, 0.0, 5.0));
+
+        let corners = frustum_slice_corners(&camera, 1.0, 10.0);
+        for c in &corners {
+            let d = (c - camera.pos).dot(&camera.front);
+            assert!((d - 1.0).abs() < 0.01 || (d - 10.0).abs() < 0.01, "{:?}", d);
+
+            // same size at near and far
+            assert!((c.y.abs() - 4.0).abs() < 0.01, "{:?}", c);
+            assert!(((c.z - 5.0).abs() - 3.0).abs() < 0.01, "{:?}", c);
         }
+    }
 
+    #[test]
+    fn cascade_contains_slice() {
+        let mut camera = Camera::new(800.0, 600.0);
+        camera.move_to(V3::new(3.0, -2.0, 5.0));
+        camera.look_at(V3::new(10.0, 4.0, 0.0));
+
+        let light_dirs = [V3::new(-1.0, -0.5, -2.0), V3::new(0.0, 0.0, -1.0)];
+
+        for light_dir in light_dirs {
+            let corners = frustum_slice_corners(&camera, 5.0, 20.0);
+            let mat = fit_cascade(&corners, light_dir, 1024.0, 10.0);
+
+            for c in &corners {
+                let p = mat * V4::new(c.x, c.y, c.z, 1.0);
+                let ndc = p.xyz() / p.w;
+                assert!(ndc.iter().all(|v| v.abs() <= 1.0), "{:?}", ndc);
+            }
+
+            // casters up to z_margin towards the light are inside
+            let center = corners.iter().sum::<V3>() / 8.0;
+            let caster = center - light_dir.normalize() * 15.0;
+            let p = mat * V4::new(caster.x, caster.y, caster.z, 1.0);
+            assert!(p.z.abs() <= 1.0);
+        }
     }
 }
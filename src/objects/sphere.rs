@@ -1,6 +1,8 @@
 use crate::buffer;
 use crate::gl;
 use crate::objects::mesh::Mesh;
+use crate::collision3d::bounds::Aabb;
+use crate::typedef::V3;
 
 // Shpere with radius 1, thus the input pos is also the normals
 pub struct Sphere;
@@ -166,7 +168,9 @@ impl Sphere {
             vao,
             vbo,
             ebo,
-            elements: indices.len() as i32
+            elements: indices.len() as i32,
+            bounds: Some(Aabb::new(V3::new(-1.0, -1.0, -1.0), V3::new(1.0, 1.0, 1.0))),
+            instance_vbo: None,
         }
     }
 
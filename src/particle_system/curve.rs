@@ -0,0 +1,98 @@
+//! Values over the lifetime of a particle, as keys at times in [0; 1] with linear interpolation between them.
+use serde::{Serialize, Deserialize};
+use crate::general_animation::Animatable;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Key<T> {
+    pub t: f32,
+    pub value: T,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Curve<T> {
+    /// Sorted by t
+    pub keys: Vec::<Key<T>>,
+}
+
+impl<T: Animatable + Default> Curve<T> {
+
+    pub fn constant(value: T) -> Self {
+        Self { keys: vec![Key { t: 0.0, value }] }
+    }
+
+    pub fn linear(from: T, to: T) -> Self {
+        Self { keys: vec![Key { t: 0.0, value: from }, Key { t: 1.0, value: to }] }
+    }
+
+    /// Add a key, keeping keys sorted. Replaces a key at the same t
+    pub fn with_key(mut self, t: f32, value: T) -> Self {
+        self.set_key(t, value);
+        self
+    }
+
+    pub fn set_key(&mut self, t: f32, value: T) {
+        match self.keys.iter().position(|k| k.t >= t) {
+            Some(i) if self.keys[i].t == t => self.keys[i].value = value,
+            Some(i) => self.keys.insert(i, Key { t, value }),
+            None => self.keys.push(Key { t, value }),
+        }
+    }
+
+    /// Sort keys after editing t directly, fx when loaded
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.t.total_cmp(&b.t));
+    }
+
+    /// Value at t, held constant before the first and after the last key. Default when there are no keys
+    pub fn sample(&self, t: f32) -> T {
+        let Some(first) = self.keys.first() else {
+            return T::default();
+        };
+
+        if t <= first.t {
+            return first.value;
+        }
+
+        for w in self.keys.windows(2) {
+            if t <= w[1].t {
+                let len = w[1].t - w[0].t;
+                let f = if len > 0.0 { (t - w[0].t) / len } else { 1.0 };
+                return T::lerp(&w[0].value, &w[1].value, f);
+            }
+        }
+
+        self.keys[self.keys.len() - 1].value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typedef::*;
+
+    #[test]
+    fn sample() {
+        let c = Curve::linear(1.0, 3.0).with_key(0.5, 5.0);
+        assert_eq!(c.keys.len(), 3);
+        assert_eq!(c.sample(-1.0), 1.0);
+        assert_eq!(c.sample(0.25), 3.0);
+        assert_eq!(c.sample(0.5), 5.0);
+        assert_eq!(c.sample(0.75), 4.0);
+        assert_eq!(c.sample(2.0), 3.0);
+
+        // replaced, not added
+        let c = c.with_key(0.5, 0.0);
+        assert_eq!(c.keys.len(), 3);
+        assert_eq!(c.sample(0.5), 0.0);
+
+        assert_eq!(Curve::constant(2.0).sample(0.7), 2.0);
+        assert_eq!(Curve::<f32> { keys: vec![] }.sample(0.7), 0.0);
+
+        let color = Curve::linear(V4::new(1.0, 1.0, 1.0, 1.0), V4::new(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(color.sample(0.5), V4::new(1.0, 0.5, 0.5, 0.5));
+    }
+}
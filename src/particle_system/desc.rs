@@ -0,0 +1,257 @@
+//! Data description of an emitter, loaded from and saved to toml. See `simulation::ParticleEmitter` for the runtime.
+//! # Example
+//! ```toml
+//! name = "sparks"
+//! rate = 40.0
+//! lifetime = [0.5, 1.0]
+//! speed = [2.0, 4.0]
+//! gravity = [0.0, 0.0, -9.8]
+//! blend = "Additive"
+//! size = [{ t = 0.0, value = 0.2 }, { t = 1.0, value = 0.0 }]
+//!
+//! [shape]
+//! type = "Cone"
+//! angle = 20.0
+//! radius = 0.1
+//! ```
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::typedef::*;
+use super::curve::Curve;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum EmitterShape {
+    /// Emit from the emitter position in all directions
+    #[default]
+    Point,
+    /// Emit from a disc in the xy plane, in directions within angle degrees of up
+    Cone { angle: f32, radius: f32 },
+    /// Emit from inside the sphere, or only the surface, going outwards
+    Sphere { radius: f32, surface: bool },
+    /// Emit from inside a box centered on the emitter, going up
+    Box { size: V3 },
+    /// Emit from the surface of a mesh going along the surface normal. The triangles are set with
+    /// `ParticleEmitter::set_mesh_surface`, fx from `GltfMeshes::triangles(name)`
+    Mesh { name: String },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Sorted back to front
+    #[default]
+    Alpha,
+    /// Order independent, good for fire and sparks
+    Additive,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Burst {
+    /// Seconds after the emitter started
+    pub time: f32,
+    pub count: u32,
+}
+
+
+/// Frames of a texture atlas, left to right and top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Atlas {
+    pub columns: u32,
+    pub rows: u32,
+    /// Play all frames over the lifetime, otherwise each particle uses a random frame
+    pub animate: bool,
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self { columns: 1, rows: 1, animate: false }
+    }
+}
+
+impl Atlas {
+    pub fn frames(&self) -> u32 {
+        (self.columns * self.rows).max(1)
+    }
+
+    /// Uv of frame as left, bottom, right, top. Textures are flipped, so the top row of the image is at v = 1
+    pub fn uv(&self, frame: u32) -> [f32; 4] {
+        let columns = self.columns.max(1);
+        let rows = self.rows.max(1);
+        let frame = frame.min(self.frames() - 1);
+
+        let w = 1.0 / columns as f32;
+        let h = 1.0 / rows as f32;
+        let x = (frame % columns) as f32 * w;
+        let top = 1.0 - (frame / columns) as f32 * h;
+
+        [x, top - h, x + w, top]
+    }
+}
+
+
+/// Smooth random force from perlin noise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseForce {
+    pub strength: f32,
+    /// Scale of positions when sampling, higher gives more turbulence
+    pub frequency: f32,
+    /// How fast the noise changes over time
+    pub scroll: f32,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmitterDesc {
+    pub name: String,
+    pub max_particles: usize,
+    /// Particles per second
+    pub rate: f32,
+    pub bursts: Vec::<Burst>,
+    /// Seconds of emission, after that the emitter stops or loops. Bursts repeat each loop
+    pub duration: f32,
+    pub looping: bool,
+    /// Min and max seconds, picked at random per particle
+    pub lifetime: [f32; 2],
+    /// Min and max start speed
+    pub speed: [f32; 2],
+    /// Min and max rotation in radians per second
+    pub rotation_speed: [f32; 2],
+    pub shape: EmitterShape,
+    /// Emit in world space, so particles stay behind when the emitter moves. Otherwise they move with it
+    pub world_space: bool,
+
+    /// Size in world units over lifetime
+    pub size: Curve<f32>,
+    /// Rgba multiplied with the texture over lifetime
+    pub color: Curve<V4>,
+    /// Multiplier of velocity over lifetime
+    pub speed_over_life: Curve<f32>,
+
+    pub gravity: V3,
+    /// Part of the velocity lost per second
+    pub drag: f32,
+    pub noise: NoiseForce,
+
+    /// Path to the texture, plain white quads when None
+    pub texture: Option<String>,
+    pub atlas: Atlas,
+    pub blend: BlendMode,
+}
+
+impl Default for EmitterDesc {
+    fn default() -> Self {
+        Self {
+            name: "emitter".to_string(),
+            max_particles: 1000,
+            rate: 20.0,
+            bursts: vec![],
+            duration: 1.0,
+            looping: true,
+            lifetime: [1.0, 1.0],
+            speed: [1.0, 1.0],
+            rotation_speed: [0.0, 0.0],
+            shape: EmitterShape::Point,
+            world_space: true,
+            size: Curve::constant(0.1),
+            color: Curve::constant(V4::new(1.0, 1.0, 1.0, 1.0)),
+            speed_over_life: Curve::constant(1.0),
+            gravity: V3::new(0.0, 0.0, 0.0),
+            drag: 0.0,
+            noise: NoiseForce::default(),
+            texture: None,
+            atlas: Atlas::default(),
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+impl EmitterDesc {
+
+    pub fn from_toml(s: &str) -> Result<Self, failure::Error> {
+        let mut desc : Self = toml::from_str(s)?;
+        desc.size.sort();
+        desc.color.sort();
+        desc.speed_over_life.sort();
+        desc.bursts.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(desc)
+    }
+
+    pub fn to_toml(&self) -> Result<String, failure::Error> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, failure::Error> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<(), failure::Error> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml() {
+        let desc = EmitterDesc::from_toml(r#"
+            name = "sparks"
+            rate = 40.0
+            lifetime = [0.5, 1.0]
+            gravity = [0.0, 0.0, -9.8]
+            blend = "Additive"
+            size = [{ t = 1.0, value = 0.0 }, { t = 0.0, value = 0.2 }]
+            bursts = [{ time = 0.0, count = 10 }]
+
+            [shape]
+            type = "Cone"
+            angle = 20.0
+            radius = 0.1
+
+            [atlas]
+            columns = 4
+            rows = 2
+        "#).unwrap();
+
+        assert_eq!(desc.name, "sparks");
+        assert_eq!(desc.blend, BlendMode::Additive);
+        assert_eq!(desc.shape, EmitterShape::Cone { angle: 20.0, radius: 0.1 });
+        assert_eq!(desc.gravity, V3::new(0.0, 0.0, -9.8));
+        assert_eq!(desc.atlas.frames(), 8);
+        assert!(!desc.atlas.animate);
+        // keys are sorted
+        assert_eq!(desc.size.sample(0.0), 0.2);
+        // missing fields are default
+        assert_eq!(desc.max_particles, 1000);
+        assert_eq!(desc.color, EmitterDesc::default().color);
+
+        let round_trip = EmitterDesc::from_toml(&desc.to_toml().unwrap()).unwrap();
+        assert_eq!(round_trip, desc);
+
+        let mesh = EmitterDesc { shape: EmitterShape::Mesh { name: "sword".to_string() }, texture: Some("smoke.png".to_string()), ..Default::default() };
+        assert_eq!(EmitterDesc::from_toml(&mesh.to_toml().unwrap()).unwrap(), mesh);
+
+        assert!(EmitterDesc::from_toml("shape = 3").is_err());
+
+        let sparks = EmitterDesc::from_toml(include_str!("../../assets/particles/sparks.toml")).unwrap();
+        assert_eq!(sparks.color.keys.len(), 3);
+    }
+
+    #[test]
+    fn atlas_uv() {
+        let atlas = Atlas { columns: 4, rows: 2, animate: true };
+        assert_eq!(atlas.uv(0), [0.0, 0.5, 0.25, 1.0]);
+        assert_eq!(atlas.uv(5), [0.25, 0.0, 0.5, 0.5]);
+        // clamped to the last frame
+        assert_eq!(atlas.uv(100), atlas.uv(7));
+        assert_eq!(Atlas::default().uv(0), [0.0, 0.0, 1.0, 1.0]);
+    }
+}
@@ -1,3 +1,24 @@
+//! `emitter::Emitter` is a small generic emitter driven by functions. The data driven system is an `desc::EmitterDesc`,
+//! usually loaded from toml, simulated on the cpu by `simulation::ParticleEmitter` and drawn with
+//! `render::ParticleRenderer` as instanced camera facing quads.
+//! # Example
+//! ```ignore
+//! let mut sparks = ParticleEmitter::new(EmitterDesc::load(&"assets/particles/sparks.toml")?);
+//! let mut renderer = ParticleRenderer::new(&gl)?;
+//!
+//! sparks.pos = hit_pos;
+//! sparks.update(dt);
+//! renderer.render(&sparks, &camera);
+//! ```
+//!
+//! `trail::Trail` samples a moving anchor into a ribbon, drawn with `trail_render::TrailRenderer` in 3D or through `Drawer2D`.
+
 pub mod emitter;
 pub mod particle;
 pub mod particle_circle;
+pub mod curve;
+pub mod desc;
+pub mod simulation;
+pub mod render;
+pub mod trail;
+pub mod trail_render;
@@ -0,0 +1,151 @@
+//! Draws a `ParticleEmitter` as camera facing quads, one instanced draw call per emitter.
+use std::collections::HashMap;
+use crate::buffer;
+use crate::gl;
+use crate::texture::{self, TextureId};
+use crate::shader::{BaseShader, Shader};
+use crate::camera::Camera;
+use super::desc::BlendMode;
+use super::simulation::{ParticleEmitter, INSTANCE_SIZE};
+
+
+pub struct ParticleRenderer {
+    gl: gl::Gl,
+    shader: BaseShader,
+    vao: buffer::VertexArray,
+    _quad_vbo: buffer::ArrayBuffer,
+    _ebo: buffer::ElementArrayBuffer,
+    instance_vbo: buffer::ArrayBuffer,
+    instance_data: Vec::<f32>,
+    /// Keyed by path, None when the image could not be loaded
+    textures: HashMap::<String, Option<TextureId>>,
+    white: TextureId,
+}
+
+impl ParticleRenderer {
+
+    pub fn new(gl: &gl::Gl) -> Result<Self, failure::Error> {
+        let vao = buffer::VertexArray::new(gl);
+        let quad_vbo = buffer::ArrayBuffer::new(gl);
+        let ebo = buffer::ElementArrayBuffer::new(gl);
+        let instance_vbo = buffer::ArrayBuffer::new(gl);
+
+        let corners : [f32; 8] = [-0.5, -0.5, 0.5, -0.5, 0.5, 0.5, -0.5, 0.5];
+        let indices : [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+        let f = std::mem::size_of::<f32>();
+        let stride = (INSTANCE_SIZE * f) as gl::types::GLint;
+
+        unsafe {
+            vao.bind();
+
+            quad_vbo.bind();
+            quad_vbo.static_draw_data(&corners);
+            gl.VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, (2 * f) as gl::types::GLint, std::ptr::null());
+            gl.EnableVertexAttribArray(0);
+
+            ebo.bind();
+            ebo.static_draw_data(&indices);
+
+            // per instance pos and size, rotation, color and uv
+            instance_vbo.bind();
+            for (loc, size, offset) in [(1, 4, 0), (2, 1, 4), (3, 4, 5), (4, 4, 9)] {
+                gl.VertexAttribPointer(loc, size, gl::FLOAT, gl::FALSE, stride, (offset * f) as *const gl::types::GLvoid);
+                gl.EnableVertexAttribArray(loc);
+                gl.VertexAttribDivisor(loc, 1);
+            }
+
+            vao.unbind();
+        }
+
+        let white = texture::gen_texture_rgba(gl, &image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])));
+
+        Ok(Self {
+            gl: gl.clone(),
+            shader: Self::create_shader(gl)?,
+            vao,
+            _quad_vbo: quad_vbo,
+            _ebo: ebo,
+            instance_vbo,
+            instance_data: vec![],
+            textures: Default::default(),
+            white,
+        })
+    }
+
+    pub fn create_shader(gl: &gl::Gl) -> Result<BaseShader, failure::Error> {
+        let vert_source = include_str!("../../assets/shaders/objects/particle.vert");
+        let frag_source = include_str!("../../assets/shaders/objects/particle.frag");
+        BaseShader::new(gl, vert_source, frag_source)
+    }
+
+    /// Load an emitter texture up front, otherwise it is loaded on first render
+    pub fn load_texture(&mut self, path: &str) -> Result<TextureId, failure::Error> {
+        if let Some(Some(id)) = self.textures.get(path) {
+            return Ok(*id);
+        }
+
+        let img = image::open(path).map_err(|e| failure::format_err!("Could not load particle texture {:?}: {}", path, e));
+        let id = img.map(|img| texture::gen_texture_rgba(&self.gl, &img.into_rgba8()));
+        self.textures.insert(path.to_string(), id.as_ref().ok().copied());
+        id
+    }
+
+    fn texture(&mut self, path: &Option<String>) -> TextureId {
+        let Some(path) = path else {
+            return self.white;
+        };
+
+        match self.textures.get(path) {
+            Some(id) => id.unwrap_or(self.white),
+            // missing textures draw white, so a wrong path is visible but not fatal
+            None => self.load_texture(path).unwrap_or(self.white),
+        }
+    }
+
+    /// Draw after opaque geometry. Depth is tested but not written, so particles do not hide each other
+    pub fn render(&mut self, emitter: &ParticleEmitter, camera: &Camera) {
+        if emitter.particles.is_empty() {
+            return;
+        }
+
+        let mut data = std::mem::take(&mut self.instance_data);
+        emitter.instance_data(camera.pos(), &mut data);
+
+        let tex = self.texture(&emitter.desc.texture);
+
+        self.shader.set_used();
+        self.shader.set_mat4(&self.gl, "view", camera.view());
+        self.shader.set_mat4(&self.gl, "projection", camera.projection());
+        self.shader.set_i32(&self.gl, "text_map", 0);
+
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            match emitter.desc.blend {
+                BlendMode::Alpha => self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+                BlendMode::Additive => self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE),
+            }
+            self.gl.DepthMask(gl::FALSE);
+            self.gl.ActiveTexture(gl::TEXTURE0);
+        }
+
+        texture::set_texture(&self.gl, tex);
+
+        self.instance_vbo.bind();
+        self.instance_vbo.dynamic_draw_data(&data);
+        self.instance_vbo.unbind();
+
+        self.vao.bind();
+        unsafe {
+            self.gl.DrawElementsInstanced(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null(), (data.len() / INSTANCE_SIZE) as i32);
+        }
+        self.vao.unbind();
+
+        unsafe {
+            self.gl.DepthMask(gl::TRUE);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+
+        self.instance_data = data;
+    }
+}
@@ -0,0 +1,545 @@
+//! Cpu simulation of an `EmitterDesc`. Spawns particles from the emitter shape, applies gravity, drag and noise,
+//! and writes per instance data for `render::ParticleRenderer`.
+use noise::{NoiseFn, Perlin};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::na::UnitQuaternion;
+use crate::typedef::*;
+use crate::collision3d::Triangle;
+use super::desc::{EmitterDesc, EmitterShape, BlendMode};
+
+
+/// Floats per particle in `instance_data`: pos xyz, size, rotation, color rgba and uv left, bottom, right, top
+pub const INSTANCE_SIZE: usize = 13;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParticleData {
+    /// In world space, or relative to the emitter when the emitter is not in world space
+    pub pos: V3,
+    pub vel: V3,
+    pub age: f32,
+    pub lifetime: f32,
+    pub rotation: f32,
+    pub rotation_speed: f32,
+    /// Atlas frame when the atlas is not animated
+    pub frame: u32,
+}
+
+impl ParticleData {
+    /// Age in [0; 1]
+    pub fn life_t(&self) -> f32 {
+        if self.lifetime <= 0.0 {
+            return 1.0;
+        }
+        (self.age / self.lifetime).min(1.0)
+    }
+}
+
+
+/// Triangles with area weights, so points are spread evenly over the surface
+#[derive(Debug, Clone, Default)]
+pub struct MeshSurface {
+    triangles: Vec::<Triangle>,
+    /// Running sum of triangle areas
+    cumulative: Vec::<f32>,
+}
+
+impl MeshSurface {
+
+    pub fn new(triangles: &[Triangle]) -> Self {
+        let mut cumulative = vec![];
+        let mut total = 0.0;
+        for t in triangles {
+            total += (t.v1 - t.v0).cross(&(t.v2 - t.v0)).magnitude() * 0.5;
+            cumulative.push(total);
+        }
+
+        Self { triangles: triangles.to_vec(), cumulative }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.cumulative.last().copied().unwrap_or(0.0)
+    }
+
+    /// Random point on the surface and the normal there
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<(V3, V3)> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let a = rng.gen::<f32>() * area;
+        let i = self.cumulative.partition_point(|c| *c < a).min(self.triangles.len() - 1);
+        let t = &self.triangles[i];
+
+        // uniform in the triangle by folding the unit square
+        let (mut u, mut v) = (rng.gen::<f32>(), rng.gen::<f32>());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+
+        Some((t.v0 + (t.v1 - t.v0) * u + (t.v2 - t.v0) * v, t.normal))
+    }
+}
+
+
+pub struct ParticleEmitter {
+    pub desc: EmitterDesc,
+    pub particles: Vec::<ParticleData>,
+    pub pos: V3,
+    pub rotation: UnitQuaternion::<f32>,
+    /// Seconds into the current loop
+    pub time: f32,
+    pub emitting: bool,
+    spawn_acc: f32,
+    next_burst: usize,
+    rng: StdRng,
+    noise: Perlin,
+    total_time: f32,
+    surface: Option<MeshSurface>,
+}
+
+impl ParticleEmitter {
+
+    pub fn new(desc: EmitterDesc) -> Self {
+        Self::with_seed(desc, rand::thread_rng().gen())
+    }
+
+    /// Same seed and same updates give the same particles, fx for tests and replays
+    pub fn with_seed(desc: EmitterDesc, seed: u64) -> Self {
+        Self {
+            particles: Vec::with_capacity(desc.max_particles.min(4096)),
+            desc,
+            pos: V3::new(0.0, 0.0, 0.0),
+            rotation: UnitQuaternion::identity(),
+            time: 0.0,
+            emitting: true,
+            spawn_acc: 0.0,
+            next_burst: 0,
+            rng: StdRng::seed_from_u64(seed),
+            noise: Perlin::new(seed as u32),
+            total_time: 0.0,
+            surface: None,
+        }
+    }
+
+    /// Triangles for `EmitterShape::Mesh`, in emitter space
+    pub fn set_mesh_surface(&mut self, triangles: &[Triangle]) {
+        self.surface = Some(MeshSurface::new(triangles));
+    }
+
+    /// Start emitting from the beginning, keeps alive particles
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+        self.spawn_acc = 0.0;
+        self.next_burst = 0;
+        self.emitting = true;
+    }
+
+    /// Stop emitting, alive particles live out their life
+    pub fn stop(&mut self) {
+        self.emitting = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Not emitting and no particles alive, so it can be removed
+    pub fn is_finished(&self) -> bool {
+        !self.emitting && self.particles.is_empty()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.total_time += dt;
+        self.simulate(dt);
+
+        if !self.emitting {
+            return;
+        }
+
+        let mut spawn = 0;
+        let mut remaining = dt;
+        loop {
+            let duration = self.desc.duration;
+            let step = if duration > 0.0 { remaining.min(duration - self.time).max(0.0) } else { remaining };
+
+            self.spawn_acc += self.desc.rate * step;
+            let end = self.time + step;
+            while let Some(b) = self.desc.bursts.get(self.next_burst) {
+                if b.time > end {
+                    break;
+                }
+                spawn += b.count as usize;
+                self.next_burst += 1;
+            }
+
+            self.time = end;
+            remaining -= step;
+
+            if duration <= 0.0 || self.time < duration {
+                break;
+            }
+
+            if !self.desc.looping {
+                self.emitting = false;
+                break;
+            }
+
+            self.time = 0.0;
+            self.next_burst = 0;
+
+            if remaining <= 0.0 {
+                break;
+            }
+        }
+
+        spawn += self.spawn_acc as usize;
+        self.spawn_acc = self.spawn_acc.fract();
+
+        self.burst(spawn);
+    }
+
+    /// Spawn count particles now, limited by max particles
+    pub fn burst(&mut self, count: usize) {
+        let count = count.min(self.desc.max_particles.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            let p = self.spawn();
+            self.particles.push(p);
+        }
+    }
+
+    fn range(&mut self, r: [f32; 2]) -> f32 {
+        if r[1] > r[0] {
+            self.rng.gen_range(r[0]..r[1])
+        } else {
+            r[0]
+        }
+    }
+
+    fn unit_vector(&mut self) -> V3 {
+        // uniform on the sphere
+        let z = self.rng.gen_range(-1.0_f32..1.0);
+        let a = self.rng.gen_range(0.0..std::f32::consts::TAU);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        V3::new(r * a.cos(), r * a.sin(), z)
+    }
+
+    /// Position and direction in emitter space
+    fn shape_sample(&mut self) -> (V3, V3) {
+        let up = V3::new(0.0, 0.0, 1.0);
+        match self.desc.shape {
+            EmitterShape::Point => (V3::new(0.0, 0.0, 0.0), self.unit_vector()),
+            EmitterShape::Cone { angle, radius } => {
+                let r = radius * self.rng.gen::<f32>().sqrt();
+                let a = self.rng.gen_range(0.0..std::f32::consts::TAU);
+                let pos = V3::new(r * a.cos(), r * a.sin(), 0.0);
+
+                // uniform over the spherical cap
+                let cos_max = angle.to_radians().cos();
+                let z = self.rng.gen_range(cos_max.min(1.0)..=1.0);
+                let b = self.rng.gen_range(0.0..std::f32::consts::TAU);
+                let s = (1.0 - z * z).max(0.0).sqrt();
+                (pos, V3::new(s * b.cos(), s * b.sin(), z))
+            },
+            EmitterShape::Sphere { radius, surface } => {
+                let dir = self.unit_vector();
+                let r = if surface { radius } else { radius * self.rng.gen::<f32>().cbrt() };
+                (dir * r, dir)
+            },
+            EmitterShape::Box { size } => {
+                let pos = V3::new(self.rng.gen::<f32>() - 0.5, self.rng.gen::<f32>() - 0.5, self.rng.gen::<f32>() - 0.5).component_mul(&size);
+                (pos, up)
+            },
+            EmitterShape::Mesh { .. } => {
+                let rng = &mut self.rng;
+                let sample = self.surface.as_ref().and_then(|s| s.sample(rng));
+                sample.unwrap_or((V3::new(0.0, 0.0, 0.0), up))
+            },
+        }
+    }
+
+    fn spawn(&mut self) -> ParticleData {
+        let (pos, dir) = self.shape_sample();
+        let speed = self.range(self.desc.speed);
+
+        let (pos, vel) = if self.desc.world_space {
+            (self.pos + self.rotation * pos, self.rotation * dir * speed)
+        } else {
+            (pos, dir * speed)
+        };
+
+        let frame = if self.desc.atlas.animate { 0 } else { self.rng.gen_range(0..self.desc.atlas.frames()) };
+
+        ParticleData {
+            pos,
+            vel,
+            age: 0.0,
+            lifetime: self.range(self.desc.lifetime),
+            rotation: self.rng.gen_range(0.0..std::f32::consts::TAU),
+            rotation_speed: self.range(self.desc.rotation_speed),
+            frame,
+        }
+    }
+
+    fn simulate(&mut self, dt: f32) {
+        let desc = &self.desc;
+
+        // gravity is in world space, so turn it in to emitter space for local particles
+        let gravity = if desc.world_space { desc.gravity } else { self.rotation.inverse() * desc.gravity };
+        let drag = (1.0 - desc.drag * dt).max(0.0);
+        let noise = desc.noise;
+        let scroll = (self.total_time * noise.scroll) as f64;
+
+        let mut i = 0;
+        while i < self.particles.len() {
+            let p = &mut self.particles[i];
+            p.age += dt;
+            if p.age >= p.lifetime {
+                self.particles.swap_remove(i);
+                continue;
+            }
+
+            p.vel += gravity * dt;
+
+            if noise.strength != 0.0 {
+                let s = p.pos * noise.frequency;
+                let (x, y, z) = (s.x as f64, s.y as f64, s.z as f64);
+                // offset the channels so they are not the same
+                let n = V3::new(
+                    self.noise.get([x, y, z, scroll]) as f32,
+                    self.noise.get([x + 31.4, y, z, scroll]) as f32,
+                    self.noise.get([x, y + 71.7, z, scroll]) as f32);
+                p.vel += n * noise.strength * dt;
+            }
+
+            p.vel *= drag;
+            p.pos += p.vel * desc.speed_over_life.sample(p.life_t()) * dt;
+            p.rotation += p.rotation_speed * dt;
+
+            i += 1;
+        }
+    }
+
+    /// World position of a particle
+    pub fn world_pos(&self, p: &ParticleData) -> V3 {
+        if self.desc.world_space {
+            p.pos
+        } else {
+            self.pos + self.rotation * p.pos
+        }
+    }
+
+    /// Per particle instance data, see `INSTANCE_SIZE`. Alpha blended particles are sorted back to front from camera_pos
+    pub fn instance_data(&self, camera_pos: V3, out: &mut Vec::<f32>) {
+        out.clear();
+        out.reserve(self.particles.len() * INSTANCE_SIZE);
+
+        let mut order : Vec::<(f32, usize)> = self.particles.iter().enumerate()
+            .map(|(i, p)| ((self.world_pos(p) - camera_pos).magnitude_squared(), i))
+            .collect();
+
+        if self.desc.blend == BlendMode::Alpha {
+            order.sort_by(|a, b| b.0.total_cmp(&a.0));
+        }
+
+        let atlas = &self.desc.atlas;
+        for (_, i) in order {
+            let p = &self.particles[i];
+            let t = p.life_t();
+
+            let pos = self.world_pos(p);
+            let color = self.desc.color.sample(t);
+            let frame = if atlas.animate { (t * atlas.frames() as f32) as u32 } else { p.frame };
+            let uv = atlas.uv(frame);
+
+            out.extend_from_slice(&[pos.x, pos.y, pos.z, self.desc.size.sample(t), p.rotation, color.x, color.y, color.z, color.w]);
+            out.extend_from_slice(&uv);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle_system::desc::Burst;
+    use crate::particle_system::curve::Curve;
+
+    fn desc() -> EmitterDesc {
+        EmitterDesc {
+            rate: 10.0,
+            duration: 1.0,
+            looping: false,
+            lifetime: [0.5, 0.5],
+            speed: [1.0, 1.0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn emission() {
+        let mut e = ParticleEmitter::with_seed(desc(), 1);
+
+        e.update(0.25);
+        assert_eq!(e.particles.len(), 2);
+
+        e.update(0.25);
+        assert_eq!(e.particles.len(), 5);
+
+        // first ones die after half a second
+        e.update(0.3);
+        assert_eq!(e.particles.len(), 6);
+
+        // stops after duration, and is finished when the last particle dies
+        for _ in 0..3 {
+            e.update(0.1);
+        }
+        assert!(!e.emitting);
+        assert!(!e.is_finished());
+        e.update(1.0);
+        assert!(e.is_finished());
+
+        // max particles
+        let mut e = ParticleEmitter::with_seed(EmitterDesc { max_particles: 5, ..desc() }, 1);
+        e.burst(100);
+        assert_eq!(e.particles.len(), 5);
+    }
+
+    #[test]
+    fn bursts_and_looping() {
+        let d = EmitterDesc {
+            rate: 0.0,
+            looping: true,
+            lifetime: [10.0, 10.0],
+            bursts: vec![Burst { time: 0.0, count: 3 }, Burst { time: 0.5, count: 2 }],
+            ..desc()
+        };
+        let mut e = ParticleEmitter::with_seed(d, 1);
+
+        e.update(0.1);
+        assert_eq!(e.particles.len(), 3);
+        e.update(0.3);
+        assert_eq!(e.particles.len(), 3);
+        e.update(0.2);
+        assert_eq!(e.particles.len(), 5);
+
+        // second loop
+        e.update(0.5);
+        assert_eq!(e.particles.len(), 8);
+        assert!(e.emitting);
+        assert!(e.time < 0.2);
+
+        // a long step covers several loops
+        e.update(2.0);
+        assert_eq!(e.particles.len(), 18);
+    }
+
+    #[test]
+    fn forces() {
+        let d = EmitterDesc {
+            rate: 0.0,
+            lifetime: [10.0, 10.0],
+            speed: [0.0, 0.0],
+            gravity: V3::new(0.0, 0.0, -10.0),
+            ..desc()
+        };
+        let mut e = ParticleEmitter::with_seed(d.clone(), 1);
+        e.pos = V3::new(1.0, 2.0, 3.0);
+        e.burst(1);
+        for _ in 0..10 {
+            e.update(0.1);
+        }
+        let p = e.particles[0];
+        assert!((p.vel.z + 10.0).abs() < 0.001);
+        assert!(p.pos.z < 3.0 && p.pos.z > -3.0);
+        assert_eq!((p.pos.x, p.pos.y), (1.0, 2.0));
+
+        // drag slows down
+        let mut e = ParticleEmitter::with_seed(EmitterDesc { gravity: V3::zeros(), speed: [5.0, 5.0], drag: 1.0, ..d.clone() }, 1);
+        e.burst(1);
+        e.update(0.5);
+        assert!((e.particles[0].vel.magnitude() - 2.5).abs() < 0.001);
+
+        // speed over life scales movement, not velocity
+        let mut e = ParticleEmitter::with_seed(EmitterDesc { gravity: V3::zeros(), speed: [5.0, 5.0], speed_over_life: Curve::constant(0.0), ..d.clone() }, 1);
+        e.burst(1);
+        e.update(0.5);
+        assert_eq!(e.particles[0].pos, V3::zeros());
+
+        // local particles follow the emitter
+        let mut e = ParticleEmitter::with_seed(EmitterDesc { world_space: false, ..d }, 1);
+        e.burst(1);
+        e.pos = V3::new(5.0, 0.0, 0.0);
+        assert_eq!(e.world_pos(&e.particles[0]), V3::new(5.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn shapes() {
+        let check = |shape: EmitterShape, f: &dyn Fn(&ParticleData) -> bool| {
+            let mut e = ParticleEmitter::with_seed(EmitterDesc { shape, lifetime: [1.0, 1.0], ..desc() }, 7);
+            e.burst(200);
+            assert!(e.particles.iter().all(f));
+        };
+
+        check(EmitterShape::Point, &|p| p.pos == V3::zeros() && (p.vel.magnitude() - 1.0).abs() < 0.001);
+        check(EmitterShape::Cone { angle: 30.0, radius: 0.5 }, &|p| {
+            p.pos.z == 0.0 && p.pos.magnitude() <= 0.5 && p.vel.z >= 30.0_f32.to_radians().cos() - 0.001
+        });
+        check(EmitterShape::Sphere { radius: 2.0, surface: true }, &|p| (p.pos.magnitude() - 2.0).abs() < 0.001);
+        check(EmitterShape::Sphere { radius: 2.0, surface: false }, &|p| p.pos.magnitude() <= 2.0);
+        check(EmitterShape::Box { size: V3::new(2.0, 4.0, 0.0) }, &|p| p.pos.x.abs() <= 1.0 && p.pos.y.abs() <= 2.0 && p.pos.z == 0.0);
+
+        // mesh surface, one large and one tiny triangle
+        let big = Triangle::new(V3::new(0.0, 0.0, 0.0), V3::new(10.0, 0.0, 0.0), V3::new(0.0, 10.0, 0.0));
+        let small = Triangle::new(V3::new(0.0, 0.0, 5.0), V3::new(0.1, 0.0, 5.0), V3::new(0.0, 0.1, 5.0));
+        let mut e = ParticleEmitter::with_seed(EmitterDesc { shape: EmitterShape::Mesh { name: "m".to_string() }, ..desc() }, 3);
+        e.set_mesh_surface(&[small, big]);
+        e.burst(200);
+        let on_big = e.particles.iter().filter(|p| p.pos.z.abs() < 0.001).count();
+        assert!(on_big > 190);
+        assert!(e.particles.iter().all(|p| p.pos.x + p.pos.y <= 10.001 && (p.vel - V3::new(0.0, 0.0, 1.0)).magnitude() < 0.001));
+    }
+
+    #[test]
+    fn instances() {
+        let d = EmitterDesc {
+            rate: 0.0,
+            size: Curve::linear(1.0, 0.0),
+            color: Curve::linear(V4::new(1.0, 1.0, 1.0, 1.0), V4::new(1.0, 1.0, 1.0, 0.0)),
+            speed: [0.0, 0.0],
+            lifetime: [1.0, 1.0],
+            ..desc()
+        };
+        let mut e = ParticleEmitter::with_seed(d, 1);
+        e.burst(1);
+        e.update(0.5);
+        e.pos = V3::new(0.0, 0.0, 10.0);
+        e.burst(1);
+
+        let mut out = vec![];
+        e.instance_data(V3::new(0.0, 0.0, 20.0), &mut out);
+        assert_eq!(out.len(), 2 * INSTANCE_SIZE);
+
+        // far one first
+        assert_eq!(&out[0..4], &[0.0, 0.0, 0.0, 0.5]);
+        assert_eq!(out[8], 0.5);
+        assert_eq!(&out[INSTANCE_SIZE..INSTANCE_SIZE + 4], &[0.0, 0.0, 10.0, 1.0]);
+        assert_eq!(&out[9..13], &[0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn deterministic() {
+        let d = EmitterDesc { shape: EmitterShape::Sphere { radius: 1.0, surface: false }, noise: crate::particle_system::desc::NoiseForce { strength: 2.0, frequency: 1.0, scroll: 1.0 }, ..desc() };
+        let run = || {
+            let mut e = ParticleEmitter::with_seed(d.clone(), 42);
+            for _ in 0..20 {
+                e.update(1.0 / 60.0);
+            }
+            e.particles
+        };
+        assert_eq!(run(), run());
+    }
+}
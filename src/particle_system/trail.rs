@@ -0,0 +1,367 @@
+//! Motion trails, fx behind a sword swing or a projectile. A `Trail` samples an anchor point over time and builds a
+//! triangle strip through the samples, with width and color over the age of each sample. See `trail_render` for drawing.
+use serde::{Serialize, Deserialize};
+use crate::typedef::*;
+use super::curve::Curve;
+use super::desc::BlendMode;
+
+
+/// Floats per strip vertex, pos and rgba
+pub const TRAIL_VERTEX_SIZE: usize = 7;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TrailOrientation {
+    /// Width is across the trail and the direction to the camera, so the strip is seen from the front
+    #[default]
+    FaceCamera,
+    /// Width is across the trail in the plane with this normal, fx z for 2D
+    Fixed { normal: V3 },
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrailDesc {
+    /// Seconds a sample lives
+    pub lifetime: f32,
+    /// Distance the anchor has to move before a new sample is added
+    pub min_distance: f32,
+    /// Oldest samples are dropped above this
+    pub max_points: usize,
+    /// Width in world units over the age of a sample, as a fraction of lifetime
+    pub width: Curve<f32>,
+    /// Rgba over the age of a sample, let alpha go to 0 to fade out
+    pub color: Curve<V4>,
+    pub orientation: TrailOrientation,
+    pub blend: BlendMode,
+}
+
+impl Default for TrailDesc {
+    fn default() -> Self {
+        Self {
+            lifetime: 0.3,
+            min_distance: 0.05,
+            max_points: 64,
+            width: Curve::linear(0.2, 0.0),
+            color: Curve::linear(V4::new(1.0, 1.0, 1.0, 1.0), V4::new(1.0, 1.0, 1.0, 0.0)),
+            orientation: TrailOrientation::FaceCamera,
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailPoint {
+    pub pos: V3,
+    pub age: f32,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Trail {
+    pub desc: TrailDesc,
+    /// Oldest first
+    pub points: Vec::<TrailPoint>,
+    /// Latest anchor position, the front of the strip even when closer than min_distance to the last sample
+    pub head: Option<V3>,
+    /// When false no samples are added and the trail fades out
+    pub emitting: bool,
+}
+
+impl Trail {
+
+    pub fn new(desc: TrailDesc) -> Self {
+        Self { desc, points: vec![], head: None, emitting: true }
+    }
+
+    /// Age samples and add the anchor as a new sample when it moved far enough
+    pub fn update(&mut self, dt: f32, anchor: V3) {
+        let lifetime = self.desc.lifetime;
+        for p in &mut self.points {
+            p.age += dt;
+        }
+        self.points.retain(|p| p.age < lifetime);
+
+        if !self.emitting {
+            return;
+        }
+
+        self.head = Some(anchor);
+
+        let moved = self.points.last().map(|p| (p.pos - anchor).magnitude() >= self.desc.min_distance).unwrap_or(true);
+        if moved {
+            self.points.push(TrailPoint { pos: anchor, age: 0.0 });
+        }
+
+        if self.points.len() > self.desc.max_points {
+            let extra = self.points.len() - self.desc.max_points;
+            self.points.drain(0..extra);
+        }
+    }
+
+    /// Update with a 2D anchor, fx a sprite position for `Drawer2D`
+    pub fn update_2d(&mut self, dt: f32, anchor: V2) {
+        self.update(dt, V3::new(anchor.x, anchor.y, 0.0));
+    }
+
+    /// Stop adding samples, the trail fades out over its lifetime
+    pub fn stop(&mut self) {
+        self.emitting = false;
+        self.head = None;
+    }
+
+    /// Start adding samples again, without connecting to the samples from before
+    pub fn start(&mut self) {
+        self.clear();
+        self.emitting = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.head = None;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.emitting && self.points.is_empty()
+    }
+
+    /// Samples newest first, starting with the head when it is not a sample
+    fn samples(&self) -> Vec::<TrailPoint> {
+        let mut res = Vec::<TrailPoint>::with_capacity(self.points.len() + 1);
+
+        if let Some(head) = self.head {
+            if self.points.last().map(|p| p.pos != head).unwrap_or(true) {
+                res.push(TrailPoint { pos: head, age: 0.0 });
+            }
+        }
+
+        res.extend(self.points.iter().rev());
+        res
+    }
+
+    /// Append the strip with the orientation of the desc to out, two vertices per sample from the head backwards.
+    /// Draw as a triangle strip. view_pos is only used to face the camera
+    pub fn build_strip(&self, view_pos: V3, out: &mut Vec::<f32>) {
+        self.build_strip_with(self.desc.orientation, view_pos, out);
+    }
+
+    /// Like `build_strip` in the xy plane, for `Drawer2D`
+    pub fn build_strip_2d(&self, out: &mut Vec::<f32>) {
+        self.build_strip_with(TrailOrientation::Fixed { normal: V3::z() }, V3::zeros(), out);
+    }
+
+    pub fn build_strip_with(&self, orientation: TrailOrientation, view_pos: V3, out: &mut Vec::<f32>) {
+        let samples = self.samples();
+        if samples.len() < 2 {
+            return;
+        }
+
+        let lifetime = self.desc.lifetime.max(0.0001);
+        let mut last_side = None;
+
+        for (i, s) in samples.iter().enumerate() {
+            // direction from the older neighbour to the newer, one sided at the ends
+            let newer = samples[i.saturating_sub(1)].pos;
+            let older = samples[(i + 1).min(samples.len() - 1)].pos;
+            let dir = newer - older;
+
+            let normal = match orientation {
+                TrailOrientation::FaceCamera => view_pos - s.pos,
+                TrailOrientation::Fixed { normal } => normal,
+            };
+
+            // keep the previous side where the trail doubles back or points at the camera
+            let side = dir.cross(&normal).try_normalize(0.00001).or(last_side).unwrap_or_else(V3::zeros);
+            last_side = Some(side);
+
+            let t = s.age / lifetime;
+            let half = side * (self.desc.width.sample(t) * 0.5);
+            let color = self.desc.color.sample(t);
+
+            for p in [s.pos + half, s.pos - half] {
+                out.extend_from_slice(&[p.x, p.y, p.z, color.x, color.y, color.z, color.w]);
+            }
+        }
+    }
+}
+
+
+/// Trails keyed by what they follow, fx an arrow or an attack animation. A new key starts a new trail, so the
+/// previous one fades out instead of being cleared or joined to the new one
+#[derive(Debug, Clone)]
+pub struct TrailGroup<K> {
+    pub desc: TrailDesc,
+    /// Oldest first, only the last one can be emitting
+    pub trails: Vec::<(K, Trail)>,
+}
+
+impl<K: PartialEq> TrailGroup<K> {
+
+    pub fn new(desc: TrailDesc) -> Self {
+        Self { desc, trails: vec![] }
+    }
+
+    /// Follow the anchor with the trail of its key. None stops the current trail. Faded out trails are removed
+    pub fn update(&mut self, dt: f32, anchor: Option<(K, V3)>) {
+        let follow = anchor.map(|(key, pos)| {
+            let same = matches!(self.trails.last(), Some((k, t)) if t.emitting && *k == key);
+            if !same {
+                self.trails.push((key, Trail::new(self.desc.clone())));
+            }
+            (self.trails.len() - 1, pos)
+        });
+
+        for (i, (_, trail)) in self.trails.iter_mut().enumerate() {
+            match follow {
+                Some((last, pos)) if i == last => trail.update(dt, pos),
+                _ => {
+                    trail.stop();
+                    trail.update(dt, V3::zeros());
+                }
+            }
+        }
+
+        self.trails.retain(|(_, t)| !t.is_finished());
+    }
+
+    /// Like `update` with a 2D anchor, for `Drawer2D`
+    pub fn update_2d(&mut self, dt: f32, anchor: Option<(K, V2)>) {
+        self.update(dt, anchor.map(|(key, p)| (key, V3::new(p.x, p.y, 0.0))));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trail> {
+        self.trails.iter().map(|(_, t)| t)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(strip: &[f32], i: usize) -> (V3, V4) {
+        let v = &strip[i * TRAIL_VERTEX_SIZE..];
+        (V3::new(v[0], v[1], v[2]), V4::new(v[3], v[4], v[5], v[6]))
+    }
+
+    fn close(a: V3, b: V3) -> bool {
+        (a - b).magnitude() < 0.001
+    }
+
+    #[test]
+    fn sampling() {
+        let mut trail = Trail::new(TrailDesc { lifetime: 1.0, min_distance: 0.5, max_points: 3, ..Default::default() });
+
+        trail.update(0.1, V3::zeros());
+        assert_eq!(trail.points.len(), 1);
+
+        // too close for a new sample, but the head follows the anchor
+        trail.update(0.1, V3::new(0.2, 0.0, 0.0));
+        assert_eq!(trail.points.len(), 1);
+        assert_eq!(trail.head, Some(V3::new(0.2, 0.0, 0.0)));
+        assert!((trail.points[0].age - 0.1).abs() < 0.0001);
+
+        for x in 1..5 {
+            trail.update(0.1, V3::new(x as f32, 0.0, 0.0));
+        }
+        assert_eq!(trail.points.len(), 3);
+        assert_eq!(trail.points[0].pos, V3::new(2.0, 0.0, 0.0));
+
+        trail.stop();
+        assert!(!trail.is_finished());
+        trail.update(0.5, V3::new(10.0, 0.0, 0.0));
+        assert_eq!(trail.points.len(), 3);
+        trail.update(0.5, V3::new(10.0, 0.0, 0.0));
+        assert!(trail.is_finished());
+    }
+
+    #[test]
+    fn strip_2d() {
+        let desc = TrailDesc {
+            lifetime: 1.0,
+            min_distance: 0.0,
+            width: Curve::constant(1.0),
+            color: Curve::linear(V4::new(1.0, 1.0, 1.0, 1.0), V4::new(1.0, 1.0, 1.0, 0.0)),
+            ..Default::default()
+        };
+        let mut trail = Trail::new(desc);
+
+        let mut strip = vec![];
+        trail.update_2d(0.0, V2::new(0.0, 0.0));
+        trail.build_strip_2d(&mut strip);
+        assert!(strip.is_empty());
+
+        trail.update_2d(0.5, V2::new(1.0, 0.0));
+        trail.update_2d(0.25, V2::new(2.0, 0.0));
+        trail.build_strip_2d(&mut strip);
+        assert_eq!(strip.len(), 6 * TRAIL_VERTEX_SIZE);
+
+        // newest first, width across the trail in the xy plane
+        let (p, c) = vertex(&strip, 0);
+        assert!(close(p, V3::new(2.0, -0.5, 0.0)));
+        assert_eq!(c.w, 1.0);
+        let (p, _) = vertex(&strip, 1);
+        assert!(close(p, V3::new(2.0, 0.5, 0.0)));
+
+        // faded by age
+        let (p, c) = vertex(&strip, 4);
+        assert!(close(p, V3::new(0.0, -0.5, 0.0)));
+        assert!((c.w - 0.25).abs() < 0.0001);
+    }
+
+    #[test]
+    fn strip_faces_camera() {
+        let desc = TrailDesc { min_distance: 0.0, width: Curve::constant(2.0), ..Default::default() };
+        let mut trail = Trail::new(desc);
+        trail.update(0.0, V3::zeros());
+        trail.update(0.0, V3::new(1.0, 0.0, 0.0));
+
+        // seen from above the width is along y
+        let mut strip = vec![];
+        trail.build_strip(V3::new(0.5, 0.0, 10.0), &mut strip);
+        assert!(close(vertex(&strip, 0).0, V3::new(1.0, -1.0, 0.0)));
+
+        // seen from the side the width is along z
+        strip.clear();
+        trail.build_strip(V3::new(0.5, -10.0, 0.0), &mut strip);
+        let (p, _) = vertex(&strip, 0);
+        assert!(close(p, V3::new(1.0, 0.0, 1.0)) || close(p, V3::new(1.0, 0.0, -1.0)));
+
+        // head closer than min_distance is still the front of the strip
+        let mut trail = Trail::new(TrailDesc { min_distance: 1.0, ..Default::default() });
+        trail.update(0.0, V3::zeros());
+        trail.update(0.0, V3::new(0.5, 0.0, 0.0));
+        strip.clear();
+        trail.build_strip(V3::new(0.0, 0.0, 10.0), &mut strip);
+        assert_eq!(strip.len(), 4 * TRAIL_VERTEX_SIZE);
+    }
+
+    #[test]
+    fn group_keeps_fading_trail() {
+        let mut group = TrailGroup::new(TrailDesc { lifetime: 0.5, min_distance: 0.0, ..Default::default() });
+
+        group.update_2d(0.1, Some((1, V2::new(0.0, 0.0))));
+        group.update_2d(0.1, Some((1, V2::new(1.0, 0.0))));
+        assert_eq!(group.trails.len(), 1);
+        assert_eq!(group.trails[0].1.points.len(), 2);
+
+        // a new key right away starts a new trail, the old one fades out
+        group.update_2d(0.1, Some((2, V2::new(5.0, 0.0))));
+        assert_eq!(group.trails.len(), 2);
+        assert!(!group.trails[0].1.emitting);
+        assert_eq!(group.trails[0].1.points.len(), 2);
+        assert_eq!(group.trails[1].1.points[0].pos, V3::new(5.0, 0.0, 0.0));
+
+        // the same key again after a stop is also a new trail
+        group.update_2d(0.1, None);
+        group.update_2d(0.1, Some((2, V2::new(6.0, 0.0))));
+        assert_eq!(group.iter().filter(|t| t.emitting).count(), 1);
+        assert_eq!(group.trails.last().unwrap().1.points.len(), 1);
+
+        group.update_2d(1.0, None);
+        assert!(group.trails.is_empty());
+    }
+}
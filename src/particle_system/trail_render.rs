@@ -0,0 +1,111 @@
+//! Draws a `Trail` as a triangle strip, in 3D with a `Camera` or in 2D with the current camera of a `Drawer2D`.
+use crate::buffer;
+use crate::gl;
+use crate::shader::{BaseShader, Shader};
+use crate::camera::Camera;
+use crate::imode_gui::drawer2d::Drawer2D;
+use crate::typedef::*;
+use super::desc::BlendMode;
+use super::trail::{Trail, TRAIL_VERTEX_SIZE};
+
+
+pub struct TrailRenderer {
+    gl: gl::Gl,
+    shader: BaseShader,
+    vao: buffer::VertexArray,
+    vbo: buffer::ArrayBuffer,
+    vertices: Vec::<f32>,
+}
+
+impl TrailRenderer {
+
+    pub fn new(gl: &gl::Gl) -> Result<Self, failure::Error> {
+        let vao = buffer::VertexArray::new(gl);
+        let vbo = buffer::ArrayBuffer::new(gl);
+
+        let f = std::mem::size_of::<f32>();
+        let stride = (TRAIL_VERTEX_SIZE * f) as gl::types::GLint;
+
+        unsafe {
+            vao.bind();
+            vbo.bind();
+
+            // pos and color
+            for (loc, size, offset) in [(0, 3, 0), (1, 4, 3)] {
+                gl.VertexAttribPointer(loc, size, gl::FLOAT, gl::FALSE, stride, (offset * f) as *const gl::types::GLvoid);
+                gl.EnableVertexAttribArray(loc);
+            }
+
+            vao.unbind();
+        }
+
+        Ok(Self {
+            gl: gl.clone(),
+            shader: Self::create_shader(gl)?,
+            vao,
+            vbo,
+            vertices: vec![],
+        })
+    }
+
+    pub fn create_shader(gl: &gl::Gl) -> Result<BaseShader, failure::Error> {
+        let vert_source = include_str!("../../assets/shaders/objects/trail.vert");
+        let frag_source = include_str!("../../assets/shaders/objects/trail.frag");
+        BaseShader::new(gl, vert_source, frag_source)
+    }
+
+    /// Draw after opaque geometry. Depth is tested but not written
+    pub fn render(&mut self, trail: &Trail, camera: &Camera) {
+        let mut vertices = std::mem::take(&mut self.vertices);
+        vertices.clear();
+        trail.build_strip(camera.pos(), &mut vertices);
+
+        self.draw(&vertices, camera.projection() * camera.view(), trail.desc.blend);
+        self.vertices = vertices;
+    }
+
+    /// Draw a trail updated with `Trail::update_2d`, in the same space as other `Drawer2D` calls
+    pub fn render_2d(&mut self, trail: &Trail, drawer: &Drawer2D) {
+        let mut vertices = std::mem::take(&mut self.vertices);
+        vertices.clear();
+        trail.build_strip_2d(&mut vertices);
+
+        self.draw(&vertices, drawer.screen_transform(), trail.desc.blend);
+        self.vertices = vertices;
+    }
+
+    fn draw(&self, vertices: &[f32], transform: Mat4, blend: BlendMode) {
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.shader.set_used();
+        self.shader.set_mat4(&self.gl, "transform", transform);
+
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            match blend {
+                BlendMode::Alpha => self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+                BlendMode::Additive => self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE),
+            }
+            self.gl.DepthMask(gl::FALSE);
+            // the strip twists when the trail turns, so both sides are drawn
+            self.gl.Disable(gl::CULL_FACE);
+        }
+
+        self.vbo.bind();
+        self.vbo.dynamic_draw_data(vertices);
+        self.vbo.unbind();
+
+        self.vao.bind();
+        unsafe {
+            self.gl.DrawArrays(gl::TRIANGLE_STRIP, 0, (vertices.len() / TRAIL_VERTEX_SIZE) as i32);
+        }
+        self.vao.unbind();
+
+        unsafe {
+            self.gl.DepthMask(gl::TRUE);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use std::rc::Rc;
 use crate::scene_3d::scene_3d::EntityId;
+use crate::scene_3d::effects::EffectTarget;
 use std::collections::VecDeque;
 
 
 
 pub type ActionQueue = VecDeque::<Action>;
 
+/// Map a fired animation event on an entity to an action, fx footstep to PlaySound
+pub type AnimationEventFn = fn(EntityId) -> Action;
+
 // Generic actions, so StartAnimation, Plays sound
 // and not Attack, Roll ect.
 pub enum Action {
@@ -14,5 +18,6 @@ pub enum Action {
     StartAnimation(EntityId, Rc::<str>, f32),
     StartAnimationLooped(EntityId, Rc::<str>, f32),
     PlaySound(Rc::<str>),
-    //SpawnParticle(String"name", loc, other info if needed)
+    /// Start a particle effect registered in `Scene::effects` by name
+    SpawnEffect(Rc::<str>, EffectTarget),
 }
@@ -0,0 +1,79 @@
+//! Components for entities in `Scene::ecs`. Entities with both a `Transform` and a `MeshRef` are rendered,
+//! without needing a `SceneEntity`. Scene entities share ids with the world, so components can be added to them too.
+use crate::typedef::*;
+use crate::na::{Translation3, UnitQuaternion};
+use crate::ecs::World;
+use crate::scene_3d::{MeshIndex, RenderPipelineId};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub pos: V3,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: V3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            pos: V3::new(0.0, 0.0, 0.0),
+            rotation: UnitQuaternion::identity(),
+            scale: V3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+
+    pub fn from_pos(pos: V3) -> Self {
+        Self { pos, ..Default::default() }
+    }
+
+    pub fn model_mat(&self) -> Mat4 {
+        Translation3::from(self.pos).to_homogeneous() * self.rotation.to_homogeneous() * Mat4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshRef {
+    pub mesh_id: MeshIndex,
+    pub render_pipeline_id: RenderPipelineId,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Velocity {
+    pub velocity: V3,
+    pub acceleration: V3,
+}
+
+
+/// Move transforms by velocity and velocity by acceleration. Usable as a system in any `ecs::Schedule`,
+/// in a `Scene::systems` system call it with `movement_system(&mut scene.ecs, &mut (), dt)`
+pub fn movement_system<Ctx>(world: &mut World, _ctx: &mut Ctx, dt: f32) {
+    world.query::<(Transform, Velocity), _>(|_, (transform, vel)| {
+        vel.velocity += vel.acceleration * dt;
+        transform.pos += vel.velocity * dt;
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movement() {
+        let mut world = World::default();
+        let id = world.spawn();
+        world.insert(id, Transform::from_pos(V3::new(1.0, 0.0, 0.0)));
+        world.insert(id, Velocity { velocity: V3::new(0.0, 2.0, 0.0), acceleration: V3::new(0.0, 0.0, -10.0) });
+
+        movement_system(&mut world, &mut (), 0.5);
+
+        assert_eq!(world.get::<Velocity>(id).unwrap().velocity, V3::new(0.0, 2.0, -5.0));
+        assert_eq!(world.get::<Transform>(id).unwrap().pos, V3::new(1.0, 1.0, -2.5));
+        assert_eq!(world.get::<Transform>(id).unwrap().model_mat().column(3).xyz(), V3::new(1.0, 1.0, -2.5));
+    }
+}
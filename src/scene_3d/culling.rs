@@ -0,0 +1,109 @@
+//! Frustum culling and draw order of render meshes
+use crate::typedef::*;
+use crate::texture::TextureId;
+use crate::collision3d::bounds::Frustum;
+use crate::scene_3d::{RenderMesh, MeshShading, MeshIndex};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawItem {
+    /// Index into render meshes
+    pub index: usize,
+    pub mesh_id: MeshIndex,
+    pub lod: usize,
+    pub blend: bool,
+    pub texture: Option<TextureId>,
+    /// Squared distance from camera to center of the mesh bounds
+    pub dist_sq: f32,
+}
+
+
+/// Opaque items first, grouped by texture, mesh and lod and front to back in each group, so depth testing can skip
+/// hidden fragments without too many texture binds, and meshes can be instanced. Blended items last, back to front
+pub fn sort_draw_items(items: &mut [DrawItem]) {
+    items.sort_by(|a, b| {
+        a.blend.cmp(&b.blend).then_with(|| {
+            if a.blend {
+                b.dist_sq.total_cmp(&a.dist_sq)
+            } else {
+                a.texture.cmp(&b.texture)
+                    .then(a.mesh_id.cmp(&b.mesh_id))
+                    .then(a.lod.cmp(&b.lod))
+                    .then(a.dist_sq.total_cmp(&b.dist_sq))
+            }
+        })
+    });
+}
+
+
+/// Fill out with the render meshes inside the frustum, in draw order. Only pbr shading uses
+/// blended materials, with basic shading every mesh is opaque
+pub fn cull_and_sort(render_meshes: &[RenderMesh], frustum: &Frustum, cam_pos: V3, shading: MeshShading, out: &mut Vec::<DrawItem>) {
+    out.clear();
+
+    for (index, rm) in render_meshes.iter().enumerate() {
+        let bounds = rm.mesh.bounds.map(|b| b.transform(&rm.model_mat));
+
+        if let Some(b) = &bounds {
+            if !frustum.intersects_aabb(b) {
+                continue;
+            }
+        }
+
+        let center = bounds.map(|b| b.center()).unwrap_or_else(|| rm.model_mat.column(3).xyz());
+
+        let (blend, texture) = match shading {
+            MeshShading::Pbr => (rm.material.is_blend(), rm.material.base_color_texture),
+            MeshShading::Basic => (false, rm.texture),
+        };
+
+        out.push(DrawItem {
+            index,
+            mesh_id: rm.mesh_id,
+            lod: rm.lod,
+            blend,
+            texture,
+            dist_sq: (center - cam_pos).magnitude_squared(),
+        });
+    }
+
+    sort_draw_items(out);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(index: usize, blend: bool, texture: Option<TextureId>, dist_sq: f32) -> DrawItem {
+        DrawItem { index, mesh_id: 0, lod: 0, blend, texture, dist_sq }
+    }
+
+    #[test]
+    fn draw_order() {
+        let mut items = vec![
+            item(0, true, Some(1), 4.0),
+            item(1, false, Some(2), 9.0),
+            item(2, false, Some(1), 16.0),
+            item(3, true, Some(1), 25.0),
+            item(4, false, Some(2), 1.0),
+            item(5, false, Some(1), 2.0),
+        ];
+
+        sort_draw_items(&mut items);
+
+        let order : Vec::<usize> = items.iter().map(|i| i.index).collect();
+        // opaque by texture then near to far, then blended far to near
+        assert_eq!(order, vec![5, 2, 4, 1, 3, 0]);
+
+        // same texture is grouped by mesh
+        let mut items = vec![
+            DrawItem { mesh_id: 2, ..item(0, false, None, 1.0) },
+            DrawItem { mesh_id: 1, ..item(1, false, None, 3.0) },
+            DrawItem { mesh_id: 2, ..item(2, false, None, 2.0) },
+        ];
+        sort_draw_items(&mut items);
+        let order : Vec::<usize> = items.iter().map(|i| i.index).collect();
+        assert_eq!(order, vec![1, 0, 2]);
+    }
+}
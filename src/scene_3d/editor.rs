@@ -0,0 +1,515 @@
+//! Editor mode for placing entities in a `Scene`. Pick entities with the mouse, move, rotate and scale them
+//! with gizmos, and edit them in the inspector. Only active in `ui_mode`, so game input is not affected.
+//! Saves to a scene file, see `scene_file`.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::f32::consts::PI;
+use crate::typedef::*;
+use crate::color::Color;
+use crate::imode_gui::{Pos, WidgetStatus};
+use crate::collision3d::bounds::Aabb;
+use crate::scene_3d::{Scene, EntityId};
+use crate::na::Rotation2;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+
+/// The parts of an entity the gizmos edit. Angles are side pitch, forward pitch and z angle,
+/// ie rotations around x, y and z
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditTransform {
+    pub pos: V3,
+    pub angles: V3,
+    pub scale: V3,
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct GizmoDrag {
+    axis: usize,
+    start_mouse: Pos,
+    start: EditTransform,
+    /// Screen space vector of the axis handle, from entity to handle
+    screen_axis: V2,
+    /// Length of the axis handle in the parent's space
+    axis_len: f32,
+}
+
+
+/// State for `Scene::editor`
+pub struct SceneEditor {
+    pub selected: Option<EntityId>,
+    pub mode: GizmoMode,
+    /// Where edits are saved
+    pub scene_path: PathBuf,
+    /// Stored with each entity in the scene file
+    pub user_data: HashMap::<EntityId, serde_json::Value>,
+    /// Length of gizmo axes as a fraction of the distance to the camera
+    pub gizmo_scale: f32,
+    pub show_outliner: bool,
+    pub show_inspector: bool,
+    pub show_meshes: bool,
+    drag: Option<GizmoDrag>,
+    window_ids: Vec::<usize>,
+    mesh_filter: String,
+    status: String,
+}
+
+
+impl SceneEditor {
+
+    pub fn new(scene_path: PathBuf) -> Self {
+        Self {
+            selected: None,
+            mode: GizmoMode::Translate,
+            scene_path,
+            user_data: HashMap::default(),
+            gizmo_scale: 0.15,
+            show_outliner: true,
+            show_inspector: true,
+            show_meshes: true,
+            drag: None,
+            window_ids: vec![],
+            mesh_filter: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+
+const AXIS_COLORS: [Color; 3] = [Color::Rgb(220, 50, 50), Color::Rgb(50, 200, 50), Color::Rgb(50, 80, 230)];
+
+
+/// How far the mouse moved along the screen axis, in lengths of the axis
+pub fn axis_drag_amount(screen_axis: V2, mouse_delta: V2) -> f32 {
+    let len_sq = screen_axis.magnitude_squared();
+    if len_sq < 1.0 {
+        return 0.0;
+    }
+
+    screen_axis.dot(&mouse_delta) / len_sq
+}
+
+
+/// Apply a drag of amount axis lengths along axis. Rotation is one radian per axis length
+pub fn apply_gizmo(mode: GizmoMode, axis: usize, amount: f32, world_len: f32, start: &EditTransform) -> EditTransform {
+    let mut res = *start;
+    match mode {
+        GizmoMode::Translate => res.pos[axis] += amount * world_len,
+        GizmoMode::Rotate => res.angles[axis] += amount,
+        GizmoMode::Scale => res.scale[axis] = (start.scale[axis] * (1.0 + amount)).max(0.01),
+    }
+    res
+}
+
+
+/// Closest entity hit by the ray
+pub fn pick_entity<I: IntoIterator<Item = (EntityId, Aabb)>>(origin: &V3, dir: &V3, bounds: I) -> Option<EntityId> {
+    bounds.into_iter()
+        .filter_map(|(id, b)| b.ray_intersection(origin, dir).map(|t| (id, t)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(id, _)| id)
+}
+
+
+impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserControllerData> {
+
+    fn edit_transform(&self, id: &EntityId) -> Option<EditTransform> {
+        let e = self.entity(id)?;
+        Some(EditTransform {
+            pos: e.pos,
+            angles: V3::new(e.side_pitch.angle(), e.forward_pitch.angle(), e.z_angle.angle()),
+            scale: e.scale,
+        })
+    }
+
+    fn set_edit_transform(&mut self, id: &EntityId, t: &EditTransform) {
+        if let Some(e) = self.entity_mut(id) {
+            e.pos = t.pos;
+            e.side_pitch = Rotation2::new(t.angles.x);
+            e.forward_pitch = Rotation2::new(t.angles.y);
+            e.z_angle = Rotation2::new(t.angles.z);
+            e.target_z_angle = e.z_angle;
+            e.scale = t.scale;
+        }
+    }
+
+    /// Editor windows, gizmos and mouse picking. Call between `frame_start` and `render`. Does nothing when not in `ui_mode`.
+    /// Returns true when the scene was saved
+    pub fn editor(&mut self, editor: &mut SceneEditor) -> bool {
+        if !self.ui_mode {
+            editor.drag = None;
+            return false;
+        }
+
+        if let Some(id) = editor.selected {
+            if self.entity(&id).is_none() {
+                editor.selected = None;
+            }
+        }
+
+        editor.window_ids.clear();
+
+        let saved = self.editor_windows(editor);
+
+        let gizmo_active = self.editor_gizmo(editor);
+
+        // pick when clicking in the scene, not on a window or widget
+        let ui = &self.ui;
+        let on_widget = ui.active_window.is_some() || ui.windows.values().any(|w| w.base_container_context.hot.is_some() || w.base_container_context.active.is_some());
+        let on_window = editor.window_ids.iter().any(|id| {
+            let mut rect = ui.get_window_rect(*id);
+            let top_bar = ui.windows.get(id).map(|w| w.top_bar_size.y).unwrap_or(0);
+            rect.y -= top_bar;
+            rect.h += top_bar;
+            ui.mouse_in_rect(&rect)
+        });
+
+        if ui.mouse_down && !gizmo_active && !on_widget && !on_window {
+            // the rendered camera, so picking works with camera paths, blends and shake
+            let camera = self.view_camera();
+            let mouse = ui.mouse_pos;
+            let (x, y) = (mouse.x as f32, camera.height - mouse.y as f32);
+            let dir = camera.screen_to_ray(x, y);
+            let origin = camera.screen_to_ray_origin(x, y);
+
+            let ids : Vec::<EntityId> = self.entities.data.keys().copied().collect();
+            editor.selected = pick_entity(&origin, &dir, ids.iter().filter_map(|id| self.entity_bounds(id).map(|b| (*id, b))));
+        }
+
+        saved
+    }
+
+    /// Axis handles for the selected entity. Returns true when a handle is dragged
+    fn editor_gizmo(&mut self, editor: &mut SceneEditor) -> bool {
+        let id = match editor.selected {
+            Some(id) => id,
+            None => {
+                editor.drag = None;
+                return false;
+            }
+        };
+
+        let transform = match self.edit_transform(&id) {
+            Some(t) => t,
+            None => return false
+        };
+
+        // children are edited in the parent's space, so draw axes in that space
+        let world = self.world_mat(&id);
+        let local = self.entity(&id).map(|e| e.model_mat()).unwrap_or_else(Mat4::identity);
+        let parent = world * local.try_inverse().unwrap_or_else(Mat4::identity);
+
+        let camera = self.view_camera();
+        let center = world.column(3).xyz();
+        let world_len = (center - camera.pos()).magnitude() * editor.gizmo_scale;
+        let center_screen = camera.world_pos_to_screen(center);
+
+        // in front of the camera only
+        if (center - camera.pos()).dot(&camera.front()) <= 0.0 {
+            return false;
+        }
+
+        let mut any_active = false;
+        for axis in 0..3 {
+            let mut dir = V3::zeros();
+            dir[axis] = 1.0;
+
+            let parent_axis = parent.transform_vector(&dir);
+            let parent_scale = parent_axis.magnitude().max(0.0001);
+
+            let end = camera.world_pos_to_screen(center + parent_axis / parent_scale * world_len);
+            let mut handle = Pos::new(end.x as i32, end.y as i32);
+
+            let color = AXIS_COLORS[axis];
+            self.ui.drawer2D.color = color;
+            self.ui.drawer2D.line(center_screen.x, center_screen.y, end.x, end.y, 3);
+
+            let (status, _) = self.ui.drag_point_no_draw(&mut handle, 10.0);
+
+            let r = if matches!(status, WidgetStatus::Inactive) { 7 } else { 10 };
+            match editor.mode {
+                GizmoMode::Translate => self.ui.drawer2D.circle(end.x, end.y, r, color),
+                GizmoMode::Rotate => self.ui.drawer2D.circle_outline(end.x, end.y, r, 3, color),
+                GizmoMode::Scale => self.ui.drawer2D.rect_color(end.x as i32 - r, end.y as i32 - r, r * 2, r * 2, color),
+            }
+
+            if !matches!(status, WidgetStatus::Active) {
+                if editor.drag.map(|d| d.axis) == Some(axis) {
+                    editor.drag = None;
+                }
+                continue;
+            }
+
+            any_active = true;
+
+            let drag = *editor.drag.get_or_insert(GizmoDrag {
+                axis,
+                start_mouse: self.ui.mouse_pos,
+                start: transform,
+                screen_axis: end - center_screen,
+                axis_len: world_len / parent_scale,
+            });
+
+            let delta = self.ui.mouse_pos - drag.start_mouse;
+            let amount = axis_drag_amount(drag.screen_axis, V2::new(delta.x as f32, delta.y as f32));
+
+            self.set_edit_transform(&id, &apply_gizmo(editor.mode, axis, amount, drag.axis_len, &drag.start));
+        }
+
+        any_active
+    }
+
+    fn editor_windows(&mut self, editor: &mut SceneEditor) -> bool {
+        let mut saved = false;
+
+        // toolbar in the main window
+        for (text, mode) in [("Translate", GizmoMode::Translate), ("Rotate", GizmoMode::Rotate), ("Scale", GizmoMode::Scale)] {
+            if editor.mode == mode {
+                self.ui.body_text(text);
+            } else if self.ui.button(text) {
+                editor.mode = mode;
+            }
+        }
+
+        for (text, show) in [("Outliner", &mut editor.show_outliner), ("Inspector", &mut editor.show_inspector), ("Meshes", &mut editor.show_meshes)] {
+            if !*show && self.ui.button(text) {
+                *show = true;
+            }
+        }
+
+        if self.ui.button("Save scene") {
+            match self.save_scene(&editor.scene_path, &editor.user_data) {
+                Ok(()) => {
+                    editor.status = format!("Saved {:?}", editor.scene_path);
+                    saved = true;
+                },
+                Err(err) => {
+                    editor.status = format!("Failed to save: {}", err);
+                }
+            }
+        }
+
+        if !editor.status.is_empty() {
+            self.ui.newline();
+            self.ui.small_text(&editor.status);
+        }
+
+        if editor.show_outliner {
+            self.editor_outliner(editor);
+        }
+
+        if editor.show_inspector {
+            self.editor_inspector(editor);
+        }
+
+        if editor.show_meshes {
+            self.editor_meshes(editor);
+        }
+
+        saved
+    }
+
+    fn editor_outliner(&mut self, editor: &mut SceneEditor) {
+        let res = self.ui.window_begin("Outliner");
+        editor.window_ids.push(res.id);
+        editor.show_outliner = !res.closed;
+
+        let mut ids : Vec::<EntityId> = self.entities.data.keys().copied().collect();
+        ids.sort();
+
+        for id in ids {
+            let mesh_id = self.entities.data[&id].mesh_id;
+            let name = format!("{id}: {}", self.mesh_name(mesh_id).as_deref().unwrap_or("?"));
+
+            if editor.selected == Some(id) {
+                self.ui.body_text(&name);
+            } else if self.ui.button(&name) {
+                editor.selected = Some(id);
+            }
+            self.ui.newline();
+        }
+
+        self.ui.window_end("Outliner");
+    }
+
+    fn editor_inspector(&mut self, editor: &mut SceneEditor) {
+        let res = self.ui.window_begin("Inspector");
+        editor.window_ids.push(res.id);
+        editor.show_inspector = !res.closed;
+
+        let id = match editor.selected {
+            Some(id) => id,
+            None => {
+                self.ui.body_text("Nothing selected");
+                self.ui.window_end("Inspector");
+                return;
+            }
+        };
+
+        let mesh_id = self.entities.data[&id].mesh_id;
+        let mesh_name = self.mesh_name(mesh_id);
+        self.ui.heading_text(&format!("{id}: {}", mesh_name.as_deref().unwrap_or("?")));
+        self.ui.newline();
+
+        let mut t = match self.edit_transform(&id) {
+            Some(t) => t,
+            None => {
+                self.ui.window_end("Inspector");
+                return;
+            }
+        };
+        let before = t;
+
+        for (label, v, min, max) in [("Pos", &mut t.pos, -1000.0, 1000.0), ("Scale", &mut t.scale, 0.01, 100.0)] {
+            self.ui.label(label);
+            for i in 0..3 {
+                self.ui.combo_box(&mut v[i], min, max);
+            }
+            self.ui.newline();
+        }
+
+        for (i, label) in ["Side pitch", "Forward pitch", "Z angle"].iter().enumerate() {
+            self.ui.label(label);
+            self.ui.slider(&mut t.angles[i], -PI, PI);
+            self.ui.newline();
+        }
+
+        if t != before {
+            self.set_edit_transform(&id, &t);
+        }
+
+        let entity = &self.entities.data[&id];
+        let info = format!("velocity: {:.2?}\nroot motion: {:.2?}\nskeleton: {:?}", entity.velocity, entity.root_motion, entity.skeleton_id);
+        let pipeline_id = entity.render_pipeline_id;
+        let parent = entity.parent;
+        self.ui.small_text(&info);
+        self.ui.newline();
+
+        self.ui.label("Pipeline");
+        for name in self.render_pipelines.names() {
+            if self.render_pipelines.id(name.clone()) == Some(pipeline_id) {
+                self.ui.body_text(&name);
+            } else if self.ui.button(&name) {
+                self.set_entity_render_pipeline(id, name);
+            }
+        }
+        self.ui.newline();
+
+        if let Some(name) = mesh_name {
+            if self.ui.button("Duplicate") {
+                let new_id = self.create_entity(&name);
+                let pipeline = self.render_pipelines.name(pipeline_id);
+                if let Some(p) = pipeline {
+                    self.set_entity_render_pipeline(new_id, p);
+                }
+                // same parent and joint, so the parent local transform puts it on top of the source
+                if let Some(e) = self.entities.get_mut(&new_id) {
+                    e.parent = parent;
+                }
+                self.set_edit_transform(&new_id, &t);
+                if let Some(data) = editor.user_data.get(&id).cloned() {
+                    editor.user_data.insert(new_id, data);
+                }
+                editor.selected = Some(new_id);
+            }
+        }
+
+        if self.ui.button("Delete") {
+            self.remove_entity(&id);
+            editor.user_data.remove(&id);
+            editor.selected = None;
+        }
+
+        self.ui.window_end("Inspector");
+    }
+
+    fn editor_meshes(&mut self, editor: &mut SceneEditor) {
+        let res = self.ui.window_begin("Meshes");
+        editor.window_ids.push(res.id);
+        editor.show_meshes = !res.closed;
+
+        self.ui.label("Filter");
+        self.ui.textbox(&mut editor.mesh_filter);
+        self.ui.newline();
+
+        let mut names : Vec::<std::rc::Rc::<str>> = self.meshes.keys()
+            .filter(|name| name.to_lowercase().contains(&editor.mesh_filter.to_lowercase()))
+            .cloned()
+            .collect();
+        names.sort();
+
+        for name in names {
+            // place new entities in front of the camera
+            if self.ui.button(&name) {
+                let id = self.create_entity(&name);
+                let camera = self.view_camera();
+                let pos = camera.pos() + camera.front() * 10.0;
+                if let Some(e) = self.entity_mut(&id) {
+                    e.pos = pos;
+                }
+                editor.selected = Some(id);
+            }
+            self.ui.newline();
+        }
+
+        self.ui.window_end("Meshes");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drag_along_axis() {
+        let axis = V2::new(100.0, 0.0);
+        assert_eq!(axis_drag_amount(axis, V2::new(50.0, 30.0)), 0.5);
+        assert_eq!(axis_drag_amount(axis, V2::new(-100.0, 0.0)), -1.0);
+
+        // axis pointing at the camera has no screen length
+        assert_eq!(axis_drag_amount(V2::new(0.0, 0.0), V2::new(10.0, 10.0)), 0.0);
+    }
+
+    #[test]
+    fn gizmo_modes() {
+        let start = EditTransform {
+            pos: V3::new(1.0, 2.0, 3.0),
+            angles: V3::zeros(),
+            scale: V3::new(1.0, 1.0, 1.0),
+        };
+
+        let t = apply_gizmo(GizmoMode::Translate, 1, 0.5, 4.0, &start);
+        assert_eq!(t.pos, V3::new(1.0, 4.0, 3.0));
+
+        let r = apply_gizmo(GizmoMode::Rotate, 2, 0.25, 4.0, &start);
+        assert_eq!(r.angles, V3::new(0.0, 0.0, 0.25));
+        assert_eq!(r.pos, start.pos);
+
+        let s = apply_gizmo(GizmoMode::Scale, 0, 1.0, 4.0, &start);
+        assert_eq!(s.scale, V3::new(2.0, 1.0, 1.0));
+
+        let s = apply_gizmo(GizmoMode::Scale, 0, -5.0, 4.0, &start);
+        assert_eq!(s.scale.x, 0.01);
+    }
+
+    #[test]
+    fn pick_closest() {
+        let unit = Aabb::new(V3::new(-1.0, -1.0, -1.0), V3::new(1.0, 1.0, 1.0));
+        let at = |x: f32| Aabb::new(unit.min + V3::new(x, 0.0, 0.0), unit.max + V3::new(x, 0.0, 0.0));
+
+        let bounds = vec![(1, at(10.0)), (2, at(5.0)), (3, Aabb::new(V3::new(0.0, 5.0, 0.0), V3::new(1.0, 6.0, 1.0)))];
+        let origin = V3::new(0.0, 0.0, 0.0);
+
+        assert_eq!(pick_entity(&origin, &V3::new(1.0, 0.0, 0.0), bounds.clone()), Some(2));
+        assert_eq!(pick_entity(&origin, &V3::new(-1.0, 0.0, 0.0), bounds.clone()), None);
+        assert_eq!(pick_entity(&origin, &V3::new(0.1, 1.0, 0.1).normalize(), bounds), Some(3));
+    }
+}
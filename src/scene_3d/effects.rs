@@ -0,0 +1,159 @@
+//! Particle effects of a `Scene` by name. Register emitter descriptions once, fx loaded from toml, then spawn them at a
+//! position or on an entity with `Action::SpawnEffect` or `Effects::spawn`. Finished effects are removed.
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use crate::typedef::*;
+use crate::particle_system::desc::EmitterDesc;
+use crate::particle_system::simulation::ParticleEmitter;
+use crate::scene_3d::{EntityId, RenderPipelineId};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectTarget {
+    Pos(V3),
+    /// Follow the entity, the effect stops emitting when the entity is removed
+    Entity(EntityId),
+}
+
+
+pub struct EffectInstance {
+    pub name: Rc::<str>,
+    pub target: EffectTarget,
+    pub emitter: ParticleEmitter,
+    pub render_pipeline_id: RenderPipelineId,
+}
+
+
+#[derive(Default)]
+pub struct Effects {
+    pub descs: HashMap::<Rc::<str>, EmitterDesc>,
+    pub active: Vec::<EffectInstance>,
+}
+
+impl Effects {
+
+    /// Add or replace the description with the name of desc. Running effects with the name use the new description
+    pub fn register(&mut self, desc: EmitterDesc) -> Rc::<str> {
+        let name : Rc::<str> = desc.name.as_str().into();
+
+        for e in self.active.iter_mut().filter(|e| e.name == name) {
+            e.emitter.desc = desc.clone();
+        }
+
+        self.descs.insert(name.clone(), desc);
+        name
+    }
+
+    /// Load an emitter description from a toml file and register it
+    pub fn load<P: AsRef<Path>>(&mut self, path: &P) -> Result<Rc::<str>, failure::Error> {
+        Ok(self.register(EmitterDesc::load(path)?))
+    }
+
+    /// Start an effect, an error when no description has the name. The instance can be changed, fx to set a mesh surface
+    pub fn spawn(&mut self, name: &str, target: EffectTarget) -> Result<&mut EffectInstance, failure::Error> {
+        let Some((name, desc)) = self.descs.get_key_value(name) else {
+            failure::bail!("No particle effect named {:?}", name);
+        };
+
+        let mut emitter = ParticleEmitter::new(desc.clone());
+        if let EffectTarget::Pos(pos) = target {
+            emitter.pos = pos;
+        }
+
+        self.active.push(EffectInstance { name: name.clone(), target, emitter, render_pipeline_id: 0 });
+        Ok(self.active.last_mut().unwrap())
+    }
+
+    /// Stop emitting all running effects with name, their particles live out their life
+    pub fn stop(&mut self, name: &str) {
+        for e in self.active.iter_mut().filter(|e| &*e.name == name) {
+            e.emitter.stop();
+        }
+    }
+
+    /// Remove running effects with name right away, including their particles
+    pub fn remove(&mut self, name: &str) {
+        self.active.retain(|e| &*e.name != name);
+    }
+
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+
+    /// Move effects to their entity and simulate. entity_pos is None for removed entities
+    pub fn update<F: Fn(EntityId) -> Option<V3>>(&mut self, dt: f32, entity_pos: F) {
+        for e in &mut self.active {
+            if let EffectTarget::Entity(id) = e.target {
+                match entity_pos(id) {
+                    Some(pos) => e.emitter.pos = pos,
+                    None => e.emitter.stop(),
+                }
+            }
+
+            e.emitter.update(dt);
+        }
+
+        self.active.retain(|e| !e.emitter.is_finished());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(name: &str, looping: bool) -> EmitterDesc {
+        EmitterDesc { name: name.to_string(), looping, duration: 0.5, lifetime: [0.5, 0.5], ..Default::default() }
+    }
+
+    #[test]
+    fn spawn_and_finish() {
+        let mut effects = Effects::default();
+        effects.register(desc("hit", false));
+
+        assert!(effects.spawn("missing", EffectTarget::Pos(V3::zeros())).is_err());
+        assert!(effects.spawn("hit", EffectTarget::Pos(V3::new(1.0, 2.0, 3.0))).is_ok());
+        assert_eq!(effects.active[0].emitter.pos, V3::new(1.0, 2.0, 3.0));
+
+        effects.update(0.25, |_| None);
+        assert!(!effects.active[0].emitter.particles.is_empty());
+
+        // emits for half a second, then the last particles live half a second more
+        for _ in 0..4 {
+            effects.update(0.25, |_| None);
+        }
+        assert!(effects.active.is_empty());
+    }
+
+    #[test]
+    fn follow_entity() {
+        let mut effects = Effects::default();
+        effects.register(desc("trail", true));
+        effects.spawn("trail", EffectTarget::Entity(7)).unwrap();
+
+        effects.update(0.1, |id| if id == 7 { Some(V3::new(5.0, 0.0, 0.0)) } else { None });
+        assert_eq!(effects.active[0].emitter.pos, V3::new(5.0, 0.0, 0.0));
+        assert!(effects.active[0].emitter.emitting);
+
+        // entity removed, so the looping effect stops and is removed when the particles die
+        effects.update(0.1, |_| None);
+        assert!(!effects.active[0].emitter.emitting);
+        effects.update(1.0, |_| None);
+        assert!(effects.active.is_empty());
+    }
+
+    #[test]
+    fn register_updates_running() {
+        let mut effects = Effects::default();
+        effects.register(desc("smoke", true));
+        effects.spawn("smoke", EffectTarget::Pos(V3::zeros())).unwrap();
+        effects.spawn("smoke", EffectTarget::Pos(V3::zeros())).unwrap();
+
+        effects.register(EmitterDesc { rate: 99.0, ..desc("smoke", true) });
+        assert!(effects.active.iter().all(|e| e.emitter.desc.rate == 99.0));
+
+        effects.stop("smoke");
+        assert!(effects.active.iter().all(|e| !e.emitter.emitting));
+    }
+}
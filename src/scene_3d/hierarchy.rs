@@ -0,0 +1,166 @@
+//! Parent/child links between entities. A child's pos, rotation and scale are relative to its parent,
+//! or to a joint in the parent's skeleton, fx a sword attached to a hand. See `Scene::attach`.
+use std::collections::HashMap;
+use crate::typedef::*;
+use crate::animations::skeleton::{Skeleton, Bones};
+use crate::scene_3d::{EntityId, SceneEntity};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent {
+    pub id: EntityId,
+    /// Joint index in the parent's skeleton, None attaches to the parent itself
+    pub joint: Option<usize>,
+}
+
+
+/// Model space matrix of joint for an entity with bones. Uses bones and not the joint world matrices directly,
+/// since entities sharing a skeleton only have their own pose in their bones
+pub fn joint_model_matrix(skeleton: &Skeleton, bones: &Bones, joint: usize) -> Option<Mat4> {
+    let bind = skeleton.joints.get(joint)?.inverse_bind_pose.try_inverse()?;
+    Some(bones.get(joint)? * bind)
+}
+
+
+/// True when setting parent of child would make child its own ancestor
+pub fn creates_cycle(entities: &HashMap::<EntityId, SceneEntity>, child: EntityId, parent: EntityId) -> bool {
+    let mut current = Some(parent);
+    let mut steps = 0;
+
+    while let Some(id) = current {
+        if id == child || steps > entities.len() {
+            return true;
+        }
+
+        current = entities.get(&id).and_then(|e| e.parent).map(|p| p.id);
+        steps += 1;
+    }
+
+    false
+}
+
+
+/// World matrix of every entity. joint_mat gives the model space matrix of a joint of an entity.
+/// Entities with a missing parent or a missing joint are placed as roots
+pub fn world_matrices<F>(entities: &HashMap::<EntityId, SceneEntity>, joint_mat: F, out: &mut HashMap::<EntityId, Mat4>)
+where F: Fn(EntityId, usize) -> Option<Mat4> {
+    out.clear();
+
+    let mut chain = Vec::<EntityId>::new();
+
+    for id in entities.keys() {
+        // walk up until a root or an entity already done, then compute back down
+        chain.clear();
+        let mut current = *id;
+        loop {
+            if out.contains_key(&current) {
+                break;
+            }
+
+            chain.push(current);
+
+            match entities.get(&current).and_then(|e| e.parent) {
+                Some(p) if entities.contains_key(&p.id) && !chain.contains(&p.id) => current = p.id,
+                _ => break,
+            }
+        }
+
+        for child in chain.iter().rev() {
+            let entity = &entities[child];
+
+            let parent_mat = entity.parent.and_then(|p| {
+                let parent_world = *out.get(&p.id)?;
+                Some(match p.joint {
+                    Some(joint) => parent_world * joint_mat(p.id, joint).unwrap_or_else(Mat4::identity),
+                    None => parent_world,
+                })
+            });
+
+            let local = entity.model_mat();
+            out.insert(*child, parent_mat.map(|m| m * local).unwrap_or(local));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::na::{self, Rotation2, UnitQuaternion};
+
+    fn entity(pos: V3, parent: Option<Parent>) -> SceneEntity {
+        SceneEntity { pos, parent, ..Default::default() }
+    }
+
+    fn pos_of(m: &Mat4) -> V3 {
+        m.column(3).xyz()
+    }
+
+    fn close(a: V3, b: V3) -> bool {
+        (a - b).magnitude() < 0.0001
+    }
+
+    #[test]
+    fn propagate() {
+        let mut entities = HashMap::new();
+        // grand child first, to check that order in map does not matter
+        entities.insert(3, entity(V3::new(0.0, 0.0, 1.0), Some(Parent { id: 2, joint: None })));
+        entities.insert(1, entity(V3::new(10.0, 0.0, 0.0), None));
+        entities.insert(2, entity(V3::new(1.0, 0.0, 0.0), Some(Parent { id: 1, joint: None })));
+
+        // parent turned 90 degrees, so child x is world y
+        entities.get_mut(&1).unwrap().z_angle = Rotation2::new(std::f32::consts::FRAC_PI_2);
+
+        let mut out = HashMap::new();
+        world_matrices(&entities, |_, _| None, &mut out);
+
+        assert!(close(pos_of(&out[&1]), V3::new(10.0, 0.0, 0.0)));
+        assert!(close(pos_of(&out[&2]), V3::new(10.0, 1.0, 0.0)));
+        assert!(close(pos_of(&out[&3]), V3::new(10.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn quaternion_rotation_and_scale() {
+        let mut entities = HashMap::new();
+        let mut parent = entity(V3::new(0.0, 0.0, 0.0), None);
+        parent.rotation = UnitQuaternion::from_axis_angle(&V3::x_axis(), std::f32::consts::FRAC_PI_2);
+        parent.scale = V3::new(2.0, 2.0, 2.0);
+        entities.insert(1, parent);
+        entities.insert(2, entity(V3::new(0.0, 1.0, 0.0), Some(Parent { id: 1, joint: None })));
+
+        let mut out = HashMap::new();
+        world_matrices(&entities, |_, _| None, &mut out);
+
+        // y rotated to z around x, and scaled by parent
+        assert!(close(pos_of(&out[&2]), V3::new(0.0, 0.0, 2.0)));
+    }
+
+    #[test]
+    fn joint_attachment() {
+        let mut entities = HashMap::new();
+        entities.insert(1, entity(V3::new(5.0, 0.0, 0.0), None));
+        entities.insert(2, entity(V3::new(0.0, 0.0, 0.0), Some(Parent { id: 1, joint: Some(4) })));
+
+        let joint = na::Translation3::new(0.0, 0.0, 2.0).to_homogeneous();
+
+        let mut out = HashMap::new();
+        world_matrices(&entities, |id, j| (id == 1 && j == 4).then_some(joint), &mut out);
+        assert!(close(pos_of(&out[&2]), V3::new(5.0, 0.0, 2.0)));
+    }
+
+    #[test]
+    fn missing_parent_and_cycles() {
+        let mut entities = HashMap::new();
+        entities.insert(1, entity(V3::new(1.0, 0.0, 0.0), Some(Parent { id: 99, joint: None })));
+        entities.insert(2, entity(V3::new(2.0, 0.0, 0.0), Some(Parent { id: 3, joint: None })));
+        entities.insert(3, entity(V3::new(3.0, 0.0, 0.0), Some(Parent { id: 2, joint: None })));
+
+        let mut out = HashMap::new();
+        world_matrices(&entities, |_, _| None, &mut out);
+        assert_eq!(out.len(), 3);
+        assert!(close(pos_of(&out[&1]), V3::new(1.0, 0.0, 0.0)));
+
+        assert!(creates_cycle(&entities, 2, 3));
+        assert!(!creates_cycle(&entities, 2, 1));
+    }
+}
@@ -0,0 +1,126 @@
+//! Instanced drawing of render meshes that share a mesh. Model matrices are a per instance attribute,
+//! see `Mesh::enable_instancing`, and bones of skinned meshes are in a float texture with a row per instance.
+use std::ops::Range;
+use crate::gl;
+use crate::texture::TextureId;
+use crate::animations::skeleton::Bones;
+
+/// Has to match size of uBones in the mesh shaders
+pub const MAX_BONES: usize = 32;
+
+/// Texture unit of the bone texture, after the pbr material textures
+pub const BONE_TEXTURE_UNIT: u32 = 6;
+
+
+/// Ranges of consecutive items with the same key. Items with key None are always alone in their range
+pub fn instance_batches<T, K: PartialEq, F: Fn(&T) -> Option<K>>(items: &[T], key: F, out: &mut Vec::<Range<usize>>) {
+    out.clear();
+
+    let mut start = 0;
+    while start < items.len() {
+        let mut end = start + 1;
+        if let Some(k) = key(&items[start]) {
+            while end < items.len() && key(&items[end]).as_ref() == Some(&k) {
+                end += 1;
+            }
+        }
+
+        out.push(start..end);
+        start = end;
+    }
+}
+
+
+/// Row per instance, with 4 rgba texels per bone, one for each column. Missing bones are identity
+pub fn bone_texture_data(bones: &[&Bones], out: &mut Vec::<f32>) {
+    out.clear();
+    out.reserve(bones.len() * MAX_BONES * 16);
+
+    let identity = crate::na::Matrix4::<f32>::identity();
+    for instance in bones {
+        for i in 0..MAX_BONES {
+            let mat = instance.get(i).unwrap_or(&identity);
+            out.extend_from_slice(mat.as_slice());
+        }
+    }
+}
+
+
+/// Bones of all instances in an instanced draw
+pub struct BoneTexture {
+    gl: gl::Gl,
+    pub id: TextureId,
+}
+
+impl BoneTexture {
+
+    pub fn new(gl: &gl::Gl) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl.GenTextures(1, &mut id);
+            gl.BindTexture(gl::TEXTURE_2D, id);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        Self { gl: gl.clone(), id }
+    }
+
+    /// Upload bones and bind to `BONE_TEXTURE_UNIT`
+    pub fn upload(&self, bones: &[&Bones]) {
+        let mut data = vec![];
+        bone_texture_data(bones, &mut data);
+
+        unsafe {
+            self.gl.ActiveTexture(gl::TEXTURE0 + BONE_TEXTURE_UNIT);
+            self.gl.BindTexture(gl::TEXTURE_2D, self.id);
+            self.gl.TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA32F as i32, (MAX_BONES * 4) as i32, bones.len() as i32, 0,
+                               gl::RGBA, gl::FLOAT, data.as_ptr() as *const gl::types::GLvoid);
+            self.gl.ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+impl Drop for BoneTexture {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::na;
+
+    #[test]
+    fn batches() {
+        let items = [Some(1), Some(1), Some(2), None, None, Some(2), Some(2), Some(2)];
+        let mut out = vec![];
+        instance_batches(&items, |i| *i, &mut out);
+        assert_eq!(out, vec![0..2, 2..3, 3..4, 4..5, 5..8]);
+
+        instance_batches(&items[..0], |i| *i, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn bone_data_layout() {
+        let a : Bones = vec![na::Matrix4::new_translation(&na::Vector3::new(1.0, 2.0, 3.0))];
+        let b : Bones = vec![];
+
+        let mut data = vec![];
+        bone_texture_data(&[&a, &b], &mut data);
+        assert_eq!(data.len(), 2 * MAX_BONES * 16);
+
+        // translation is the 4th column, ie the 4th texel
+        assert_eq!(&data[12..16], &[1.0, 2.0, 3.0, 1.0]);
+        // missing bones are identity
+        assert_eq!(&data[16..20], &[1.0, 0.0, 0.0, 0.0]);
+        let row = MAX_BONES * 16;
+        assert_eq!(&data[row + 12..row + 16], &[0.0, 0.0, 0.0, 1.0]);
+    }
+}
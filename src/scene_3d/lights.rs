@@ -0,0 +1,321 @@
+//! Scene lights. All lights are uploaded once per frame to a uniform buffer, and each mesh
+//! uses the `LIGHTS_PER_MESH` most relevant lights, see `select_lights`.
+use crate::{gl, buffer};
+use crate::typedef::*;
+use crate::color::Color;
+use serde::{Serialize, Deserialize};
+
+/// Max lights in the uniform buffer, has to match MAX_LIGHTS in the mesh shaders
+pub const MAX_LIGHTS: usize = 16;
+
+/// Has to match size of meshLights in the mesh shaders
+pub const LIGHTS_PER_MESH: usize = 4;
+
+/// Uniform buffer binding point of the light block
+pub const LIGHTS_BINDING: u32 = 0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Like the sun, only direction matters
+    Directional,
+    Point,
+    /// Angles are half angles of the cone in radians. Light fades from inner to outer angle
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+
+/// Attenuation 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn none() -> Self {
+        Self { constant: 1.0, linear: 0.0, quadratic: 0.0 }
+    }
+
+    /// Attenuation that is close to 0 at range
+    pub fn from_range(range: f32) -> Self {
+        let range = range.max(0.001);
+        Self {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+        }
+    }
+
+    pub fn at(&self, dist: f32) -> f32 {
+        1.0 / (self.constant + self.linear * dist + self.quadratic * dist * dist)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    /// For directional lights this is only used as the shadow map origin
+    pub pos: V3,
+    /// Direction the light points, used by directional and spot lights
+    pub direction: V3,
+    pub color: Color,
+    pub intensity: f32,
+    /// Point and spot lights have no effect beyond range
+    #[serde(with = "infinite_as_none")]
+    pub range: f32,
+    pub attenuation: Attenuation,
+    /// The first light with casts_shadows is used for the shadow map
+    pub casts_shadows: bool,
+}
+
+
+impl Light {
+
+    pub fn directional(direction: V3, color: Color) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            pos: -direction.normalize() * 30.0,
+            direction: direction.normalize(),
+            color,
+            intensity: 1.0,
+            range: f32::INFINITY,
+            attenuation: Attenuation::none(),
+            casts_shadows: false,
+        }
+    }
+
+    pub fn point(pos: V3, color: Color, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            pos,
+            direction: V3::new(0.0, 0.0, -1.0),
+            color,
+            intensity: 1.0,
+            range,
+            attenuation: Attenuation::from_range(range),
+            casts_shadows: false,
+        }
+    }
+
+    pub fn spot(pos: V3, direction: V3, color: Color, range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            kind: LightKind::Spot { inner_angle, outer_angle },
+            direction: direction.normalize(),
+            ..Self::point(pos, color, range)
+        }
+    }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.casts_shadows = true;
+        self
+    }
+
+    /// Direction used for the shadow map. Point and spot lights look towards the origin
+    pub fn shadow_direction(&self) -> V3 {
+        match self.kind {
+            LightKind::Directional => self.direction,
+            _ if self.pos.magnitude() > 0.0 => -self.pos.normalize(),
+            _ => V3::new(0.0, 0.0, -1.0),
+        }
+    }
+
+    /// How much the light contributes at pos. Directional lights are always the most relevant
+    pub fn relevance(&self, pos: V3) -> f32 {
+        let to_pos = pos - self.pos;
+        let dist = to_pos.magnitude();
+
+        match self.kind {
+            LightKind::Directional => f32::MAX,
+            LightKind::Point => {
+                if dist > self.range {
+                    return 0.0;
+                }
+                self.intensity * self.attenuation.at(dist)
+            },
+            LightKind::Spot { outer_angle, .. } => {
+                if dist > self.range {
+                    return 0.0;
+                }
+
+                // outside cone, but a large mesh can still be partly lit, so don't drop it completely
+                let cone = if dist > 0.0 && to_pos.dot(&self.direction) / dist < outer_angle.cos() { 0.1 } else { 1.0 };
+                self.intensity * self.attenuation.at(dist) * cone
+            }
+        }
+    }
+
+    fn gpu(&self) -> GpuLight {
+        let (kind, cone) = match self.kind {
+            LightKind::Directional => (0.0, [0.0; 4]),
+            LightKind::Point => (1.0, [0.0; 4]),
+            LightKind::Spot { inner_angle, outer_angle } => (2.0, [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0]),
+        };
+
+        let c = self.color.as_vec4() * self.intensity;
+        let range = if self.range.is_finite() { self.range } else { -1.0 };
+
+        GpuLight {
+            pos_kind: [self.pos.x, self.pos.y, self.pos.z, kind],
+            direction_range: [self.direction.x, self.direction.y, self.direction.z, range],
+            color: [c.x, c.y, c.z, self.casts_shadows as i32 as f32],
+            attenuation: [self.attenuation.constant, self.attenuation.linear, self.attenuation.quadratic, 0.0],
+            cone,
+        }
+    }
+}
+
+
+/// Json has no infinity, so infinite range is stored as null
+mod infinite_as_none {
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+
+    pub fn serialize<S: Serializer>(range: &f32, s: S) -> Result<S::Ok, S::Error> {
+        range.is_finite().then_some(*range).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f32, D::Error> {
+        Ok(Option::<f32>::deserialize(d)?.unwrap_or(f32::INFINITY))
+    }
+}
+
+
+/// Index of the light used for the shadow map
+pub fn shadow_light(lights: &[Light]) -> Option<usize> {
+    lights.iter().take(MAX_LIGHTS).position(|l| l.casts_shadows)
+}
+
+/// Fill `out` with indices of the `n` most relevant lights at pos, most relevant first.
+/// Lights with no effect are skipped
+pub fn select_lights(lights: &[Light], pos: V3, n: usize, out: &mut Vec::<usize>) {
+    out.clear();
+
+    let mut scored : Vec::<(usize, f32)> = lights.iter().take(MAX_LIGHTS).enumerate()
+        .map(|(i, l)| (i, l.relevance(pos)))
+        .filter(|(_, r)| *r > 0.0)
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    out.extend(scored.iter().take(n).map(|(i, _)| *i));
+}
+
+
+/// std140 layout, everything is a vec4
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuLight {
+    pos_kind: [f32; 4],
+    direction_range: [f32; 4],
+    color: [f32; 4],
+    attenuation: [f32; 4],
+    cone: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct GpuLightBlock {
+    lights: [GpuLight; MAX_LIGHTS],
+    ambient: [f32; 4],
+}
+
+
+/// Uniform buffer with all lights in the scene
+pub struct LightBuffer {
+    ubo: buffer::UniformBuffer,
+}
+
+impl LightBuffer {
+
+    pub fn new(gl: &gl::Gl) -> Self {
+        let ubo = buffer::UniformBuffer::new(gl);
+        ubo.bind();
+        ubo.dynamic_draw_size(std::mem::size_of::<GpuLightBlock>() as u32);
+        ubo.unbind();
+        ubo.bind_base(LIGHTS_BINDING);
+
+        Self { ubo }
+    }
+
+    /// Upload lights and bind the buffer to `LIGHTS_BINDING`. Only the first `MAX_LIGHTS` lights are used
+    pub fn upload(&self, lights: &[Light], ambient: Color) {
+        let mut block = GpuLightBlock {
+            lights: [GpuLight::default(); MAX_LIGHTS],
+            ambient: ambient.as_vec4().into(),
+        };
+
+        for (i, l) in lights.iter().take(MAX_LIGHTS).enumerate() {
+            block.lights[i] = l.gpu();
+        }
+
+        self.ubo.bind();
+        self.ubo.sub_data(&[block], 0);
+        self.ubo.unbind();
+        self.ubo.bind_base(LIGHTS_BINDING);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Color {
+        Color::Rgb(255, 255, 255)
+    }
+
+    #[test]
+    fn attenuation_from_range() {
+        let a = Attenuation::from_range(10.0);
+        assert_eq!(a.at(0.0), 1.0);
+        assert!(a.at(5.0) < a.at(1.0));
+        assert!(a.at(10.0) < 0.02);
+    }
+
+    #[test]
+    fn select_closest_lights() {
+        let lights = vec![
+            Light::point(V3::new(100.0, 0.0, 0.0), white(), 10.0), // out of range
+            Light::point(V3::new(5.0, 0.0, 0.0), white(), 20.0),
+            Light::point(V3::new(1.0, 0.0, 0.0), white(), 20.0),
+            Light::directional(V3::new(0.0, 0.0, -1.0), white()),
+            Light::point(V3::new(3.0, 0.0, 0.0), white(), 20.0),
+        ];
+
+        let mut out = vec![];
+        select_lights(&lights, V3::zeros(), 3, &mut out);
+        assert_eq!(out, vec![3, 2, 4]);
+
+        select_lights(&lights, V3::zeros(), 10, &mut out);
+        assert_eq!(out, vec![3, 2, 4, 1]);
+    }
+
+    #[test]
+    fn spot_cone() {
+        let spot = Light::spot(V3::zeros(), V3::new(1.0, 0.0, 0.0), white(), 20.0, 0.2, 0.4);
+        let inside = spot.relevance(V3::new(5.0, 0.0, 0.0));
+        let outside = spot.relevance(V3::new(-5.0, 0.0, 0.0));
+        assert!(inside > outside);
+        assert!(outside > 0.0);
+    }
+
+    #[test]
+    fn first_shadow_caster() {
+        let lights = vec![
+            Light::point(V3::zeros(), white(), 10.0),
+            Light::point(V3::zeros(), white(), 10.0).with_shadows(),
+            Light::directional(V3::new(0.0, 0.0, -1.0), white()).with_shadows(),
+        ];
+        assert_eq!(shadow_light(&lights), Some(1));
+        assert_eq!(shadow_light(&lights[..1]), None);
+    }
+
+    #[test]
+    fn gpu_layout() {
+        // 5 vec4 per light, plus ambient
+        assert_eq!(std::mem::size_of::<GpuLight>(), 80);
+        assert_eq!(std::mem::size_of::<GpuLightBlock>(), 80 * MAX_LIGHTS + 16);
+    }
+}
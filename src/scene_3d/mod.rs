@@ -16,5 +16,28 @@ pub use rendering::*;
 pub mod render_pipeline;
 pub use render_pipeline::*;
 
+pub mod render_graph;
+pub use render_graph::*;
+
+pub mod lights;
+
+pub mod culling;
+
+pub mod instancing;
+
+pub mod scene_file;
+
+pub mod editor;
+
+pub mod hierarchy;
+
+pub mod components;
+
 pub mod particle;
 pub use particle::*;
+
+pub mod effects;
+
+pub mod particle_editor;
+
+pub mod trails;
@@ -0,0 +1,402 @@
+//! Window for editing an `EmitterDesc` while it plays in the scene. Changes are registered in `Scene::effects`
+//! right away, so the preview and all running effects with the same name update live. Saves and loads toml.
+use std::path::PathBuf;
+use crate::typedef::*;
+use crate::color::Color;
+use crate::imode_gui::ui::Ui;
+use crate::imode_gui::widgets::GraphInfo;
+use crate::math::numeric::Numeric;
+use crate::general_animation::Animatable;
+use crate::particle_system::curve::{Curve, Key};
+use crate::particle_system::desc::{EmitterDesc, EmitterShape, BlendMode};
+use crate::scene_3d::Scene;
+use crate::scene_3d::effects::EffectTarget;
+
+
+/// Over lifetime curve shown in the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveEdit {
+    Size,
+    SpeedOverLife,
+    /// Graph shows alpha, the selected key is edited with a color picker
+    Color,
+}
+
+
+/// State for `Scene::particle_editor`
+pub struct ParticleEditor {
+    pub desc: EmitterDesc,
+    /// Where the description is saved and loaded
+    pub path: PathBuf,
+    /// Where the preview effect is spawned
+    pub preview_pos: V3,
+    pub show: bool,
+    pub curve: CurveEdit,
+    /// Index of the selected key in the shown curve
+    pub key: usize,
+    status: String,
+}
+
+
+impl ParticleEditor {
+
+    pub fn new(desc: EmitterDesc, path: PathBuf) -> Self {
+        Self {
+            desc,
+            path,
+            preview_pos: V3::zeros(),
+            show: true,
+            curve: CurveEdit::Size,
+            key: 0,
+            status: String::new(),
+        }
+    }
+}
+
+
+/// Add a key halfway between the selected key and the next, or the previous for the last key. Returns the index of the new key
+pub fn add_key<T: Animatable + Default>(curve: &mut Curve<T>, selected: usize) -> usize {
+    if curve.keys.is_empty() {
+        curve.keys.push(Key { t: 0.0, value: T::default() });
+        return 0;
+    }
+
+    let selected = selected.min(curve.keys.len() - 1);
+    let t = match (curve.keys.get(selected), curve.keys.get(selected + 1)) {
+        (Some(a), Some(b)) => (a.t + b.t) * 0.5,
+        (Some(a), None) if selected > 0 => (curve.keys[selected - 1].t + a.t) * 0.5,
+        // single key
+        (Some(a), None) => if a.t < 1.0 { 1.0 } else { 0.0 },
+        _ => unreachable!(),
+    };
+
+    // keep the shape of the curve
+    curve.set_key(t, curve.sample(t));
+    curve.keys.iter().position(|k| k.t == t).unwrap_or(0)
+}
+
+/// Remove the key at index, the last key is kept. Returns the index to select after
+pub fn remove_key<T>(curve: &mut Curve<T>, index: usize) -> usize {
+    if curve.keys.len() > 1 && index < curve.keys.len() {
+        curve.keys.remove(index);
+    }
+
+    index.min(curve.keys.len().saturating_sub(1))
+}
+
+/// Move key to t, clamped between its neighbours so the keys stay sorted
+pub fn set_key_t<T>(curve: &mut Curve<T>, index: usize, t: f32) {
+    let min = if index > 0 { curve.keys[index - 1].t } else { 0.0 };
+    let max = curve.keys.get(index + 1).map(|k| k.t).unwrap_or(1.0);
+
+    if let Some(key) = curve.keys.get_mut(index) {
+        key.t = t.clamp(min, max);
+    }
+}
+
+
+fn slider_row<T: Numeric>(ui: &mut Ui, label: &str, item: &mut T, min: T, max: T) -> bool {
+    ui.body_text(label);
+    let changed = ui.slider(item, min, max);
+    ui.newline();
+    changed
+}
+
+/// Graph, key selection and add/remove buttons. Returns true when the curve changed
+fn edit_curve_keys<T: Animatable + Default>(ui: &mut Ui, curve: &mut Curve<T>, key: &mut usize, graph: impl Fn(&Curve<T>, f32) -> f32) -> bool {
+    let mut changed = false;
+
+    ui.graph(&GraphInfo { w: 240, h: 80, start: 0.0, end: 1.0 }, |t| graph(curve, t));
+    ui.newline();
+
+    for i in 0..curve.keys.len() {
+        let text = format!("{:.2}", curve.keys[i].t);
+        if i == *key {
+            ui.body_text(&text);
+        } else if ui.button(&text) {
+            *key = i;
+        }
+    }
+    ui.newline();
+
+    if ui.button("Add key") {
+        *key = add_key(curve, *key);
+        changed = true;
+    }
+
+    if ui.button("Remove key") {
+        *key = remove_key(curve, *key);
+        changed = true;
+    }
+    ui.newline();
+
+    *key = (*key).min(curve.keys.len().saturating_sub(1));
+
+    if let Some(k) = curve.keys.get(*key) {
+        let mut t = k.t;
+        ui.body_text("Time");
+        if ui.slider(&mut t, 0.0, 1.0) {
+            set_key_t(curve, *key, t);
+            changed = true;
+        }
+        ui.newline();
+    }
+
+    changed
+}
+
+/// Widgets for all fields of desc. Returns true when desc changed
+pub fn edit_emitter_desc(ui: &mut Ui, editor: &mut ParticleEditor) -> bool {
+    let desc = &mut editor.desc;
+    let mut changed = false;
+
+    ui.heading_text("Emission");
+    ui.newline();
+    changed |= slider_row(ui, "Rate", &mut desc.rate, 0.0, 200.0);
+    changed |= slider_row(ui, "Max particles", &mut desc.max_particles, 1, 10000);
+    changed |= slider_row(ui, "Duration", &mut desc.duration, 0.1, 10.0);
+
+    ui.body_text("Looping");
+    changed |= ui.checkbox(&mut desc.looping);
+    ui.body_text("World space");
+    changed |= ui.checkbox(&mut desc.world_space);
+    ui.newline();
+
+    changed |= slider_row(ui, "Lifetime min", &mut desc.lifetime[0], 0.01, 10.0);
+    changed |= slider_row(ui, "Lifetime max", &mut desc.lifetime[1], 0.01, 10.0);
+    changed |= slider_row(ui, "Speed min", &mut desc.speed[0], 0.0, 20.0);
+    changed |= slider_row(ui, "Speed max", &mut desc.speed[1], 0.0, 20.0);
+    changed |= slider_row(ui, "Rotation min", &mut desc.rotation_speed[0], -10.0, 10.0);
+    changed |= slider_row(ui, "Rotation max", &mut desc.rotation_speed[1], -10.0, 10.0);
+
+    ui.heading_text("Shape");
+    ui.newline();
+    let shapes = [
+        ("Point", EmitterShape::Point),
+        ("Cone", EmitterShape::Cone { angle: 20.0, radius: 0.1 }),
+        ("Sphere", EmitterShape::Sphere { radius: 0.5, surface: false }),
+        ("Box", EmitterShape::Box { size: V3::new(1.0, 1.0, 1.0) }),
+    ];
+    for (text, shape) in shapes {
+        if std::mem::discriminant(&desc.shape) == std::mem::discriminant(&shape) {
+            ui.body_text(text);
+        } else if ui.button(text) {
+            desc.shape = shape;
+            changed = true;
+        }
+    }
+    ui.newline();
+
+    match &mut desc.shape {
+        EmitterShape::Point => {},
+        EmitterShape::Cone { angle, radius } => {
+            changed |= slider_row(ui, "Angle", angle, 0.0, 180.0);
+            changed |= slider_row(ui, "Radius", radius, 0.0, 5.0);
+        },
+        EmitterShape::Sphere { radius, surface } => {
+            changed |= slider_row(ui, "Radius", radius, 0.0, 5.0);
+            ui.body_text("Surface");
+            changed |= ui.checkbox(surface);
+            ui.newline();
+        },
+        EmitterShape::Box { size } => {
+            changed |= slider_row(ui, "Size x", &mut size.x, 0.0, 10.0);
+            changed |= slider_row(ui, "Size y", &mut size.y, 0.0, 10.0);
+            changed |= slider_row(ui, "Size z", &mut size.z, 0.0, 10.0);
+        },
+        EmitterShape::Mesh { name } => {
+            ui.small_text(&format!("Mesh: {}", name));
+            ui.newline();
+        }
+    }
+
+    ui.heading_text("Forces");
+    ui.newline();
+    changed |= slider_row(ui, "Gravity x", &mut desc.gravity.x, -20.0, 20.0);
+    changed |= slider_row(ui, "Gravity y", &mut desc.gravity.y, -20.0, 20.0);
+    changed |= slider_row(ui, "Gravity z", &mut desc.gravity.z, -20.0, 20.0);
+    changed |= slider_row(ui, "Drag", &mut desc.drag, 0.0, 5.0);
+    changed |= slider_row(ui, "Noise", &mut desc.noise.strength, 0.0, 20.0);
+    changed |= slider_row(ui, "Noise frequency", &mut desc.noise.frequency, 0.0, 5.0);
+    changed |= slider_row(ui, "Noise scroll", &mut desc.noise.scroll, 0.0, 5.0);
+
+    ui.heading_text("Over lifetime");
+    ui.newline();
+    for (text, curve) in [("Size", CurveEdit::Size), ("Speed", CurveEdit::SpeedOverLife), ("Color", CurveEdit::Color)] {
+        if editor.curve == curve {
+            ui.body_text(text);
+        } else if ui.button(text) {
+            editor.curve = curve;
+            editor.key = 0;
+        }
+    }
+    ui.newline();
+
+    let key = &mut editor.key;
+    match editor.curve {
+        CurveEdit::Size | CurveEdit::SpeedOverLife => {
+            let curve = if editor.curve == CurveEdit::Size { &mut desc.size } else { &mut desc.speed_over_life };
+            changed |= edit_curve_keys(ui, curve, key, |c, t| c.sample(t));
+
+            if let Some(k) = curve.keys.get_mut(*key) {
+                changed |= slider_row(ui, "Value", &mut k.value, 0.0, 5.0);
+            }
+        },
+        CurveEdit::Color => {
+            let curve = &mut desc.color;
+            changed |= edit_curve_keys(ui, curve, key, |c, t| c.sample(t).w);
+
+            if let Some(k) = curve.keys.get_mut(*key) {
+                let mut color = Color::from_vec4(k.value);
+                if ui.color_picker(&mut color) {
+                    let alpha = k.value.w;
+                    k.value = color.as_vec4();
+                    k.value.w = alpha;
+                    changed = true;
+                }
+                ui.newline();
+                changed |= slider_row(ui, "Alpha", &mut k.value.w, 0.0, 1.0);
+            }
+        }
+    }
+
+    ui.heading_text("Rendering");
+    ui.newline();
+    for (text, blend) in [("Alpha", BlendMode::Alpha), ("Additive", BlendMode::Additive)] {
+        if desc.blend == blend {
+            ui.body_text(text);
+        } else if ui.button(text) {
+            desc.blend = blend;
+            changed = true;
+        }
+    }
+    ui.newline();
+
+    changed
+}
+
+
+impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserControllerData> {
+
+    /// Show the particle editor window and keep a preview of the edited effect playing at `preview_pos`.
+    /// Non looping effects are restarted when finished. Returns true when the description was saved
+    pub fn particle_editor(&mut self, editor: &mut ParticleEditor) -> bool {
+        if !editor.show {
+            return false;
+        }
+
+        let mut saved = false;
+        let mut restart = false;
+
+        let res = self.ui.window_begin("Particles");
+        editor.show = !res.closed;
+
+        self.ui.body_text(&editor.desc.name);
+        self.ui.newline();
+
+        if self.ui.button("Save") {
+            match editor.desc.save(&editor.path) {
+                Ok(()) => {
+                    editor.status = format!("Saved {:?}", editor.path);
+                    saved = true;
+                },
+                Err(err) => {
+                    editor.status = format!("Failed to save: {}", err);
+                }
+            }
+        }
+
+        if self.ui.button("Load") {
+            match EmitterDesc::load(&editor.path) {
+                Ok(desc) => {
+                    editor.status = format!("Loaded {:?}", editor.path);
+                    editor.desc = desc;
+                    editor.key = 0;
+                    restart = true;
+                },
+                Err(err) => {
+                    editor.status = format!("Failed to load: {}", err);
+                }
+            }
+        }
+
+        if self.ui.button("Restart") {
+            restart = true;
+        }
+
+        if !editor.status.is_empty() {
+            self.ui.newline();
+            self.ui.small_text(&editor.status);
+        }
+        self.ui.newline();
+
+        let changed = edit_emitter_desc(&mut self.ui, editor);
+
+        self.ui.window_end("Particles");
+
+        let name = editor.desc.name.as_str();
+        if restart {
+            self.effects.remove(name);
+        }
+
+        if changed || restart || !self.effects.descs.contains_key(name) {
+            self.effects.register(editor.desc.clone());
+        }
+
+        if !self.effects.active.iter().any(|e| &*e.name == name) {
+            // registered above, so it can not fail
+            let _ = self.effects.spawn(name, EffectTarget::Pos(editor.preview_pos));
+        }
+
+        saved
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove_keys() {
+        let mut curve = Curve::linear(0.0, 2.0);
+
+        // halfway to the next key, keeping the value
+        assert_eq!(add_key(&mut curve, 0), 1);
+        assert_eq!(curve.keys[1], Key { t: 0.5, value: 1.0 });
+
+        // the last key adds before it
+        assert_eq!(add_key(&mut curve, 2), 2);
+        assert_eq!(curve.keys[2].t, 0.75);
+        assert_eq!(curve.keys.len(), 4);
+
+        assert_eq!(remove_key(&mut curve, 3), 2);
+        assert_eq!(remove_key(&mut curve, 0), 0);
+        assert_eq!(curve.keys.len(), 2);
+        assert_eq!(curve.keys[0].t, 0.5);
+
+        // the last key is kept
+        assert_eq!(remove_key(&mut curve, 0), 0);
+        assert_eq!(remove_key(&mut curve, 0), 0);
+        assert_eq!(curve.keys.len(), 1);
+
+        let mut single = Curve::constant(3.0);
+        assert_eq!(add_key(&mut single, 0), 1);
+        assert_eq!(single.keys[1], Key { t: 1.0, value: 3.0 });
+    }
+
+    #[test]
+    fn key_time_stays_sorted() {
+        let mut curve = Curve::linear(0.0, 1.0).with_key(0.5, 2.0);
+
+        set_key_t(&mut curve, 1, 2.0);
+        assert_eq!(curve.keys[1].t, 1.0);
+
+        set_key_t(&mut curve, 1, 0.25);
+        assert_eq!(curve.keys[1].t, 0.25);
+
+        set_key_t(&mut curve, 0, -1.0);
+        assert_eq!(curve.keys[0].t, 0.0);
+        assert!(curve.keys.windows(2).all(|w| w[0].t <= w[1].t));
+    }
+}
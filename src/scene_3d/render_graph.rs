@@ -0,0 +1,675 @@
+//! Render graph for a `RenderPipeline`. Passes declare which resources they read and write.
+//! The graph orders the passes, culls passes that do not contribute to the backbuffer, and
+//! allocates frame buffers, reusing them for targets whose lifetimes does not overlap.
+//!
+//! Compilation does not touch gl, so it can be tested without a context. Frame buffers are
+//! allocated on first render and resized when the viewport changes.
+use crate::{gl, buffer, texture};
+use crate::imode_gui::ui::*;
+use crate::animations::skeleton::Bones;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::scene_3d::{RenderPipeline, RenderMesh};
+use crate::scene_3d::lights::Light;
+use crate::objects::shadow_map::Cascades;
+use crate::particle_system::simulation::ParticleEmitter;
+use crate::particle_system::trail::Trail;
+use crate::scene_3d::EffectRenderers;
+use std::rc::Rc;
+
+
+pub type ResourceId = usize;
+pub type PassId = usize;
+
+/// The window frame buffer. Always the first resource in a graph
+pub const BACKBUFFER: ResourceId = 0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetSize {
+    Viewport,
+    /// Fraction of the viewport, fx 0.5 for a half resolution bloom target
+    Scaled(f32),
+    Fixed(i32, i32),
+}
+
+impl TargetSize {
+    pub fn resolve(&self, viewport: &gl::viewport::Viewport) -> (i32, i32) {
+        match *self {
+            TargetSize::Viewport => (viewport.w, viewport.h),
+            TargetSize::Scaled(s) => (((viewport.w as f32 * s) as i32).max(1), ((viewport.h as f32 * s) as i32).max(1)),
+            TargetSize::Fixed(w, h) => (w, h)
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct TargetDesc {
+    pub size: TargetSize,
+    /// Used when the first pass writing the target clears it
+    pub clear_color: Color,
+}
+
+impl Default for TargetDesc {
+    fn default() -> Self {
+        Self {
+            size: TargetSize::Viewport,
+            clear_color: Color::Rgb(230, 230, 230),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum ResourceKind {
+    Backbuffer,
+    /// Frame buffer with color and depth/stencil textures, owned by the graph
+    Target(TargetDesc),
+    /// Texture owned by the pass writing it, fx the shadow map depth texture. Set with `PassContext::set_texture`
+    External,
+}
+
+#[derive(Debug, Clone)]
+pub struct Resource {
+    pub name: Rc::<str>,
+    pub kind: ResourceKind,
+}
+
+
+pub trait RenderPass<Data> {
+    fn execute(&mut self, ctx: &mut PassContext<Data>);
+
+    /// Called when the viewport changes, before the next execute
+    fn resize(&mut self, _gl: &gl::Gl, _viewport: &gl::viewport::Viewport) {}
+}
+
+struct PassNode<Data> {
+    name: Rc::<str>,
+    inputs: Vec::<ResourceId>,
+    outputs: Vec::<ResourceId>,
+    enabled: bool,
+    pass: Box<dyn RenderPass<Data>>,
+}
+
+
+/// Result of compiling a graph, only depends on the declared passes and resources
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledGraph {
+    /// Passes in execution order
+    pub order: Vec::<PassId>,
+    /// Enabled passes that does not contribute to the backbuffer
+    pub culled: Vec::<PassId>,
+    /// Index into order of first and last pass using a target. None for unused targets and other resources
+    pub lifetimes: Vec::<Option<(usize, usize)>>,
+    /// Index of the physical frame buffer for each resource
+    pub physical: Vec::<Option<usize>>,
+    /// Size of each physical frame buffer
+    pub physical_sizes: Vec::<TargetSize>,
+    /// (pass, target) where the pass is the first writer, and should clear the target
+    pub clears: Vec::<(PassId, ResourceId)>,
+}
+
+
+pub struct RenderGraph<Data> {
+    resources: Vec::<Resource>,
+    passes: Vec::<PassNode<Data>>,
+    compiled: Option<CompiledGraph>,
+
+    // gl state
+    targets: Vec::<buffer::FrameBuffer>,
+    textures: Vec::<texture::TextureId>,
+    allocated_size: Option<(i32, i32)>,
+}
+
+impl<Data> Default for RenderGraph<Data> {
+    fn default() -> Self {
+        Self {
+            resources: vec![Resource { name: "backbuffer".into(), kind: ResourceKind::Backbuffer }],
+            passes: vec![],
+            compiled: None,
+            targets: vec![],
+            textures: vec![],
+            allocated_size: None,
+        }
+    }
+}
+
+
+impl<Data> RenderGraph<Data> {
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_target(&mut self, name: &str, desc: TargetDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Target(desc))
+    }
+
+    pub fn add_external(&mut self, name: &str) -> ResourceId {
+        self.add_resource(name, ResourceKind::External)
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceId {
+        self.compiled = None;
+        self.resources.push(Resource { name: name.into(), kind });
+        self.resources.len() - 1
+    }
+
+    /// Add pass reading `inputs` and writing `outputs`. Passes writing the same resource runs in the order they are added
+    pub fn add_pass(&mut self, name: &str, inputs: &[ResourceId], outputs: &[ResourceId], pass: Box<dyn RenderPass<Data>>) -> PassId {
+        self.compiled = None;
+        self.passes.push(PassNode {
+            name: name.into(),
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+            enabled: true,
+            pass
+        });
+        self.passes.len() - 1
+    }
+
+    pub fn set_inputs(&mut self, pass: PassId, inputs: &[ResourceId]) {
+        self.compiled = None;
+        self.passes[pass].inputs = inputs.to_vec();
+    }
+
+    pub fn set_outputs(&mut self, pass: PassId, outputs: &[ResourceId]) {
+        self.compiled = None;
+        self.passes[pass].outputs = outputs.to_vec();
+    }
+
+    pub fn set_enabled(&mut self, pass: PassId, enabled: bool) {
+        if self.passes[pass].enabled != enabled {
+            self.compiled = None;
+            self.passes[pass].enabled = enabled;
+        }
+    }
+
+    pub fn pass_id(&self, name: &str) -> Option<PassId> {
+        self.passes.iter().position(|p| &*p.name == name)
+    }
+
+    pub fn resource_id(&self, name: &str) -> Option<ResourceId> {
+        self.resources.iter().position(|r| &*r.name == name)
+    }
+
+    pub fn resource(&self, id: ResourceId) -> &Resource {
+        &self.resources[id]
+    }
+
+    pub fn pass_name(&self, id: PassId) -> &str {
+        &self.passes[id].name
+    }
+
+    /// Get the compiled graph, compiling it if anything changed
+    pub fn compiled(&mut self) -> Result<&CompiledGraph, failure::Error> {
+        if self.compiled.is_none() {
+            self.compiled = Some(self.compile()?);
+            // physical targets might have changed, existing targets are resized and reused when allocating
+            self.allocated_size = None;
+        }
+
+        Ok(self.compiled.as_ref().unwrap())
+    }
+
+    pub fn compile(&self) -> Result<CompiledGraph, failure::Error> {
+        let res_count = self.resources.len();
+
+        for (id, pass) in self.passes.iter().enumerate() {
+            for r in pass.inputs.iter().chain(pass.outputs.iter()) {
+                if *r >= res_count {
+                    failure::bail!("Pass '{}' uses unknown resource {}", pass.name, r);
+                }
+            }
+
+            if let Some(r) = pass.inputs.iter().find(|r| pass.outputs.contains(r)) {
+                failure::bail!("Pass '{}' reads and writes '{}'", pass.name, self.resources[*r].name);
+            }
+
+            if pass.inputs.contains(&BACKBUFFER) {
+                failure::bail!("Pass {} '{}' reads the backbuffer", id, pass.name);
+            }
+        }
+
+        // enabled writers of each resource, in the order they were added
+        let mut writers = vec![vec![]; res_count];
+        for (id, pass) in self.passes.iter().enumerate().filter(|(_, p)| p.enabled) {
+            for r in &pass.outputs {
+                writers[*r].push(id);
+            }
+        }
+
+        // find live passes, working back from the backbuffer
+        let mut live = vec![false; self.passes.len()];
+        let mut live_res = vec![false; res_count];
+        let mut stack = vec![BACKBUFFER];
+        live_res[BACKBUFFER] = true;
+
+        while let Some(r) = stack.pop() {
+            for w in &writers[r] {
+                if live[*w] {
+                    continue;
+                }
+                live[*w] = true;
+
+                for input in &self.passes[*w].inputs {
+                    if writers[*input].is_empty() {
+                        failure::bail!("Pass '{}' reads '{}', which no pass writes", self.passes[*w].name, self.resources[*input].name);
+                    }
+
+                    if !live_res[*input] {
+                        live_res[*input] = true;
+                        stack.push(*input);
+                    }
+                }
+            }
+        }
+
+        // dependencies: readers after all writers, and writers in the order they were added
+        let mut deps = vec![vec![]; self.passes.len()];
+        for (id, pass) in self.passes.iter().enumerate().filter(|(i, _)| live[*i]) {
+            for input in &pass.inputs {
+                deps[id].extend(writers[*input].iter().copied());
+            }
+        }
+
+        for ws in &writers {
+            for pair in ws.windows(2) {
+                if live[pair[0]] && live[pair[1]] {
+                    deps[pair[1]].push(pair[0]);
+                }
+            }
+        }
+
+        // topological sort, ties broken by the order passes were added
+        let mut order = vec![];
+        let mut done = vec![false; self.passes.len()];
+        let live_count = live.iter().filter(|l| **l).count();
+        while order.len() < live_count {
+            let next = (0..self.passes.len()).find(|i| live[*i] && !done[*i] && deps[*i].iter().all(|d| done[*d]));
+            match next {
+                Some(i) => {
+                    done[i] = true;
+                    order.push(i);
+                },
+                None => {
+                    let names : Vec::<&str> = (0..self.passes.len()).filter(|i| live[*i] && !done[*i]).map(|i| &*self.passes[i].name).collect();
+                    failure::bail!("Render graph has a cycle between passes {:?}", names);
+                }
+            }
+        }
+
+        let culled = (0..self.passes.len()).filter(|i| self.passes[*i].enabled && !live[*i]).collect();
+
+        // target lifetimes
+        let mut lifetimes = vec![None; res_count];
+        let mut clears = vec![];
+        for (idx, pass_id) in order.iter().enumerate() {
+            let pass = &self.passes[*pass_id];
+            for r in pass.inputs.iter().chain(pass.outputs.iter()) {
+                if !matches!(self.resources[*r].kind, ResourceKind::Target(_)) {
+                    continue;
+                }
+
+                lifetimes[*r] = match lifetimes[*r] {
+                    None => {
+                        clears.push((*pass_id, *r));
+                        Some((idx, idx))
+                    },
+                    Some((first, _)) => Some((first, idx))
+                };
+            }
+        }
+
+        // alias targets with the same size and no overlap
+        let mut by_start : Vec::<ResourceId> = (0..res_count).filter(|r| lifetimes[*r].is_some()).collect();
+        by_start.sort_by_key(|r| lifetimes[*r].unwrap().0);
+
+        let mut physical = vec![None; res_count];
+        let mut physical_sizes = vec![];
+        let mut physical_end = vec![];
+        for r in by_start {
+            let (first, last) = lifetimes[r].unwrap();
+            let size = match self.resources[r].kind {
+                ResourceKind::Target(desc) => desc.size,
+                _ => unreachable!()
+            };
+
+            let slot = (0..physical_sizes.len()).find(|s| physical_sizes[*s] == size && physical_end[*s] < first);
+            let slot = match slot {
+                Some(s) => s,
+                None => {
+                    physical_sizes.push(size);
+                    physical_end.push(0);
+                    physical_sizes.len() - 1
+                }
+            };
+
+            physical_end[slot] = last;
+            physical[r] = Some(slot);
+        }
+
+        Ok(CompiledGraph {
+            order,
+            culled,
+            lifetimes,
+            physical,
+            physical_sizes,
+            clears,
+        })
+    }
+
+    fn allocate(&mut self, gl: &gl::Gl, viewport: &gl::viewport::Viewport) {
+        let size = (viewport.w, viewport.h);
+        if self.allocated_size == Some(size) {
+            return;
+        }
+
+        let compiled = self.compiled.as_ref().expect("Graph should be compiled before allocating");
+
+        // targets no longer used after a recompile
+        self.targets.truncate(compiled.physical_sizes.len());
+
+        for (i, target_size) in compiled.physical_sizes.iter().enumerate() {
+            let (w, h) = target_size.resolve(viewport);
+            let vp = gl::viewport::Viewport::for_window(w, h);
+            if i < self.targets.len() {
+                self.targets[i].update_viewport(gl, &vp);
+            } else {
+                self.targets.push(buffer::FrameBuffer::new(gl, &vp));
+            }
+        }
+
+        self.textures = vec![0; self.resources.len()];
+
+        for pass in &mut self.passes {
+            pass.pass.resize(gl, viewport);
+        }
+
+        self.allocated_size = Some(size);
+    }
+
+    /// Compile if needed, allocate targets and execute passes in order
+    pub fn execute(&mut self, frame: &mut SceneFrame<Data>) -> Result<(), failure::Error> {
+        self.compiled()?;
+        let gl = frame.gl.clone();
+        self.allocate(&gl, frame.viewport);
+
+        let compiled = self.compiled.as_ref().unwrap();
+
+        for pass_id in &compiled.order {
+            let node = &mut self.passes[*pass_id];
+            let mut ctx = PassContext {
+                frame,
+                pass_id: *pass_id,
+                inputs: &node.inputs,
+                outputs: &node.outputs,
+                resources: &self.resources,
+                compiled,
+                targets: &self.targets,
+                textures: &mut self.textures,
+            };
+
+            node.pass.execute(&mut ctx);
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Particles of `Scene::effects` and trails of `Scene::trails` using a pipeline
+#[derive(Default)]
+pub struct FrameEffects<'a> {
+    pub particles: Vec::<&'a ParticleEmitter>,
+    pub trails: Vec::<&'a Trail>,
+}
+
+
+/// Per frame data for passes in a `RenderPipeline`
+pub struct SceneFrame<'a, 'b, Data> {
+    pub gl: &'a gl::Gl,
+    pub viewport: &'a gl::viewport::Viewport,
+    pub pipeline: &'a mut RenderPipeline<Data>,
+    pub camera: &'a Camera,
+    pub lights: &'a [Light],
+    /// Index of the light used for the shadow map, set by the shadow pass
+    pub shadow_light: Option<usize>,
+    pub ui: &'a mut Ui,
+    pub default_bones: &'a Bones,
+    pub render_meshes: &'a [RenderMesh<'b>],
+    pub effects: &'a FrameEffects<'b>,
+    pub effect_renderers: &'a mut EffectRenderers,
+    /// Set by the shadow pass
+    pub cascades: Cascades,
+}
+
+
+pub struct PassContext<'f, 'a, 'b, Data> {
+    pub frame: &'f mut SceneFrame<'a, 'b, Data>,
+    pub pass_id: PassId,
+    inputs: &'f [ResourceId],
+    outputs: &'f [ResourceId],
+    resources: &'f [Resource],
+    compiled: &'f CompiledGraph,
+    targets: &'f [buffer::FrameBuffer],
+    textures: &'f mut Vec::<texture::TextureId>,
+}
+
+impl<'f, 'a, 'b, Data> PassContext<'f, 'a, 'b, Data> {
+
+    pub fn inputs(&self) -> &[ResourceId] {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> &[ResourceId] {
+        self.outputs
+    }
+
+    fn target(&self, r: ResourceId) -> Option<&buffer::FrameBuffer> {
+        self.compiled.physical[r].map(|slot| &self.targets[slot])
+    }
+
+    /// Color texture of a target input, or the texture set by the pass writing an external input
+    pub fn input_texture(&self, i: usize) -> texture::TextureId {
+        let r = self.inputs[i];
+        match self.target(r) {
+            Some(fb) => fb.color_tex,
+            None => self.textures[r]
+        }
+    }
+
+    /// Depth and stencil texture of a target input
+    pub fn input_depth(&self, i: usize) -> Option<texture::TextureId> {
+        self.target(self.inputs[i]).map(|fb| fb.depth_stencil_tex)
+    }
+
+    /// Bind input textures to texture units, starting at `first_unit`
+    pub fn bind_inputs(&self, first_unit: u32) {
+        for i in 0..self.inputs.len() {
+            texture::active_texture(self.frame.gl, first_unit + i as u32);
+            texture::set_texture(self.frame.gl, self.input_texture(i));
+        }
+    }
+
+    /// Set the texture for an external output, so passes reading it can use it
+    pub fn set_texture(&mut self, output: usize, texture_id: texture::TextureId) {
+        self.textures[self.outputs[output]] = texture_id;
+    }
+
+    pub fn output_size(&self, output: usize) -> (i32, i32) {
+        let r = self.outputs[output];
+        match self.compiled.physical[r] {
+            Some(slot) => self.compiled.physical_sizes[slot].resolve(self.frame.viewport),
+            None => (self.frame.viewport.w, self.frame.viewport.h)
+        }
+    }
+
+    /// Bind output for rendering and set the gl viewport to its size. Targets are cleared with `clear_bits`
+    /// when this is the first pass writing them this frame
+    pub fn bind_output(&self, output: usize, clear_bits: u32) {
+        let r = self.outputs[output];
+        let (w, h) = self.output_size(output);
+
+        match (self.target(r), self.resources[r].kind) {
+            (Some(fb), ResourceKind::Target(desc)) => {
+                fb.bind();
+                if self.compiled.clears.contains(&(self.pass_id, r)) {
+                    let c = desc.clear_color.as_vec4();
+                    unsafe {
+                        self.frame.gl.ClearColor(c.x, c.y, c.z, c.w);
+                        self.frame.gl.Clear(clear_bits);
+                    }
+                }
+            },
+            _ => {
+                unsafe {
+                    self.frame.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                }
+            }
+        }
+
+        unsafe {
+            self.frame.gl.Viewport(0, 0, w, h);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Noop;
+
+    impl RenderPass<()> for Noop {
+        fn execute(&mut self, _ctx: &mut PassContext<()>) {}
+    }
+
+    fn graph() -> RenderGraph<()> {
+        RenderGraph::new()
+    }
+
+    fn names(g: &RenderGraph<()>, passes: &[PassId]) -> Vec::<String> {
+        passes.iter().map(|p| g.pass_name(*p).to_string()).collect()
+    }
+
+    #[test]
+    fn orders_by_dependencies() {
+        let mut g = graph();
+        let color = g.add_target("color", Default::default());
+        let shadow = g.add_external("shadow");
+
+        // added out of order
+        g.add_pass("post", &[color], &[BACKBUFFER], Box::new(Noop));
+        g.add_pass("scene", &[shadow], &[color], Box::new(Noop));
+        g.add_pass("shadow", &[], &[shadow], Box::new(Noop));
+
+        let c = g.compile().unwrap();
+        assert_eq!(names(&g, &c.order), vec!["shadow", "scene", "post"]);
+        assert_eq!(c.lifetimes[color], Some((1, 2)));
+        assert_eq!(c.lifetimes[shadow], None);
+        assert_eq!(c.clears, vec![(1, color)]);
+    }
+
+    #[test]
+    fn writers_keep_insertion_order() {
+        let mut g = graph();
+        let color = g.add_target("color", Default::default());
+        g.add_pass("meshes", &[], &[color], Box::new(Noop));
+        g.add_pass("sky", &[], &[color], Box::new(Noop));
+        g.add_pass("post", &[color], &[BACKBUFFER], Box::new(Noop));
+        g.add_pass("ui", &[], &[BACKBUFFER], Box::new(Noop));
+
+        let c = g.compile().unwrap();
+        assert_eq!(names(&g, &c.order), vec!["meshes", "sky", "post", "ui"]);
+        // only first writer clears
+        assert_eq!(c.clears, vec![(0, color)]);
+    }
+
+    #[test]
+    fn culls_unused_passes() {
+        let mut g = graph();
+        let color = g.add_target("color", Default::default());
+        let debug = g.add_target("debug", Default::default());
+        g.add_pass("scene", &[], &[color], Box::new(Noop));
+        g.add_pass("debug", &[color], &[debug], Box::new(Noop));
+        g.add_pass("post", &[color], &[BACKBUFFER], Box::new(Noop));
+
+        let c = g.compile().unwrap();
+        assert_eq!(names(&g, &c.order), vec!["scene", "post"]);
+        assert_eq!(names(&g, &c.culled), vec!["debug"]);
+        assert_eq!(c.physical[debug], None);
+    }
+
+    #[test]
+    fn disabled_pass_is_skipped() {
+        let mut g = graph();
+        let color = g.add_target("color", Default::default());
+        let scene = g.add_pass("scene", &[], &[color], Box::new(Noop));
+        let post = g.add_pass("post", &[color], &[BACKBUFFER], Box::new(Noop));
+
+        g.set_enabled(post, false);
+        assert!(g.compile().unwrap().order.is_empty());
+
+        // scene straight to backbuffer, like a pipeline without post processing
+        g.set_outputs(scene, &[BACKBUFFER]);
+        assert_eq!(g.compile().unwrap().order, vec![scene]);
+    }
+
+    #[test]
+    fn aliases_non_overlapping_targets() {
+        let mut g = graph();
+        let scene = g.add_target("scene", Default::default());
+        let bright = g.add_target("bright", Default::default());
+        let blur = g.add_target("blur", Default::default());
+        let half = g.add_target("half", TargetDesc { size: TargetSize::Scaled(0.5), ..Default::default() });
+
+        g.add_pass("scene", &[], &[scene], Box::new(Noop));
+        g.add_pass("bright", &[scene], &[bright], Box::new(Noop));
+        g.add_pass("half", &[bright], &[half], Box::new(Noop));
+        g.add_pass("blur", &[half], &[blur], Box::new(Noop));
+        g.add_pass("combine", &[scene, blur], &[BACKBUFFER], Box::new(Noop));
+
+        let c = g.compile().unwrap();
+        assert_eq!(c.lifetimes[scene], Some((0, 4)));
+        assert_eq!(c.lifetimes[bright], Some((1, 2)));
+        assert_eq!(c.lifetimes[blur], Some((3, 4)));
+
+        // blur can reuse brights buffer, half has another size
+        assert_eq!(c.physical[blur], c.physical[bright]);
+        assert_ne!(c.physical[scene], c.physical[bright]);
+        assert_ne!(c.physical[half], c.physical[bright]);
+        assert_eq!(c.physical_sizes.len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        let mut g = graph();
+        let a = g.add_target("a", Default::default());
+        let b = g.add_target("b", Default::default());
+        g.add_pass("first", &[b], &[a], Box::new(Noop));
+        g.add_pass("second", &[a], &[b], Box::new(Noop));
+        g.add_pass("present", &[a], &[BACKBUFFER], Box::new(Noop));
+        assert!(g.compile().is_err());
+
+        let mut g = graph();
+        let a = g.add_target("a", Default::default());
+        g.add_pass("present", &[a], &[BACKBUFFER], Box::new(Noop));
+        assert!(g.compile().is_err());
+
+        let mut g = graph();
+        let a = g.add_target("a", Default::default());
+        g.add_pass("feedback", &[a], &[a, BACKBUFFER], Box::new(Noop));
+        assert!(g.compile().is_err());
+    }
+
+    #[test]
+    fn target_size() {
+        let vp = gl::viewport::Viewport::for_window(1200, 800);
+        assert_eq!(TargetSize::Viewport.resolve(&vp), (1200, 800));
+        assert_eq!(TargetSize::Scaled(0.5).resolve(&vp), (600, 400));
+        assert_eq!(TargetSize::Fixed(2048, 2048).resolve(&vp), (2048, 2048));
+    }
+}
@@ -8,18 +8,18 @@ use crate::objects::{shadow_map::ShadowMap, cubemap::{Cubemap}};
 use crate::camera::{self};
 
 use crate::shader::Shader;
-use std::collections::{HashMap};
-use crate::color::Color;
-use crate::scene_3d::scene_3d::{SceneMesh, EntityId};
 
-use crate::scene_3d::Fbos;
 use crate::scene_3d::render_scene;
 use std::rc::Rc;
 use crate::shader::reload_object_shader;
 use crate::shader::texture_shader;
 use crate::scene_3d::PostProcessUniformSet;
-use crate::buffer;
 use crate::scene_3d::RenderMesh;
+use crate::scene_3d::render_graph::*;
+use crate::texture;
+use crate::scene_3d::lights::{Light, shadow_light};
+use crate::scene_3d::instancing::{BoneTexture, instance_batches, BONE_TEXTURE_UNIT};
+use crate::scene_3d::EffectRenderers;
 
 pub type RenderPipelineId = usize;
 
@@ -29,6 +29,36 @@ fn default_uniform_set<T>(_ :&gl::Gl, _: &mut BaseShader, _ : &T) {
 }
 
 
+/// Which mesh shader variant a pipeline uses. Pbr uses the glTF material on each `SceneMesh`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MeshShading {
+    #[default]
+    Basic,
+    Pbr,
+}
+
+
+pub struct PostProcess<UserPostProcessData> {
+    pub shader: texture_shader::TextureShader,
+    pub uniform_set: PostProcessUniformSet<UserPostProcessData>,
+    pub data: UserPostProcessData
+}
+
+
+/// Ids of the passes and resources every pipeline graph starts with.
+/// Custom passes can read and write these, fx a bloom pass reading `scene_color`
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineGraphIds {
+    /// Depth texture of the shadow map, external since `ShadowMap` owns it
+    pub shadow_map: ResourceId,
+    /// Target the scene is rendered to when post processing is used
+    pub scene_color: ResourceId,
+    pub shadow_pass: PassId,
+    pub scene_pass: PassId,
+    pub post_process_pass: PassId,
+}
+
+
 pub struct RenderPipeline<UserPostProcessData> {
 
     pub name: Rc::<str>,
@@ -38,11 +68,18 @@ pub struct RenderPipeline<UserPostProcessData> {
 
     pub clear_buffer_bits: u32,
 
+    pub shading: MeshShading,
+
     // Should these be share?d
     pub shadow_map: Option<ShadowMap>,
     pub cubemap : Option::<Cubemap>,
-    pub fbos: Option::<Fbos<UserPostProcessData>>,
+    pub post_process: Option::<PostProcess<UserPostProcessData>>,
 
+    pub graph: RenderGraph<UserPostProcessData>,
+    pub graph_ids: PipelineGraphIds,
+
+    /// Bones of skinned meshes drawn instanced
+    pub bone_texture: BoneTexture,
 
     // SHADERS
     pub cubemap_shader: BaseShader,
@@ -59,6 +96,17 @@ impl<UserPostProcessData> RenderPipeline<UserPostProcessData> {
         sm.texture_offset = 1;
         let mesh_shader = mesh_shader::MeshShader::new(&gl)?;
         let cubemap_shader = load_object_shader("cubemap", &gl).unwrap();
+        let bone_texture = BoneTexture::new(&gl);
+
+        // default graph is shadow -> scene -> backbuffer, post process is enabled with use_post_process
+        let mut graph = RenderGraph::new();
+        let shadow_map = graph.add_external("shadow_map");
+        let scene_color = graph.add_target("scene_color", Default::default());
+
+        let shadow_pass = graph.add_pass("shadow", &[], &[shadow_map], Box::new(ShadowPass));
+        let scene_pass = graph.add_pass("scene", &[shadow_map], &[BACKBUFFER], Box::new(ScenePass));
+        let post_process_pass = graph.add_pass("post_process", &[scene_color], &[BACKBUFFER], Box::new(PostProcessPass));
+        graph.set_enabled(post_process_pass, false);
 
         Ok(Self {
             gl,
@@ -67,41 +115,54 @@ impl<UserPostProcessData> RenderPipeline<UserPostProcessData> {
             mesh_shader,
             cubemap_shader,
             cubemap: None,
-            fbos: None,
+            post_process: None,
+            graph,
+            graph_ids: PipelineGraphIds {
+                shadow_map,
+                scene_color,
+                shadow_pass,
+                scene_pass,
+                post_process_pass,
+            },
             stencil_shader: None,
+            bone_texture,
+            shading: MeshShading::Basic,
             clear_buffer_bits: gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT,
             shadow_map: Some(sm),
         })
     }
 
-    pub fn use_fbos(&mut self,
-                    data: UserPostProcessData,
-                    fun: Option<PostProcessUniformSet<UserPostProcessData>>,
-                    viewport: &gl::viewport::Viewport) {
+    /// Render the scene to `scene_color` and draw it to the screen with the postprocess shader
+    pub fn use_post_process(&mut self,
+                            data: UserPostProcessData,
+                            fun: Option<PostProcessUniformSet<UserPostProcessData>>) {
 
-        // frame buffer to render to
-        let mesh_fbo = buffer::FrameBuffer::new(&self.gl, &viewport);
+        let mut shader = texture_shader::TextureShader::new(&self.gl).unwrap();
 
-        let mut ui_fbo = buffer::FrameBuffer::new(&self.gl, &viewport);
-
-        // all has to be 0, since opengl works with premultiplied alphas, so if a is 0, all others have to be 0
-        ui_fbo.r = 0.0;
-        ui_fbo.g = 0.0;
-        ui_fbo.b = 0.0;
-        ui_fbo.a = 0.0;
+        reload_object_shader("postprocess", &self.gl, &mut shader.shader);
 
+        self.post_process = Some(PostProcess {
+            shader,
+            uniform_set: fun.unwrap_or(default_uniform_set),
+            data
+        });
 
-        let mut post_process_shader = texture_shader::TextureShader::new(&self.gl).unwrap();
+        let ids = self.graph_ids;
+        self.graph.set_outputs(ids.scene_pass, &[ids.scene_color]);
+        self.graph.set_enabled(ids.post_process_pass, true);
+    }
 
-        reload_object_shader("postprocess", &self.gl, &mut post_process_shader.shader);
+    /// Switch mesh shader to the metallic roughness shader
+    pub fn use_pbr(&mut self) -> Result<(), failure::Error> {
+        self.mesh_shader = mesh_shader::MeshShader::pbr(&self.gl)?;
+        self.shading = MeshShading::Pbr;
+        Ok(())
+    }
 
-        self.fbos = Some(Fbos {
-            mesh_fbo,
-            ui_fbo,
-            post_process_shader,
-            post_process_uniform_set: fun.unwrap_or(default_uniform_set),
-            post_process_data: data
-        });
+    pub fn use_basic_shading(&mut self) -> Result<(), failure::Error> {
+        self.mesh_shader = mesh_shader::MeshShader::new(&self.gl)?;
+        self.shading = MeshShading::Basic;
+        Ok(())
     }
 
     pub fn use_shadow_map(&mut self) {
@@ -113,9 +174,7 @@ impl<UserPostProcessData> RenderPipeline<UserPostProcessData> {
     }
 
     pub fn use_stencil(&mut self) {
-        let mut mesh_shader = mesh_shader::MeshShader::new(&self.gl).unwrap();
-        mesh_shader.shader = load_object_shader("stencil", &self.gl).unwrap();
-
+        let mesh_shader = mesh_shader::MeshShader::from_base(&self.gl, load_object_shader("stencil", &self.gl).unwrap());
         self.stencil_shader = Some(mesh_shader);
         self.clear_buffer_bits |= gl::STENCIL_BUFFER_BIT;
 
@@ -151,90 +210,187 @@ impl<UserPostProcessData> RenderPipeline<UserPostProcessData> {
 
     }
 
-    pub fn render(&mut self, _mesh_data: &Vec::<SceneMesh>,
+    pub fn render(&mut self,
                   camera: &camera::Camera,
-                  light_pos: V3,
-                  light_color: Color,
+                  lights: &[Light],
                   ui: &mut Ui,
                   viewport: &gl::viewport::Viewport,
-                  _bones: &HashMap::<EntityId, Bones>,
                   default_bones: &Bones,
-                  render_meshes: &[RenderMesh],) {
+                  render_meshes: &[RenderMesh],
+                  effects: &FrameEffects,
+                  effect_renderers: &mut EffectRenderers) {
 
+        self.setup_gl_state();
 
+        // take graph out while executing, so passes can use the rest of the pipeline
+        let mut graph = std::mem::take(&mut self.graph);
+        let gl = self.gl.clone();
+        let name = self.name.clone();
+
+        let mut frame = SceneFrame {
+            gl: &gl,
+            viewport,
+            pipeline: self,
+            camera,
+            lights,
+            shadow_light: None,
+            ui,
+            default_bones,
+            render_meshes,
+            effects,
+            effect_renderers,
+            cascades: Default::default(),
+        };
+
+        if let Err(err) = graph.execute(&mut frame) {
+            println!("Render pipeline '{}' failed: {}", name, err);
+        }
+
+        self.graph = graph;
+    }
+}
 
-        self.setup_gl_state();
 
-        // TODO: Allocate once and reuse a vec, maybe just on scene
-        let mut light_space_mats = vec![];
+/// Render meshes to the shadow map from the first shadow casting light, if the pipeline uses a shadow map
+pub struct ShadowPass;
 
-        // RENDER TO SHADOW MAP
-        if let Some(sm) = &self.shadow_map {
-            // calc and set light_space_mats
-            sm.pre_render(&self.gl, light_pos,  &mut light_space_mats);
+impl<Data> RenderPass<Data> for ShadowPass {
+    fn execute(&mut self, ctx: &mut PassContext<Data>) {
+
+        let depth_map = {
+            let frame = &mut *ctx.frame;
+            let gl = frame.gl;
+            let sm = match &frame.pipeline.shadow_map {
+                Some(sm) => sm,
+                None => return
+            };
+
+            let light = match shadow_light(frame.lights) {
+                Some(l) => l,
+                None => return
+            };
+            frame.shadow_light = Some(light);
+
+            sm.pre_render(gl, frame.camera, frame.lights[light].shadow_direction(), &mut frame.cascades);
 
             unsafe {
-                self.gl.Enable(gl::CULL_FACE);
-                self.gl.CullFace(gl::FRONT);
+                gl.Enable(gl::CULL_FACE);
+                gl.CullFace(gl::FRONT);
             }
 
+            // everything is drawn into the shadow map, so instance all render meshes with the same mesh
+            let meshes = frame.render_meshes;
+            let mut order : Vec::<usize> = (0..meshes.len()).collect();
+            order.sort_by_key(|i| (meshes[*i].mesh_id, meshes[*i].lod));
+
+            let mut batches = vec![];
+            instance_batches(&order, |i| meshes[*i].mesh.is_instanced().then_some((meshes[*i].mesh_id, meshes[*i].lod)), &mut batches);
+
+            sm.shader.set_i32(gl, "boneTexture", BONE_TEXTURE_UNIT as i32);
+
+            for cascade in 0..frame.cascades.light_space_mats.len() {
+                sm.bind_cascade(gl, cascade, &frame.cascades);
 
-            for rm in render_meshes {
-                sm.shader.set_mat4(&self.gl, "model", rm.model_mat);
-                sm.shader.set_slice_mat4(&self.gl, "uBones", rm.bones);
+                for batch in &batches {
+                    let items = &order[batch.clone()];
+                    let rm = &meshes[items[0]];
 
-                rm.mesh.render(&self.gl);
+                    if items.len() == 1 {
+                        sm.shader.set_mat4(gl, "model", rm.model_mat);
+                        sm.shader.set_slice_mat4(gl, "uBones", rm.bones);
+
+                        rm.mesh.render(gl);
+                        continue;
+                    }
+
+                    let models : Vec::<Mat4> = items.iter().map(|i| meshes[*i].model_mat).collect();
+                    let skinned = items.iter().any(|i| !std::ptr::eq(meshes[*i].bones, frame.default_bones));
+                    if skinned {
+                        let bones : Vec::<_> = items.iter().map(|i| meshes[*i].bones).collect();
+                        frame.pipeline.bone_texture.upload(&bones);
+                    }
+
+                    sm.shader.set_i32(gl, "boneTextureEnabled", skinned as i32);
+                    sm.shader.set_i32(gl, "instanced", 1);
+                    rm.mesh.render_instanced(gl, &models);
+                    sm.shader.set_i32(gl, "instanced", 0);
+                    sm.shader.set_i32(gl, "boneTextureEnabled", 0);
+                }
             }
 
             unsafe {
-                self.gl.CullFace(gl::BACK);
+                gl.CullFace(gl::BACK);
             }
 
-            sm.post_render(&self.gl, viewport.w, viewport.h);
+            sm.post_render(gl, frame.viewport.w, frame.viewport.h);
+            sm.depth_map
+        };
 
-        }
+        ctx.set_texture(0, depth_map);
+    }
+}
 
 
-        if let Some(ref mut fbos) = self.fbos {
-            fbos.ui_fbo.unbind();
+/// Render meshes with stencil outline and the skybox. Reads the shadow map
+pub struct ScenePass;
 
-            fbos.mesh_fbo.bind_and_clear(self.clear_buffer_bits);
+impl<Data> RenderPass<Data> for ScenePass {
+    fn execute(&mut self, ctx: &mut PassContext<Data>) {
+        ctx.bind_output(0, ctx.frame.pipeline.clear_buffer_bits);
 
+        let frame = &*ctx.frame;
+        let p = &*frame.pipeline;
 
+        if let Some(sm) = &p.shadow_map {
+            texture::active_texture(frame.gl, sm.texture_offset);
+            texture::set_texture_array(frame.gl, ctx.input_texture(0));
+        }
 
-            render_scene(&self.gl, &camera, &self.mesh_shader, &default_bones,
-                         &self.cubemap, &self.cubemap_shader, &self.stencil_shader, &render_meshes,
-                         &light_space_mats, light_pos, light_color);
+        render_scene(frame.gl, frame.camera, &p.mesh_shader, p.shading, frame.default_bones,
+                     &p.cubemap, &p.cubemap_shader, &p.stencil_shader, frame.render_meshes,
+                     &frame.cascades, frame.lights, frame.shadow_light, &p.bone_texture);
 
+        // effects after opaque geometry, so they are depth tested against it and post processed
+        let frame = &mut *ctx.frame;
+        for trail in &frame.effects.trails {
+            frame.effect_renderers.trails.render(trail, frame.camera);
+        }
 
-            fbos.mesh_fbo.unbind();
+        for emitter in &frame.effects.particles {
+            frame.effect_renderers.particles.render(emitter, frame.camera);
+        }
+    }
+}
 
 
-            // Post process 2, render fbo color texture
-            // TODO: maybe add this to unbind?? since unbind is a frame buffer bind or screen buffer.
-            unsafe {
-                self.gl.Disable(gl::DEPTH_TEST);
-                self.gl.ClearColor(0.0, 0.0, 0.0, 0.0);
-                self.gl.Clear(gl::COLOR_BUFFER_BIT);
-            }
+/// Draw the first input with the post process shader. Other inputs are bound to texture unit 1 and up
+pub struct PostProcessPass;
+
+impl<Data> RenderPass<Data> for PostProcessPass {
+    fn execute(&mut self, ctx: &mut PassContext<Data>) {
+        ctx.bind_output(0, 0);
 
-            let w = viewport.w as f32;
-            let h = viewport.h as f32;
+        let gl = ctx.frame.gl;
+        unsafe {
+            gl.Disable(gl::DEPTH_TEST);
+            gl.ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl.Clear(gl::COLOR_BUFFER_BIT);
+        }
 
-            let size =  V2::new(w, h);
+        for i in 1..ctx.inputs().len() {
+            texture::active_texture(gl, i as u32);
+            texture::set_texture(gl, ctx.input_texture(i));
+        }
 
-            fbos.post_process_shader.shader.set_used();
-            (fbos.post_process_uniform_set)(&self.gl, &mut fbos.post_process_shader.shader, &fbos.post_process_data);
+        let tex = ctx.input_texture(0);
+        let size = V2::new(ctx.frame.viewport.w as f32, ctx.frame.viewport.h as f32);
 
-            ui.drawer2D.render_img_custom_shader(fbos.mesh_fbo.color_tex, 0, 0, size, &fbos.post_process_shader);
+        let frame = &mut *ctx.frame;
+        if let Some(pp) = &mut frame.pipeline.post_process {
+            pp.shader.shader.set_used();
+            (pp.uniform_set)(gl, &mut pp.shader.shader, &pp.data);
 
-            // TODO: Handle this when using instanced ui rendering
-            // Draw ui on top at last
-            ui.drawer2D.render_img(fbos.ui_fbo.color_tex, 0, 0, size);
-        } else {
-            render_scene(&self.gl, &camera, &self.mesh_shader, &default_bones,
-                         &self.cubemap, &self.cubemap_shader, &self.stencil_shader, &render_meshes,
-                         &light_space_mats, light_pos, light_color);
+            frame.ui.drawer2D.render_img_custom_shader(tex, 0, 0, size, &pp.shader);
         }
     }
 }
@@ -4,13 +4,13 @@ use crate::animations::skeleton::{Bones};
 use crate::shader::{mesh_shader, BaseShader};
 use crate::typedef::*;
 use crate::texture;
-use crate::objects::{mesh::Mesh, cubemap::{Cubemap}};
-use crate::camera::{self, Camera};
-use crate::na::{Translation3, Rotation3};
+use crate::objects::{mesh::Mesh, cubemap::{Cubemap}, lod};
+use crate::camera::{self, Camera, Projection};
+use crate::na::{Translation3};
 use crate::shader::Shader;
 use std::collections::{HashMap};
 use crate::color::Color;
-use crate::scene_3d::scene_3d::{SceneMesh, EntityId};
+use crate::scene_3d::scene_3d::{SceneMesh, EntityId, MeshIndex};
 use crate::scene_3d::SceneEntity;
 
 use std::rc::Rc;
@@ -18,14 +18,38 @@ use std::rc::Rc;
 
 
 
-use crate::scene_3d::{RenderPipeline, RenderPipelineId, ParticleScene};
+use crate::scene_3d::{RenderPipeline, RenderPipelineId, ParticleScene, MeshShading};
+use crate::scene_3d::lights::*;
+use crate::scene_3d::{culling, instancing};
+use crate::objects::material::Material;
+use crate::objects::shadow_map::Cascades;
 use crate::particle_system::{emitter};
+use crate::particle_system::render::ParticleRenderer;
+use crate::particle_system::trail_render::TrailRenderer;
+use crate::scene_3d::effects::Effects;
+use crate::scene_3d::trails::Trails;
+use crate::scene_3d::render_graph::FrameEffects;
+use crate::scene_3d::components::MeshRef;
+use crate::ecs;
 
 
 // where to keep ids? on scene?
 pub struct RenderPipelines<Data> {
     gl: gl::Gl,
-    pipelines: Vec::<RenderPipeline<Data>>
+    pipelines: Vec::<RenderPipeline<Data>>,
+    light_buffer: LightBuffer,
+    /// Current level of detail of entities with lods
+    lod_state: HashMap::<EntityId, usize>,
+    /// How far past a level threshold the screen size has to be before switching level, fx 0.1 is 10%
+    pub lod_hysteresis: f32,
+    effect_renderers: EffectRenderers,
+}
+
+
+/// Shared by all pipelines, see `FrameEffects`
+pub struct EffectRenderers {
+    pub particles: ParticleRenderer,
+    pub trails: TrailRenderer,
 }
 
 
@@ -35,7 +59,14 @@ impl<Data> RenderPipelines<Data> {
     pub fn new(gl: gl::Gl) -> Result::<Self, failure::Error> {
         Ok(Self {
             gl: gl.clone(),
-            pipelines: vec![RenderPipeline::new(gl, "default".into(), 0)?]
+            light_buffer: LightBuffer::new(&gl),
+            pipelines: vec![RenderPipeline::new(gl.clone(), "default".into(), 0)?],
+            lod_state: HashMap::default(),
+            lod_hysteresis: 0.1,
+            effect_renderers: EffectRenderers {
+                particles: ParticleRenderer::new(&gl)?,
+                trails: TrailRenderer::new(&gl)?,
+            },
         })
     }
 
@@ -65,6 +96,14 @@ impl<Data> RenderPipelines<Data> {
         return None;
     }
 
+    pub fn names(&self) -> Vec::<Rc::<str>> {
+        self.pipelines.iter().map(|p| p.name.clone()).collect()
+    }
+
+    pub fn name(&self, id: RenderPipelineId) -> Option::<Rc::<str>> {
+        self.pipelines.iter().find(|p| p.id == id).map(|p| p.name.clone())
+    }
+
     pub fn default(&mut self) -> &mut RenderPipeline<Data> {
         return self.pipeline("default".into()).expect("Default pipeline should be there, and if removed, don't query it!!");
     }
@@ -101,6 +140,16 @@ impl<Data> RenderPipelines<Data> {
         }
     }
 
+    pub fn use_pbr(&mut self, name: Rc::<str>) -> Result<(), failure::Error> {
+        for pipeline in &mut self.pipelines {
+            if name == pipeline.name {
+                return pipeline.use_pbr();
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn use_stencil(&mut self, name: Rc::<str>) {
         for pipeline in &mut self.pipelines {
             if name == pipeline.name {
@@ -115,14 +164,18 @@ impl<Data> RenderPipelines<Data> {
 
     pub fn render(&mut self, mesh_data: &Vec::<SceneMesh>,
                   camera: &camera::Camera,
-                  light_pos: V3,
-                  light_color: Color,
+                  lights: &[Light],
+                  ambient: Color,
                   ui: &mut Ui,
                   viewport: &gl::viewport::Viewport,
                   bones: &HashMap::<EntityId, Bones>,
                   default_bones: &Bones,
                   entities: &HashMap::<usize, SceneEntity>,
-                  emitter: &emitter::Emitter<ParticleScene>) {
+                  world_mats: &HashMap::<EntityId, Mat4>,
+                  ecs: &ecs::World,
+                  emitter: &emitter::Emitter<ParticleScene>,
+                  effects: &Effects,
+                  trails: &Trails) {
 
 
 
@@ -132,59 +185,142 @@ impl<Data> RenderPipelines<Data> {
             render_meshes.push(vec![]);
         }
 
+        self.lod_state.retain(|id, _| entities.contains_key(id) || ecs.has::<MeshRef>(*id));
+
         for (key, entity) in entities.iter() {
 
-            let trans = Translation3::from(entity.pos + entity.root_motion);
+            let model_mat = world_mats.get(key).copied().unwrap_or_else(|| entity.model_mat());
 
-            let rotation = Rotation3::from_euler_angles(entity.side_pitch.angle(), entity.forward_pitch.angle(), entity.z_angle.angle());
+            let scene_mesh = &mesh_data[entity.mesh_id];
+            let mut level = 0;
+            if !scene_mesh.lod_coverage.is_empty() {
+                let current = self.lod_state.entry(*key).or_insert(0);
+                *current = lod_level(scene_mesh, &model_mat, camera, *current, self.lod_hysteresis);
+                level = *current;
+            }
 
             // TODO: We could index out of bound, since pipeline has ids, and we are using them as index
 
             render_meshes[entity.render_pipeline_id].push(RenderMesh {
-                model_mat: trans.to_homogeneous() * rotation.to_homogeneous(),
+                mesh_id: entity.mesh_id,
+                lod: level,
+                model_mat,
                 bones: bones.get(key).unwrap_or(&default_bones),
-                mesh: &mesh_data[entity.mesh_id].mesh,
-                texture: mesh_data[entity.mesh_id].texture_id,
+                mesh: scene_mesh.level(level),
+                texture: scene_mesh.texture_id,
+                material: &scene_mesh.material,
             });
         }
 
+        // ecs entities, scene entities also have ids in ecs but are already added above
+        let lod_state = &mut self.lod_state;
+        let hysteresis = self.lod_hysteresis;
+        ecs.query::<(MeshRef,), _>(|id, (mesh_ref,)| {
+            if entities.contains_key(&id) {
+                return;
+            }
+
+            let model_mat = world_mats.get(&id).copied().unwrap_or_else(Mat4::identity);
+            let scene_mesh = &mesh_data[mesh_ref.mesh_id];
+            let mut level = 0;
+            if !scene_mesh.lod_coverage.is_empty() {
+                let current = lod_state.entry(id).or_insert(0);
+                *current = lod_level(scene_mesh, &model_mat, camera, *current, hysteresis);
+                level = *current;
+            }
+
+            render_meshes[mesh_ref.render_pipeline_id].push(RenderMesh {
+                mesh_id: mesh_ref.mesh_id,
+                lod: level,
+                model_mat,
+                bones: default_bones,
+                mesh: scene_mesh.level(level),
+                texture: scene_mesh.texture_id,
+                material: &scene_mesh.material,
+            });
+        });
+
         // add particle entities
 
+        // particles are short lived, so they have no lod state and no hysteresis
         for p in emitter.iter() {
             let trans = Translation3::from(p.pos);
+            let model_mat = trans.to_homogeneous();
+            let level = lod_level(&mesh_data[p.mesh_id], &model_mat, camera, 0, 0.0);
 
             render_meshes[p.render_pipeline_id].push(RenderMesh {
-                model_mat: trans.to_homogeneous(),
+                mesh_id: p.mesh_id,
+                lod: level,
+                model_mat,
                 bones: &default_bones,
-                mesh: &mesh_data[p.mesh_id].mesh,
+                mesh: mesh_data[p.mesh_id].level(level),
                 texture: mesh_data[p.mesh_id].texture_id,
+                material: &mesh_data[p.mesh_id].material,
             });
         }
 
 
+        self.light_buffer.upload(lights, ambient);
+
         // setup render meshes for each pipeline
         for render_pipeline in &mut self.pipelines {
             let id = render_pipeline.id;
 
-            render_pipeline.render(&mesh_data,
-                                   &camera,
-                                   light_pos,
-                                   light_color,
+            let frame_effects = FrameEffects {
+                particles: effects.active.iter()
+                    .filter(|e| e.render_pipeline_id == id)
+                    .map(|e| &e.emitter)
+                    .collect(),
+                trails: trails.data.data.values()
+                    .filter(|t| t.render_pipeline_id == id)
+                    .map(|t| &t.trail)
+                    .collect(),
+            };
+
+            render_pipeline.render(&camera,
+                                   lights,
                                    ui,
                                    viewport,
-                                   bones,
                                    default_bones,
-                                   &render_meshes[id]
+                                   &render_meshes[id],
+                                   &frame_effects,
+                                   &mut self.effect_renderers
             );
         }
     }
 }
 
+/// Level of detail of scene mesh from its projected size on screen, given the current level
+fn lod_level(scene_mesh: &SceneMesh, model_mat: &Mat4, camera: &Camera, current: usize, hysteresis: f32) -> usize {
+    let bounds = match scene_mesh.mesh.bounds {
+        Some(b) if !scene_mesh.lod_coverage.is_empty() => b.transform(model_mat),
+        _ => return 0
+    };
+
+    let radius = bounds.half_size().magnitude();
+    let size = match camera.projection_mode {
+        Projection::Perspective => {
+            let dist = (bounds.center() - camera.pos()).magnitude();
+            lod::screen_size(radius, dist, camera.fov.to_radians())
+        },
+        // size on screen does not change with distance
+        Projection::Orthographic { height } => radius * 2.0 / height,
+    };
+
+    lod::select_lod(current, size, &scene_mesh.lod_coverage, hysteresis)
+}
+
+
 pub struct RenderMesh<'a> {
+    /// Render meshes with the same mesh_id and lod are drawn instanced when possible
+    pub mesh_id: MeshIndex,
+    /// Level of detail of mesh, 0 is the finest
+    pub lod: usize,
     pub model_mat: Mat4,
     pub mesh: &'a Mesh,
     pub bones: &'a Bones,
-    pub texture: Option<texture::TextureId>
+    pub texture: Option<texture::TextureId>,
+    pub material: &'a Material<texture::TextureId>,
 }
 
 
@@ -193,19 +329,21 @@ pub struct RenderMesh<'a> {
 // since we want to call it from mutiple places
 pub fn render_scene(gl: &gl::Gl, camera: &Camera,
                     mesh_shader: &mesh_shader::MeshShader,
+                    shading: MeshShading,
                     default_bones: &Bones,
                     cubemap_opt: &Option<Cubemap>,
                     cubemap_shader: &BaseShader,
                     stencil_shader: &Option<mesh_shader::MeshShader>,
                     render_meshes: &[RenderMesh],
-                    light_space_mats: &Vec::<Mat4>,
-                    light_pos: V3,
-                    light_color: Color) {
+                    cascades: &Cascades,
+                    lights: &[Light],
+                    shadow_light: Option<usize>,
+                    bone_texture: &instancing::BoneTexture) {
 
 
     let mut uniforms = mesh_shader::Uniforms {
-        light_pos,
-        light_color,
+        light_pos: V3::zeros(),
+        light_color: Color::black(),
         projection: camera.projection(),
         model: Mat4::identity(),
         view: camera.view(),
@@ -228,24 +366,95 @@ pub fn render_scene(gl: &gl::Gl, camera: &Camera,
     mesh_shader.shader.set_i32(gl, "shadowMap", 1);
 
 
-    // TODO: We should set mats as a vec
-    let mut light_space_mat = Mat4::identity();
-
-    if light_space_mats.len() > 0 {
-        light_space_mat = light_space_mats[0];
+    // first cascade is also set as lightSpaceMat, for shaders that only use a single shadow map layer
+    let light_space_mat = cascades.light_space_mats.first().copied().unwrap_or_else(Mat4::identity);
+    if !cascades.light_space_mats.is_empty() {
+        mesh_shader.shader.set_slice_mat4(gl, "lightSpaceMats", &cascades.light_space_mats);
+    }
+    for (i, split) in cascades.splits.iter().enumerate() {
+        mesh_shader.shader.set_f32(gl, &format!("cascadeSplits[{i}]"), *split);
     }
+    mesh_shader.shader.set_i32(gl, "cascadeCount", cascades.light_space_mats.len() as i32);
+    mesh_shader.shader.set_i32(gl, "pcfRadius", cascades.pcf_radius);
+
+    // skip meshes outside the view, and draw opaque front to back, blended back to front
+    let mut order = Vec::with_capacity(render_meshes.len());
+    culling::cull_and_sort(render_meshes, &camera.frustum(), camera.pos(), shading, &mut order);
+    let first_blend = order.iter().position(|item| item.blend).unwrap_or(order.len());
+
+    let blend_was_enabled = unsafe { gl.IsEnabled(gl::BLEND) == gl::TRUE };
+
+    // opaque render meshes with the same mesh are drawn in one instanced call, when the shader and mesh support it.
+    // Stencil outlines are cleared per mesh, so no instancing with a stencil shader
+    let instancing = stencil_shader.is_none() && mesh_shader.shader.get_location("instanced") >= 0;
+    let mut batches = vec![];
+    instancing::instance_batches(&order, |item| {
+        (instancing && !item.blend && render_meshes[item.index].mesh.is_instanced()).then_some((item.mesh_id, item.lod))
+    }, &mut batches);
+
+    mesh_shader.shader.set_i32(gl, "boneTexture", instancing::BONE_TEXTURE_UNIT as i32);
+
+    let mut mesh_lights = Vec::with_capacity(LIGHTS_PER_MESH);
+    let mut models = vec![];
+    let mut instance_bones = vec![];
+
+    for batch in &batches {
+
+        if batch.start == first_blend {
+            unsafe {
+                gl.Enable(gl::BLEND);
+                gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                gl.DepthMask(gl::FALSE);
+            }
+        }
+
+        if batch.len() > 1 {
+            let items = &order[batch.clone()];
+            let rm = &render_meshes[items[0].index];
+
+            models.clear();
+            models.extend(items.iter().map(|item| render_meshes[item.index].model_mat));
+
+            // lights are selected once for the whole batch, at the center of the instances
+            let center = models.iter().map(|m| m.column(3).xyz()).sum::<V3>() / models.len() as f32;
+            select_mesh_lights(&mut uniforms, lights, center, &mut mesh_lights);
+
+            uniforms.model = Mat4::identity();
+            uniforms.bones = default_bones;
+            mesh_shader.set_uniforms(uniforms);
+            mesh_shader.set_lights(&mesh_lights, shadow_light);
+            mesh_shader.shader.set_mat4(gl,"lightSpaceMat", light_space_mat);
+
+            let skinned = items.iter().any(|item| !std::ptr::eq(render_meshes[item.index].bones, default_bones));
+            if skinned {
+                instance_bones.clear();
+                instance_bones.extend(items.iter().map(|item| render_meshes[item.index].bones));
+                bone_texture.upload(&instance_bones);
+            }
+
+            mesh_shader.shader.set_i32(gl, "boneTextureEnabled", skinned as i32);
+            mesh_shader.shader.set_i32(gl, "instanced", 1);
+
+            bind_mesh_textures(gl, mesh_shader, shading, rm);
+            rm.mesh.render_instanced(gl, &models);
+
+            mesh_shader.shader.set_i32(gl, "instanced", 0);
+            mesh_shader.shader.set_i32(gl, "boneTextureEnabled", 0);
+            continue;
+        }
+
+        let rm = &render_meshes[order[batch.start].index];
 
-    for rm in render_meshes {
         uniforms.model = rm.model_mat;
         uniforms.bones = rm.bones;
 
+        select_mesh_lights(&mut uniforms, lights, rm.model_mat.column(3).xyz(), &mut mesh_lights);
+
         mesh_shader.set_uniforms(uniforms);
+        mesh_shader.set_lights(&mesh_lights, shadow_light);
         mesh_shader.shader.set_mat4(gl,"lightSpaceMat", light_space_mat);
 
-        if let Some(tex) = rm.texture {
-            texture::active_texture(gl, 0);
-            texture::set_texture(gl, tex);
-        }
+        bind_mesh_textures(gl, mesh_shader, shading, rm);
         rm.mesh.render(gl);
 
         // STENCIL RENDER PASS
@@ -269,6 +478,17 @@ pub fn render_scene(gl: &gl::Gl, camera: &Camera,
                 // can also be changes to this is a field on scene and can be set to 0, ie. not clearing
                 gl.Clear(gl::STENCIL_BUFFER_BIT);
             }
+
+            mesh_shader.shader.set_used();
+        }
+    }
+
+    if first_blend < order.len() {
+        unsafe {
+            gl.DepthMask(gl::TRUE);
+            if !blend_was_enabled {
+                gl.Disable(gl::BLEND);
+            }
         }
     }
 
@@ -284,3 +504,22 @@ pub fn render_scene(gl: &gl::Gl, camera: &Camera,
         cubemap.render(gl);
     }
 }
+
+
+/// Select lights at pos, and set the most relevant as the single light in uniforms
+fn select_mesh_lights(uniforms: &mut mesh_shader::Uniforms, lights: &[Light], pos: V3, mesh_lights: &mut Vec::<usize>) {
+    select_lights(lights, pos, LIGHTS_PER_MESH, mesh_lights);
+    if let Some(l) = mesh_lights.first().map(|i| &lights[*i]) {
+        uniforms.light_pos = l.pos;
+        uniforms.light_color = l.color;
+    }
+}
+
+fn bind_mesh_textures(gl: &gl::Gl, mesh_shader: &mesh_shader::MeshShader, shading: MeshShading, rm: &RenderMesh) {
+    if shading == MeshShading::Pbr {
+        mesh_shader.set_material(rm.material);
+    } else if let Some(tex) = rm.texture {
+        texture::active_texture(gl, 0);
+        texture::set_texture(gl, tex);
+    }
+}
@@ -7,23 +7,33 @@ use crate::shader::{BaseShader, texture_shader};
 use crate::particle_system::{emitter};
 use crate::typedef::*;
 use crate::texture;
-use crate::objects::{mesh::Mesh, cubemap::{self, Cubemap}};
+use crate::objects::{mesh::Mesh, cubemap::{self, Cubemap}, material::Material, lod};
 use crate::camera::{self, free_camera, follow_camera, Camera};
-use crate::na::{Rotation3, Rotation2};
+use crate::camera::cinematic::{CameraShake, CameraBlend, CameraPath, PathPlayback};
+use crate::na::{self, Rotation3, Rotation2, Translation3, UnitQuaternion};
 use crate::{buffer, movement::Inputs};
 use crate::audio::audio_player::AudioPlayer;
 use std::{thread, sync::{Arc, Mutex}};
 use std::rc::Rc;
-use std::collections::{VecDeque, HashMap};
+use std::collections::{VecDeque, HashMap, HashSet};
 use crate::helpers;
 use sdl2::event::{Event, WindowEvent};
-use crate::collision3d::CollisionBox;
+use crate::collision3d::{CollisionBox, bounds::Aabb};
 use crate::color::Color;
 use crate::scene_3d::types::DataMap;
 use crate::scene_3d::actions::*;
 use crate::scene_3d::RenderPipelines;
 use crate::scene_3d::RenderPipelineId;
 use crate::scene_3d::ParticleScene;
+use crate::scene_3d::effects::Effects;
+use crate::scene_3d::trails::{Trails, TrailAnchor, TrailId};
+use crate::particle_system::trail::TrailDesc;
+use crate::scene_3d::lights::{Light, Attenuation};
+use crate::scene_3d::hierarchy::{self, Parent};
+use crate::scene_3d::components::{Transform, MeshRef};
+use crate::ecs;
+use crate::game_loop::GameLoop;
+use crate::scene_3d::scene_file::{SceneFile, GltfFile, EntityData, ParentData, LoadedScene};
 
 
 pub type EntityId = usize;
@@ -34,7 +44,24 @@ pub type SkeletonIndex = usize;
 pub struct SceneMesh {
     pub mesh: Mesh,
     pub skeleton: Option<SkeletonIndex>,
-    pub texture_id: Option<texture::TextureId>
+    pub texture_id: Option<texture::TextureId>,
+    pub material: Material<texture::TextureId>,
+    /// Coarser levels of detail, mesh is level 0
+    pub lods: Vec::<Mesh>,
+    /// Screen coverage of each level including level 0, see `lod::select_lod`. Empty when there are no lods
+    pub lod_coverage: Vec::<f32>,
+}
+
+impl SceneMesh {
+
+    /// Mesh of level, levels past the coarsest gives the coarsest
+    pub fn level(&self, lod: usize) -> &Mesh {
+        if lod == 0 || self.lods.is_empty() {
+            return &self.mesh;
+        }
+
+        &self.lods[(lod - 1).min(self.lods.len() - 1)]
+    }
 }
 
 
@@ -98,10 +125,20 @@ pub struct Scene<UserPostProcessData, UserControllerData = ()> {
     pub gl: gl::Gl,
     pub ui_mode: bool,
 
+    /// Animations, particles and actions update in fixed steps, see `fixed_steps`. Advanced by ui dt
+    pub game_loop: GameLoop,
+
     pub sdl: sdl2::Sdl,
 
     pub camera: camera::Camera,
 
+    /// Applied on top of the camera when rendering, add trauma fx on hits
+    pub camera_shake: CameraShake,
+    /// Seconds to blend when changing camera or when a camera path ends
+    pub camera_blend_time: f32,
+    camera_blend: Option<CameraBlend>,
+    camera_path: Option<PathPlayback>,
+
     pub inputs: SceneInputs,
     pub follow_controller: follow_camera::Controller,
 
@@ -110,8 +147,9 @@ pub struct Scene<UserPostProcessData, UserControllerData = ()> {
     // for own implementation keep this as none and just do in in user code
     pub controlled_entity: Option<ControlledEntity<UserControllerData>>,
 
-    pub light_pos: V3,
-    pub light_color: Color,
+    /// Only the first `lights::MAX_LIGHTS` are used
+    pub lights: Vec::<Light>,
+    pub ambient_color: Color,
 
     pub player: AnimationPlayer<EntityId>,
 
@@ -119,10 +157,17 @@ pub struct Scene<UserPostProcessData, UserControllerData = ()> {
 
     pub cubemap : Option::<Cubemap>,
 
+    // what was loaded, so it can be saved with the scene
+    skybox_path: Option::<String>,
+    gltf_files: Vec::<GltfFile>,
+
     pub clear_buffer_bits: u32,
 
     pub action_queue: ActionQueue,
 
+    // animation event name to action, actions are queued when the event is fired
+    pub animation_events: HashMap::<Rc::<str>, AnimationEventFn>,
+
     cubemap_imgs: Option::<Arc::<Mutex::<Option<Vec::<image::RgbImage>>>>>,
 
     // multiple entities can use the same mesh
@@ -143,8 +188,26 @@ pub struct Scene<UserPostProcessData, UserControllerData = ()> {
     //pub animation_ids: HashMap::<EntityId, EntityId>, // Kinda want to get rid of this, and maybe just use entityId as key to animaiton player. Maybe the player should just take an id in Start. This is already out of sync and make root motion buggy;
     pub entities: DataMap::<SceneEntity>,
 
+    /// World matrix of each entity, with parents applied. Updated by `update_transforms`
+    pub world_mats: HashMap::<EntityId, Mat4>,
+
+    /// Components by entity. Every scene entity is alive here with the same id, entities with a
+    /// `Transform` and `MeshRef` are rendered too, see `spawn`
+    pub ecs: ecs::World,
+
+    /// Run each fixed step in `frame_start`. Systems get the scene and use `scene.ecs`, so they can spawn
+    /// and remove entities through the scene
+    pub systems: ecs::OwnerSchedule<Scene<UserPostProcessData, UserControllerData>>,
+
     pub emitter: emitter::Emitter<ParticleScene>,
 
+    /// Particle effects by name, spawned with `Action::SpawnEffect`
+    pub effects: Effects,
+    missing_effects: HashSet::<Rc::<str>>,
+
+    /// Motion trails following positions, entities or joints, see `add_trail`
+    pub trails: Trails,
+
     default_bones: Bones,
 
     pub fbos: Option::<Fbos<UserPostProcessData>>,
@@ -156,7 +219,6 @@ pub struct Scene<UserPostProcessData, UserControllerData = ()> {
 }
 
 
-#[derive(Default)]
 pub struct SceneEntity {
     pub mesh_id: MeshIndex,
     // TODO: Maybe have some of this in arrays like data driven, so fx world does not have velocity
@@ -178,7 +240,46 @@ pub struct SceneEntity {
     pub root_motion: V3,
     pub skeleton_id: Option::<SkeletonIndex>, // Is indirectly a duplicated data, since meshindex points to Scene mesh, which has skel_id. But lets keep it as a convinience
 
-    pub render_pipeline_id: RenderPipelineId
+    pub render_pipeline_id: RenderPipelineId,
+
+    /// Applied after the angles, for rotations that z_angle and pitches can't express
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: V3,
+
+    /// When set pos, angles, rotation and scale are relative to the parent, see `Scene::attach`
+    pub parent: Option<Parent>,
+}
+
+impl Default for SceneEntity {
+    fn default() -> Self {
+        Self {
+            mesh_id: 0,
+            pos: V3::new(0.0, 0.0, 0.0),
+            acceleration: V3::new(0.0, 0.0, 0.0),
+            velocity: V3::new(0.0, 0.0, 0.0),
+            target_z_angle: Rotation2::identity(),
+            z_angle: Rotation2::identity(),
+            forward_pitch: Rotation2::identity(),
+            side_pitch: Rotation2::identity(),
+            root_motion: V3::new(0.0, 0.0, 0.0),
+            skeleton_id: None,
+            render_pipeline_id: 0,
+            rotation: UnitQuaternion::identity(),
+            scale: V3::new(1.0, 1.0, 1.0),
+            parent: None,
+        }
+    }
+}
+
+impl SceneEntity {
+
+    /// Local model matrix, the world matrix when the entity has no parent
+    pub fn model_mat(&self) -> Mat4 {
+        let trans = Translation3::from(self.pos + self.root_motion);
+        let rotation = Rotation3::from_euler_angles(self.side_pitch.angle(), self.forward_pitch.angle(), self.z_angle.angle());
+
+        trans.to_homogeneous() * rotation.to_homogeneous() * self.rotation.to_homogeneous() * Mat4::new_nonuniform_scaling(&self.scale)
+    }
 }
 
 
@@ -220,12 +321,25 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             gl,
             sdl,
             ui_mode: true,
+            game_loop: GameLoop::new(1.0 / 60.0),
             ui,
             viewport,
             emitter: emitter::Emitter::new(1000, |_, _, _| {}, |_, _,| {}),
+            effects: Default::default(),
+            missing_effects: Default::default(),
+            trails: Default::default(),
             camera,
-            light_pos: V3::new(0.0, 10.0, 30.0),
-            light_color: Color::Rgb(255, 255, 255),
+            camera_shake: Default::default(),
+            camera_blend_time: 0.5,
+            camera_blend: None,
+            camera_path: None,
+            // same as the old single light, white without attenuation
+            lights: vec![Light {
+                range: f32::INFINITY,
+                attenuation: Attenuation::none(),
+                ..Light::point(V3::new(0.0, 10.0, 30.0), Color::Rgb(255, 255, 255), 1.0)
+            }.with_shadows()],
+            ambient_color: Color::Rgb(128, 128, 128),
             inputs : SceneInputs {
                 follow: Default::default(),
                 free: Default::default(),
@@ -235,9 +349,14 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             player,
             audio_player,
             cubemap: None,
+            skybox_path: None,
+            gltf_files: vec![],
             meshes: Default::default(),
             mesh_data: Default::default(),
             entities: Default::default(),
+            world_mats: Default::default(),
+            ecs: Default::default(),
+            systems: Default::default(),
             skeletons: Default::default(),
             animations: Default::default(),
             bones: Default::default(),
@@ -247,6 +366,7 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             clear_buffer_bits: gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT,
             controlled_entity: None::<ControlledEntity<UserControllerData>>,
             action_queue: VecDeque::default(),
+            animation_events: Default::default(),
             skeleton_hit_boxes: Default::default(),
         })
     }
@@ -265,19 +385,12 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
         let skeleton_id = self.mesh_data[mesh_id].skeleton;
         let entity = SceneEntity {
             mesh_id,
-            z_angle: Rotation2::identity(),
-            forward_pitch: Rotation2::identity(),
-            side_pitch: Rotation2::identity(),
-            pos: V3::new(0.0, 0.0, 0.0),
-            acceleration: V3::new(0.0, 0.0, 0.0),
-            velocity: V3::new(0.0, 0.0, 0.0),
-            target_z_angle: Rotation2::identity(),
-            root_motion: V3::new(0.0, 0.0, 0.0),
             skeleton_id: self.mesh_data[mesh_id].skeleton,
-            render_pipeline_id: 0
+            ..Default::default()
         };
 
-        let id = self.entities.insert(entity);
+        let id = self.ecs.spawn();
+        self.entities.insert_at(id, entity);
 
         //println!("Added entity {:?} - {} tex={:?}", id, mesh_name, self.mesh_data[mesh_id].texture_id);
 
@@ -296,6 +409,165 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             // entity e removed, will be destroyed at end of this scope
             self.bones.remove(id);
         }
+
+        let children : Vec::<EntityId> = self.entities.data.iter().filter(|(_, e)| e.parent.map(|p| p.id) == Some(*id)).map(|(c, _)| *c).collect();
+        for child in &children {
+            self.detach(child);
+        }
+
+        self.world_mats.remove(id);
+        self.ecs.despawn(*id);
+    }
+
+    /// Create an entity that only lives in `ecs`, with a `Transform` and a `MeshRef`. It is rendered
+    /// but has no bones, animations or parent
+    pub fn spawn(&mut self, mesh_name: &str, transform: Transform) -> Option<EntityId> {
+        let mesh_id = *self.meshes.get(mesh_name)?;
+
+        let id = self.ecs.spawn();
+        self.ecs.insert(id, transform);
+        self.ecs.insert(id, MeshRef { mesh_id, render_pipeline_id: 0 });
+        Some(id)
+    }
+
+    /// Attach child to parent, or to a joint in the parent's skeleton. The child keeps its pos, angles, rotation and scale,
+    /// which are now relative to the parent
+    pub fn attach(&mut self, child: EntityId, parent: EntityId, joint_name: Option<&str>) -> Result<(), failure::Error> {
+        let parent_entity = match self.entities.get(&parent) {
+            Some(e) => e,
+            None => failure::bail!("Parent entity {} does not exist", parent)
+        };
+
+        let joint = match joint_name {
+            Some(name) => {
+                let skeleton = parent_entity.skeleton_id.and_then(|s| self.skeletons.get(s));
+                match skeleton.and_then(|s| s.joint_index(name)) {
+                    Some(j) => Some(j),
+                    None => failure::bail!("Entity {} has no joint named {:?}", parent, name)
+                }
+            },
+            None => None
+        };
+
+        if hierarchy::creates_cycle(&self.entities.data, child, parent) {
+            failure::bail!("Attaching {} to {} would make it its own parent", child, parent);
+        }
+
+        match self.entities.get_mut(&child) {
+            Some(e) => e.parent = Some(Parent { id: parent, joint }),
+            None => failure::bail!("Child entity {} does not exist", child)
+        }
+
+        Ok(())
+    }
+
+    /// Remove parent and keep the current world position, rotation and scale
+    pub fn detach(&mut self, id: &EntityId) {
+        let world = self.world_mat(id);
+        if let Some(e) = self.entities.get_mut(id) {
+            if e.parent.take().is_none() {
+                return;
+            }
+
+            let scale = V3::new(world.column(0).xyz().magnitude(), world.column(1).xyz().magnitude(), world.column(2).xyz().magnitude());
+            let rot = na::Matrix3::from_columns(&[world.column(0).xyz() / scale.x, world.column(1).xyz() / scale.y, world.column(2).xyz() / scale.z]);
+
+            e.pos = world.column(3).xyz() - e.root_motion;
+            e.z_angle = Rotation2::identity();
+            e.target_z_angle = Rotation2::identity();
+            e.forward_pitch = Rotation2::identity();
+            e.side_pitch = Rotation2::identity();
+            e.rotation = UnitQuaternion::from_matrix(&rot);
+            e.scale = scale;
+        }
+    }
+
+    /// World matrix of entity from the last `update_transforms`, or its model matrix when not updated yet
+    pub fn world_mat(&self, id: &EntityId) -> Mat4 {
+        self.world_mats.get(id).copied()
+            .or_else(|| self.entities.get(id).map(|e| e.model_mat()))
+            .unwrap_or_else(Mat4::identity)
+    }
+
+    /// Add a trail following entity, or a joint in the entity's skeleton, fx the tip of a sword with an offset along the blade.
+    /// Offset is in the entity's or the joint's space. The trail fades out and is removed when the entity is removed
+    pub fn add_trail(&mut self, desc: TrailDesc, entity: EntityId, joint_name: Option<&str>, offset: V3) -> Result<TrailId, failure::Error> {
+        let anchor = match joint_name {
+            Some(name) => {
+                let e = match self.entities.get(&entity) {
+                    Some(e) => e,
+                    None => failure::bail!("Entity {} does not exist", entity)
+                };
+
+                let skeleton = e.skeleton_id.and_then(|s| self.skeletons.get(s));
+                match skeleton.and_then(|s| s.joint_index(name)) {
+                    Some(joint) => TrailAnchor::Joint { id: entity, joint, offset },
+                    None => failure::bail!("Entity {} has no joint named {:?}", entity, name)
+                }
+            },
+            None => {
+                if self.entities.get(&entity).is_none() && !self.world_mats.contains_key(&entity) {
+                    failure::bail!("Entity {} does not exist", entity);
+                }
+                TrailAnchor::Entity { id: entity, offset }
+            }
+        };
+
+        let pos = self.trail_anchor_pos(&anchor);
+        let id = self.trails.add(desc, anchor);
+
+        // start at the anchor, so the first frame does not draw from the origin
+        if let (Some(t), Some(pos)) = (self.trails.get_mut(&id), pos) {
+            t.trail.update(0.0, pos);
+        }
+
+        Ok(id)
+    }
+
+    /// World position of a trail anchor, None when its entity or joint is gone
+    pub fn trail_anchor_pos(&self, anchor: &TrailAnchor) -> Option<V3> {
+        let exists = |id: &EntityId| self.entities.get(id).is_some() || self.world_mats.contains_key(id);
+
+        let (mat, offset) = match *anchor {
+            TrailAnchor::Pos(pos) => return Some(pos),
+            TrailAnchor::Entity { id, offset } => {
+                if !exists(&id) {
+                    return None;
+                }
+                (self.world_mat(&id), offset)
+            },
+            TrailAnchor::Joint { id, joint, offset } => {
+                let skeleton = self.skeletons.get(self.entities.get(&id)?.skeleton_id?)?;
+                let joint_mat = hierarchy::joint_model_matrix(skeleton, self.bones.get(&id)?, joint)?;
+                (self.world_mat(&id) * joint_mat, offset)
+            }
+        };
+
+        Some(mat.transform_point(&offset.into()).coords)
+    }
+
+    /// Propagate parent transforms to children. Called in `render`, call it before using `world_mats` after moving entities
+    pub fn update_transforms(&mut self) {
+        let entities = &self.entities.data;
+        let skeletons = &self.skeletons;
+        let bones = &self.bones;
+
+        hierarchy::world_matrices(entities, |id, joint| {
+            let skeleton = skeletons.get(entities.get(&id)?.skeleton_id?)?;
+            hierarchy::joint_model_matrix(skeleton, bones.get(&id)?, joint)
+        }, &mut self.world_mats);
+
+        let world_mats = &mut self.world_mats;
+        self.ecs.query::<(Transform, MeshRef), _>(|id, (transform, _)| {
+            if !entities.contains_key(&id) {
+                world_mats.insert(id, transform.model_mat());
+            }
+        });
+    }
+
+    /// Queue the action returned by f every time an animation fires an event with the given name.
+    pub fn on_animation_event(&mut self, name: Rc::<str>, f: AnimationEventFn) {
+        self.animation_events.insert(name, f);
     }
 
     pub fn load_sound(&mut self, name: Rc::<str>, path: &str) {
@@ -303,17 +575,27 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
     }
 
     pub fn set_entity_render_pipeline(&mut self, id: EntityId, name: Rc::<str>) {
-        if let Some(entity) = self.entities.get_mut(&id) {
-            if let Some(pipe_id) = self.render_pipelines.id(name) {
+        if let Some(pipe_id) = self.render_pipelines.id(name) {
+            if let Some(entity) = self.entities.get_mut(&id) {
                 entity.render_pipeline_id = pipe_id;
             }
+
+            if let Some(mut mesh_ref) = self.ecs.get_mut::<MeshRef>(id) {
+                mesh_ref.render_pipeline_id = pipe_id;
+            }
         }
     }
 
     pub fn load_all_meshes(&mut self, path: &str, root_motion: bool) {
+        self.try_load_all_meshes(path, root_motion).unwrap();
+    }
+
+    /// Like `load_all_meshes`, but returns an error when the glTF file can not be loaded
+    pub fn try_load_all_meshes(&mut self, path: &str, root_motion: bool) -> Result<(), failure::Error> {
 
         // defaults to not split animations into rotation/scale and motion into root motion
-        let gltf_data = gltf_mesh::meshes_from_gltf(path, root_motion).unwrap();
+        let gltf_data = gltf_mesh::meshes_from_gltf(path, root_motion)?;
+        self.gltf_files.push(GltfFile { path: path.to_string(), root_motion });
 
         let mut skin_id_to_skel_idx : HashMap::<usize, usize> = HashMap::default();
         for (skin_id, skeleton) in &gltf_data.skins.skeletons {
@@ -324,32 +606,70 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
 
         let mut tex_to_id : HashMap::<usize, texture::TextureId> = HashMap::default();
 
+        // coarser levels are loaded with their level 0 mesh
+        let mut lod_of = HashMap::<&str, (&str, &lod::LodGroup)>::default();
+        for (base, group) in &gltf_data.meshes.lod_groups {
+            for level in &group.levels {
+                lod_of.insert(level, (base, group));
+            }
+        }
+
         for (name, gltf_mesh) in &gltf_data.meshes.meshes {
+            let group = lod_of.get(name.as_str()).copied();
+            if let Some((_, group)) = group {
+                if group.levels[0] != *name {
+                    continue;
+                }
+            }
+
             let mesh = gltf_mesh.get_mesh(&self.gl);
             // find index into skeletons, if mesh has skeleton
             let skeleton = gltf_data.skins.mesh_to_skin.get(name).map(|skin_id| *skin_id_to_skel_idx.get(skin_id).unwrap());
 
+            // base color uses nearest filtering like before, other material textures are linear
             let mut texture_id = None;
             if let Some(tex) = gltf_mesh.texture {
-                println!("tex for {name}  {:#?}", tex);
                 if !tex_to_id.contains_key(&tex) {
-                    println!("{:?}", tex);
-                    //TODO: Fix this so we load correct texture and not just 0
-                    println!("IMAGES: {:?}",gltf_data.images.len());
-                    let id = texture::gen_texture_rgba_nearest(&self.gl, &gltf_data.images[0]);
+                    let id = texture::gen_texture_rgba_nearest(&self.gl, &gltf_data.images[tex]);
                     tex_to_id.insert(tex, id);
-                    // load texture
                 }
                 texture_id = tex_to_id.get(&tex).map(|id| *id);
             }
 
+            let gl = &self.gl;
+            let material = gltf_mesh.material.map_textures(|img| {
+                *tex_to_id.entry(*img).or_insert_with(|| texture::gen_texture_rgba(gl, &gltf_data.images[*img]))
+            });
+
+            let mut lods = vec![];
+            let mut lod_coverage = vec![];
+            if let Some((_, group)) = group {
+                lods = group.levels[1..].iter()
+                    .filter_map(|level| gltf_data.meshes.meshes.get(level))
+                    .map(|m| m.get_mesh(&self.gl))
+                    .collect();
+                lod_coverage = group.screen_coverage.clone();
+            }
+
             self.mesh_data.push(SceneMesh {
                 mesh,
                 skeleton,
-                texture_id
+                texture_id,
+                material,
+                lods,
+                lod_coverage,
             });
 
-            self.meshes.insert(Rc::from(name.to_string()), self.mesh_data.len() - 1);
+            let index = self.mesh_data.len() - 1;
+            self.meshes.insert(Rc::from(name.to_string()), index);
+
+            // base name and every level name gives the whole group
+            if let Some((base, group)) = group {
+                self.meshes.insert(Rc::from(base), index);
+                for level in &group.levels {
+                    self.meshes.insert(Rc::from(level.as_str()), index);
+                }
+            }
         }
 
         for skin_id in gltf_data.animations.keys() {
@@ -365,7 +685,153 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             }
         }
 
+        Ok(())
+    }
+
+    /// Several names can point to the same mesh, fx lod groups, so this is the shortest for a stable name
+    pub fn mesh_name(&self, mesh_id: MeshIndex) -> Option<Rc::<str>> {
+        self.meshes.iter()
+            .filter(|(_, idx)| **idx == mesh_id)
+            .map(|(name, _)| name)
+            .min_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)))
+            .cloned()
+    }
+
+    /// World space bounds of entity, None when its mesh has no bounds
+    pub fn entity_bounds(&self, id: &EntityId) -> Option<Aabb> {
+        let entity = self.entities.get(id)?;
+        let bounds = self.mesh_data.get(entity.mesh_id)?.mesh.bounds?;
+        Some(bounds.transform(&self.world_mat(id)))
+    }
+
+    /// Scene as saveable data. user_data is stored with the entity with the same id
+    pub fn scene_file(&self, user_data: &HashMap::<EntityId, serde_json::Value>) -> SceneFile {
+        let mut ids : Vec::<EntityId> = self.entities.data.keys().copied().collect();
+        ids.sort();
+
+        // entities without a mesh name can't be loaded again, so they are not saved
+        ids.retain(|id| self.mesh_name(self.entities.data[id].mesh_id).is_some());
+        let index_of = |id: EntityId| ids.iter().position(|i| *i == id);
+
+        let entities = ids.iter().filter_map(|id| {
+            let entity = &self.entities.data[id];
+
+            let mesh = self.mesh_name(entity.mesh_id)?;
 
+            let parent = entity.parent.and_then(|p| {
+                let skeleton = self.entities.get(&p.id)?.skeleton_id.and_then(|s| self.skeletons.get(s));
+                Some(ParentData {
+                    entity: index_of(p.id)?,
+                    joint: p.joint.and_then(|j| skeleton.and_then(|s| s.joints.get(j)).map(|joint| joint.name.clone())),
+                })
+            });
+
+            Some(EntityData {
+                mesh: mesh.to_string(),
+                pos: entity.pos,
+                rotation: entity.rotation,
+                scale: entity.scale,
+                z_angle: entity.z_angle.angle(),
+                forward_pitch: entity.forward_pitch.angle(),
+                side_pitch: entity.side_pitch.angle(),
+                render_pipeline: self.render_pipelines.name(entity.render_pipeline_id).map(|n| n.to_string()).unwrap_or_else(|| "default".to_string()),
+                parent,
+                user_data: user_data.get(id).cloned().unwrap_or_default(),
+            })
+        }).collect();
+
+        SceneFile {
+            gltf_files: self.gltf_files.clone(),
+            skybox: self.skybox_path.clone(),
+            ambient_color: self.ambient_color,
+            lights: self.lights.clone(),
+            entities,
+            ..Default::default()
+        }
+    }
+
+    pub fn save_scene<P: AsRef<std::path::Path>>(&self, path: &P, user_data: &HashMap::<EntityId, serde_json::Value>) -> Result<(), failure::Error> {
+        self.scene_file(user_data).save(path)
+    }
+
+    /// Replace entities, lights and skybox with the ones in file. glTF files not already loaded are loaded first.
+    /// glTF files that fail to load and entities with meshes that are not loaded are skipped and reported in the result.
+    /// Removes the controlled entity
+    pub fn load_scene(&mut self, file: &SceneFile) -> LoadedScene {
+        let mut errors = vec![];
+        for gltf in &file.gltf_files {
+            if !self.gltf_files.contains(gltf) {
+                if let Err(err) = self.try_load_all_meshes(&gltf.path, gltf.root_motion) {
+                    errors.push(format!("Could not load {}: {}", gltf.path, err));
+                }
+            }
+        }
+
+        let ids : Vec::<EntityId> = self.entities.data.keys().copied().collect();
+        for id in &ids {
+            self.remove_entity(id);
+        }
+        self.controlled_entity = None;
+
+        match &file.skybox {
+            Some(skybox) => {
+                if self.skybox_path.as_ref() != Some(skybox) {
+                    self.set_skybox(skybox.clone());
+                }
+            },
+            None => self.clear_skybox(),
+        }
+
+        self.lights = file.lights.clone();
+        self.ambient_color = file.ambient_color;
+
+        let mut res = LoadedScene {
+            missing_meshes: file.missing_meshes(|name| self.meshes.contains_key(name)),
+            errors,
+            ..Default::default()
+        };
+
+        // index in file to created entity
+        let mut created = Vec::<Option<EntityId>>::with_capacity(file.entities.len());
+
+        for data in &file.entities {
+            if !self.meshes.contains_key(data.mesh.as_str()) {
+                created.push(None);
+                continue;
+            }
+
+            let id = self.create_entity(&data.mesh);
+            self.set_entity_render_pipeline(id, data.render_pipeline.as_str().into());
+
+            if let Some(entity) = self.entities.get_mut(&id) {
+                entity.pos = data.pos;
+                entity.rotation = data.rotation;
+                entity.scale = data.scale;
+                entity.z_angle = Rotation2::new(data.z_angle);
+                entity.target_z_angle = entity.z_angle;
+                entity.forward_pitch = Rotation2::new(data.forward_pitch);
+                entity.side_pitch = Rotation2::new(data.side_pitch);
+            }
+
+            created.push(Some(id));
+            res.entities.push((id, data.user_data.clone()));
+        }
+
+        // parents can come after their children in the file, so attach when all are created
+        for (data, child) in file.entities.iter().zip(&created) {
+            let (child, parent) = match (child, &data.parent) {
+                (Some(c), Some(p)) => (*c, p),
+                _ => continue
+            };
+
+            if let Some(Some(parent_id)) = created.get(parent.entity) {
+                if let Err(err) = self.attach(child, *parent_id, parent.joint.as_deref()) {
+                    res.errors.push(err.to_string());
+                }
+            }
+        }
+
+        res
     }
 
     pub fn controlled_data_mut(&mut self) -> Option<&mut UserControllerData> {
@@ -374,6 +840,10 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
 
     pub fn set_skybox(&mut self, path: String) {
 
+        self.skybox_path = Some(path.clone());
+        // replaced when the new images are loaded
+        self.cubemap = None;
+
         //START load
         let cm = Arc::new(Mutex::new(None));
         self.cubemap_imgs = Some(cm.clone());
@@ -387,6 +857,12 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
         });
     }
 
+    pub fn clear_skybox(&mut self) {
+        self.skybox_path = None;
+        self.cubemap = None;
+        self.cubemap_imgs = None;
+    }
+
     pub fn camera_follow(&mut self, pos: V3) {
         self.follow_controller.update_camera_target(pos);
     }
@@ -395,7 +871,46 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
         self.inputs.selected = match self.inputs.selected {
             SceneControllerSelected::Free => SceneControllerSelected::Follow,
             SceneControllerSelected::Follow => SceneControllerSelected::Free
+        };
+        self.blend_camera();
+    }
+
+    /// Blend from what is currently shown to the camera over `camera_blend_time`
+    pub fn blend_camera(&mut self) {
+        self.camera_blend = Some(CameraBlend::new(self.view_camera().state(), self.camera_blend_time));
+    }
+
+    /// Render from the path instead of the camera until the path ends, then blend back to the camera
+    pub fn play_camera_path(&mut self, path: CameraPath) {
+        self.camera_blend = None;
+        self.camera_path = Some(PathPlayback::new(path));
+    }
+
+    pub fn stop_camera_path(&mut self) {
+        if self.camera_path.is_some() {
+            self.blend_camera();
+            self.camera_path = None;
+        }
+    }
+
+    pub fn camera_path_playing(&self) -> bool {
+        self.camera_path.is_some()
+    }
+
+    /// The camera used for rendering. `camera` with camera path, blend and shake applied
+    pub fn view_camera(&self) -> Camera {
+        let mut state = match &self.camera_path {
+            Some(playback) => playback.state().unwrap_or_else(|| self.camera.state()),
+            None => self.camera.state(),
+        };
+
+        if let Some(blend) = &self.camera_blend {
+            state = blend.sample(&state);
         }
+
+        let mut camera = self.camera.clone();
+        camera.set_state(&self.camera_shake.apply(&state));
+        camera
     }
 
     pub fn allow_char_inputs(&self) -> bool {
@@ -415,12 +930,15 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             }
         }
 
-        let dt = self.dt();
-
         self.ui.start_frame(event_pump);
 
+        // ui time, so recorded dt is used in playback
+        self.game_loop.advance(self.ui.deltatime.unscaled_time());
+        let dt = self.dt();
+
         self.inputs.current_mut().frame_start();
 
+        let mut camera_changed = false;
         for event in &self.ui.frame_events {
             if !self.ui_mode {
                 self.inputs.update_events(event);
@@ -439,7 +957,8 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
                     self.inputs.selected = match self.inputs.selected {
                         SceneControllerSelected::Free => SceneControllerSelected::Follow,
                         SceneControllerSelected::Follow => SceneControllerSelected::Free
-                    }
+                    };
+                    camera_changed = true;
                 },
                 Event::Window {win_event: WindowEvent::Resized(x,y), ..} => {
                     self.camera.width = *x as f32;
@@ -457,11 +976,34 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             }
         }
 
-        // update particles
-        self.emitter.update(dt);
+        if camera_changed {
+            self.blend_camera();
+        }
+
+        self.update_camera_effects(dt);
+
+        // update systems and particles
+        for _ in 0..self.fixed_steps() {
+            self.run_systems(self.game_loop.fixed_dt);
+
+            self.emitter.update(self.game_loop.fixed_dt);
+
+            let world_mats = &self.world_mats;
+            let entities = &self.entities;
+            self.effects.update(self.game_loop.fixed_dt, |id| {
+                world_mats.get(&id).copied()
+                    .or_else(|| entities.get(&id).map(|e| e.model_mat()))
+                    .map(|m| m.column(3).xyz())
+            });
+
+            let mut trails = std::mem::take(&mut self.trails);
+            trails.update(self.game_loop.fixed_dt, |anchor| self.trail_anchor_pos(anchor));
+            self.trails = trails;
+        }
 
         match self.inputs.selected {
-            SceneControllerSelected::Free => free_camera::update_camera(&mut self.camera, dt, &self.inputs.free),
+            // free camera uses real time, so it can look around while paused
+            SceneControllerSelected::Free => free_camera::update_camera(&mut self.camera, self.ui.dt(), &self.inputs.free),
             SceneControllerSelected::Follow => {
                 // TODO: Should be a function points or something, we most likely want to disable/ignore movement inputs
                 // when fx roll animation is playing
@@ -491,13 +1033,37 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
             }
         }
 
-        //TODO: have a playing/pause bool
-        self.update_actions();
-        self.update_animations();
+        for _ in 0..self.fixed_steps() {
+            self.update_actions();
+            self.update_animations();
+
+            // run actions queued by animation events this step
+            self.update_actions();
+        }
+    }
+
+
+    fn update_camera_effects(&mut self, dt: f32) {
+        self.camera_shake.update(dt);
+
+        if let Some(blend) = &mut self.camera_blend {
+            blend.update(dt);
+            if blend.is_done() {
+                self.camera_blend = None;
+            }
+        }
+
+        if let Some(playback) = &mut self.camera_path {
+            playback.update(dt);
+            if playback.is_done() {
+                self.stop_camera_path();
+            }
+        }
     }
 
 
     pub fn frame_end(&mut self) {
+        self.game_loop.end_frame();
         self.ui.end_frame();
     }
 
@@ -519,14 +1085,28 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
                 },
                 Action::PlaySound(name) => {
                     self.audio_player.play_sound(&name)
+                },
+                Action::SpawnEffect(name, target) => {
+                    if let Err(err) = self.effects.spawn(&name, target) {
+                        // actions can be queued every frame, so each missing effect is reported once
+                        if self.missing_effects.insert(name) {
+                            println!("{}", err);
+                        }
+                    }
                 }
             }
         }
     }
 
+    /// Advance animations one fixed step
     pub fn update_animations(&mut self) {
-        let dt = self.dt();
-        self.player.update(dt);
+        self.player.update(self.game_loop.fixed_dt);
+
+        for event in self.player.drain_events() {
+            if let Some(f) = self.animation_events.get(&event.name) {
+                self.action_queue.push_back(f(event.id));
+            }
+        }
 
         // update entities skeleton
         for (entity_id, entity) in &mut self.entities.data {
@@ -572,26 +1152,47 @@ impl<UserPostProcessData, UserControllerData> Scene<UserPostProcessData, UserCon
 
     pub fn render(&mut self) {
 
+        self.update_transforms();
+
+        let camera = self.view_camera();
 
         self.render_pipelines.render(
             &self.mesh_data,
-            &self.camera,
-            self.light_pos,
-            self.light_color,
+            &camera,
+            &self.lights,
+            self.ambient_color,
             &mut self.ui,
             &self.viewport,
             &self.bones,
             &self.default_bones,
             &self.entities.data,
-            &self.emitter
+            &self.world_mats,
+            &self.ecs,
+            &self.emitter,
+            &self.effects,
+            &self.trails
         );
 
 
     }
 
 
+    /// Frame time scaled by `game_loop.time_scale`, 0 when paused
     pub fn dt(&self) -> f32 {
-        self.ui.dt()
+        self.game_loop.frame_dt()
+    }
+
+    /// Run `systems` once with the scene as ctx. Systems added while running run from the next call
+    pub fn run_systems(&mut self, dt: f32) {
+        let mut systems = std::mem::take(&mut self.systems);
+        systems.run_owner(self, dt);
+        systems.merge(std::mem::take(&mut self.systems));
+        self.systems = systems;
+    }
+
+    /// Number of fixed steps this frame. Run game simulation this many times with `game_loop.fixed_dt`
+    pub fn fixed_steps(&self) -> usize {
+        self.game_loop.steps()
     }
 
 }
@@ -0,0 +1,240 @@
+//! Versioned json format for saving and loading a `Scene`. Only references meshes by name, so the glTF files
+//! are stored as paths and loaded again with `load_all_meshes`, see `Scene::save_scene` and `Scene::load_scene`.
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::typedef::*;
+use crate::na::UnitQuaternion;
+use crate::color::Color;
+use crate::scene_3d::lights::Light;
+use crate::scene_3d::EntityId;
+
+/// Current version of the scene format. Bump when making breaking changes and add migration in `parse`.
+pub const SCENE_FILE_VERSION: u32 = 1;
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GltfFile {
+    pub path: String,
+    pub root_motion: bool,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParentData {
+    /// Index of parent in the scene file entities
+    pub entity: usize,
+    /// Joint name in the parent's skeleton
+    #[serde(default)]
+    pub joint: Option<String>,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityData {
+    /// Name of mesh in the loaded glTF files
+    pub mesh: String,
+    pub pos: V3,
+    #[serde(default = "UnitQuaternion::identity")]
+    pub rotation: UnitQuaternion<f32>,
+    #[serde(default = "default_scale")]
+    pub scale: V3,
+    /// Angles in radians
+    pub z_angle: f32,
+    #[serde(default)]
+    pub forward_pitch: f32,
+    #[serde(default)]
+    pub side_pitch: f32,
+    /// Name of render pipeline, unknown names use the default pipeline
+    #[serde(default = "default_pipeline")]
+    pub render_pipeline: String,
+    /// Pos, angles, rotation and scale are relative to the parent
+    #[serde(default)]
+    pub parent: Option<ParentData>,
+    /// Game specific data, fx health or ai settings. Not used by the scene
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub user_data: serde_json::Value,
+}
+
+fn default_scale() -> V3 {
+    V3::new(1.0, 1.0, 1.0)
+}
+
+fn default_pipeline() -> String {
+    "default".to_string()
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    /// Loaded in order before entities are created
+    #[serde(default)]
+    pub gltf_files: Vec::<GltfFile>,
+    #[serde(default)]
+    pub skybox: Option<String>,
+    pub ambient_color: Color,
+    #[serde(default)]
+    pub lights: Vec::<Light>,
+    #[serde(default)]
+    pub entities: Vec::<EntityData>,
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        Self {
+            version: SCENE_FILE_VERSION,
+            gltf_files: vec![],
+            skybox: None,
+            ambient_color: Color::Rgb(128, 128, 128),
+            lights: vec![],
+            entities: vec![],
+        }
+    }
+}
+
+
+impl SceneFile {
+
+    pub fn parse(json: &str) -> Result<Self, failure::Error> {
+        let res: SceneFile = serde_json::from_str(json)?;
+
+        if res.version > SCENE_FILE_VERSION {
+            failure::bail!("Scene file version {} is newer than supported version {}", res.version, SCENE_FILE_VERSION);
+        }
+
+        Ok(res)
+    }
+
+    pub fn to_json(&self) -> Result<String, failure::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, failure::Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<(), failure::Error> {
+        let mut data = self.clone();
+        data.version = SCENE_FILE_VERSION;
+        std::fs::write(path, data.to_json()?)?;
+        Ok(())
+    }
+
+    /// Mesh names used by entities that has_mesh returns false for, each name once
+    pub fn missing_meshes<F: Fn(&str) -> bool>(&self, has_mesh: F) -> Vec::<String> {
+        let mut res = Vec::<String>::new();
+        for entity in &self.entities {
+            if !has_mesh(&entity.mesh) && !res.contains(&entity.mesh) {
+                res.push(entity.mesh.clone());
+            }
+        }
+        res
+    }
+}
+
+
+/// Result of `Scene::load_scene`
+#[derive(Debug, Default)]
+pub struct LoadedScene {
+    /// Created entities with their user data, in file order
+    pub entities: Vec::<(EntityId, serde_json::Value)>,
+    /// Entities using these meshes were not created
+    pub missing_meshes: Vec::<String>,
+    /// glTF files that could not be loaded and parents that could not be attached, fx a missing joint
+    pub errors: Vec::<String>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_3d::lights::LightKind;
+
+    fn scene() -> SceneFile {
+        SceneFile {
+            gltf_files: vec![GltfFile { path: "assets/world.glb".to_string(), root_motion: false }],
+            skybox: Some("assets/skybox/".to_string()),
+            lights: vec![
+                Light::directional(V3::new(0.0, -1.0, -1.0), Color::Rgb(255, 240, 220)).with_shadows(),
+                Light::spot(V3::new(1.0, 2.0, 3.0), V3::new(0.0, 0.0, -1.0), Color::RgbAf32(1.0, 0.5, 0.0, 1.0), 10.0, 0.3, 0.5),
+            ],
+            entities: vec![
+                EntityData {
+                    mesh: "Player".to_string(),
+                    pos: V3::new(1.0, 2.0, 0.0),
+                    rotation: UnitQuaternion::from_axis_angle(&V3::x_axis(), 0.5),
+                    scale: V3::new(2.0, 2.0, 2.0),
+                    z_angle: 1.5,
+                    forward_pitch: 0.0,
+                    side_pitch: 0.1,
+                    render_pipeline: "outline".to_string(),
+                    parent: None,
+                    user_data: serde_json::json!({ "hp": 100 }),
+                },
+                EntityData {
+                    mesh: "Rock".to_string(),
+                    pos: V3::new(-5.0, 0.0, 0.0),
+                    rotation: UnitQuaternion::identity(),
+                    scale: V3::new(1.0, 1.0, 1.0),
+                    z_angle: 0.0,
+                    forward_pitch: 0.0,
+                    side_pitch: 0.0,
+                    render_pipeline: "default".to_string(),
+                    parent: Some(ParentData { entity: 0, joint: Some("hand.R".to_string()) }),
+                    user_data: serde_json::Value::Null,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = scene().to_json().unwrap();
+        let parsed = SceneFile::parse(&json).unwrap();
+
+        assert_eq!(parsed.to_json().unwrap(), json);
+        assert_eq!(parsed.gltf_files, scene().gltf_files);
+        assert_eq!(parsed.entities[0].pos, V3::new(1.0, 2.0, 0.0));
+        assert_eq!(parsed.entities[0].user_data["hp"], 100);
+        assert!(parsed.entities[1].user_data.is_null());
+        assert_eq!(parsed.entities[0].rotation, scene().entities[0].rotation);
+        assert_eq!(parsed.entities[1].parent, Some(ParentData { entity: 0, joint: Some("hand.R".to_string()) }));
+
+        // directional light has infinite range, which json does not have
+        assert_eq!(parsed.lights[0].range, f32::INFINITY);
+        assert!(parsed.lights[0].casts_shadows);
+        assert_eq!(parsed.lights[1].kind, LightKind::Spot { inner_angle: 0.3, outer_angle: 0.5 });
+        assert_eq!(parsed.lights[1].range, 10.0);
+    }
+
+    #[test]
+    fn defaults_and_version() {
+        let json = r#"{
+            "version": 1,
+            "ambient_color": { "Rgb": [10, 20, 30] },
+            "entities": [{ "mesh": "Rock", "pos": [1.0, 2.0, 3.0], "z_angle": 0.5 }]
+        }"#;
+
+        let parsed = SceneFile::parse(json).unwrap();
+        assert!(parsed.lights.is_empty());
+        assert_eq!(parsed.entities[0].render_pipeline, "default");
+        assert_eq!(parsed.entities[0].side_pitch, 0.0);
+        assert_eq!(parsed.entities[0].scale, V3::new(1.0, 1.0, 1.0));
+        assert_eq!(parsed.entities[0].rotation, UnitQuaternion::identity());
+        assert!(parsed.entities[0].parent.is_none());
+
+        let newer = format!(r#"{{ "version": {}, "ambient_color": {{ "Rgb": [0, 0, 0] }} }}"#, SCENE_FILE_VERSION + 1);
+        assert!(SceneFile::parse(&newer).is_err());
+    }
+
+    #[test]
+    fn missing() {
+        let mut file = scene();
+        file.entities.push(EntityData { mesh: "Tree".to_string(), ..file.entities[1].clone() });
+        file.entities.push(EntityData { mesh: "Tree".to_string(), ..file.entities[1].clone() });
+
+        assert_eq!(file.missing_meshes(|name| name != "Tree"), vec!["Tree".to_string()]);
+        assert!(file.missing_meshes(|_| true).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+//! Trails of a `Scene`, following a position, an entity or a joint in an entity's skeleton, fx the tip of a sword.
+//! See `Scene::add_trail`. Trails are drawn by their render pipeline after the opaque scene.
+use crate::typedef::*;
+use crate::particle_system::trail::{Trail, TrailDesc};
+use crate::scene_3d::{EntityId, RenderPipelineId};
+use crate::scene_3d::types::DataMap;
+
+
+pub type TrailId = usize;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrailAnchor {
+    /// Move it by setting the anchor, fx to a projectile position
+    Pos(V3),
+    /// Offset is in the entity's model space
+    Entity { id: EntityId, offset: V3 },
+    /// Joint index in the entity's skeleton, offset is in the joint's space
+    Joint { id: EntityId, joint: usize, offset: V3 },
+}
+
+
+pub struct SceneTrail {
+    pub trail: Trail,
+    pub anchor: TrailAnchor,
+    pub render_pipeline_id: RenderPipelineId,
+}
+
+
+#[derive(Default)]
+pub struct Trails {
+    pub data: DataMap::<SceneTrail>,
+}
+
+impl Trails {
+
+    pub fn add(&mut self, desc: TrailDesc, anchor: TrailAnchor) -> TrailId {
+        self.data.insert(SceneTrail { trail: Trail::new(desc), anchor, render_pipeline_id: 0 })
+    }
+
+    pub fn get(&self, id: &TrailId) -> Option<&SceneTrail> {
+        self.data.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &TrailId) -> Option<&mut SceneTrail> {
+        self.data.get_mut(id)
+    }
+
+    pub fn remove(&mut self, id: &TrailId) -> Option<SceneTrail> {
+        self.data.remove(id)
+    }
+
+    /// Sample the anchor of each trail. anchor_pos is None when the entity is removed,
+    /// then the trail stops and is removed once it has faded out
+    pub fn update<F: Fn(&TrailAnchor) -> Option<V3>>(&mut self, dt: f32, anchor_pos: F) {
+        self.data.data.retain(|_, t| {
+            match anchor_pos(&t.anchor) {
+                Some(pos) => t.trail.update(dt, pos),
+                None => {
+                    t.trail.stop();
+                    t.trail.update(dt, V3::zeros());
+                    return !t.trail.is_finished();
+                }
+            }
+            true
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_and_remove() {
+        let mut trails = Trails::default();
+        let desc = TrailDesc { lifetime: 0.5, min_distance: 0.0, ..Default::default() };
+        let pos = trails.add(desc.clone(), TrailAnchor::Pos(V3::zeros()));
+        let entity = trails.add(desc, TrailAnchor::Entity { id: 3, offset: V3::new(0.0, 0.0, 1.0) });
+
+        let anchor_pos = |exists: bool| move |a: &TrailAnchor| match *a {
+            TrailAnchor::Pos(p) => Some(p),
+            TrailAnchor::Entity { offset, .. } if exists => Some(V3::new(1.0, 0.0, 0.0) + offset),
+            _ => None,
+        };
+
+        trails.update(0.1, anchor_pos(true));
+        trails.get_mut(&pos).unwrap().anchor = TrailAnchor::Pos(V3::new(2.0, 0.0, 0.0));
+        trails.update(0.1, anchor_pos(true));
+
+        assert_eq!(trails.get(&pos).unwrap().trail.points.len(), 2);
+        assert_eq!(trails.get(&entity).unwrap().trail.head, Some(V3::new(1.0, 0.0, 1.0)));
+
+        // entity removed, its trail fades out and is removed. Stopped trails with an anchor are kept
+        trails.get_mut(&pos).unwrap().trail.stop();
+        trails.update(0.1, anchor_pos(false));
+        assert!(trails.get(&entity).is_some());
+        trails.update(1.0, anchor_pos(false));
+        assert!(trails.get(&entity).is_none());
+        assert!(trails.get(&pos).unwrap().trail.is_finished());
+    }
+}
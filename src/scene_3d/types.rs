@@ -24,6 +24,12 @@ impl<T> DataMap<T> {
         id
     }
 
+    /// Insert with an id given from elsewhere, fx `ecs::World::spawn`. Later `insert` ids come after it
+    pub fn insert_at(&mut self, id: usize, data: T) {
+        self.next_id = self.next_id.max(id + 1);
+        self.data.insert(id, data);
+    }
+
     pub fn get(&self, id: &usize) -> Option<&T> {
         self.data.get(&id)
     }
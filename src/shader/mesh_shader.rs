@@ -3,6 +3,9 @@ use failure;
 use crate::gl;
 use super::*;
 use crate::typedef::*;
+use crate::texture::{self, TextureId};
+use crate::objects::material::{Material, AlphaMode};
+use crate::scene_3d::lights::LIGHTS_PER_MESH;
 
 
 #[derive( Clone)]
@@ -21,7 +24,77 @@ impl fmt::Debug for MeshShader {
 impl MeshShader {
 
     pub fn new(gl: &gl::Gl) -> Result<Self, failure::Error> {
-        create_shader(gl).map(|s| Self { gl: gl.clone(), shader:s })
+        create_shader(gl).map(|s| Self::from_base(gl, s))
+    }
+
+    /// Mesh shader from other sources, fx the stencil shader. Uniforms set per draw are looked up here once,
+    /// so replace the whole `MeshShader` instead of only its `shader`
+    pub fn from_base(gl: &gl::Gl, mut shader: BaseShader) -> Self {
+        shader.set_locations(gl, "meshLights");
+        // -1 when the shader can not draw instanced, see `scene_3d::rendering`
+        shader.set_locations(gl, "instanced");
+        Self { gl: gl.clone(), shader }
+    }
+
+    /// Set which lights from the light uniform block the next meshes use, see `scene_3d::lights`
+    pub fn set_lights(&self, lights: &[usize], shadow_light: Option<usize>) {
+        let mut ids = [0; LIGHTS_PER_MESH];
+        let count = lights.len().min(LIGHTS_PER_MESH);
+        for (id, light) in ids.iter_mut().zip(lights) {
+            *id = *light as i32;
+        }
+
+        self.shader.set_i32(&self.gl, "meshLightCount", count as i32);
+        unsafe {
+            self.gl.Uniform1iv(self.shader.get_location("meshLights"), count as i32, ids.as_ptr());
+        }
+        self.shader.set_i32(&self.gl, "shadowLight", shadow_light.map(|l| l as i32).unwrap_or(-1));
+    }
+
+    /// Metallic roughness shader using the mesh material, see `set_material`
+    pub fn pbr(gl: &gl::Gl) -> Result<Self, failure::Error> {
+        let vert_source = include_str!("../../assets/shaders/objects/pbr.vert");
+        let frag_source = include_str!("../../assets/shaders/objects/pbr.frag");
+
+        BaseShader::new(gl, vert_source, frag_source).map(|s| Self::from_base(gl, s))
+    }
+
+    /// Set material uniforms and bind material textures. Texture unit 1 is left for the shadow map
+    pub fn set_material(&self, material: &Material<TextureId>) {
+        let gl = &self.gl;
+        self.shader.set_vec4(gl, "baseColorFactor", material.base_color_factor);
+        self.shader.set_f32(gl, "metallicFactor", material.metallic_factor);
+        self.shader.set_f32(gl, "roughnessFactor", material.roughness_factor);
+        self.shader.set_f32(gl, "normalScale", material.normal_scale);
+        self.shader.set_f32(gl, "occlusionStrength", material.occlusion_strength);
+        self.shader.set_vec3(gl, "emissiveFactor", material.emissive_factor);
+
+        let (mode, cutoff) = match material.alpha_mode {
+            AlphaMode::Opaque => (0, 0.0),
+            AlphaMode::Mask(cutoff) => (1, cutoff),
+            AlphaMode::Blend => (2, 0.0),
+        };
+        self.shader.set_i32(gl, "alphaMode", mode);
+        self.shader.set_f32(gl, "alphaCutoff", cutoff);
+
+        let names = ["hasBaseColorTex", "hasMetallicRoughnessTex", "hasNormalTex", "hasOcclusionTex", "hasEmissiveTex"];
+        let units = [0, 2, 3, 4, 5];
+
+        for ((tex, name), unit) in material.textures().iter().zip(names).zip(units) {
+            self.shader.set_i32(gl, name, tex.is_some() as i32);
+            if let Some(id) = tex {
+                texture::active_texture(gl, unit);
+                texture::set_texture(gl, **id);
+            }
+        }
+
+        unsafe {
+            if material.double_sided {
+                gl.Disable(gl::CULL_FACE);
+            } else {
+                gl.Enable(gl::CULL_FACE);
+            }
+        }
     }
 
     pub fn set_uniforms(&self, uni: Uniforms) {
@@ -37,8 +110,9 @@ impl MeshShader {
 
 #[derive(Clone, Debug, Copy)]
 pub struct Uniforms<'a> {
+    // most relevant light, for shaders that only use a single light
     pub light_pos: V3,
-    pub light_color: Color, // TODO: this should be uniform, for now hard coded to 1.0, 1.0, 1.0
+    pub light_color: Color,
     pub projection: Mat4,
     pub view_pos: V3, // should be camera pos
     pub view: Mat4,
@@ -152,8 +152,10 @@ pub fn reload_object_shader(name: &str, gl: &gl::Gl, shader: &mut BaseShader) {
         .expect(&format!("Could not reader frag shader file at: {:?}", frag_shader_path));
 
     match shader::BaseShader::new(gl, &vert_source, &frag_source) {
-        Ok(s) => {
+        Ok(mut s) => {
             println!("Reloaded {name}");
+            // keep looked up uniforms, fx the ones a `MeshShader` sets per draw
+            s.relocate(gl, shader);
             *shader = s;
         },
         Err(e) => {
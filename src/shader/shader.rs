@@ -72,6 +72,13 @@ impl BaseShader {
 
 
 
+    /// Look up the uniforms cached in other, fx when this replaces other after a reload
+    pub fn relocate(&mut self, gl: &gl::Gl, other: &BaseShader) {
+        for name in other.locations.keys() {
+            self.set_locations(gl, name);
+        }
+    }
+
     /// a default shader for rendering a bezier curve
     pub fn bezier_shader(gl: &gl::Gl) -> Result<BaseShader, failure::Error> {
         // default program for square
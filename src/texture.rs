@@ -148,6 +148,35 @@ pub fn gen_texture_depth(gl: &gl::Gl, width: i32, height: i32) -> TextureId {
 
 
 
+/// Depth texture array, fx one layer per shadow cascade
+pub fn gen_texture_depth_array(gl: &gl::Gl, width: i32, height: i32, layers: i32) -> TextureId {
+
+    let mut id = 0;
+
+    unsafe {
+        gl.GenTextures(1, &mut id);
+
+        gl.BindTexture(gl::TEXTURE_2D_ARRAY, id);
+
+        gl.TexImage3D(gl::TEXTURE_2D_ARRAY, 0, gl::DEPTH_COMPONENT32F as i32, width, height, layers, 0, gl::DEPTH_COMPONENT, gl::FLOAT, 0 as *const gl::types::GLvoid);
+
+        gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+        gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+
+        let border_colors : [f32; 4] = [1.0; 4];
+        gl.TexParameterfv(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BORDER_COLOR, border_colors.as_ptr() as *const gl::types::GLfloat) ;
+
+        gl.BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+    }
+
+    id
+}
+
+
+
 pub fn gen_texture_framebuffer(gl: &gl::Gl, w: i32, h: i32) -> TextureId {
 
     let mut id: gl::types::GLuint = 0;
@@ -238,3 +267,11 @@ pub fn set_texture(gl: &gl::Gl, texture_id: TextureId) {
         gl.BindTexture(gl::TEXTURE_2D, texture_id);
     }
 }
+
+/// Wrapper of BindTexture for 2d texture arrays
+pub fn set_texture_array(gl: &gl::Gl, texture_id: TextureId) {
+
+    unsafe {
+        gl.BindTexture(gl::TEXTURE_2D_ARRAY, texture_id);
+    }
+}
@@ -0,0 +1,298 @@
+//! Tile layers are split in square chunks of tiles. Only chunks overlapping the view are drawn, and each chunk is
+//! one mesh per tileset, so a chunk is a few draw calls no matter how many tiles it has.
+use crate::typedef::*;
+use crate::camera::camera_2d::Bounds2D;
+use super::{TileMap, TileRef};
+
+
+/// Tiles per side of a chunk
+pub const DEFAULT_CHUNK_SIZE: u32 = 16;
+
+/// Floats per vertex, pos xyz and uv
+pub const VERTEX_SIZE: usize = 5;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: u32,
+    pub y: u32,
+}
+
+
+/// Vertices of all tiles from one tileset in a chunk
+#[derive(Debug, Clone, Default)]
+pub struct ChunkBatch {
+    pub tileset: usize,
+    /// Pos in map pixels with y down, and uv. See `VERTEX_SIZE`
+    pub vertices: Vec::<f32>,
+    pub indices: Vec::<u32>,
+}
+
+impl ChunkBatch {
+    pub fn tile_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMesh {
+    pub batches: Vec::<ChunkBatch>,
+    /// Has animated tiles, so it must be rebuilt when time changes
+    pub animated: bool,
+}
+
+
+/// Number of chunks in x and y for a map
+pub fn chunk_count(map: &TileMap, chunk_size: u32) -> (u32, u32) {
+    let size = chunk_size.max(1);
+    (map.width.div_ceil(size), map.height.div_ceil(size))
+}
+
+
+/// Chunks of a layer with offset that can have tiles visible in view. Tiles larger than the map cells are drawn up and
+/// to the right of their cell, so the view is grown by that much to not cull them too early
+pub fn visible_chunks(map: &TileMap, offset: V2, view: &Bounds2D, chunk_size: u32) -> Vec::<ChunkCoord> {
+    let size = chunk_size.max(1);
+    let (cw, ch) = chunk_count(map, size);
+    if cw == 0 || ch == 0 {
+        return vec![];
+    }
+
+    let overdraw = map.tilesets.iter().fold(V2::new(0.0, 0.0), |acc, ts| {
+        V2::new(acc.x.max(ts.tile_width as f32 - map.tile_width as f32), acc.y.max(ts.tile_height as f32 - map.tile_height as f32))
+    });
+
+    let min = view.min - offset - V2::new(overdraw.x, 0.0);
+    let max = view.max - offset + V2::new(0.0, overdraw.y);
+
+    let chunk_w = (size * map.tile_width.max(1)) as f32;
+    let chunk_h = (size * map.tile_height.max(1)) as f32;
+
+    // outside the map
+    if max.x < 0.0 || max.y < 0.0 || min.x >= cw as f32 * chunk_w || min.y >= ch as f32 * chunk_h {
+        return vec![];
+    }
+
+    let x0 = (min.x / chunk_w).floor().max(0.0) as u32;
+    let y0 = (min.y / chunk_h).floor().max(0.0) as u32;
+    let x1 = ((max.x / chunk_w).floor() as u32).min(cw - 1);
+    let y1 = ((max.y / chunk_h).floor() as u32).min(ch - 1);
+
+    let mut res = vec![];
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            res.push(ChunkCoord { x, y });
+        }
+    }
+
+    res
+}
+
+
+/// Build the mesh for a chunk of a tile layer, with animated tiles at time_ms. Layer offset is included
+pub fn build_chunk(map: &TileMap, layer: usize, chunk: ChunkCoord, chunk_size: u32, time_ms: u64) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+
+    let Some(l) = map.layers.get(layer) else {
+        return mesh;
+    };
+
+    let size = chunk_size.max(1);
+    let x0 = chunk.x * size;
+    let y0 = chunk.y * size;
+
+    for y in y0..(y0 + size).min(map.height) {
+        for x in x0..(x0 + size).min(map.width) {
+            let Some(tile) = map.tile(layer, x, y) else { continue; };
+            let Some(ts) = map.tilesets.get(tile.tileset) else { continue; };
+
+            if ts.is_animated(tile.id) {
+                mesh.animated = true;
+            }
+
+            let batch = match mesh.batches.iter().position(|b| b.tileset == tile.tileset) {
+                Some(i) => &mut mesh.batches[i],
+                None => {
+                    mesh.batches.push(ChunkBatch { tileset: tile.tileset, ..Default::default() });
+                    mesh.batches.last_mut().unwrap()
+                }
+            };
+
+            // drawn from bottom left of the cell
+            let w = ts.tile_width as f32;
+            let h = ts.tile_height as f32;
+            let left = l.offset.x + (x * map.tile_width) as f32;
+            let bottom = l.offset.y + ((y + 1) * map.tile_height) as f32;
+
+            let uv = tile_corner_uvs(&tile, ts.tile_uv(ts.animated_id(tile.id, time_ms)));
+            let corners = [V2::new(left, bottom - h), V2::new(left + w, bottom - h), V2::new(left + w, bottom), V2::new(left, bottom)];
+
+            let base = (batch.vertices.len() / VERTEX_SIZE) as u32;
+            for (p, uv) in corners.iter().zip(uv.iter()) {
+                batch.vertices.extend_from_slice(&[p.x, p.y, 0.0, uv.x, uv.y]);
+            }
+            batch.indices.extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 3, base]);
+        }
+    }
+
+    mesh
+}
+
+
+/// True when an animated tile in the chunk shows another frame at time_ms than at prev_ms, so the chunk must be rebuilt
+pub fn frame_changed(map: &TileMap, layer: usize, chunk: ChunkCoord, chunk_size: u32, prev_ms: u64, time_ms: u64) -> bool {
+    let size = chunk_size.max(1);
+    let x0 = chunk.x * size;
+    let y0 = chunk.y * size;
+
+    for y in y0..(y0 + size).min(map.height) {
+        for x in x0..(x0 + size).min(map.width) {
+            let Some(tile) = map.tile(layer, x, y) else { continue; };
+            let Some(ts) = map.tilesets.get(tile.tileset) else { continue; };
+
+            if ts.is_animated(tile.id) && ts.animated_id(tile.id, prev_ms) != ts.animated_id(tile.id, time_ms) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+
+/// Uv of the top left, top right, bottom right and bottom left corners with flips applied. uv is left, right, top, bottom
+pub fn tile_corner_uvs(tile: &TileRef, uv: [f32; 4]) -> [V2; 4] {
+    let [l, r, t, b] = uv;
+    let mut c = [V2::new(l, t), V2::new(r, t), V2::new(r, b), V2::new(l, b)];
+
+    // transpose, top right and bottom left swap
+    if tile.flip_d {
+        c.swap(1, 3);
+    }
+
+    if tile.flip_h {
+        c.swap(0, 1);
+        c.swap(2, 3);
+    }
+
+    if tile.flip_v {
+        c.swap(0, 3);
+        c.swap(1, 2);
+    }
+
+    c
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::tests::MAP;
+
+    fn coords(v: &[ChunkCoord]) -> Vec::<(u32, u32)> {
+        v.iter().map(|c| (c.x, c.y)).collect()
+    }
+
+    #[test]
+    fn culling() {
+        let mut map = TileMap::parse(MAP).unwrap();
+        // 40x30 tiles of 16 pixels, chunks of 4 tiles are 64 pixels, so 10x8 chunks
+        map.width = 40;
+        map.height = 30;
+        map.tilesets.truncate(1);
+
+        let zero = V2::new(0.0, 0.0);
+        assert_eq!(chunk_count(&map, 4), (10, 8));
+
+        let view = Bounds2D::new(V2::new(70.0, 10.0), V2::new(200.0, 64.0));
+        assert_eq!(coords(&visible_chunks(&map, zero, &view, 4)), vec![(1, 0), (2, 0), (3, 0), (1, 1), (2, 1), (3, 1)]);
+
+        // clamped to the map, and nothing when outside
+        let view = Bounds2D::new(V2::new(-100.0, -100.0), V2::new(10.0, 10.0));
+        assert_eq!(coords(&visible_chunks(&map, zero, &view, 4)), vec![(0, 0)]);
+        let view = Bounds2D::new(V2::new(-100.0, -100.0), V2::new(-10.0, 10.0));
+        assert!(visible_chunks(&map, zero, &view, 4).is_empty());
+        let view = Bounds2D::new(V2::new(640.0, 0.0), V2::new(700.0, 10.0));
+        assert!(visible_chunks(&map, zero, &view, 4).is_empty());
+
+        // layer offset moves the chunks
+        let view = Bounds2D::new(V2::new(70.0, 10.0), V2::new(100.0, 20.0));
+        assert_eq!(coords(&visible_chunks(&map, V2::new(10.0, 0.0), &view, 4)), vec![(0, 0), (1, 0)]);
+
+        // 32 pixel props reach 16 pixels into the chunk to the left and below
+        let map = {
+            let mut m = TileMap::parse(MAP).unwrap();
+            m.width = 40;
+            m.height = 30;
+            m
+        };
+        let view = Bounds2D::new(V2::new(70.0, 10.0), V2::new(100.0, 50.0));
+        assert_eq!(coords(&visible_chunks(&map, zero, &view, 4)), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn build() {
+        let map = TileMap::parse(MAP).unwrap();
+
+        // whole ground layer in one chunk, 11 tiles from one tileset
+        let mesh = build_chunk(&map, 0, ChunkCoord { x: 0, y: 0 }, 16, 0);
+        assert_eq!(mesh.batches.len(), 1);
+        assert_eq!(mesh.batches[0].tile_count(), 11);
+        assert_eq!(mesh.batches[0].vertices.len(), 11 * 4 * VERTEX_SIZE);
+        assert!(mesh.animated);
+
+        // chunk size 2, chunk 1,0 has tiles 2..4 in row 0 and 1, no animated tile
+        let mesh = build_chunk(&map, 0, ChunkCoord { x: 1, y: 0 }, 2, 0);
+        assert_eq!(mesh.batches[0].tile_count(), 3);
+        assert!(!mesh.animated);
+        let v = &mesh.batches[0].vertices;
+        // first tile top left at 32,0
+        assert_eq!(&v[0..3], &[32.0, 0.0, 0.0]);
+        // bottom left
+        assert_eq!(&v[15..18], &[32.0, 16.0, 0.0]);
+        assert_eq!(&mesh.batches[0].indices[0..6], &[0, 1, 2, 2, 3, 0]);
+
+        // animated tile at 1,1 changes uv with time
+        let uv_at = |time| {
+            let mesh = build_chunk(&map, 0, ChunkCoord { x: 0, y: 0 }, 2, time);
+            // tiles 0,0 1,0 0,1 then 1,1
+            mesh.batches[0].vertices[3 * 4 * VERTEX_SIZE + 3]
+        };
+        assert_eq!(uv_at(0), map.tilesets[0].tile_uv(2)[0]);
+        assert_eq!(uv_at(150), map.tilesets[0].tile_uv(3)[0]);
+
+        // frames are 100 and 200 ms, only rebuilt when the frame changes
+        let changed = |prev, time| frame_changed(&map, 0, ChunkCoord { x: 0, y: 0 }, 2, prev, time);
+        assert!(!changed(0, 50));
+        assert!(changed(50, 150));
+        assert!(!changed(150, 299));
+        assert!(!frame_changed(&map, 0, ChunkCoord { x: 1, y: 0 }, 2, 50, 150));
+
+        // large tile in decor layer is drawn up from the bottom of its cell, with the group offset
+        let mesh = build_chunk(&map, 1, ChunkCoord { x: 0, y: 0 }, 16, 0);
+        assert_eq!(mesh.batches.len(), 1);
+        assert_eq!(mesh.batches[0].tileset, 1);
+        assert_eq!(&mesh.batches[0].vertices[0..2], &[4.0 + 16.0, 2.0 + 48.0 - 32.0]);
+
+        assert!(build_chunk(&map, 5, ChunkCoord { x: 0, y: 0 }, 16, 0).batches.is_empty());
+    }
+
+    #[test]
+    fn flips() {
+        let uv = [0.0, 1.0, 1.0, 0.0];
+        let tile = |h, v, d| TileRef { tileset: 0, id: 0, flip_h: h, flip_v: v, flip_d: d };
+
+        let tl = V2::new(0.0, 1.0);
+        let tr = V2::new(1.0, 1.0);
+        let br = V2::new(1.0, 0.0);
+        let bl = V2::new(0.0, 0.0);
+
+        assert_eq!(tile_corner_uvs(&tile(false, false, false), uv), [tl, tr, br, bl]);
+        assert_eq!(tile_corner_uvs(&tile(true, false, false), uv), [tr, tl, bl, br]);
+        assert_eq!(tile_corner_uvs(&tile(false, true, false), uv), [bl, br, tr, tl]);
+        assert_eq!(tile_corner_uvs(&tile(false, false, true), uv), [tl, bl, br, tr]);
+        // Tiled rotates 90 clockwise with diagonal and horizontal flip
+        assert_eq!(tile_corner_uvs(&tile(true, false, true), uv), [bl, tl, tr, br]);
+    }
+}
@@ -0,0 +1,757 @@
+//! Tile maps loaded from Tiled json maps. Supports orthogonal maps with embedded or external tilesets, tile and object
+//! layers, group layers (flattened), per tile collision shapes and animated tiles.
+//! Positions are in map pixels with y down, the same as `Drawer2D` screen pixels, so a map can be drawn with a
+//! `Camera2D` pushed, see `render::TileMapRenderer`.
+//! # Example
+//! ```ignore
+//! let map = TileMap::load("assets/level1.json")?;
+//! let mut renderer = TileMapRenderer::new(&gl, &map)?;
+//!
+//! drawer2d.push_camera(&camera);
+//! renderer.render(&drawer2d, &map, &camera.visible_bounds(), time_ms);
+//! drawer2d.pop_camera();
+//!
+//! let walls = map.collision_shapes(Some(&player_bounds));
+//! ```
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use failure::bail;
+use crate::typedef::*;
+use crate::camera::camera_2d::Bounds2D;
+use crate::collision2d::polygon::Polygon;
+
+pub mod tiled;
+pub mod chunks;
+pub mod render;
+
+use self::tiled::*;
+
+
+pub const FLIP_H: u32 = 0x8000_0000;
+pub const FLIP_V: u32 = 0x4000_0000;
+pub const FLIP_D: u32 = 0x2000_0000;
+const FLAGS: u32 = FLIP_H | FLIP_V | FLIP_D | 0x1000_0000;
+
+pub type Properties = HashMap::<String, serde_json::Value>;
+
+
+/// Gid decoded to tileset and local id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRef {
+    /// Index in `TileMap::tilesets`
+    pub tileset: usize,
+    /// Id in the tileset
+    pub id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    /// Diagonal flip, swaps x and y. Applied before flip_h and flip_v
+    pub flip_d: bool,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrame {
+    pub tile_id: u32,
+    pub duration_ms: u32,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct TileData {
+    pub class: String,
+    pub animation: Vec::<AnimationFrame>,
+    /// Shapes relative to the tile top left corner
+    pub collision: Vec::<MapObject>,
+    pub properties: Properties,
+}
+
+impl TileData {
+    /// Has collision shapes or the bool property "solid" set
+    pub fn is_solid(&self) -> bool {
+        !self.collision.is_empty() || self.properties.get("solid").and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct Tileset {
+    pub name: String,
+    pub first_gid: u32,
+    /// Relative to the working dir when loaded with `TileMap::load`, otherwise as in the json.
+    /// None for image collection tilesets, they are not rendered
+    pub image: Option<PathBuf>,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: u32,
+    pub columns: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    /// Only tiles with extra data, fx animation or collision
+    pub tiles: HashMap::<u32, TileData>,
+    pub properties: Properties,
+}
+
+impl Tileset {
+
+    /// Pixel rect of tile id in the image, x, y, w, h
+    pub fn tile_rect(&self, id: u32) -> (u32, u32, u32, u32) {
+        let columns = self.columns.max(1);
+        let x = self.margin + (id % columns) * (self.tile_width + self.spacing);
+        let y = self.margin + (id / columns) * (self.tile_height + self.spacing);
+        (x, y, self.tile_width, self.tile_height)
+    }
+
+    /// Texture coords left, right, top, bottom. Images are flipped when made into textures, so top is the larger v
+    pub fn tile_uv(&self, id: u32) -> [f32; 4] {
+        let (x, y, w, h) = self.tile_rect(id);
+        let iw = self.image_width.max(1) as f32;
+        let ih = self.image_height.max(1) as f32;
+
+        [x as f32 / iw, (x + w) as f32 / iw, 1.0 - y as f32 / ih, 1.0 - (y + h) as f32 / ih]
+    }
+
+    pub fn is_animated(&self, id: u32) -> bool {
+        self.tiles.get(&id).map(|t| !t.animation.is_empty()).unwrap_or(false)
+    }
+
+    /// Tile to show for id at time in milliseconds since the map started. Animations loop
+    pub fn animated_id(&self, id: u32, time_ms: u64) -> u32 {
+        let Some(frames) = self.tiles.get(&id).map(|t| &t.animation).filter(|a| !a.is_empty()) else {
+            return id;
+        };
+
+        let total : u64 = frames.iter().map(|f| f.duration_ms as u64).sum();
+        if total == 0 {
+            return frames[0].tile_id;
+        }
+
+        let mut t = time_ms % total;
+        for f in frames {
+            if t < f.duration_ms as u64 {
+                return f.tile_id;
+            }
+            t -= f.duration_ms as u64;
+        }
+
+        frames[frames.len() - 1].tile_id
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Rect,
+    Ellipse,
+    Point,
+    /// Points relative to the object pos
+    Polygon(Vec::<V2>),
+    Polyline(Vec::<V2>),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    pub class: String,
+    /// Top left, or bottom left for tile objects
+    pub pos: V2,
+    pub size: V2,
+    /// Degrees clockwise around pos
+    pub rotation: f32,
+    pub visible: bool,
+    /// Tile objects show this tile
+    pub gid: Option<u32>,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+impl MapObject {
+
+    /// Outline moved by offset, for collision. None for points and polylines. Ellipses are made of 12 points.
+    /// Concave polygons are treated as their convex hull by gjk
+    pub fn collision_polygon(&self, offset: V2) -> Option<Polygon> {
+        let local : Vec::<V2> = match &self.shape {
+            ObjectShape::Rect => vec![V2::new(0.0, 0.0), V2::new(self.size.x, 0.0), self.size, V2::new(0.0, self.size.y)],
+            ObjectShape::Ellipse => {
+                let r = self.size * 0.5;
+                (0..12).map(|i| {
+                    let a = i as f32 / 12.0 * std::f32::consts::TAU;
+                    r + V2::new(a.cos() * r.x, a.sin() * r.y)
+                }).collect()
+            },
+            ObjectShape::Polygon(points) if points.len() >= 3 => points.clone(),
+            _ => return None,
+        };
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let vertices = local.iter().map(|p| offset + self.pos + V2::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos)).collect();
+
+        Some(Polygon { vertices })
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub enum LayerData {
+    /// Gids, row by row with map width. 0 is empty
+    Tiles(Vec::<u32>),
+    Objects(Vec::<MapObject>),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    /// Pixels, includes the offset of parent group layers
+    pub offset: V2,
+    pub data: LayerData,
+    pub properties: Properties,
+}
+
+impl Layer {
+
+    pub fn gids(&self) -> Option<&[u32]> {
+        match &self.data {
+            LayerData::Tiles(gids) => Some(gids),
+            LayerData::Objects(_) => None,
+        }
+    }
+
+    pub fn objects(&self) -> Option<&[MapObject]> {
+        match &self.data {
+            LayerData::Tiles(_) => None,
+            LayerData::Objects(objects) => Some(objects),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct TileMap {
+    /// Size in tiles
+    pub width: u32,
+    pub height: u32,
+    /// Size of a grid cell in pixels. Tileset tiles can be larger, they are drawn from the bottom left of the cell
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec::<Tileset>,
+    /// In draw order
+    pub layers: Vec::<Layer>,
+    pub properties: Properties,
+}
+
+
+impl TileMap {
+
+    /// Load map and any external tilesets. Image paths are made relative to the working dir
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| failure::format_err!("Could not read tile map {:?}: {}", path, e))?;
+        Self::parse_in_dir(&json, path.parent())
+    }
+
+    /// Parse a map with only embedded tilesets
+    pub fn parse(json: &str) -> Result<Self, failure::Error> {
+        Self::parse_in_dir(json, None)
+    }
+
+    fn parse_in_dir(json: &str, dir: Option<&Path>) -> Result<Self, failure::Error> {
+        let raw : RawMap = serde_json::from_str(json)?;
+
+        if raw.infinite {
+            bail!("Infinite tile maps are not supported");
+        }
+
+        if !raw.orientation.is_empty() && raw.orientation != "orthogonal" {
+            bail!("Only orthogonal tile maps are supported, got '{}'", raw.orientation);
+        }
+
+        let mut tilesets = vec![];
+        for ts in &raw.tilesets {
+            tilesets.push(convert_tileset(ts, dir)?);
+        }
+        tilesets.sort_by_key(|ts| ts.first_gid);
+
+        let mut layers = vec![];
+        for l in &raw.layers {
+            convert_layer(l, &raw, V2::new(0.0, 0.0), true, 1.0, &mut layers)?;
+        }
+
+        Ok(Self {
+            width: raw.width,
+            height: raw.height,
+            tile_width: raw.tilewidth,
+            tile_height: raw.tileheight,
+            tilesets,
+            layers,
+            properties: convert_properties(&raw.properties),
+        })
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    pub fn tile_ref(&self, gid: u32) -> Option<TileRef> {
+        let id = gid & !FLAGS;
+        if id == 0 {
+            return None;
+        }
+
+        let tileset = self.tilesets.iter().rposition(|ts| ts.first_gid <= id)?;
+
+        Some(TileRef {
+            tileset,
+            id: id - self.tilesets[tileset].first_gid,
+            flip_h: gid & FLIP_H != 0,
+            flip_v: gid & FLIP_V != 0,
+            flip_d: gid & FLIP_D != 0,
+        })
+    }
+
+    pub fn gid(&self, layer: usize, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+
+        self.layers.get(layer)
+            .and_then(|l| l.gids())
+            .and_then(|gids| gids.get((y * self.width + x) as usize).copied())
+            .unwrap_or(0)
+    }
+
+    /// Change a tile, remember to invalidate the chunk in the renderer
+    pub fn set_gid(&mut self, layer: usize, x: u32, y: u32, gid: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let width = self.width;
+        if let Some(LayerData::Tiles(gids)) = self.layers.get_mut(layer).map(|l| &mut l.data) {
+            gids[(y * width + x) as usize] = gid;
+        }
+    }
+
+    pub fn tile(&self, layer: usize, x: u32, y: u32) -> Option<TileRef> {
+        self.tile_ref(self.gid(layer, x, y))
+    }
+
+    pub fn tile_data(&self, tile: &TileRef) -> Option<&TileData> {
+        self.tilesets.get(tile.tileset)?.tiles.get(&tile.id)
+    }
+
+    pub fn tile_size(&self) -> V2 {
+        V2::new(self.tile_width as f32, self.tile_height as f32)
+    }
+
+    /// Size in pixels
+    pub fn pixel_size(&self) -> V2 {
+        V2::new((self.width * self.tile_width) as f32, (self.height * self.tile_height) as f32)
+    }
+
+    pub fn bounds(&self) -> Bounds2D {
+        Bounds2D::new(V2::new(0.0, 0.0), self.pixel_size())
+    }
+
+    /// Tile coord of a pixel pos, can be outside of the map
+    pub fn world_to_tile(&self, pos: V2) -> (i32, i32) {
+        ((pos.x / self.tile_width.max(1) as f32).floor() as i32, (pos.y / self.tile_height.max(1) as f32).floor() as i32)
+    }
+
+    /// Top left pixel pos of tile
+    pub fn tile_to_world(&self, x: u32, y: u32) -> V2 {
+        V2::new((x * self.tile_width) as f32, (y * self.tile_height) as f32)
+    }
+
+    pub fn is_solid(&self, layer: usize, x: u32, y: u32) -> bool {
+        self.tile(layer, x, y)
+            .and_then(|t| self.tile_data(&t))
+            .map(|d| d.is_solid())
+            .unwrap_or(false)
+    }
+
+    /// Collision polygons of solid tiles in all visible tile layers, only tiles overlapping area when given.
+    /// Tiles with the "solid" property and no shapes collide with the whole cell. Diagonal flips are ignored
+    pub fn collision_shapes(&self, area: Option<&Bounds2D>) -> Vec::<Polygon> {
+        let mut res = vec![];
+
+        for (layer_idx, layer) in self.layers.iter().enumerate() {
+            if !layer.visible || layer.gids().is_none() {
+                continue;
+            }
+
+            let (x0, y0, x1, y1) = match area {
+                Some(a) => self.tile_range(&Bounds2D::new(a.min - layer.offset, a.max - layer.offset)),
+                None => (0, 0, self.width, self.height),
+            };
+
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let Some(tile) = self.tile(layer_idx, x, y) else { continue; };
+                    let Some(data) = self.tile_data(&tile).filter(|d| d.is_solid()) else { continue; };
+
+                    let ts = &self.tilesets[tile.tileset];
+                    let tile_size = V2::new(ts.tile_width as f32, ts.tile_height as f32);
+                    // large tiles are drawn from the bottom left of the cell
+                    let top_left = layer.offset + self.tile_to_world(x, y + 1) - V2::new(0.0, tile_size.y);
+
+                    if data.collision.is_empty() {
+                        let full = V2::new(self.tile_width as f32, self.tile_height as f32);
+                        let cell = layer.offset + self.tile_to_world(x, y);
+                        res.push(Polygon { vertices: vec![cell, cell + V2::new(full.x, 0.0), cell + full, cell + V2::new(0.0, full.y)] });
+                        continue;
+                    }
+
+                    for obj in &data.collision {
+                        if let Some(mut poly) = obj.collision_polygon(V2::new(0.0, 0.0)) {
+                            for v in &mut poly.vertices {
+                                if tile.flip_h {
+                                    v.x = tile_size.x - v.x;
+                                }
+                                if tile.flip_v {
+                                    v.y = tile_size.y - v.y;
+                                }
+                                *v += top_left;
+                            }
+                            res.push(poly);
+                        }
+                    }
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Tiles overlapping area, as x0, y0, x1, y1 with x1 and y1 exclusive, clamped to the map
+    pub fn tile_range(&self, area: &Bounds2D) -> (u32, u32, u32, u32) {
+        let (x0, y0) = self.world_to_tile(area.min);
+        let (x1, y1) = self.world_to_tile(area.max);
+
+        let clamp_x = |x: i32| x.clamp(0, self.width as i32) as u32;
+        let clamp_y = |y: i32| y.clamp(0, self.height as i32) as u32;
+
+        (clamp_x(x0), clamp_y(y0), clamp_x(x1 + 1), clamp_y(y1 + 1))
+    }
+}
+
+
+fn convert_properties(props: &[RawProperty]) -> Properties {
+    props.iter().map(|p| (p.name.clone(), p.value.clone())).collect()
+}
+
+
+fn convert_object(o: &RawObject) -> MapObject {
+    let points = |ps: &Vec::<RawPoint>| ps.iter().map(|p| V2::new(p.x, p.y)).collect();
+
+    let shape = if let Some(ps) = &o.polygon {
+        ObjectShape::Polygon(points(ps))
+    } else if let Some(ps) = &o.polyline {
+        ObjectShape::Polyline(points(ps))
+    } else if o.ellipse {
+        ObjectShape::Ellipse
+    } else if o.point {
+        ObjectShape::Point
+    } else {
+        ObjectShape::Rect
+    };
+
+    MapObject {
+        id: o.id,
+        name: o.name.clone(),
+        class: if o.class.is_empty() { o.kind.clone() } else { o.class.clone() },
+        pos: V2::new(o.x, o.y),
+        size: V2::new(o.width, o.height),
+        rotation: o.rotation,
+        visible: o.visible,
+        gid: o.gid,
+        shape,
+        properties: convert_properties(&o.properties),
+    }
+}
+
+
+fn convert_layer(l: &RawLayer, map: &RawMap, parent_offset: V2, parent_visible: bool, parent_opacity: f32, out: &mut Vec::<Layer>) -> Result<(), failure::Error> {
+    let offset = parent_offset + V2::new(l.offsetx, l.offsety);
+    let visible = parent_visible && l.visible;
+    let opacity = parent_opacity * l.opacity;
+
+    let data = match l.kind.as_str() {
+        "tilelayer" => LayerData::Tiles(decode_tiles(l, map)?),
+        "objectgroup" => LayerData::Objects(l.objects.iter().map(convert_object).collect()),
+        "group" => {
+            for child in &l.layers {
+                convert_layer(child, map, offset, visible, opacity, out)?;
+            }
+            return Ok(());
+        },
+        // image layers are skipped
+        _ => return Ok(()),
+    };
+
+    out.push(Layer {
+        name: l.name.clone(),
+        visible,
+        opacity,
+        offset,
+        data,
+        properties: convert_properties(&l.properties),
+    });
+
+    Ok(())
+}
+
+
+fn decode_tiles(l: &RawLayer, map: &RawMap) -> Result<Vec::<u32>, failure::Error> {
+    let gids : Vec::<u32> = match (&l.data, l.encoding.as_deref()) {
+        (Some(serde_json::Value::Array(values)), _) => {
+            values.iter().map(|v| v.as_u64().map(|g| g as u32)).collect::<Option<_>>()
+                .ok_or_else(|| failure::format_err!("Layer '{}' has tile data that is not numbers", l.name))?
+        },
+        (Some(serde_json::Value::String(s)), Some("csv")) => {
+            s.split(',').map(|v| v.trim().parse::<u32>()).collect::<Result<_,_>>()?
+        },
+        (_, Some(enc)) => bail!("Layer '{}' uses {} encoding, save the map with CSV layer format", l.name, enc),
+        _ => bail!("Layer '{}' has no tile data", l.name),
+    };
+
+    let expected = (map.width * map.height) as usize;
+    if gids.len() != expected {
+        bail!("Layer '{}' has {} tiles, expected {}", l.name, gids.len(), expected);
+    }
+
+    Ok(gids)
+}
+
+
+fn convert_tileset(raw: &RawTileset, dir: Option<&Path>) -> Result<Tileset, failure::Error> {
+    let (ts, ts_dir) = match &raw.source {
+        Some(source) => {
+            let Some(dir) = dir else {
+                bail!("Tileset '{}' is external, use TileMap::load", source);
+            };
+
+            let path = dir.join(source);
+            let json = std::fs::read_to_string(&path).map_err(|e| failure::format_err!("Could not read tileset {:?}: {}", path, e))?;
+            let mut ts : RawTileset = serde_json::from_str(&json)?;
+            ts.firstgid = raw.firstgid;
+            (ts, path.parent().map(|p| p.to_path_buf()))
+        },
+        None => (raw.clone(), dir.map(|d| d.to_path_buf())),
+    };
+
+    let mut tiles = HashMap::<u32, TileData>::default();
+    for t in &ts.tiles {
+        tiles.insert(t.id, TileData {
+            class: if t.class.is_empty() { t.kind.clone() } else { t.class.clone() },
+            animation: t.animation.iter().map(|f| AnimationFrame { tile_id: f.tileid, duration_ms: f.duration }).collect(),
+            collision: t.objectgroup.as_ref().map(|g| g.objects.iter().map(convert_object).collect()).unwrap_or_default(),
+            properties: convert_properties(&t.properties),
+        });
+    }
+
+    let image = ts.image.as_ref().map(|img| match &ts_dir {
+        Some(d) => d.join(img),
+        None => PathBuf::from(img),
+    });
+
+    Ok(Tileset {
+        name: ts.name.clone(),
+        first_gid: ts.firstgid,
+        image,
+        image_width: ts.imagewidth,
+        image_height: ts.imageheight,
+        tile_width: ts.tilewidth,
+        tile_height: ts.tileheight,
+        tile_count: ts.tilecount,
+        columns: ts.columns,
+        margin: ts.margin,
+        spacing: ts.spacing,
+        tiles,
+        properties: convert_properties(&ts.properties),
+    })
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 4x3 map, 16x16 tiles. Tile 1 (gid 2) is solid with a half tile shape, tile 2 (gid 3) animates 2 -> 3
+    pub(crate) const MAP: &str = r#"{
+        "width": 4, "height": 3, "tilewidth": 16, "tileheight": 16,
+        "orientation": "orthogonal", "infinite": false,
+        "properties": [{"name": "music", "type": "string", "value": "cave.ogg"}],
+        "tilesets": [
+            {"firstgid": 1, "name": "terrain", "image": "terrain.png", "imagewidth": 66, "imageheight": 34,
+             "tilewidth": 16, "tileheight": 16, "tilecount": 8, "columns": 4, "margin": 1, "spacing": 0,
+             "tiles": [
+                {"id": 1, "objectgroup": {"type": "objectgroup", "objects": [{"id": 1, "x": 0, "y": 8, "width": 16, "height": 8}]}},
+                {"id": 2, "animation": [{"tileid": 2, "duration": 100}, {"tileid": 3, "duration": 200}]},
+                {"id": 4, "properties": [{"name": "solid", "type": "bool", "value": true}]}
+             ]},
+            {"firstgid": 9, "name": "props", "image": "props.png", "imagewidth": 32, "imageheight": 32,
+             "tilewidth": 32, "tileheight": 32, "tilecount": 1, "columns": 1}
+        ],
+        "layers": [
+            {"type": "tilelayer", "name": "ground", "width": 4, "height": 3, "data": [
+                1, 1, 1, 1,
+                1, 3, 0, 1,
+                2, 2, 5, 2147483650]},
+            {"type": "group", "name": "top", "offsetx": 4, "layers": [
+                {"type": "tilelayer", "name": "decor", "width": 4, "height": 3, "offsety": 2, "visible": false,
+                 "encoding": "csv", "data": "0,0,0,0,0,0,0,0,0,9,0,0"},
+                {"type": "objectgroup", "name": "spawns", "objects": [
+                    {"id": 3, "name": "player", "type": "spawn", "x": 20, "y": 30, "point": true},
+                    {"id": 4, "name": "zone", "class": "trigger", "x": 0, "y": 0, "width": 10, "height": 20, "rotation": 90,
+                     "properties": [{"name": "target", "type": "string", "value": "level2"}]},
+                    {"id": 5, "x": 10, "y": 10, "polygon": [{"x": 0, "y": 0}, {"x": 5, "y": 0}, {"x": 0, "y": 5}]}
+                ]}
+            ]},
+            {"type": "imagelayer", "name": "bg"}
+        ]
+    }"#;
+
+    fn close(a: V2, b: V2) -> bool {
+        (a - b).magnitude() < 0.001
+    }
+
+    #[test]
+    fn parse() {
+        let map = TileMap::parse(MAP).unwrap();
+
+        assert_eq!((map.width, map.height, map.tile_width, map.tile_height), (4, 3, 16, 16));
+        assert_eq!(map.properties["music"], "cave.ogg");
+        assert_eq!(map.tilesets.len(), 2);
+
+        // group flattened, image layer skipped
+        let names : Vec::<&str> = map.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["ground", "decor", "spawns"]);
+
+        let decor = map.layer("decor").unwrap();
+        assert!(!decor.visible);
+        assert!(close(decor.offset, V2::new(4.0, 2.0)));
+        assert!(close(map.layer("spawns").unwrap().offset, V2::new(4.0, 0.0)));
+
+        // tiles
+        assert_eq!(map.tile(0, 1, 1), Some(TileRef { tileset: 0, id: 2, flip_h: false, flip_v: false, flip_d: false }));
+        assert_eq!(map.tile(0, 2, 1), None);
+        assert_eq!(map.tile(0, 10, 1), None);
+        let flipped = map.tile(0, 3, 2).unwrap();
+        assert_eq!((flipped.tileset, flipped.id, flipped.flip_h), (0, 1, true));
+        assert_eq!(map.tile(1, 1, 2), Some(TileRef { tileset: 1, id: 0, flip_h: false, flip_v: false, flip_d: false }));
+
+        // objects
+        let objects = map.layer("spawns").unwrap().objects().unwrap();
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[0].class, "spawn");
+        assert_eq!(objects[0].shape, ObjectShape::Point);
+        assert_eq!(objects[1].class, "trigger");
+        assert_eq!(objects[1].properties["target"], "level2");
+        assert_eq!(objects[1].shape, ObjectShape::Rect);
+        assert!(matches!(objects[2].shape, ObjectShape::Polygon(ref ps) if ps.len() == 3));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(TileMap::parse("{").is_err());
+
+        let infinite = MAP.replace(r#""infinite": false"#, r#""infinite": true"#);
+        assert!(TileMap::parse(&infinite).is_err());
+
+        let base64 = MAP.replace(r#""encoding": "csv""#, r#""encoding": "base64""#);
+        assert!(TileMap::parse(&base64).is_err());
+
+        let external = MAP.replace(r#"{"firstgid": 9, "name": "props""#, r#"{"firstgid": 9, "source": "props.tsj", "name": "props""#);
+        assert!(TileMap::parse(&external).is_err());
+
+        let short = MAP.replace("2, 2, 5, 2147483650", "2, 2, 5");
+        assert!(TileMap::parse(&short).is_err());
+    }
+
+    #[test]
+    fn tileset_uv_and_animation() {
+        let map = TileMap::parse(MAP).unwrap();
+        let ts = &map.tilesets[0];
+
+        assert_eq!(ts.tile_rect(0), (1, 1, 16, 16));
+        assert_eq!(ts.tile_rect(5), (17, 17, 16, 16));
+
+        let [l, r, t, b] = ts.tile_uv(5);
+        assert!((l - 17.0 / 66.0).abs() < 0.0001);
+        assert!((r - 33.0 / 66.0).abs() < 0.0001);
+        assert!((t - (1.0 - 17.0 / 34.0)).abs() < 0.0001);
+        assert!((b - (1.0 - 33.0 / 34.0)).abs() < 0.0001);
+
+        assert!(ts.is_animated(2));
+        assert!(!ts.is_animated(1));
+        assert_eq!(ts.animated_id(2, 0), 2);
+        assert_eq!(ts.animated_id(2, 99), 2);
+        assert_eq!(ts.animated_id(2, 100), 3);
+        assert_eq!(ts.animated_id(2, 299), 3);
+        assert_eq!(ts.animated_id(2, 300), 2);
+        assert_eq!(ts.animated_id(1, 150), 1);
+    }
+
+    #[test]
+    fn collision() {
+        let map = TileMap::parse(MAP).unwrap();
+
+        assert!(map.is_solid(0, 0, 2));
+        assert!(map.is_solid(0, 2, 2));
+        assert!(!map.is_solid(0, 0, 0));
+
+        // gid 2 three times, one flipped, and gid 5 with solid property
+        let shapes = map.collision_shapes(None);
+        assert_eq!(shapes.len(), 4);
+
+        // bottom half of tile 0,2
+        let first = &shapes[0].vertices;
+        assert!(close(first[0], V2::new(0.0, 40.0)));
+        assert!(close(first[2], V2::new(16.0, 48.0)));
+
+        // flipped horizontally, same box with x flipped inside the tile
+        let flipped = &shapes[3].vertices;
+        assert!(close(flipped[0], V2::new(64.0, 40.0)));
+        assert!(close(flipped[1], V2::new(48.0, 40.0)));
+
+        // whole cell from property
+        assert!(close(shapes[2].vertices[0], V2::new(32.0, 32.0)));
+        assert!(close(shapes[2].vertices[2], V2::new(48.0, 48.0)));
+
+        let area = Bounds2D::new(V2::new(0.0, 33.0), V2::new(10.0, 40.0));
+        assert_eq!(map.collision_shapes(Some(&area)).len(), 1);
+
+        // collides with gjk
+        use crate::collision2d::gjk::gjk_intersection;
+        let player = Polygon { vertices: vec![V2::new(2.0, 38.0), V2::new(6.0, 38.0), V2::new(6.0, 42.0), V2::new(2.0, 42.0)] };
+        assert!(gjk_intersection(&player, &shapes[0]));
+        let above = Polygon { vertices: vec![V2::new(2.0, 33.0), V2::new(6.0, 33.0), V2::new(6.0, 37.0), V2::new(2.0, 37.0)] };
+        assert!(!gjk_intersection(&above, &shapes[0]));
+    }
+
+    #[test]
+    fn object_polygon() {
+        let map = TileMap::parse(MAP).unwrap();
+        let objects = map.layer("spawns").unwrap().objects().unwrap();
+
+        assert!(objects[0].collision_polygon(V2::new(0.0, 0.0)).is_none());
+
+        // 10x20 rect rotated 90 degrees clockwise around its top left
+        let rect = objects[1].collision_polygon(V2::new(4.0, 0.0)).unwrap();
+        assert!(close(rect.vertices[1], V2::new(4.0, 10.0)));
+        assert!(close(rect.vertices[3], V2::new(-16.0, 0.0)));
+
+        let poly = objects[2].collision_polygon(V2::new(0.0, 0.0)).unwrap();
+        assert!(close(poly.vertices[2], V2::new(10.0, 15.0)));
+    }
+}
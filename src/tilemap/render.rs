@@ -0,0 +1,192 @@
+//! Draws tile layers of a `TileMap` with `Drawer2D`, one draw call per tileset in each visible chunk.
+//! Chunk meshes are cached, chunks with animated tiles re-upload their vertices when an animation frame changes.
+use std::collections::HashMap;
+use crate::buffer;
+use crate::gl;
+use crate::texture::{self, TextureId};
+use crate::shader::{BaseShader, Shader};
+use crate::imode_gui::drawer2d::Drawer2D;
+use crate::camera::camera_2d::Bounds2D;
+use super::TileMap;
+use super::chunks::{self, ChunkCoord, ChunkBatch, DEFAULT_CHUNK_SIZE, VERTEX_SIZE};
+
+
+struct GpuBatch {
+    tileset: usize,
+    vao: buffer::VertexArray,
+    vbo: buffer::ArrayBuffer,
+    _ebo: buffer::ElementArrayBuffer,
+    elements: i32,
+}
+
+impl GpuBatch {
+    /// Animated batches get a dynamic vertex buffer, see `update_vertices`
+    fn new(gl: &gl::Gl, batch: &ChunkBatch, animated: bool) -> Self {
+        let vao = buffer::VertexArray::new(gl);
+        let vbo = buffer::ArrayBuffer::new(gl);
+        let ebo = buffer::ElementArrayBuffer::new(gl);
+
+        let stride = (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint;
+
+        unsafe {
+            vao.bind();
+
+            vbo.bind();
+            if animated {
+                vbo.dynamic_draw_data(&batch.vertices);
+            } else {
+                vbo.static_draw_data(&batch.vertices);
+            }
+
+            ebo.bind();
+            ebo.static_draw_data(&batch.indices);
+
+            // pos
+            gl.VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl.EnableVertexAttribArray(0);
+
+            // uv
+            gl.VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (3 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid);
+            gl.EnableVertexAttribArray(1);
+
+            vao.unbind();
+        }
+
+        Self { tileset: batch.tileset, vao, vbo, _ebo: ebo, elements: batch.indices.len() as i32 }
+    }
+
+    /// Animation frames only change uvs, so the tiles and indices are the same
+    fn update_vertices(&self, batch: &ChunkBatch) {
+        self.vbo.bind();
+        self.vbo.dynamic_draw_data(&batch.vertices);
+        self.vbo.unbind();
+    }
+}
+
+
+struct GpuChunk {
+    batches: Vec::<GpuBatch>,
+    animated: bool,
+    /// Time the vertices were built at
+    time_ms: u64,
+}
+
+
+pub struct TileMapRenderer {
+    gl: gl::Gl,
+    shader: BaseShader,
+    /// Per tileset, None for tilesets without an image
+    textures: Vec::<Option<TextureId>>,
+    /// Keyed by layer index and chunk
+    chunks: HashMap::<(usize, ChunkCoord), GpuChunk>,
+    chunk_size: u32,
+    /// Draw calls in the last render
+    pub draw_calls: usize,
+}
+
+impl TileMapRenderer {
+
+    /// Load tileset images as textures, with nearest filtering for pixel art
+    pub fn new(gl: &gl::Gl, map: &TileMap) -> Result<Self, failure::Error> {
+        let mut textures = vec![];
+        for ts in &map.tilesets {
+            let tex = match &ts.image {
+                Some(path) => {
+                    let img = image::open(path).map_err(|e| failure::format_err!("Could not load tileset image {:?}: {}", path, e))?;
+                    Some(texture::gen_texture_rgba_nearest(gl, &img.into_rgba8()))
+                },
+                None => None,
+            };
+            textures.push(tex);
+        }
+
+        Ok(Self {
+            gl: gl.clone(),
+            shader: Self::create_shader(gl)?,
+            textures,
+            chunks: Default::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            draw_calls: 0,
+        })
+    }
+
+    pub fn create_shader(gl: &gl::Gl) -> Result<BaseShader, failure::Error> {
+        let vert_source = include_str!("../../assets/shaders/objects/tile.vert");
+        let frag_source = include_str!("../../assets/shaders/objects/tile.frag");
+        BaseShader::new(gl, vert_source, frag_source)
+    }
+
+    /// Tiles per chunk side. Clears the cache
+    pub fn set_chunk_size(&mut self, size: u32) {
+        self.chunk_size = size.max(1);
+        self.invalidate();
+    }
+
+    /// Rebuild all chunks, fx after loading a new map
+    pub fn invalidate(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Rebuild the chunk with tile x, y after fx `TileMap::set_gid`
+    pub fn invalidate_tile(&mut self, layer: usize, x: u32, y: u32) {
+        self.chunks.remove(&(layer, ChunkCoord { x: x / self.chunk_size, y: y / self.chunk_size }));
+    }
+
+    /// Draw visible tile layers in order. View is the visible area in map pixels, fx `Camera2D::visible_bounds`
+    /// with the camera pushed on the drawer. Time in milliseconds is used for animated tiles
+    pub fn render(&mut self, drawer: &Drawer2D, map: &TileMap, view: &Bounds2D, time_ms: u64) {
+        self.draw_calls = 0;
+
+        self.shader.set_used();
+        self.shader.set_mat4(&self.gl, "transform", drawer.screen_transform());
+        self.shader.set_i32(&self.gl, "text_map", 0);
+
+        unsafe {
+            self.gl.Enable(gl::BLEND);
+            self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            self.gl.ActiveTexture(gl::TEXTURE0);
+        }
+
+        for (layer_idx, layer) in map.layers.iter().enumerate() {
+            if !layer.visible || layer.gids().is_none() {
+                continue;
+            }
+
+            self.shader.set_f32(&self.gl, "opacity", layer.opacity);
+
+            for coord in chunks::visible_chunks(map, layer.offset, view, self.chunk_size) {
+                let key = (layer_idx, coord);
+
+                match self.chunks.get_mut(&key) {
+                    Some(chunk) => {
+                        if chunk.animated && chunks::frame_changed(map, layer_idx, coord, self.chunk_size, chunk.time_ms, time_ms) {
+                            let mesh = chunks::build_chunk(map, layer_idx, coord, self.chunk_size, time_ms);
+                            for (gpu, batch) in chunk.batches.iter().zip(&mesh.batches) {
+                                gpu.update_vertices(batch);
+                            }
+                            chunk.time_ms = time_ms;
+                        }
+                    },
+                    None => {
+                        let mesh = chunks::build_chunk(map, layer_idx, coord, self.chunk_size, time_ms);
+                        let batches = mesh.batches.iter().map(|b| GpuBatch::new(&self.gl, b, mesh.animated)).collect();
+                        self.chunks.insert(key, GpuChunk { batches, animated: mesh.animated, time_ms });
+                    }
+                }
+
+                let chunk = &self.chunks[&key];
+                for batch in &chunk.batches {
+                    let Some(Some(tex)) = self.textures.get(batch.tileset) else { continue; };
+
+                    texture::set_texture(&self.gl, *tex);
+                    batch.vao.bind();
+                    unsafe {
+                        self.gl.DrawElements(gl::TRIANGLES, batch.elements, gl::UNSIGNED_INT, std::ptr::null());
+                    }
+                    batch.vao.unbind();
+                    self.draw_calls += 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Serde types for the Tiled json format, only the parts we use. Converted to `TileMap` types in `TileMap::parse`.
+//! See <https://doc.mapeditor.org/en/stable/reference/json-map-format/>
+use serde::Deserialize;
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawMap {
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    #[serde(default)]
+    pub orientation: String,
+    #[serde(default)]
+    pub infinite: bool,
+    #[serde(default)]
+    pub layers: Vec::<RawLayer>,
+    #[serde(default)]
+    pub tilesets: Vec::<RawTileset>,
+    #[serde(default)]
+    pub properties: Vec::<RawProperty>,
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawLayer {
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub visible: bool,
+    #[serde(default = "default_one")]
+    pub opacity: f32,
+    #[serde(default)]
+    pub offsetx: f32,
+    #[serde(default)]
+    pub offsety: f32,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Array of gids, or a string when encoded
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub encoding: Option<String>,
+    #[serde(default)]
+    pub compression: Option<String>,
+    #[serde(default)]
+    pub objects: Vec::<RawObject>,
+    /// Children of group layers
+    #[serde(default)]
+    pub layers: Vec::<RawLayer>,
+    #[serde(default)]
+    pub properties: Vec::<RawProperty>,
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawObject {
+    #[serde(default)]
+    pub id: u32,
+    #[serde(default)]
+    pub name: String,
+    /// Called class since Tiled 1.9
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub class: String,
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub y: f32,
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub height: f32,
+    /// Degrees clockwise
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_true")]
+    pub visible: bool,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    #[serde(default)]
+    pub ellipse: bool,
+    #[serde(default)]
+    pub point: bool,
+    #[serde(default)]
+    pub polygon: Option<Vec::<RawPoint>>,
+    #[serde(default)]
+    pub polyline: Option<Vec::<RawPoint>>,
+    #[serde(default)]
+    pub properties: Vec::<RawProperty>,
+}
+
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RawPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawProperty {
+    pub name: String,
+    pub value: serde_json::Value,
+}
+
+
+/// Embedded tileset, or a reference to a tileset file with only firstgid and source set
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawTileset {
+    #[serde(default)]
+    pub firstgid: u32,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub imagewidth: u32,
+    #[serde(default)]
+    pub imageheight: u32,
+    #[serde(default)]
+    pub tilewidth: u32,
+    #[serde(default)]
+    pub tileheight: u32,
+    #[serde(default)]
+    pub tilecount: u32,
+    #[serde(default)]
+    pub columns: u32,
+    #[serde(default)]
+    pub margin: u32,
+    #[serde(default)]
+    pub spacing: u32,
+    #[serde(default)]
+    pub tiles: Vec::<RawTile>,
+    #[serde(default)]
+    pub properties: Vec::<RawProperty>,
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawTile {
+    pub id: u32,
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub class: String,
+    #[serde(default)]
+    pub animation: Vec::<RawFrame>,
+    #[serde(default)]
+    pub objectgroup: Option<RawLayer>,
+    #[serde(default)]
+    pub properties: Vec::<RawProperty>,
+}
+
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RawFrame {
+    pub tileid: u32,
+    /// Milliseconds
+    pub duration: u32,
+}
+
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_one() -> f32 {
+    1.0
+}
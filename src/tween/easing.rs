@@ -0,0 +1,142 @@
+use std::f32::consts::PI;
+use serde::{Serialize, Deserialize};
+
+/// Easing curves, maps t in \[0;1\] to eased t. Elastic and back overshoot outside \[0;1\].
+/// See <https://easings.net/> for what the curves look like
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    /// Css like cubic-bezier with control points (x1, y1) and (x2, y2). Start is (0,0) and end (1,1)
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Easing {
+
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        use Easing::*;
+        match *self {
+            Linear => t,
+            QuadIn => t * t,
+            QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0 },
+            CubicIn => t * t * t,
+            CubicOut => 1.0 - (1.0 - t).powi(3),
+            CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 },
+            ElasticIn => elastic_in(t),
+            ElasticOut => 1.0 - elastic_in(1.0 - t),
+            ElasticInOut => in_out(t, elastic_in),
+            BackIn => back_in(t),
+            BackOut => 1.0 - back_in(1.0 - t),
+            BackInOut => in_out(t, back_in),
+            BounceIn => 1.0 - bounce_out(1.0 - t),
+            BounceOut => bounce_out(t),
+            BounceInOut => in_out(t, |t| 1.0 - bounce_out(1.0 - t)),
+            CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+fn in_out(t: f32, ease_in: fn(f32) -> f32) -> f32 {
+    if t < 0.5 {
+        ease_in(2.0 * t) / 2.0
+    } else {
+        1.0 - ease_in(2.0 - 2.0 * t) / 2.0
+    }
+}
+
+fn elastic_in(t: f32) -> f32 {
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    let c4 = (2.0 * PI) / 3.0;
+    -(2.0_f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * c4).sin()
+}
+
+fn back_in(t: f32) -> f32 {
+    let c1 = 1.70158;
+    let c3 = c1 + 1.0;
+    c3 * t * t * t - c1 * t * t
+}
+
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+
+    if t < 1.0 / d1 {
+        n1 * t * t
+    } else if t < 2.0 / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+
+fn bezier(p1: f32, p2: f32, s: f32) -> f32 {
+    // with p0 = 0 and p3 = 1
+    let u = 1.0 - s;
+    3.0 * u * u * s * p1 + 3.0 * u * s * s * p2 + s * s * s
+}
+
+fn bezier_derivative(p1: f32, p2: f32, s: f32) -> f32 {
+    let u = 1.0 - s;
+    3.0 * u * u * p1 + 6.0 * u * s * (p2 - p1) + 3.0 * s * s * (1.0 - p2)
+}
+
+/// Find the curve parameter where x = t, then return y at that parameter.
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, t: f32) -> f32 {
+    // newton first, it converges fast for most curves
+    let mut s = t;
+    for _ in 0..8 {
+        let x = bezier(x1, x2, s) - t;
+        if x.abs() < 1e-6 {
+            return bezier(y1, y2, s);
+        }
+        let d = bezier_derivative(x1, x2, s);
+        if d.abs() < 1e-6 {
+            break;
+        }
+        s -= x / d;
+    }
+
+    // fall back to bisection, x is monotonic when x1 and x2 are in [0;1]
+    let mut lo = 0.0;
+    let mut hi = 1.0;
+    s = t;
+    for _ in 0..32 {
+        let x = bezier(x1, x2, s);
+        if (x - t).abs() < 1e-6 {
+            break;
+        }
+        if x < t {
+            lo = s;
+        } else {
+            hi = s;
+        }
+        s = (lo + hi) / 2.0;
+    }
+
+    bezier(y1, y2, s)
+}
@@ -0,0 +1,563 @@
+//! Tweens between two values of any [Animatable] type, using an [Easing] curve.
+//! Tweens can be combined into a [Sequence] where one plays after the other,
+//! or a [Parallel] group where all play at the same time.
+//!
+//! ```
+//! use gl_lib::tween::{Tween, Easing, Repeat};
+//! use gl_lib::typedef::V2;
+//!
+//! let mut tween = Tween::new(V2::new(0.0, 0.0), V2::new(100.0, 0.0), 0.5)
+//!     .easing(Easing::BackOut)
+//!     .repeat(Repeat::Forever)
+//!     .yoyo(true);
+//!
+//! let pos = tween.update(1.0 / 60.0);
+//! ```
+
+use crate::general_animation::Animatable;
+use crate::color::Color;
+use crate::typedef::*;
+use crate::imode_gui::Rect;
+use crate::na;
+
+mod easing;
+pub use self::easing::*;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Repeat {
+    #[default]
+    Once,
+    /// Play this many times in total
+    Times(u32),
+    Forever,
+}
+
+
+/// Something that plays over time, like a tween or a group of tweens.
+pub trait Playable {
+    /// Advance by dt. Returns the part of dt that was not used, because we finished.
+    fn advance(&mut self, dt: f32) -> f32;
+
+    fn finished(&self) -> bool;
+
+    fn reset(&mut self);
+}
+
+impl Playable for Box<dyn Playable> {
+    fn advance(&mut self, dt: f32) -> f32 {
+        (**self).advance(dt)
+    }
+
+    fn finished(&self) -> bool {
+        (**self).finished()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Tween<T: Animatable> {
+    pub from: T,
+    pub to: T,
+    pub duration: f32,
+    pub easing: Easing,
+    pub repeat: Repeat,
+    /// Play backwards every other loop, a loop back and forth counts as 2 in repeat
+    pub yoyo: bool,
+    /// Seconds to wait before starting
+    pub delay: f32,
+    elapsed: f32,
+    loops: u32,
+    value: T,
+}
+
+impl<T: Animatable> Tween<T> {
+
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            easing: Easing::Linear,
+            repeat: Repeat::Once,
+            yoyo: false,
+            delay: 0.0,
+            elapsed: 0.0,
+            loops: 0,
+            value: from,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn yoyo(mut self, yoyo: bool) -> Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    pub fn delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Advance by dt and return the new value
+    pub fn update(&mut self, dt: f32) -> T {
+        self.advance(dt);
+        self.value
+    }
+
+    pub fn value(&self) -> T {
+        self.value
+    }
+
+    /// Progress of the current loop in \[0;1\], before easing
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        f32::min(1.0, f32::max(0.0, self.elapsed - self.delay) / self.duration)
+    }
+
+    /// Start over from `value()` towards new target, fx when a widget changes state mid tween
+    pub fn retarget(&mut self, to: T) {
+        self.from = self.value;
+        self.to = to;
+        self.reset();
+        self.value = self.from;
+    }
+
+    fn total_loops(&self) -> Option<u32> {
+        match self.repeat {
+            Repeat::Once => Some(1),
+            Repeat::Times(n) => Some(u32::max(n, 1)),
+            Repeat::Forever => None
+        }
+    }
+
+    fn update_value(&mut self, loop_index: u32) {
+        let mut t = self.progress();
+        if self.yoyo && loop_index % 2 == 1 {
+            t = 1.0 - t;
+        }
+        self.value = T::lerp(&self.from, &self.to, self.easing.apply(t));
+    }
+}
+
+
+impl<T: Animatable> Playable for Tween<T> {
+
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.finished() {
+            return dt;
+        }
+
+        self.elapsed += dt;
+
+        // loop until we have used all of dt, or we are done. A large dt can skip several loops
+        loop {
+            let loop_end = self.delay + self.duration;
+            if self.elapsed < loop_end {
+                break;
+            }
+
+            let left = self.elapsed - loop_end;
+            self.loops += 1;
+
+            if let Some(total) = self.total_loops() {
+                if self.loops >= total {
+                    // keep value at the end of the last loop
+                    self.elapsed = loop_end;
+                    self.update_value(self.loops - 1);
+                    return left;
+                }
+            }
+
+            if self.duration <= 0.0 {
+                // forever with no duration, nothing more to do
+                self.elapsed = loop_end;
+                break;
+            }
+
+            self.elapsed = self.delay + left;
+        }
+
+        self.update_value(self.loops);
+        0.0
+    }
+
+    fn finished(&self) -> bool {
+        match self.total_loops() {
+            Some(total) => self.loops >= total,
+            None => false
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.loops = 0;
+        self.value = self.from;
+    }
+}
+
+
+/// Play items one after the other. Left over time from one item is passed to the next.
+#[derive(Debug, Clone, Default)]
+pub struct Sequence<P: Playable> {
+    pub items: Vec::<P>,
+    pub repeat: Repeat,
+    current: usize,
+    loops: u32,
+}
+
+impl<P: Playable> Sequence<P> {
+
+    pub fn new(items: Vec::<P>) -> Self {
+        Self {
+            items,
+            repeat: Repeat::Once,
+            current: 0,
+            loops: 0
+        }
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn then(mut self, item: P) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    pub fn current_index(&self) -> usize {
+        usize::min(self.current, self.items.len().saturating_sub(1))
+    }
+
+    pub fn current(&self) -> Option<&P> {
+        self.items.get(self.current_index())
+    }
+}
+
+impl<T: Animatable> Sequence<Tween<T>> {
+    pub fn update(&mut self, dt: f32) -> Option<T> {
+        self.advance(dt);
+        self.value()
+    }
+
+    pub fn value(&self) -> Option<T> {
+        self.current().map(|t| t.value())
+    }
+}
+
+impl<P: Playable> Playable for Sequence<P> {
+
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.items.is_empty() {
+            return dt;
+        }
+
+        let mut dt = dt;
+        // dt at the start of the current pass, to detect passes that use no time
+        let mut pass_dt = dt;
+        while !self.finished() {
+            dt = self.items[self.current].advance(dt);
+
+            if !self.items[self.current].finished() {
+                return 0.0;
+            }
+
+            if self.current + 1 < self.items.len() {
+                self.current += 1;
+                continue;
+            }
+
+            // end of sequence
+            self.loops += 1;
+            let done = match self.repeat {
+                Repeat::Once => true,
+                Repeat::Times(n) => self.loops >= u32::max(n, 1),
+                Repeat::Forever => false
+            };
+
+            if done {
+                self.current = self.items.len();
+                return dt;
+            }
+
+            self.current = 0;
+            for item in &mut self.items {
+                item.reset();
+            }
+
+            // a pass using no time, fx only zero duration tweens, would repeat forever
+            if dt <= 0.0 || dt >= pass_dt {
+                return 0.0;
+            }
+            pass_dt = dt;
+        }
+
+        dt
+    }
+
+    fn finished(&self) -> bool {
+        self.current >= self.items.len()
+    }
+
+    fn reset(&mut self) {
+        self.current = 0;
+        self.loops = 0;
+        for item in &mut self.items {
+            item.reset();
+        }
+    }
+}
+
+
+/// Play all items at the same time. Finished when all items are finished.
+#[derive(Debug, Clone, Default)]
+pub struct Parallel<P: Playable> {
+    pub items: Vec::<P>,
+}
+
+impl<P: Playable> Parallel<P> {
+    pub fn new(items: Vec::<P>) -> Self {
+        Self { items }
+    }
+
+    pub fn with(mut self, item: P) -> Self {
+        self.items.push(item);
+        self
+    }
+}
+
+impl<P: Playable> Playable for Parallel<P> {
+
+    fn advance(&mut self, dt: f32) -> f32 {
+        let mut left = dt;
+        for item in &mut self.items {
+            left = f32::min(left, item.advance(dt));
+        }
+        left
+    }
+
+    fn finished(&self) -> bool {
+        self.items.iter().all(|i| i.finished())
+    }
+
+    fn reset(&mut self) {
+        for item in &mut self.items {
+            item.reset();
+        }
+    }
+}
+
+
+impl Animatable for f32 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+impl Animatable for V2 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+}
+
+impl Animatable for V3 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+}
+
+impl Animatable for V4 {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+}
+
+impl Animatable for na::UnitQuaternion::<f32> {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        a.slerp(b, t)
+    }
+}
+
+impl Animatable for Color {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        Color::lerp(*a, *b, t)
+    }
+}
+
+impl Animatable for Rect {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        let l = |x: i32, y: i32| (x as f32 + (y - x) as f32 * t).round() as i32;
+        Rect {
+            x: l(a.x, b.x),
+            y: l(a.y, b.y),
+            w: l(a.w, b.w),
+            h: l(a.h, b.h),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    #[test]
+    fn easing_end_points() {
+        use Easing::*;
+        let all = [Linear, QuadIn, QuadOut, QuadInOut, CubicIn, CubicOut, CubicInOut,
+                   ElasticIn, ElasticOut, ElasticInOut, BackIn, BackOut, BackInOut,
+                   BounceIn, BounceOut, BounceInOut, CubicBezier(0.25, 0.1, 0.25, 1.0)];
+
+        for e in all {
+            assert!(e.apply(0.0).abs() < EPS, "{:?} at 0 was {}", e, e.apply(0.0));
+            assert!((e.apply(1.0) - 1.0).abs() < EPS, "{:?} at 1 was {}", e, e.apply(1.0));
+        }
+    }
+
+    #[test]
+    fn easing_shapes() {
+        assert!((Easing::QuadIn.apply(0.5) - 0.25).abs() < EPS);
+        assert!((Easing::CubicOut.apply(0.5) - 0.875).abs() < EPS);
+        // back overshoots below 0 at the start
+        assert!(Easing::BackIn.apply(0.2) < 0.0);
+        assert!(Easing::ElasticOut.apply(0.1) > 1.0);
+        assert!((Easing::BounceOut.apply(1.0 / 2.75) - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn cubic_bezier_linear() {
+        let e = Easing::CubicBezier(1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+        for i in 0..=10 {
+            let t = i as f32 / 10.0;
+            assert!((e.apply(t) - t).abs() < 1e-3, "t = {}, got {}", t, e.apply(t));
+        }
+
+        // css ease-in-out is symmetric
+        let e = Easing::CubicBezier(0.42, 0.0, 0.58, 1.0);
+        assert!((e.apply(0.5) - 0.5).abs() < 1e-3);
+        assert!((e.apply(0.25) + e.apply(0.75) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tween_once() {
+        let mut t = Tween::new(0.0, 10.0, 1.0);
+        assert_eq!(t.update(0.5), 5.0);
+        assert!(!t.finished());
+
+        let left = t.advance(0.75);
+        assert!((left - 0.25).abs() < EPS);
+        assert_eq!(t.value(), 10.0);
+        assert!(t.finished());
+    }
+
+    #[test]
+    fn tween_delay() {
+        let mut t = Tween::new(0.0, 10.0, 1.0).delay(0.5);
+        assert_eq!(t.update(0.5), 0.0);
+        assert_eq!(t.update(0.5), 5.0);
+    }
+
+    #[test]
+    fn tween_yoyo_repeat() {
+        let mut t = Tween::new(0.0, 10.0, 1.0).repeat(Repeat::Times(2)).yoyo(true);
+
+        assert!((t.update(0.5) - 5.0).abs() < EPS);
+        // into second loop, going back
+        assert!((t.update(0.75) - 7.5).abs() < EPS);
+        assert!((t.update(0.5) - 2.5).abs() < EPS);
+        t.update(1.0);
+        assert!(t.finished());
+        assert!(t.value().abs() < EPS);
+    }
+
+    #[test]
+    fn tween_forever_large_dt() {
+        let mut t = Tween::new(0.0, 10.0, 1.0).repeat(Repeat::Forever);
+        assert!((t.update(3.25) - 2.5).abs() < EPS);
+        assert!(!t.finished());
+    }
+
+    #[test]
+    fn sequence_carries_left_over_time() {
+        let mut seq = Sequence::new(vec![Tween::new(0.0, 1.0, 1.0), Tween::new(1.0, 3.0, 1.0)]);
+
+        assert_eq!(seq.update(1.5), Some(2.0));
+        assert_eq!(seq.current_index(), 1);
+
+        let left = seq.advance(1.0);
+        assert!((left - 0.5).abs() < EPS);
+        assert!(seq.finished());
+        assert_eq!(seq.value(), Some(3.0));
+    }
+
+    #[test]
+    fn sequence_repeat() {
+        let mut seq = Sequence::new(vec![Tween::new(0.0, 1.0, 1.0), Tween::new(1.0, 0.0, 1.0)]).repeat(Repeat::Forever);
+        assert!((seq.update(2.5).unwrap() - 0.5).abs() < EPS);
+        assert_eq!(seq.current_index(), 0);
+        assert!(!seq.finished());
+    }
+
+    #[test]
+    fn sequence_forever_zero_duration() {
+        // used to loop forever, since no pass uses any time
+        let mut seq = Sequence::new(vec![Tween::new(0.0, 1.0, 0.0)]).repeat(Repeat::Forever);
+        assert_eq!(seq.advance(1.0), 0.0);
+        assert_eq!(seq.current_index(), 0);
+        assert!(!seq.finished());
+    }
+
+    #[test]
+    fn parallel_finishes_with_longest() {
+        let mut par = Parallel::new(vec![Tween::new(0.0, 1.0, 1.0), Tween::new(0.0, 1.0, 2.0)]);
+        assert!(par.advance(1.5) == 0.0);
+        assert!(par.items[0].finished());
+        assert!(!par.finished());
+        let left = par.advance(1.0);
+        assert!((left - 0.5).abs() < EPS);
+        assert!(par.finished());
+    }
+
+    #[test]
+    fn nested_boxed() {
+        let a: Box<dyn Playable> = Box::new(Tween::new(V2::new(0.0, 0.0), V2::new(1.0, 1.0), 1.0));
+        let b: Box<dyn Playable> = Box::new(Parallel::new(vec![Tween::new(Color::black(), Color::white(), 1.0)]));
+        let mut seq = Sequence::new(vec![a, b]);
+        seq.advance(1.5);
+        assert!(!seq.finished());
+        seq.advance(0.5);
+        assert!(seq.finished());
+    }
+
+    #[test]
+    fn rect_and_quaternion() {
+        let a = Rect { x: 0, y: 0, w: 10, h: 10 };
+        let b = Rect { x: 10, y: 20, w: 20, h: 10 };
+        let r = Rect::lerp(&a, &b, 0.5);
+        assert_eq!((r.x, r.y, r.w, r.h), (5, 10, 15, 10));
+
+        let q0 = na::UnitQuaternion::identity();
+        let q1 = na::UnitQuaternion::from_euler_angles(0.0, 0.0, 1.0);
+        let q = Tween::new(q0, q1, 1.0).update(0.5);
+        assert!((q.euler_angles().2 - 0.5).abs() < EPS);
+    }
+}